@@ -1,106 +1,826 @@
 use neon::prelude::*;
-use spritec::math::{Rgba, Rgb, Vec3, Mat4, Radians};
-use spritec::query3d::{File, GeometryFilter, GeometryQuery};
+use neon::object::This;
+use spritec::math::{Rgba, Rgb, Vec3, Mat4, Radians, Handedness};
+use spritec::query3d::{File, GeometryFilter, GeometryQuery, Manifest};
 use spritec::renderer::{
     FileQuery,
     Light,
     Camera,
+    Framing,
+    AspectMode,
     Outline,
+    OutlineMode,
+    EdgeThresholds,
+    CelBands,
+    ShadingMode,
+    ColorSpace,
     RenderCamera,
     RenderJob,
+    PixelFormat,
+    ScaleMode,
     RenderLights,
+    RenderGeometry,
     RenderNode,
     RenderedImage,
+    Background,
     Size,
     ThreadRenderContext,
+    DrawLayoutError,
+    RenderStats,
 };
-use spritec::scene::{LightType, CameraType};
+use spritec::scene::{CameraType, NormalMode};
+use image::RgbaImage;
 use std::num::NonZeroU32;
+use std::collections::HashMap;
 use std::path::Path;
 use std::sync::{Arc, Mutex};
 
+/// The projection used by the camera that renders the sprite
+#[derive(Debug, Clone, Copy)]
+enum Projection {
+    Perspective,
+    /// An orthographic projection where the viewing volume is `scale` world units across
+    Orthographic {scale: f32},
+    /// One of the isometric camera presets, where the viewing volume is `scale` world units across
+    Isometric {dimetric: bool, scale: f32},
+}
+
+/// The field of view and clipping planes of the camera that renders the sprite
+#[derive(Debug, Clone, Copy)]
+struct Lens {
+    /// The vertical field of view of a perspective camera (in degrees)
+    field_of_view: f32,
+    /// The distance from the camera to the near clipping plane
+    near: f32,
+    /// The distance from the camera to the far clipping plane
+    far: f32,
+}
+
+impl Default for Lens {
+    fn default() -> Self {
+        Self {
+            field_of_view: 40.0,
+            near: 0.1,
+            far: 1000.0,
+        }
+    }
+}
+
+/// Options that change how a sprite is rendered, read from the `options` object passed from
+/// JavaScript. Any option that is not provided keeps its default value.
+#[derive(Debug, Clone)]
+struct RenderOptions {
+    /// The direction that the directional light shines in
+    light_direction: Vec3,
+    /// The color of the directional light
+    light_color: Rgb,
+    /// The brightness of the directional light
+    light_intensity: f32,
+    /// The color of the ambient light that reaches every surface
+    ambient_color: Rgb,
+    /// The color drawn behind the geometry
+    background: Rgba,
+    /// The thickness of the outline in `outline_units`, or zero for no outline
+    outline_thickness: f32,
+    /// If true, the outline thickness is in pixels of the image instead of model units
+    outline_in_pixels: bool,
+    /// The color of the outline
+    outline_color: Rgba,
+    /// The technique used to shade the model
+    shading: ShadingMode,
+    /// The way that the normals of the model are generated
+    normals: NormalMode,
+    /// The color space that the lighting is computed in
+    color_space: ColorSpace,
+    /// The factor that the lit color is multiplied by
+    exposure: f32,
+    /// The gamma that the lit color is adjusted with after it is encoded
+    gamma: f32,
+    /// If true, the camera is moved so that the entire model fits in the image
+    auto_frame: bool,
+    /// The field of view and clipping planes of the camera, only used when the camera is created
+    lens: Lens,
+    /// If true, the color channels of each pixel are multiplied by its alpha
+    premultiply: bool,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        // Shines forward and down at 60° below the horizon
+        let angle = 60.0f32.to_radians();
+        Self {
+            light_direction: Vec3::new(0.0, -angle.sin(), -angle.cos()),
+            light_color: Rgb::white(),
+            light_intensity: 1.0,
+            ambient_color: Rgb::white() * 0.5,
+            background: Rgba::zero(),
+            outline_thickness: 0.0,
+            outline_in_pixels: false,
+            outline_color: Rgba::black(),
+            shading: ShadingMode::Cel,
+            normals: NormalMode::Smooth,
+            color_space: ColorSpace::Linear,
+            exposure: 1.0,
+            gamma: 1.0,
+            auto_frame: false,
+            lens: Lens::default(),
+            premultiply: false,
+        }
+    }
+}
+
+/// Holds the resources needed to render sprites so that they can be reused between renders
+pub struct Renderer {
+    ctx: ThreadRenderContext,
+    file: Arc<Mutex<File>>,
+    /// The image that every render is drawn into, which is only reallocated when the size of the
+    /// rendered image changes
+    buffer: RgbaImage,
+}
+
+impl Renderer {
+    /// Renders the geometry in the loaded file with the given camera and options into the buffer
+    /// of this renderer, returning how long each stage of the render took
+    fn render(
+        &mut self,
+        width: NonZeroU32,
+        height: NonZeroU32,
+        camera: Camera,
+        options: &RenderOptions,
+    ) -> Result<RenderStats, DrawLayoutError> {
+        let &RenderOptions {
+            light_direction,
+            light_color,
+            light_intensity,
+            ambient_color,
+            background,
+            outline_thickness,
+            outline_in_pixels,
+            outline_color,
+            shading,
+            normals,
+            color_space,
+            exposure,
+            gamma,
+            auto_frame,
+            lens: _,
+            premultiply,
+        } = options;
+
+        let outline_mode = if outline_in_pixels {
+            let EdgeThresholds {depth, normal} = EdgeThresholds::default();
+            OutlineMode::EdgeDetect {
+                thickness: outline_thickness.round() as u32,
+                depth_threshold: depth,
+                normal_threshold: normal,
+            }
+        } else {
+            OutlineMode::InvertedHull {thickness: outline_thickness}
+        };
+
+        let job = RenderJob {
+            scale: NonZeroU32::new(1).expect("bug: 1 is not zero"),
+            scale_mode: ScaleMode::Nearest,
+            flip_y: false,
+            premultiply,
+            format: PixelFormat::Rgba8,
+            root: RenderNode::RenderedImage(RenderedImage {
+                size: Size {width, height},
+                background: Background::Color(background),
+                cell_background: None,
+                camera: RenderCamera::Camera(Arc::new(camera)),
+                // Leaves a little space around the model so that its outline is not cut off
+                framing: if auto_frame { Some(Framing::Fit {margin: 0.05}) } else { None },
+                aspect: AspectMode::Stretch,
+                lights: RenderLights::Lights(Arc::new(vec![Arc::new(Light::directional(
+                    light_direction,
+                    light_color,
+                    light_intensity,
+                ))])),
+                ambient_light: ambient_color,
+                key_light: None,
+                bands: CelBands::default(),
+                shading,
+                color_space,
+                exposure,
+                gamma,
+                geometry: RenderGeometry::Query(FileQuery {
+                    query: GeometryQuery {
+                        models: GeometryFilter::all_in_default_scene(),
+                        animation: None,
+                        morph_weights: None,
+                        transform_overrides: HashMap::new(),
+                        normals,
+                    },
+                    file: self.file.clone(),
+                }),
+                outline: Outline {
+                    mode: outline_mode,
+                    color: outline_color,
+                },
+                rim: None,
+                specular: None,
+                shadows: None,
+                ground: None,
+                light_gizmos: false,
+                wireframe: None,
+                supersample: None,
+                depth: None,
+            }),
+        };
+
+        // Discard anything that was rendered with the context before this job
+        self.ctx.take_stats();
+        job.execute_into(&mut self.buffer, &mut self.ctx)?;
+        Ok(self.ctx.take_stats())
+    }
+}
+
+declare_types! {
+    /// A renderer that keeps its render context and loaded file between renders
+    ///
+    /// Constructor arguments: `path`
+    pub class JsRenderer for Renderer {
+        init(mut cx) {
+            let path = cx.argument::<JsString>(0)?.value();
+
+            let ctx = match ThreadRenderContext::new() {
+                Ok(ctx) => ctx,
+                Err(err) => return cx.throw_error(format!("Unable to create render context: {}", err)),
+            };
+            let file = open_file(&mut cx, &path)?;
+
+            Ok(Renderer {ctx, file, buffer: RgbaImage::new(0, 0)})
+        }
+
+        // Returns the rendered sprite as an object with its RGBA pixels, size, and render statistics
+        // (see `render_sprite`)
+        //
+        // Arguments: `width, height, [projection, [scale, [eye, [target, [options]]]]]` (see
+        // `render_sprite`)
+        method render(mut cx) {
+            let width = dimension_argument(&mut cx, 0, "width")?;
+            let height = dimension_argument(&mut cx, 1, "height")?;
+            let options = options_argument(&mut cx, 6)?;
+            let camera = camera_arguments(&mut cx, 2, options.lens)?;
+
+            let mut this = cx.this();
+            let stats = {
+                let guard = cx.lock();
+                let mut renderer = this.borrow_mut(&guard);
+                renderer.render(width, height, camera, &options)
+            };
+
+            match stats {
+                Ok(stats) => {
+                    let (data, size) = copy_rendered_pixels(&mut cx, None)?;
+                    Ok(image_object(&mut cx, data, size, &stats)?.upcast())
+                },
+                Err(err) => cx.throw_error(format!("Sprite creation failed: {}", err)),
+            }
+        }
+
+        // Renders the sprite into the given array buffer instead of allocating a new one, so that
+        // the same buffer can be reused for every frame of a live preview. The buffer must hold
+        // exactly `width * height * 4` bytes. Returns the same object as `render`, with the given
+        // buffer as its `data`.
+        //
+        // Arguments: `buffer, width, height, [projection, [scale, [eye, [target, [options]]]]]`
+        // (see `render_sprite`)
+        method render_into(mut cx) {
+            let data = cx.argument::<JsArrayBuffer>(0)?;
+            let width = dimension_argument(&mut cx, 1, "width")?;
+            let height = dimension_argument(&mut cx, 2, "height")?;
+            let options = options_argument(&mut cx, 7)?;
+            let camera = camera_arguments(&mut cx, 3, options.lens)?;
+
+            let mut this = cx.this();
+            let stats = {
+                let guard = cx.lock();
+                let mut renderer = this.borrow_mut(&guard);
+                renderer.render(width, height, camera, &options)
+            };
+
+            match stats {
+                Ok(stats) => {
+                    let (data, size) = copy_rendered_pixels(&mut cx, Some(data))?;
+                    Ok(image_object(&mut cx, data, size, &stats)?.upcast())
+                },
+                Err(err) => cx.throw_error(format!("Sprite creation failed: {}", err)),
+            }
+        }
+
+        // Replaces the loaded file with the file at the given path, keeping the render context
+        //
+        // Arguments: `path`
+        method open(mut cx) {
+            let path = cx.argument::<JsString>(0)?.value();
+            let file = open_file(&mut cx, &path)?;
+
+            let mut this = cx.this();
+            {
+                let guard = cx.lock();
+                let mut renderer = this.borrow_mut(&guard);
+                renderer.file = file;
+            }
+
+            Ok(cx.undefined().upcast())
+        }
+
+        // Returns the scenes, cameras, lights, and animations in the loaded file (see
+        // `describe_file`)
+        method describe(mut cx) {
+            let this = cx.this();
+            let manifest = {
+                let guard = cx.lock();
+                let renderer = this.borrow(&guard);
+                let file = renderer.file.lock().expect("bug: file lock was poisoned");
+                file.describe()
+            };
+
+            Ok(manifest_object(&mut cx, &manifest)?.upcast())
+        }
+    }
+}
+
 /// Returns the rendered sprite given parameters from JavaScript
-fn render_sprite(mut cx: FunctionContext) -> JsResult<JsArrayBuffer> {
+///
+/// Arguments: `path, width, height, [projection, [scale, [eye, [target, [options]]]]]`
+///
+/// * `projection` - either `"perspective"` (the default), `"orthographic"`, or one of the isometric
+///   presets `"isometric"` and `"dimetric"` (2:1). The presets ignore `eye` and `target`.
+/// * `scale` - the width of the viewing volume of an orthographic or isometric camera
+///   (default: 10)
+/// * `eye` - the position of the camera as an `[x, y, z]` array (default: `[8, 8, 8]`)
+/// * `target` - the point the camera looks at as an `[x, y, z]` array (default: `[0, 0, 0]`)
+/// * `options` - an object with any of the following properties:
+///   * `light_direction` - the direction the light shines in as an `[x, y, z]` array (default:
+///     forward and 60° down)
+///   * `light_color` - the color of the light as an `[r, g, b]` array (default: `[1, 1, 1]`)
+///   * `light_intensity` - the brightness of the light (default: 1)
+///   * `ambient_color` - the color of the ambient light as an `[r, g, b]` array (default:
+///     `[0.5, 0.5, 0.5]`)
+///   * `background` - the color drawn behind the sprite as an `[r, g, b, a]` array (default:
+///     `[0, 0, 0, 0]`, which is fully transparent)
+///   * `outline_thickness` - how thick the outline around the sprite is, in `outline_units`
+///     (default: 0, which draws no outline)
+///   * `outline_units` - either `"model"` (the default), where the outline extends past the
+///     sprite by `outline_thickness` model units, or `"pixels"`, where the outline is drawn over
+///     the edges of the image and is `outline_thickness` pixels wide (rounded to a whole number)
+///     no matter how large or far away the model is
+///   * `outline_color` - the color of the outline as an `[r, g, b, a]` array (default:
+///     `[0, 0, 0, 1]`)
+///   * `shading` - either `"cel"` (the default), `"flat"`, which lights the sprite smoothly
+///     with no cel shading bands and no outline, or `"mask"`, which draws the silhouette of the
+///     sprite in white over a transparent background
+///   * `normals` - either `"smooth"` (the default), which shades the model with its vertex
+///     normals, or `"flat"`, which gives each triangle a single flat shade
+///   * `color_space` - either `"linear"` (the default), which decodes textures from sRGB before
+///     lighting them and encodes the result as sRGB, or `"srgb"`, which lights the stored texture
+///     values directly and writes the result as is
+///   * `exposure` - the factor that the lit color is multiplied by, at least zero (default: 1)
+///   * `gamma` - the lit color is raised to the power of `1 / gamma` once it is encoded, greater
+///     than zero (default: 1). Results are clamped between 0 and 1.
+///   * `auto_frame` - if true, the camera keeps looking in the same direction but is moved (and
+///     zoomed, if it is orthographic) so that the entire model fits in the image (default: false)
+///   * `field_of_view` - the vertical field of view of a perspective camera in degrees, greater
+///     than 0 and less than 180 (default: 40)
+///   * `near` - the distance from the camera to the near clipping plane of a perspective or
+///     orthographic camera, greater than zero (default: 0.1)
+///   * `far` - the distance from the camera to the far clipping plane of a perspective or
+///     orthographic camera, greater than `near` (default: 1000)
+///   * `premultiply` - if true, the color channels of each pixel are multiplied by its alpha
+///     (default: false). A fully transparent background is transparent black either way.
+///
+/// Color components are clamped between 0 and 1.
+///
+/// Returns an object with the properties `data` (an array buffer of 8-bit RGBA pixels, one row at
+/// a time starting from the top), `width`, `height`, `channels` (always 4), and `stats` (see
+/// `image_object`).
+///
+/// This creates a new render context and reopens the file every time it is called. Use the
+/// `Renderer` class to render the same file many times.
+fn render_sprite(mut cx: FunctionContext) -> JsResult<JsObject> {
     // Arguments from JavaScript
     let path = cx.argument::<JsString>(0)?.value();
-    let width = cx.argument::<JsNumber>(1)?.value() as u32;
-    let height = cx.argument::<JsNumber>(2)?.value() as u32;
-
-    // TODO: Change to return a class so we can reuse resources
-    let mut ctx = ThreadRenderContext::new().expect("Unable to create ThreadRenderContext");
-    let file = Arc::new(Mutex::new(File::open(Path::new(&path)).expect("Unable to open file")));
-    let camera = RenderCamera::Camera(Arc::new(default_camera()));
-
-    let job = RenderJob {
-        scale: unsafe { NonZeroU32::new_unchecked(1) },
-        root: RenderNode::RenderedImage(RenderedImage {
-            size: Size {
-                width: NonZeroU32::new(width).expect("Width is not a u32"),
-                height: NonZeroU32::new(height).expect("Height is not a u32"),
-            },
-            background: Rgba {
-                r: 0.0,
-                g: 0.0,
-                b: 0.0,
-                a: 0.0,
-            },
-            camera,
-            lights: RenderLights::Lights(Arc::new(vec![Arc::new(Light {
-                data: Arc::new(LightType::Directional {
-                    color: Rgb::white(),
-                    intensity: 1.0,
-                }),
-                world_transform: Mat4::rotation_x((-60.0f32).to_radians()),
-            })])),
-            ambient_light: Rgb::white() * 0.5,
-            geometry: FileQuery {
-                query: GeometryQuery {
-                    models: GeometryFilter::all_in_default_scene(),
-                    animation: None,
-                },
-                file,
-            },
-            outline: Outline {
-                thickness: 0.0,
-                color: Rgba::black(),
-            },
-        }),
+    let width = dimension_argument(&mut cx, 1, "width")?;
+    let height = dimension_argument(&mut cx, 2, "height")?;
+    let options = options_argument(&mut cx, 7)?;
+    let camera = camera_arguments(&mut cx, 3, options.lens)?;
+
+    let ctx = match ThreadRenderContext::new() {
+        Ok(ctx) => ctx,
+        Err(err) => return cx.throw_error(format!("Unable to create render context: {}", err)),
+    };
+    let file = open_file(&mut cx, &path)?;
+
+    let mut renderer = Renderer {ctx, file, buffer: RgbaImage::new(0, 0)};
+    match renderer.render(width, height, camera, &options) {
+        Ok(stats) => {
+            let mut data = cx.array_buffer(renderer.buffer.len() as u32)?;
+            cx.borrow_mut(&mut data, |data| data.as_mut_slice::<u8>().copy_from_slice(&renderer.buffer));
+            image_object(&mut cx, data, renderer.buffer.dimensions(), &stats)
+        },
+        Err(err) => cx.throw_error(format!("Sprite creation failed: {}", err)),
+    }
+}
+
+/// Returns the scenes, cameras, lights, and animations in the given file
+///
+/// Arguments: `path`
+///
+/// Returns an object with the properties `scenes`, `cameras`, `lights`, and `animations`. Each is
+/// an array of objects with a `name` property (null if unnamed). Animations also have a
+/// `duration` property (in seconds). The file is opened without creating a render context.
+fn describe_file(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let path = cx.argument::<JsString>(0)?.value();
+    let file = open_file(&mut cx, &path)?;
+    let manifest = file.lock().expect("bug: file lock was poisoned").describe();
+    manifest_object(&mut cx, &manifest)
+}
+
+/// Opens the 3D file at the given path, throwing an exception if it could not be opened
+fn open_file<'a, C: Context<'a>>(cx: &mut C, path: &str) -> NeonResult<Arc<Mutex<File>>> {
+    match File::open(Path::new(path)) {
+        Ok(file) => Ok(Arc::new(Mutex::new(file))),
+        Err(err) => cx.throw_error(format!("Unable to open `{}`: {}", path, err)),
+    }
+}
+
+/// Copies the pixels of the last render of `this` renderer into the given array buffer, or into a
+/// new array buffer if none is given, returning the array buffer and the size of the image
+///
+/// Throws an exception if the given array buffer is not the same size as the image.
+fn copy_rendered_pixels<'a>(
+    cx: &mut CallContext<'a, JsRenderer>,
+    data: Option<Handle<'a, JsArrayBuffer>>,
+) -> NeonResult<(Handle<'a, JsArrayBuffer>, (u32, u32))> {
+    let this = cx.this();
+    let (size, len) = {
+        let guard = cx.lock();
+        let renderer = this.borrow(&guard);
+        (renderer.buffer.dimensions(), renderer.buffer.len())
     };
-    let image = job.execute(&mut ctx).expect("Sprite creation failed");
 
-    let mut array_buffer = cx.array_buffer(image.width() * image.height() * 4)?;
-    cx.borrow_mut(&mut array_buffer, |data| {
+    let mut data = match data {
+        Some(data) => data,
+        None => cx.array_buffer(len as u32)?,
+    };
+    let copied = {
+        let guard = cx.lock();
+        let renderer = this.borrow(&guard);
+        let mut data = data.borrow_mut(&guard);
         let slice = data.as_mut_slice::<u8>();
-        for (i, pixel) in image.pixels().enumerate() {
-            slice[i * 4 + 0] = pixel[0];
-            slice[i * 4 + 1] = pixel[1];
-            slice[i * 4 + 2] = pixel[2];
-            slice[i * 4 + 3] = pixel[3];
-        }
-    });
-    Ok(array_buffer)
-}
-
-fn default_camera() -> Camera {
-    let eye = Vec3 {x: 8.0, y: 8.0, z: 8.0};
-    let target = Vec3::zero();
-
-    let cam_type = CameraType::Perspective {
-        name: None,
-        aspect_ratio: 1.0,
-        field_of_view_y: Radians::from_degrees(40.0),
-        near_z: 0.1,
-        far_z: Some(1000.0),
+        if slice.len() == len {
+            slice.copy_from_slice(&renderer.buffer);
+            true
+        } else {
+            false
+        }
+    };
+    if !copied {
+        let (width, height) = size;
+        return cx.throw_error(format!("Expected a buffer of {} bytes for a {}x{} image", len, width, height));
+    }
+
+    Ok((data, size))
+}
+
+/// Returns an object with the given pixels, the size of their image, and the statistics of the
+/// render that produced them
+///
+/// The object has the properties `data` (an array buffer of 8-bit RGBA pixels, one row at a time
+/// starting from the top), `width`, `height`, `channels` (always 4), and `stats`. The `stats`
+/// object has the properties `parse_ms`, `upload_ms`, `draw_ms`, `triangles`, and `draw_calls`.
+fn image_object<'a, C: Context<'a>>(
+    cx: &mut C,
+    data: Handle<'a, JsArrayBuffer>,
+    (width, height): (u32, u32),
+    stats: &RenderStats,
+) -> JsResult<'a, JsObject> {
+    let object = cx.empty_object();
+    let width = cx.number(width);
+    let height = cx.number(height);
+    let channels = cx.number(4);
+    object.set(cx, "data", data)?;
+    object.set(cx, "width", width)?;
+    object.set(cx, "height", height)?;
+    object.set(cx, "channels", channels)?;
+
+    let &RenderStats {parse_ms, upload_ms, draw_ms, triangles, draw_calls} = stats;
+    let stats = cx.empty_object();
+    let parse_ms = cx.number(parse_ms);
+    let upload_ms = cx.number(upload_ms);
+    let draw_ms = cx.number(draw_ms);
+    let triangles = cx.number(triangles as f64);
+    let draw_calls = cx.number(draw_calls as f64);
+    stats.set(cx, "parse_ms", parse_ms)?;
+    stats.set(cx, "upload_ms", upload_ms)?;
+    stats.set(cx, "draw_ms", draw_ms)?;
+    stats.set(cx, "triangles", triangles)?;
+    stats.set(cx, "draw_calls", draw_calls)?;
+    object.set(cx, "stats", stats)?;
+
+    Ok(object)
+}
+
+/// Returns an object with the contents of the given manifest (see `describe_file`)
+fn manifest_object<'a, C: Context<'a>>(cx: &mut C, manifest: &Manifest) -> JsResult<'a, JsObject> {
+    let Manifest {scenes, cameras, lights, animations} = manifest;
+
+    let object = cx.empty_object();
+    let scenes = named_array(cx, scenes.iter().map(|scene| (scene.name.as_deref(), None)))?;
+    let cameras = named_array(cx, cameras.iter().map(|cam| (cam.name.as_deref(), None)))?;
+    let lights = named_array(cx, lights.iter().map(|light| (light.name.as_deref(), None)))?;
+    let animations = named_array(cx, animations.iter()
+        .map(|anim| (anim.name.as_deref(), Some(anim.duration))))?;
+    object.set(cx, "scenes", scenes)?;
+    object.set(cx, "cameras", cameras)?;
+    object.set(cx, "lights", lights)?;
+    object.set(cx, "animations", animations)?;
+    Ok(object)
+}
+
+/// Returns an array of objects with a `name` property (null if None) and, if present, a
+/// `duration` property
+fn named_array<'a, 'b, C: Context<'a>>(
+    cx: &mut C,
+    items: impl Iterator<Item=(Option<&'b str>, Option<f32>)>,
+) -> JsResult<'a, JsArray> {
+    let array = cx.empty_array();
+    for (i, (name, duration)) in items.enumerate() {
+        let item = cx.empty_object();
+        let name = match name {
+            Some(name) => cx.string(name).upcast::<JsValue>(),
+            None => cx.null().upcast(),
+        };
+        item.set(cx, "name", name)?;
+        if let Some(duration) = duration {
+            let duration = cx.number(duration);
+            item.set(cx, "duration", duration)?;
+        }
+        array.set(cx, i as u32, item)?;
+    }
+    Ok(array)
+}
+
+/// Reads the camera arguments `[projection, [scale, [eye, [target]]]]` starting at the given
+/// argument index
+fn camera_arguments<T: This>(cx: &mut CallContext<T>, start: i32, lens: Lens) -> NeonResult<Camera> {
+    let projection = match optional_argument::<_, JsString>(cx, start)? {
+        None => Projection::Perspective,
+        Some(projection) => match projection.value().as_str() {
+            "perspective" => Projection::Perspective,
+            "orthographic" => Projection::Orthographic {scale: scale_argument(cx, start + 1)?},
+            "isometric" => Projection::Isometric {dimetric: false, scale: scale_argument(cx, start + 1)?},
+            "dimetric" => Projection::Isometric {dimetric: true, scale: scale_argument(cx, start + 1)?},
+            projection => return cx.throw_error(format!(
+                "Unknown projection `{}`, expected `perspective`, `orthographic`, `isometric`, or `dimetric`",
+                projection,
+            )),
+        },
+    };
+    let eye = vec3_argument(cx, start + 2)?.unwrap_or(Vec3 {x: 8.0, y: 8.0, z: 8.0});
+    let target = vec3_argument(cx, start + 3)?.unwrap_or_else(Vec3::zero);
+
+    Ok(camera(projection, lens, eye, target))
+}
+
+/// Returns the scale of an orthographic camera at the given argument index, or the default scale
+/// if it was not provided
+fn scale_argument<T: This>(cx: &mut CallContext<T>, i: i32) -> NeonResult<f32> {
+    let scale = optional_argument::<_, JsNumber>(cx, i)?
+        .map(|scale| scale.value() as f32)
+        .unwrap_or(10.0);
+    if scale <= 0.0 {
+        return cx.throw_error(format!("Expected scale to be greater than zero, got {}", scale));
+    }
+
+    Ok(scale)
+}
+
+/// Returns the argument at the given index as an image dimension, throwing an exception if it
+/// is not a positive integer that fits in a u32
+fn dimension_argument<T: This>(cx: &mut CallContext<T>, i: i32, name: &str) -> NeonResult<NonZeroU32> {
+    let value = cx.argument::<JsNumber>(i)?.value();
+    if value.fract() != 0.0 || value < 1.0 || value > u32::max_value() as f64 {
+        return cx.throw_error(format!("Expected {} to be a positive integer, got {}", name, value));
+    }
+
+    Ok(NonZeroU32::new(value as u32).expect("bug: dimension was validated to be non-zero"))
+}
+
+/// Returns the argument at the given index, or None if it was not provided or is undefined
+fn optional_argument<'a, T: This, V: Value>(cx: &mut CallContext<'a, T>, i: i32) -> NeonResult<Option<Handle<'a, V>>> {
+    match cx.argument_opt(i) {
+        Some(arg) if !arg.is_a::<JsUndefined>() => Ok(Some(arg.downcast::<V>().or_throw(cx)?)),
+        _ => Ok(None),
+    }
+}
+
+/// Returns the property of the given object with the given name, or None if it was not provided
+/// or is undefined
+fn optional_property<'a, C: Context<'a>, V: Value>(
+    cx: &mut C,
+    object: Handle<'a, JsObject>,
+    name: &str,
+) -> NeonResult<Option<Handle<'a, V>>> {
+    let value = object.get(cx, name)?;
+    if value.is_a::<JsUndefined>() {
+        Ok(None)
+    } else {
+        Ok(Some(value.downcast::<V>().or_throw(cx)?))
+    }
+}
+
+/// Reads the `options` object argument at the given index. Any option that is not provided keeps
+/// its default value.
+fn options_argument<T: This>(cx: &mut CallContext<T>, i: i32) -> NeonResult<RenderOptions> {
+    let mut options = RenderOptions::default();
+    let object = match optional_argument::<_, JsObject>(cx, i)? {
+        Some(object) => object,
+        None => return Ok(options),
+    };
+
+    if let Some(array) = optional_property(cx, object, "light_direction")? {
+        let [x, y, z] = number_array::<_, 3>(cx, array)?;
+        if x == 0.0 && y == 0.0 && z == 0.0 {
+            return cx.throw_error("Expected light_direction to be a non-zero vector");
+        }
+        options.light_direction = Vec3 {x, y, z};
+    }
+    if let Some(array) = optional_property(cx, object, "light_color")? {
+        options.light_color = Rgb::from(clamp_components(number_array::<_, 3>(cx, array)?));
+    }
+    if let Some(intensity) = optional_property::<_, JsNumber>(cx, object, "light_intensity")? {
+        let intensity = intensity.value() as f32;
+        if intensity < 0.0 {
+            return cx.throw_error(format!("Expected light_intensity to be at least zero, got {}", intensity));
+        }
+        options.light_intensity = intensity;
+    }
+    if let Some(array) = optional_property(cx, object, "ambient_color")? {
+        options.ambient_color = Rgb::from(clamp_components(number_array::<_, 3>(cx, array)?));
+    }
+    if let Some(array) = optional_property(cx, object, "background")? {
+        options.background = Rgba::from(clamp_components(number_array::<_, 4>(cx, array)?));
+    }
+    if let Some(thickness) = optional_property::<_, JsNumber>(cx, object, "outline_thickness")? {
+        let thickness = thickness.value() as f32;
+        if thickness < 0.0 {
+            return cx.throw_error(format!("Expected outline_thickness to be at least zero, got {}", thickness));
+        }
+        options.outline_thickness = thickness;
+    }
+    if let Some(units) = optional_property::<_, JsString>(cx, object, "outline_units")? {
+        options.outline_in_pixels = match units.value().as_str() {
+            "model" => false,
+            "pixels" => true,
+            units => return cx.throw_error(format!("Unknown outline_units `{}`, expected `model` or `pixels`", units)),
+        };
+    }
+    if let Some(array) = optional_property(cx, object, "outline_color")? {
+        options.outline_color = Rgba::from(clamp_components(number_array::<_, 4>(cx, array)?));
+    }
+    if let Some(shading) = optional_property::<_, JsString>(cx, object, "shading")? {
+        options.shading = match shading.value().as_str() {
+            "cel" => ShadingMode::Cel,
+            "flat" => ShadingMode::Flat,
+            "mask" => ShadingMode::Mask,
+            shading => return cx.throw_error(format!("Unknown shading `{}`, expected `cel`, `flat`, or `mask`", shading)),
+        };
+    }
+    if let Some(normals) = optional_property::<_, JsString>(cx, object, "normals")? {
+        options.normals = match normals.value().as_str() {
+            "smooth" => NormalMode::Smooth,
+            "flat" => NormalMode::Flat,
+            normals => return cx.throw_error(format!("Unknown normals `{}`, expected `smooth` or `flat`", normals)),
+        };
+    }
+    if let Some(color_space) = optional_property::<_, JsString>(cx, object, "color_space")? {
+        options.color_space = match color_space.value().as_str() {
+            "linear" => ColorSpace::Linear,
+            "srgb" => ColorSpace::Srgb,
+            color_space => {
+                return cx.throw_error(format!("Unknown color_space `{}`, expected `linear` or `srgb`", color_space));
+            },
+        };
+    }
+    if let Some(exposure) = optional_property::<_, JsNumber>(cx, object, "exposure")? {
+        let exposure = exposure.value() as f32;
+        if exposure < 0.0 {
+            return cx.throw_error(format!("Expected exposure to be at least zero, got {}", exposure));
+        }
+        options.exposure = exposure;
+    }
+    if let Some(gamma) = optional_property::<_, JsNumber>(cx, object, "gamma")? {
+        let gamma = gamma.value() as f32;
+        if gamma <= 0.0 {
+            return cx.throw_error(format!("Expected gamma to be greater than zero, got {}", gamma));
+        }
+        options.gamma = gamma;
+    }
+    if let Some(auto_frame) = optional_property::<_, JsBoolean>(cx, object, "auto_frame")? {
+        options.auto_frame = auto_frame.value();
+    }
+    if let Some(field_of_view) = optional_property::<_, JsNumber>(cx, object, "field_of_view")? {
+        let field_of_view = field_of_view.value() as f32;
+        if !(field_of_view > 0.0 && field_of_view < 180.0) {
+            return cx.throw_error(format!(
+                "Expected field_of_view to be between 0 and 180 degrees (exclusive), got {}",
+                field_of_view,
+            ));
+        }
+        options.lens.field_of_view = field_of_view;
+    }
+    if let Some(near) = optional_property::<_, JsNumber>(cx, object, "near")? {
+        let near = near.value() as f32;
+        if !(near > 0.0 && near.is_finite()) {
+            return cx.throw_error(format!("Expected near to be a finite number greater than zero, got {}", near));
+        }
+        options.lens.near = near;
+    }
+    if let Some(far) = optional_property::<_, JsNumber>(cx, object, "far")? {
+        options.lens.far = far.value() as f32;
+    }
+    // Checked once both are known so that either one can be changed on its own
+    let Lens {near, far, ..} = options.lens;
+    if !(near < far && far.is_finite()) {
+        return cx.throw_error(format!("Expected near ({}) to be less than far ({}), which must be finite", near, far));
+    }
+    if let Some(premultiply) = optional_property::<_, JsBoolean>(cx, object, "premultiply")? {
+        options.premultiply = premultiply.value();
+    }
+
+    Ok(options)
+}
+
+/// Returns the `[x, y, z]` array argument at the given index as a vector, or None if the
+/// argument was not provided
+fn vec3_argument<T: This>(cx: &mut CallContext<T>, i: i32) -> NeonResult<Option<Vec3>> {
+    match optional_argument::<_, JsArray>(cx, i)? {
+        Some(array) => Ok(Some(Vec3::from(number_array::<_, 3>(cx, array)?))),
+        None => Ok(None),
+    }
+}
+
+/// Returns the numbers in the given array, throwing an exception if it does not contain exactly
+/// `N` numbers
+fn number_array<'a, C: Context<'a>, const N: usize>(cx: &mut C, array: Handle<'a, JsArray>) -> NeonResult<[f32; N]> {
+    let array = array.to_vec(cx)?;
+    if array.len() != N {
+        return cx.throw_error(format!("Expected an array of {} numbers, got {} values", N, array.len()));
+    }
+
+    let mut components = [0.0; N];
+    for (component, value) in components.iter_mut().zip(array) {
+        *component = value.downcast::<JsNumber>().or_throw(cx)?.value() as f32;
+    }
+    Ok(components)
+}
+
+/// Clamps each of the given color components between 0.0 and 1.0
+fn clamp_components<const N: usize>(mut components: [f32; N]) -> [f32; N] {
+    for component in &mut components {
+        *component = component.clamp(0.0, 1.0);
+    }
+    components
+}
+
+fn camera(projection: Projection, lens: Lens, eye: Vec3, target: Vec3) -> Camera {
+    let Lens {field_of_view, near, far} = lens;
+    let cam_type = match projection {
+        Projection::Perspective => CameraType::Perspective {
+            name: None,
+            aspect_ratio: 1.0,
+            field_of_view_y: Radians::from_degrees(field_of_view),
+            near_z: near,
+            far_z: Some(far),
+        },
+
+        Projection::Orthographic {scale} => CameraType::Orthographic {
+            name: None,
+            mag_x: scale,
+            mag_y: scale,
+            near_z: near,
+            far_z: far,
+        },
+
+        // The isometric presets always look at the origin from a fixed angle
+        Projection::Isometric {dimetric, scale} => return Camera::isometric(dimetric, scale),
     };
 
     Camera {
         view: Mat4::look_at_rh(eye, target, Vec3::up()),
-        projection: cam_type.to_projection(),
+        projection: cam_type.to_projection(Handedness::Right),
+        handedness: Handedness::Right,
     }
 }
 
 
 register_module!(mut cx, {
     cx.export_function("render_sprite", render_sprite)?;
+    cx.export_function("describe_file", describe_file)?;
+    cx.export_class::<JsRenderer>("Renderer")?;
     Ok(())
 });
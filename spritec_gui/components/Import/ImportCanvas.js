@@ -31,12 +31,9 @@ class ImportCanvas extends Component {
     if (file === null) return;
 
     // TODO: use offscreen canvas when calling spritec
-    let imageBuffer = new Uint8ClampedArray(spritec.render_sprite(
-      file,
-      width,
-      height
-    ));
-    let imageData = new ImageData(imageBuffer, width);
+    let sprite = spritec.render_sprite(file, width, height);
+    let imageBuffer = new Uint8ClampedArray(sprite.data);
+    let imageData = new ImageData(imageBuffer, sprite.width, sprite.height);
 
     createImageBitmap(imageData).then((bitmap) => {
       ctx.clearRect(0, 0, canvas.width, canvas.height);
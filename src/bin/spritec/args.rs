@@ -2,6 +2,8 @@ use std::fs;
 use std::io;
 use std::path::PathBuf;
 use std::error::Error;
+use std::num::NonZeroU32;
+use std::str::FromStr;
 
 use structopt::{
     StructOpt,
@@ -13,12 +15,13 @@ use structopt::{
         },
     },
 };
-use spritec::config::TaskConfig;
+use spritec::config::{TaskConfig, Pose, PoseModel, PresetCamera, Perspective, NamedCamera};
 
 /// A tool for generating pixel art from 3D models.
 ///
 /// Rather than have you specify too many options on the command line, the spritec tool takes in a
-/// configuration file in the TOML format.
+/// configuration file in the TOML format. Use the `render` command to quickly render a single
+/// image without a configuration file.
 #[derive(Debug, StructOpt)]
 #[structopt(author = "The ProtoArt Team <https://protoart.me>")]
 #[structopt(global_settings = &[ColoredHelp, DontCollapseArgsInUsage, ArgRequiredElseHelp])]
@@ -26,6 +29,113 @@ pub struct AppArgs {
     /// Path to the configuration file to execute tasks from
     #[structopt(name = "config-file", default_value = "spritec.toml", parse(from_os_str))]
     config_path: PathBuf,
+    #[structopt(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum Command {
+    /// Renders a single image of a model without a configuration file
+    Render(RenderArgs),
+}
+
+/// The options of the `render` command
+#[derive(Debug, StructOpt)]
+pub struct RenderArgs {
+    /// Path to the model to render (glTF, OBJ, STL, or PLY)
+    #[structopt(long, parse(from_os_str))]
+    pub input: PathBuf,
+    /// Path to write the rendered PNG image to
+    #[structopt(long, parse(from_os_str))]
+    pub output: PathBuf,
+    /// The width at which to render the image (in pixels)
+    #[structopt(long, default_value = "64")]
+    pub width: NonZeroU32,
+    /// The height at which to render the image (in pixels)
+    #[structopt(long, default_value = "64")]
+    pub height: NonZeroU32,
+    /// The camera to render from: one of `front`, `back`, `left`, `right`, `top`, or `bottom`, or
+    /// the name of a camera in the model file
+    #[structopt(long, default_value = "front", parse(from_str = parse_camera))]
+    pub camera: PresetCamera,
+    /// The animation to pose a glTF model with, as `name@time` where the time is in ms. The name
+    /// can be left out (e.g. `@250`) if the file only has a single animation.
+    #[structopt(long)]
+    pub anim: Option<AnimationArg>,
+    /// A scale factor to apply to the rendered image
+    #[structopt(long, default_value = "1")]
+    pub scale: NonZeroU32,
+}
+
+impl RenderArgs {
+    /// Converts these options into the pose that they describe. Paths are resolved relative to the
+    /// current directory.
+    pub fn into_pose(self) -> Pose {
+        let Self {input, output, width, height, camera, anim, scale} = self;
+
+        let model = match anim {
+            Some(AnimationArg {name, time}) => PoseModel::GltfFrame {
+                gltf: input.into(),
+                animation: name,
+                time,
+            },
+            None => PoseModel::Model(input.into()),
+        };
+
+        Pose {
+            model,
+            path: output.into(),
+            width,
+            height,
+            camera,
+            scale,
+            scale_mode: Default::default(),
+            background: Default::default(),
+            outline: Default::default(),
+            supersample: NonZeroU32::new(1).expect("bug: 1 is not zero"),
+            supersample_filter: Default::default(),
+        }
+    }
+}
+
+/// An animation name and a time (in ms) in that animation, written as `name@time`
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnimationArg {
+    /// The name of the animation, or None if the only animation in the file should be used
+    pub name: Option<String>,
+    pub time: f32,
+}
+
+impl FromStr for AnimationArg {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (name, time) = match value.rfind('@') {
+            Some(index) => (&value[..index], &value[index + 1..]),
+            None => return Err(format!("expected `name@time`, got `{}`", value)),
+        };
+        let time = time.parse().map_err(|err| format!("invalid animation time `{}`: {}", time, err))?;
+        let name = if name.is_empty() { None } else { Some(name.to_string()) };
+
+        Ok(Self {name, time})
+    }
+}
+
+/// Selects one of the preset perspective cameras by name, or a camera in the model file with any
+/// other name
+fn parse_camera(name: &str) -> PresetCamera {
+    use Perspective::*;
+    let perspective = match name {
+        "front" => PerspectiveFront,
+        "back" => PerspectiveBack,
+        "left" => PerspectiveLeft,
+        "right" => PerspectiveRight,
+        "top" => PerspectiveTop,
+        "bottom" => PerspectiveBottom,
+        _ => return PresetCamera::Named(NamedCamera {name: name.to_string(), scene: None}),
+    };
+
+    PresetCamera::Perspective(perspective)
 }
 
 impl AppArgs {
@@ -14,6 +14,7 @@
 
 mod args;
 
+use std::env;
 use std::path::Path;
 
 use terminator::Terminator;
@@ -22,17 +23,21 @@ use spritec::{
     tasks::{self, Task, WeakFileCache},
     query3d::FileError,
     config::{TaskConfig, Spritesheet, Pose},
-    renderer::ThreadRenderContext,
+    renderer::{ThreadRenderContext, write_png},
 };
 
-use crate::args::AppArgs;
+use crate::args::{AppArgs, Command, RenderArgs};
 
 fn main() -> Result<(), Terminator> {
-    let args = AppArgs::from_args();
-    let TaskConfig {spritesheets, poses} = args.load_config()?;
+    let mut args = AppArgs::from_args();
+    if let Some(Command::Render(render_args)) = args.command.take() {
+        return render(render_args);
+    }
+
+    let TaskConfig {spritesheets, poses, obj_unit_scale} = args.load_config()?;
     let base_dir = args.base_directory()?;
 
-    let tasks = create_tasks(spritesheets, poses, &base_dir)?;
+    let tasks = create_tasks(spritesheets, poses, obj_unit_scale, &base_dir)?;
 
     let mut ctx = ThreadRenderContext::new()?;
     // This loop should not be parallelised. Rendering is done in parallel on the
@@ -45,12 +50,26 @@ fn main() -> Result<(), Terminator> {
     Ok(())
 }
 
+/// Renders the single image described by the options of the `render` command
+fn render(args: RenderArgs) -> Result<(), Terminator> {
+    let base_dir = env::current_dir()?;
+    let mut file_cache = WeakFileCache::with_obj_unit_scale(1.0);
+    let Task {output_path, job} = tasks::generate_pose_task(args.into_pose(), &base_dir, &mut file_cache)?;
+
+    let mut ctx = ThreadRenderContext::new()?;
+    let image = job.execute(&mut ctx)?;
+    write_png(&image, &output_path)?;
+
+    Ok(())
+}
+
 fn create_tasks(
     spritesheets: Vec<Spritesheet>,
     poses: Vec<Pose>,
+    obj_unit_scale: f32,
     base_dir: &Path,
 ) -> Result<Vec<Task>, FileError> {
-    let mut file_cache = WeakFileCache::default();
+    let mut file_cache = WeakFileCache::with_obj_unit_scale(obj_unit_scale);
 
     let mut tasks = Vec::new();
     for sheet in spritesheets {
@@ -1,8 +1,12 @@
+mod job_spec;
+
+pub use job_spec::*;
+
 use std::num::NonZeroU32;
 
 use serde::{Serialize, Deserialize};
 
-use crate::math::{Vec3, Rgba, Degrees};
+use crate::math::{Vec3, Rgba, Degrees, Handedness};
 
 // PathBuf is not imported to avoid its use in this module. Every path in this module should
 // be an UnresolvedPath.
@@ -70,6 +74,12 @@ impl UnresolvedPath {
     }
 }
 
+impl From<std::path::PathBuf> for UnresolvedPath {
+    fn from(path: std::path::PathBuf) -> Self {
+        UnresolvedPath(path)
+    }
+}
+
 /// A configuration that represents the tasks that spritec should complete
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
@@ -80,6 +90,11 @@ pub struct TaskConfig {
     /// A list of individual poses for spritec to generate images for
     #[serde(default)]
     pub poses: Vec<Pose>,
+    /// The size of one unit in OBJ files, in meters. OBJ files do not store the units they were
+    /// authored in, so this is used to bring them into the same meter-based space as glTF files.
+    /// For example, use 0.01 for OBJ files authored in centimeters. (default: 1.0)
+    #[serde(default = "default_unit_scale")]
+    pub obj_unit_scale: f32,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -89,13 +104,30 @@ pub struct Spritesheet {
     pub path: UnresolvedPath,
     /// Animations to include in the spritesheet
     pub animations: Vec<Animation>,
-    /// A scale factor to apply to the generated images. Each image is scaled without interpolation.
-    /// The value must be greater than zero. (default: 1).
+    /// A scale factor to apply to the generated images. The value must be greater than zero.
+    /// (default: 1).
     #[serde(default = "default_scale_factor")]
     pub scale: NonZeroU32,
+    /// The way that pixels are filled in when the images are scaled (default: Nearest)
+    #[serde(default)]
+    pub scale_mode: ScaleMode,
     /// The background color of the spritesheet (default: transparent black)
     #[serde(default = "default_background")]
     pub background: Rgba,
+    /// The number of pixels to extrude the edges of each frame outward by, to avoid colors
+    /// bleeding between frames when the spritesheet is sampled with filtering (default: 0)
+    #[serde(default)]
+    pub bleed: u32,
+    /// The number of transparent pixels between neighboring frames (default: 0)
+    #[serde(default)]
+    pub spacing: u32,
+    /// The number of transparent pixels around the outside of the spritesheet (default: 0)
+    #[serde(default)]
+    pub padding: u32,
+    /// The position of frames that are smaller than the largest frame within their cell of the
+    /// spritesheet (default: TopLeft)
+    #[serde(default)]
+    pub align: CellAlignment,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -111,6 +143,14 @@ pub struct Animation {
     /// The outline to use when drawing each frame. (default: no outline)
     #[serde(default)]
     pub outline: Outline,
+    /// Renders each frame at this many times its size and then averages each block of pixels to
+    /// smooth out jagged edges. A value of 1 disables supersampling. (default: 1)
+    #[serde(default = "default_scale_factor")]
+    pub supersample: NonZeroU32,
+    /// The filter used to combine each block of pixels when supersampling. Use `Dominant` to keep
+    /// the hard edges of pixel art. (default: Box)
+    #[serde(default)]
+    pub supersample_filter: DownscaleFilter,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -162,16 +202,27 @@ pub struct Pose {
     pub height: NonZeroU32,
     /// The camera perspective from which to render each frame
     pub camera: PresetCamera,
-    /// A scale factor to apply to the generated image. The image is scaled without interpolation.
-    /// The value must be greater than zero. (default: 1).
+    /// A scale factor to apply to the generated image. The value must be greater than zero.
+    /// (default: 1).
     #[serde(default = "default_scale_factor")]
     pub scale: NonZeroU32,
+    /// The way that pixels are filled in when the image is scaled (default: Nearest)
+    #[serde(default)]
+    pub scale_mode: ScaleMode,
     /// The background color of the generated image (default: transparent black)
     #[serde(default = "default_background")]
     pub background: Rgba,
     /// The outline to use when drawing the generated image. (default: no outline)
     #[serde(default)]
     pub outline: Outline,
+    /// Renders the image at this many times its size and then averages each block of pixels to
+    /// smooth out jagged edges. A value of 1 disables supersampling. (default: 1)
+    #[serde(default = "default_scale_factor")]
+    pub supersample: NonZeroU32,
+    /// The filter used to combine each block of pixels when supersampling. Use `Dominant` to keep
+    /// the hard edges of pixel art. (default: Box)
+    #[serde(default)]
+    pub supersample_filter: DownscaleFilter,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -198,9 +249,11 @@ pub enum PoseModel {
 #[serde(deny_unknown_fields)]
 #[serde(default)]
 pub struct Outline {
-    /// The outline thickness to use when drawing the generated image. Value must not be negative.
-    /// (default: 0.0)
+    /// The outline thickness to use when drawing the generated image, in the given units. Value
+    /// must not be negative. (default: 0.0)
     pub thickness: f32,
+    /// The units that the thickness is measured in (default: Model)
+    pub units: OutlineUnits,
     /// The color of the outline to draw (default: black)
     pub color: Rgba,
 }
@@ -209,11 +262,62 @@ impl Default for Outline {
     fn default() -> Self {
         Self {
             thickness: 0.0,
+            units: OutlineUnits::Model,
             color: Rgba::black(),
         }
     }
 }
 
+/// The way that pixels are filled in when a generated image is scaled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub enum ScaleMode {
+    /// Each pixel is copied into a square block of pixels, which keeps pixel art crisp
+    #[default]
+    Nearest,
+    /// Each pixel is blended with its neighbors, which smooths out images that are not pixel art
+    Bilinear,
+}
+
+/// The position of an image within a cell that is larger than the image
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub enum CellAlignment {
+    #[default]
+    TopLeft,
+    TopCenter,
+    Center,
+    BottomCenter,
+}
+
+/// The filter used to combine each block of pixels when supersampling
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub enum DownscaleFilter {
+    /// Averages each block of pixels. Fast, but produces slightly soft edges.
+    #[default]
+    Box,
+    /// A windowed sinc filter with 3 lobes. Produces sharper edges than `Box`, but can produce
+    /// faint halos next to high contrast edges.
+    Lanczos3,
+    /// Uses the most common color in each block of pixels, so no new colors are created and edges
+    /// stay hard
+    Dominant,
+}
+
+/// The units of the thickness of an outline, which also determine how the outline is drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub enum OutlineUnits {
+    /// The outline extends this far past the geometry in the units of the model. The outline gets
+    /// thinner as the model gets further from the camera or is scaled down.
+    Model,
+    /// The outline is drawn over the edges of the rendered image (its silhouette, creases, and
+    /// overlapping parts) and is this many pixels of the final image wide, no matter how far away
+    /// or how large the model is. The thickness is rounded to a whole number of pixels.
+    Pixels,
+}
+
 /// A number of present camera angles or a completely custom configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
@@ -263,6 +367,10 @@ pub struct Camera {
     ///
     /// If None, a special "infinite projection matrix" will be used.
     pub far_z: Option<f32>,
+    /// The handedness of the coordinate system that `eye` and `target` are given in
+    /// (default: Right)
+    #[serde(default)]
+    pub handedness: Handedness,
 }
 
 impl Default for Camera {
@@ -274,6 +382,7 @@ impl Default for Camera {
             fov_y: Degrees::from_degrees(40.0),
             near_z: 0.1,
             far_z: Some(100.0),
+            handedness: Handedness::Right,
         }
     }
 }
@@ -296,6 +405,7 @@ impl From<Perspective> for Camera {
 }
 
 fn default_scale_factor() -> NonZeroU32 { NonZeroU32::new(1).unwrap() }
+fn default_unit_scale() -> f32 { 1.0 }
 fn default_background() -> Rgba { Rgba {r: 0.0, g: 0.0, b: 0.0, a: 0.0} }
 
 #[cfg(test)]
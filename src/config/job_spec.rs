@@ -0,0 +1,339 @@
+use std::num::NonZeroU32;
+
+use serde::{Serialize, Deserialize};
+
+use crate::math::{Vec3, Rgb, Rgba, Degrees, Handedness};
+use super::{
+    UnresolvedPath,
+    Outline,
+    ScaleMode,
+    CellAlignment,
+    default_scale_factor,
+    default_unit_scale,
+    default_background,
+};
+
+/// A complete description of a render job, including every node of its layout
+///
+/// Variants of the enums in this module are selected with a "type" field, e.g.
+/// `{"type": "Grid", "cols": 4, "nodes": [...]}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JobSpec {
+    /// A scale factor to apply to the generated image. The value must be greater than zero.
+    /// (default: 1)
+    #[serde(default = "default_scale_factor")]
+    pub scale: NonZeroU32,
+    /// The way that pixels are filled in when the generated image is scaled (default: Nearest)
+    #[serde(default)]
+    pub scale_mode: ScaleMode,
+    /// If true, the rows of the generated image are stored from the bottom to the top
+    /// (default: false)
+    #[serde(default)]
+    pub flip_y: bool,
+    /// If true, the color channels of the generated image are multiplied by its alpha channel
+    /// (default: false)
+    #[serde(default)]
+    pub premultiply: bool,
+    /// The pixel format of the generated image (default: Rgba8)
+    #[serde(default)]
+    pub format: PixelFormat,
+    /// The size of one unit in OBJ files, in meters (default: 1.0)
+    #[serde(default = "default_unit_scale")]
+    pub obj_unit_scale: f32,
+    /// The root node of the tree that describes the image to render
+    pub root: NodeSpec,
+}
+
+/// A single node in the layout of a render job
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[serde(tag = "type")]
+pub enum NodeSpec {
+    /// An image rendered from a 3D file
+    Image(ImageSpec),
+    /// A regular grid of nodes with the given number of columns
+    Grid {
+        nodes: Vec<NodeSpec>,
+        cols: NonZeroU32,
+        /// The order in which the cells of the grid are filled (default: RowMajor)
+        #[serde(default)]
+        fill: FillOrder,
+        /// The number of transparent pixels between neighboring cells (default: 0)
+        #[serde(default)]
+        spacing: u32,
+        /// The number of transparent pixels around the outside of the grid (default: 0)
+        #[serde(default)]
+        padding: u32,
+        /// The position of each node within a cell that is larger than the node
+        /// (default: TopLeft)
+        #[serde(default)]
+        align: CellAlignment,
+        /// The color to fill each cell with before it is drawn (default: transparent)
+        #[serde(default)]
+        background: Option<Rgba>,
+        /// The number of pixels to extrude the edges of each cell outward by (default: 0)
+        #[serde(default)]
+        bleed: u32,
+    },
+    /// Nodes tightly packed into an image of at most the given width
+    Packed {
+        nodes: Vec<NodeSpec>,
+        width: NonZeroU32,
+        /// The color to fill each cell with before it is drawn (default: transparent)
+        #[serde(default)]
+        background: Option<Rgba>,
+        /// The number of pixels to extrude the edges of each cell outward by (default: 0)
+        #[serde(default)]
+        bleed: u32,
+    },
+    /// A gap in the layout
+    Empty {
+        width: NonZeroU32,
+        height: NonZeroU32,
+        /// The color to fill the gap with (default: the background of the layout)
+        #[serde(default)]
+        background: Option<Rgba>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ImageSpec {
+    /// The width of the rendered image (in pixels)
+    pub width: NonZeroU32,
+    /// The height of the rendered image (in pixels)
+    pub height: NonZeroU32,
+    /// The 3D file to render, relative to the job specification
+    pub model: UnresolvedPath,
+    /// The scene to render, or None if the default scene should be used
+    #[serde(default)]
+    pub scene: Option<String>,
+    /// The names of the nodes to render, or None if every node in the scene should be rendered
+    #[serde(default)]
+    pub nodes: Option<Vec<String>>,
+    /// The position in an animation to pose the model at (default: the rest pose)
+    #[serde(default)]
+    pub animation: Option<AnimationSpec>,
+    pub camera: CameraSpec,
+    /// The way that the view of the camera is fitted to the image when their aspect ratios
+    /// differ (default: Stretch)
+    #[serde(default)]
+    pub aspect: AspectMode,
+    /// The lights of the image, or None if the lights in the scene of the 3D file should be used
+    #[serde(default)]
+    pub lights: Option<Vec<LightSpec>>,
+    /// The color of the ambient light in the image (default: 50% white)
+    #[serde(default = "default_ambient_light")]
+    pub ambient_light: Rgb,
+    /// The index of the light that drives the cel shading bands (default: every light)
+    #[serde(default)]
+    pub key_light: Option<usize>,
+    /// The technique used to shade the surfaces of the geometry (default: Cel)
+    #[serde(default)]
+    pub shading: ShadingMode,
+    /// The color space that the lighting is computed in (default: Linear)
+    #[serde(default)]
+    pub color_space: ColorSpace,
+    /// The factor that the lit color of the geometry is multiplied by (default: 1)
+    #[serde(default = "default_exposure")]
+    pub exposure: f32,
+    /// The gamma of the lit color of the geometry, which must be greater than zero (default: 1)
+    #[serde(default = "default_gamma")]
+    pub gamma: f32,
+    /// The background color of the image (default: transparent black)
+    #[serde(default = "default_background")]
+    pub background: Rgba,
+    #[serde(default)]
+    pub outline: Outline,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AnimationSpec {
+    /// The name of the animation, or None if the default animation should be used
+    #[serde(default)]
+    pub name: Option<String>,
+    /// The time in the animation, in milliseconds
+    pub time: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[serde(tag = "type")]
+pub enum CameraSpec {
+    /// A camera from the 3D file of the image. The camera is posed with the animation of the
+    /// image, if any.
+    File {
+        /// The name of the camera, or None if the first camera in the scene should be used
+        #[serde(default)]
+        name: Option<String>,
+        /// The scene to look in, or None if the default scene should be used
+        #[serde(default)]
+        scene: Option<String>,
+    },
+    Perspective {
+        /// The position of the camera in world coordinates
+        eye: Vec3,
+        /// The target position that the camera should be looking at
+        target: Vec3,
+        /// The aspect ratio of the viewport (default: the aspect ratio of the image)
+        #[serde(default)]
+        aspect_ratio: Option<f32>,
+        /// The vertical field of view of the camera in degrees (default: 40)
+        #[serde(default = "default_fov_y")]
+        fov_y: Degrees,
+        /// Coordinate of the near clipping plane on the camera's local z-axis (default: 0.1)
+        #[serde(default = "default_near_z")]
+        near_z: f32,
+        /// Coordinate of the far clipping plane on the camera's local z-axis, or None for an
+        /// infinite projection (default: None)
+        #[serde(default)]
+        far_z: Option<f32>,
+        /// The handedness of the coordinate system that `eye` and `target` are given in
+        /// (default: Right)
+        #[serde(default)]
+        handedness: Handedness,
+    },
+    Orthographic {
+        /// The position of the camera in world coordinates
+        eye: Vec3,
+        /// The target position that the camera should be looking at
+        target: Vec3,
+        /// The width of the viewing volume
+        mag_x: f32,
+        /// The height of the viewing volume
+        mag_y: f32,
+        /// Coordinate of the near clipping plane on the camera's local z-axis (default: 0.1)
+        #[serde(default = "default_near_z")]
+        near_z: f32,
+        /// Coordinate of the far clipping plane on the camera's local z-axis
+        far_z: f32,
+        /// The handedness of the coordinate system that `eye` and `target` are given in
+        /// (default: Right)
+        #[serde(default)]
+        handedness: Handedness,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[serde(tag = "type")]
+pub enum LightSpec {
+    Directional {
+        /// The direction that the light shines in. Must not be zero.
+        direction: Vec3,
+        /// The color of the light in linear space (default: white)
+        #[serde(default = "Rgb::white")]
+        color: Rgb,
+        /// The intensity of the light in lux (default: 1.0)
+        #[serde(default = "default_intensity")]
+        intensity: f32,
+    },
+    Point {
+        /// The position of the light in world coordinates
+        position: Vec3,
+        /// The color of the light in linear space (default: white)
+        #[serde(default = "Rgb::white")]
+        color: Rgb,
+        /// The intensity of the light in candela (default: 1.0)
+        #[serde(default = "default_intensity")]
+        intensity: f32,
+        /// The distance at which the light reaches zero intensity, or None for an infinite range
+        /// (default: None)
+        #[serde(default)]
+        range: Option<f32>,
+    },
+    Spot {
+        /// The position of the light in world coordinates
+        position: Vec3,
+        /// The direction that the light shines in. Must not be zero.
+        direction: Vec3,
+        /// The color of the light in linear space (default: white)
+        #[serde(default = "Rgb::white")]
+        color: Rgb,
+        /// The intensity of the light in candela (default: 1.0)
+        #[serde(default = "default_intensity")]
+        intensity: f32,
+        /// The distance at which the light reaches zero intensity, or None for an infinite range
+        /// (default: None)
+        #[serde(default)]
+        range: Option<f32>,
+        /// The angle in degrees from the center of the light where falloff begins (default: 0)
+        #[serde(default = "default_inner_cone_angle")]
+        inner_cone_angle: Degrees,
+        /// The angle in degrees from the center of the light where falloff ends (default: 45)
+        #[serde(default = "default_outer_cone_angle")]
+        outer_cone_angle: Degrees,
+    },
+}
+
+/// The pixel format of a generated image
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub enum PixelFormat {
+    /// 8-bit red, green, blue, and alpha channels
+    #[default]
+    Rgba8,
+    /// 8-bit red, green, and blue channels. The alpha channel is discarded.
+    Rgb8,
+    /// A single 8-bit luminance (brightness) channel. The alpha channel is discarded.
+    Gray8,
+}
+
+/// The order in which the cells of a grid are filled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub enum FillOrder {
+    /// Each row is filled from left to right before moving down to the next row
+    #[default]
+    RowMajor,
+    /// Each column is filled from top to bottom before moving right to the next column
+    ColumnMajor,
+}
+
+/// The way that the view of a camera is fitted to an image with a different aspect ratio
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub enum AspectMode {
+    /// The view is stretched to fill the entire image
+    #[default]
+    Stretch,
+    /// The view keeps the aspect ratio of the camera and the rest of the image is filled with the
+    /// background
+    Letterbox,
+}
+
+/// The technique used to shade the surfaces of the geometry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub enum ShadingMode {
+    /// Cel shading bands, with the outline of the image
+    #[default]
+    Cel,
+    /// Smooth diffuse lighting without cel shading bands
+    Flat,
+    /// The silhouette of the geometry in solid white over a transparent background
+    Mask,
+}
+
+/// The color space that the lighting is computed in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub enum ColorSpace {
+    /// Lighting is computed in linear space, which is physically correct
+    #[default]
+    Linear,
+    /// Lighting is computed directly on the stored sRGB values of textures, which makes lit
+    /// surfaces look darker and more saturated
+    Srgb,
+}
+
+fn default_ambient_light() -> Rgb { Rgb::white() * 0.5 }
+fn default_fov_y() -> Degrees { Degrees::from_degrees(40.0) }
+fn default_near_z() -> f32 { 0.1 }
+fn default_intensity() -> f32 { 1.0 }
+fn default_exposure() -> f32 { 1.0 }
+fn default_gamma() -> f32 { 1.0 }
+fn default_inner_cone_angle() -> Degrees { Degrees::from_degrees(0.0) }
+fn default_outer_cone_angle() -> Degrees { Degrees::from_degrees(45.0) }
@@ -29,6 +29,29 @@ pub type FrustumPlanes = vek::FrustumPlanes<f32>;
 
 pub type Transforms = transforms::Transforms<f32>;
 
+/// Decodes a color channel (between 0.0 and 1.0) that was encoded with the sRGB transfer function
+///
+/// See `renderer::ColorSpace` for the exact function used.
+pub fn srgb_to_linear(srgb: f32) -> f32 {
+    if srgb <= 0.04045 {
+        srgb / 12.92
+    } else {
+        ((srgb + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Returns the (min, max) corners of the axis-aligned box that contains every given point, or
+/// None if there are no points
+///
+/// Boxes can be combined by passing in both of the corners of each box.
+pub fn bounds(points: impl IntoIterator<Item = Vec3>) -> Option<(Vec3, Vec3)> {
+    let mut points = points.into_iter();
+    let first = points.next()?;
+    Some(points.fold((first, first), |(min, max), point| {
+        (Vec3::partial_min(min, point), Vec3::partial_max(max, point))
+    }))
+}
+
 /// A "newtype" to represent a value with the unit "radians"
 #[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize)]
 #[serde(transparent)]
@@ -86,3 +109,54 @@ impl Degrees {
         self.0
     }
 }
+
+/// The handedness of a coordinate system
+///
+/// In a right-handed coordinate system (e.g. glTF, OpenGL), a camera looks down its negative
+/// z-axis and front faces are wound counter-clockwise. In a left-handed coordinate system
+/// (e.g. Direct3D, Unity), a camera looks down its positive z-axis and front faces are wound
+/// clockwise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Handedness {
+    #[default]
+    Right,
+    Left,
+}
+
+impl Handedness {
+    /// Returns a view matrix for a camera at `eye` looking at `target`
+    pub fn look_at(self, eye: Vec3, target: Vec3, up: Vec3) -> Mat4 {
+        use Handedness::*;
+        match self {
+            Right => Mat4::look_at_rh(eye, target, up),
+            Left => Mat4::look_at_lh(eye, target, up),
+        }
+    }
+
+    /// Returns a perspective projection matrix with OpenGL clip planes (-1 to 1)
+    pub fn perspective_no(self, fov_y_radians: f32, aspect_ratio: f32, near: f32, far: f32) -> Mat4 {
+        use Handedness::*;
+        match self {
+            Right => Mat4::perspective_rh_no(fov_y_radians, aspect_ratio, near, far),
+            Left => Mat4::perspective_lh_no(fov_y_radians, aspect_ratio, near, far),
+        }
+    }
+
+    /// Returns an orthographic projection matrix with OpenGL clip planes (-1 to 1)
+    pub fn orthographic_no(self, planes: FrustumPlanes) -> Mat4 {
+        use Handedness::*;
+        match self {
+            Right => Mat4::orthographic_rh_no(planes),
+            Left => Mat4::orthographic_lh_no(planes),
+        }
+    }
+
+    /// The sign of the z-coordinate of points in front of a camera in view space
+    pub fn forward_z(self) -> f32 {
+        use Handedness::*;
+        match self {
+            Right => -1.0,
+            Left => 1.0,
+        }
+    }
+}
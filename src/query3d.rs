@@ -1,5 +1,7 @@
 mod backend;
 mod query;
+mod manifest;
 
 pub use backend::*;
 pub use query::*;
+pub use manifest::*;
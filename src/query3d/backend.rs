@@ -1,6 +1,9 @@
 pub mod obj;
 pub mod gltf;
+pub mod stl;
+pub mod ply;
 
+use std::fs;
 use std::sync::Arc;
 use std::path::{Path, PathBuf};
 
@@ -9,6 +12,7 @@ use thiserror::Error;
 use crate::renderer::{Display, ShaderGeometry, ShaderGeometryError, Camera, Light};
 
 use super::query::{GeometryQuery, CameraQuery, LightQuery};
+use super::manifest::{Manifest, SceneInfo, CameraInfo, AnimationInfo};
 
 #[derive(Debug, Error)]
 pub enum QueryError {
@@ -18,23 +22,42 @@ pub enum QueryError {
     #[error("Could not find scene named `{name}` in model file")]
     UnknownScene {name: String},
 
+    #[error("Model file has {} scenes named `{name}` (indexes {indexes:?}), use `{name}#1` to `{name}#{}` to pick one", indexes.len(), indexes.len())]
+    AmbiguousScene {name: String, indexes: Vec<usize>},
+
+    #[error("Could not find scene {index} in model file (the file has {count} scenes)")]
+    SceneIndexOutOfRange {index: usize, count: usize},
+
     #[error("Could not find animation named `{name}` in model file")]
     UnknownAnimation {name: String},
 
     #[error("Could not find any matching animation in model file")]
     NoAnimationFound,
 
+    #[error("Model file has {count} animations, so the animation to use must be named")]
+    AmbiguousAnimation {count: usize},
+
+    #[error("Could not find node named `{name}` in model file")]
+    UnknownNode {name: String},
+
     #[error("Could not find any matching geometry in model file")]
     NoGeometryFound,
 
     #[error("Could not find camera named `{name}` in model file")]
     UnknownCamera {name: String},
 
+    #[error("Scene has {} cameras named `{name}` (nodes {nodes:?}), use `{name}#1` to `{name}#{}` to pick one", nodes.len(), nodes.len())]
+    AmbiguousCamera {name: String, nodes: Vec<usize>},
+
     #[error("Could not find any matching cameras in model file")]
     NoCameraFound,
 
     #[error("Could not find any matching lights in model file")]
     NoLightsFound,
+
+    /// An error from a file format library that does not have a more specific variant
+    #[error(transparent)]
+    Backend(Box<dyn std::error::Error + Send + Sync>),
 }
 
 pub trait QueryBackend {
@@ -53,6 +76,19 @@ pub trait QueryBackend {
 pub enum FileError {
     ObjError(#[from] tobj::LoadError),
     GltfError(#[from] ::gltf::Error),
+    #[error("{path:?} references {resource}, which could not be loaded: {reason}")]
+    MissingGltfResource {
+        path: PathBuf,
+        /// The kind, index, and URI of the resource, e.g. "buffer 0 (`model.bin`)"
+        resource: String,
+        reason: String,
+    },
+    StlError(#[from] stl::StlError),
+    PlyError(#[from] ply::PlyError),
+    #[error("Could not read the OBJ files in directory {path:?}: {error}")]
+    ObjDirectoryError {path: PathBuf, error: std::io::Error},
+    #[error("Directory {path:?} does not contain any OBJ files")]
+    NoObjFilesInDirectory {path: PathBuf},
     #[error("Unsupported file extension: {path:?}")]
     UnsupportedFileExtension {path: PathBuf},
 }
@@ -61,22 +97,100 @@ pub enum FileError {
 pub enum File {
     Obj(obj::ObjFile),
     Gltf(gltf::GltfFile),
+    Stl(stl::StlFile),
+    Ply(ply::PlyFile),
 }
 
 impl File {
     /// Opens a 3D file based on its extension
     pub fn open(path: &Path) -> Result<Self, FileError> {
+        Self::open_with_obj_unit_scale(path, 1.0)
+    }
+
+    /// Opens a 3D file based on its extension. If the file is an OBJ file, every unit in the file
+    /// is assumed to be the given size in meters.
+    ///
+    /// If the path is a directory, every OBJ file in it is opened as a single model (see
+    /// `open_objs`).
+    pub fn open_with_obj_unit_scale(path: &Path, obj_unit_scale: f32) -> Result<Self, FileError> {
+        if path.is_dir() {
+            return Self::open_obj_directory(path, obj_unit_scale);
+        }
+
         match path.extension().and_then(|p| p.to_str()) {
-            Some("obj") => Ok(File::Obj(obj::ObjFile::open(path)?)),
+            Some("obj") => Ok(File::Obj(obj::ObjFile::open_with_unit_scale(path, obj_unit_scale)?)),
             Some("gltf") | Some("glb") => Ok(File::Gltf(gltf::GltfFile::open(path)?)),
+            Some("stl") => Ok(File::Stl(stl::StlFile::open(path)?)),
+            Some("ply") => Ok(File::Ply(ply::PlyFile::open(path)?)),
             _ => Err(FileError::UnsupportedFileExtension {path: path.to_path_buf()}),
         }
     }
 
+    /// Opens several OBJ files as a single model, in the given order. Every unit in the files is
+    /// assumed to be the given size in meters.
+    pub fn open_objs(paths: &[PathBuf], obj_unit_scale: f32) -> Result<Self, FileError> {
+        Ok(File::Obj(obj::ObjFile::open_many_with_unit_scale(paths, obj_unit_scale)?))
+    }
+
+    /// Opens every OBJ file directly inside the given directory as a single model, in order of
+    /// their file names
+    fn open_obj_directory(path: &Path, obj_unit_scale: f32) -> Result<Self, FileError> {
+        let dir_error = |error| FileError::ObjDirectoryError {path: path.to_path_buf(), error};
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(path).map_err(dir_error)? {
+            let entry_path = entry.map_err(dir_error)?.path();
+            if entry_path.is_file() && entry_path.extension().is_some_and(|ext| ext == "obj") {
+                paths.push(entry_path);
+            }
+        }
+        if paths.is_empty() {
+            return Err(FileError::NoObjFilesInDirectory {path: path.to_path_buf()});
+        }
+        paths.sort();
+
+        Self::open_objs(&paths, obj_unit_scale)
+    }
+
     /// Opens a glTF file
     pub fn open_gltf(path: &Path) -> Result<Self, FileError> {
         Ok(File::Gltf(gltf::GltfFile::open(path)?))
     }
+
+    /// Returns a summary of the scenes, cameras, lights, and animations in this file
+    pub fn describe(&self) -> Manifest {
+        use File::*;
+        match self {
+            Gltf(gltf) => gltf.describe().clone(),
+            // These formats only store a single mesh
+            Obj(_) | Stl(_) | Ply(_) => Manifest::single_scene(),
+        }
+    }
+
+    /// Returns the scenes in this file, in the order they are stored in the file
+    pub fn list_scenes(&self) -> Vec<SceneInfo> {
+        self.describe().scenes
+    }
+
+    /// Returns the cameras in this file, in the order they are stored in the file
+    pub fn list_cameras(&self) -> Vec<CameraInfo> {
+        self.describe().cameras
+    }
+
+    /// Returns the animations in this file (with their durations), in the order they are stored
+    /// in the file
+    pub fn list_animations(&self) -> Vec<AnimationInfo> {
+        self.describe().animations
+    }
+
+    /// Returns the duration (in seconds) of the animation with the given name, or None if the
+    /// file has no such animation. Only glTF files can contain animations.
+    pub fn animation_duration(&self, name: &str) -> Option<f32> {
+        use File::*;
+        match self {
+            Gltf(gltf) => gltf.animation_duration(name),
+            Obj(_) | Stl(_) | Ply(_) => None,
+        }
+    }
 }
 
 impl QueryBackend for File {
@@ -85,6 +199,8 @@ impl QueryBackend for File {
         match self {
             Obj(objs) => objs.query_geometry(query, display),
             Gltf(gltf) => gltf.query_geometry(query, display),
+            Stl(stl) => stl.query_geometry(query, display),
+            Ply(ply) => ply.query_geometry(query, display),
         }
     }
 
@@ -93,6 +209,8 @@ impl QueryBackend for File {
         match self {
             Obj(objs) => objs.query_camera(query),
             Gltf(gltf) => gltf.query_camera(query),
+            Stl(stl) => stl.query_camera(query),
+            Ply(ply) => ply.query_camera(query),
         }
     }
 
@@ -101,6 +219,8 @@ impl QueryBackend for File {
         match self {
             Obj(objs) => objs.query_lights(query),
             Gltf(gltf) => gltf.query_lights(query),
+            Stl(stl) => stl.query_lights(query),
+            Ply(ply) => ply.query_lights(query),
         }
     }
 }
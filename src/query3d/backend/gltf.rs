@@ -1,39 +1,91 @@
+use std::borrow::Cow;
 use std::sync::Arc;
-use std::path::Path;
-use std::collections::HashMap;
-
-use crate::scene::{Scene, Traverse, Mesh, Material, CameraType, LightType};
-use crate::renderer::{Display, ShaderGeometry, Camera, Light};
-use crate::query3d::{GeometryQuery, GeometryFilter, CameraQuery, LightQuery};
-
-use super::{QueryBackend, QueryError};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::collections::{HashMap, HashSet};
+
+use crate::math::Handedness;
+use crate::math::Mat4;
+use crate::scene::{Scene, Node, Traverse, Mesh, Geometry, Skin, Material, Texture, WrapMode, FilterMode, CameraType, LightType, Animation, NormalMode};
+use crate::renderer::{Display, DisplayTracker, ShaderGeometry, TextureCache, Camera, Light};
+use crate::query3d::{
+    GeometryQuery,
+    GeometryFilter,
+    AnimationQuery,
+    AnimationPosition,
+    CameraQuery,
+    CameraFilter,
+    LightQuery,
+    Manifest,
+};
+
+use super::{QueryBackend, QueryError, FileError};
+
+/// An animation index and a time in that animation (in ms), quantized to the nearest ms
+type AnimationFrame = (usize, u32);
+/// A scene index and the animation frame the scene is posed at, if any
+type PosedScene = (usize, Option<AnimationFrame>);
+/// A posed scene, the names of the nodes selected from it (or None if the entire scene is used),
+/// the bits of the morph target weights requested for every mesh, if any, the bits of each
+/// transform override sorted by node name, and the way that the normals are generated
+type GeometryKey = (PosedScene, Option<Vec<String>>, Option<Vec<u32>>, Vec<(String, [u32; 16])>, NormalMode);
 
 /// Represents a single glTF file
 #[derive(Debug)]
 pub struct GltfFile {
+    /// A summary of the contents of this file
+    manifest: Manifest,
     default_scene: usize,
     scenes: Vec<Arc<Scene>>,
-    /// Cache the geometry of the entire scene, referenced by scene index
-    scene_shader_geometry: HashMap<usize, Arc<Vec<Arc<ShaderGeometry>>>>,
+    animations: Vec<Arc<Animation>>,
+    /// Cache the geometry of the entire scene (or the selected nodes), referenced by scene index,
+    /// by the animation frame (animation index, time in ms) the geometry was posed at, if any, by
+    /// the names of the selected nodes, if any, by the morph target weights, if any, and by the
+    /// transform overrides
+    scene_shader_geometry: HashMap<GeometryKey, Arc<Vec<Arc<ShaderGeometry>>>>,
+    /// The textures of this file that have been uploaded to the GPU, shared by all geometry
+    textures: TextureCache,
+    /// The display that the cached geometry and textures were uploaded to
+    display: DisplayTracker,
     /// Cache all of the lights in an entire scene, referenced by scene index
     scene_lights: HashMap<usize, Arc<Vec<Arc<Light>>>>,
-    /// Cache the first camera in the scene
-    scene_first_camera: Option<Arc<Camera>>,
-    /// Cache each camera by scene index and name
-    scene_cameras: HashMap<(usize, String), Arc<Camera>>,
+    /// Cache the first camera in each scene, referenced by scene index and by the animation frame
+    /// the camera was posed at, if any
+    scene_first_camera: HashMap<PosedScene, Arc<Camera>>,
+    /// Cache each camera by scene index, animation frame, and name
+    scene_cameras: HashMap<(PosedScene, String), Arc<Camera>>,
 }
 
 impl GltfFile {
     /// Opens a glTF file
-    pub fn open(path: &Path) -> Result<Self, gltf::Error> {
-        let (document, buffers, _images) = gltf::import(path)?;
-
+    ///
+    /// Every buffer and image that the file references is checked before anything is loaded, so
+    /// that a missing resource is reported by name.
+    pub fn open(path: &Path) -> Result<Self, FileError> {
+        let gltf::Gltf {document, blob} = gltf::Gltf::open(path)?;
+        validate_resources(path, &document, blob.as_deref())?;
+
+        let (document, buffers, images) = gltf::import(path)?;
+        let manifest = Manifest::from(&document);
+
+        // Each glTF texture pairs an image with a sampler, so an image used by several textures is
+        // converted once for each of them
+        let textures: Vec<_> = document.textures()
+            .map(|texture| Arc::new(Texture {
+                wrap: WrapMode::from_gltf_sampler(&texture.sampler()),
+                filter: FilterMode::from_gltf_sampler(&texture.sampler()),
+                ..Texture::from_gltf(&images[texture.source().index()])
+            }))
+            .collect();
         let materials: Vec<_> = document.materials()
-            .map(|mat| Arc::new(Material::from(mat)))
+            .map(|mat| Arc::new(Material::from_gltf(mat, &textures)))
             .collect();
         let meshes: Vec<_> = document.meshes()
             .map(|mesh| Arc::new(Mesh::from_gltf(mesh, &materials, &buffers)))
             .collect();
+        let skins: Vec<_> = document.skins()
+            .map(|skin| Arc::new(Skin::from_gltf(skin, &buffers)))
+            .collect();
 
         let cameras: Vec<_> = document.cameras()
             .map(|cam| Arc::new(CameraType::from(cam)))
@@ -44,7 +96,11 @@ impl GltfFile {
         }).unwrap_or_default();
 
         let scenes: Vec<_> = document.scenes()
-            .map(|scene| Arc::new(Scene::from_gltf(scene, &meshes, &cameras, &lights)))
+            .map(|scene| Arc::new(Scene::from_gltf(scene, &meshes, &skins, &cameras, &lights)))
+            .collect();
+
+        let animations = document.animations()
+            .map(|anim| Arc::new(Animation::from_gltf(anim, &buffers)))
             .collect();
         assert!(!scenes.is_empty(), "glTF file must have at least one scene");
 
@@ -52,55 +108,232 @@ impl GltfFile {
         let default_scene = document.default_scene().map(|scene| scene.index()).unwrap_or(0);
 
         Ok(Self {
+            manifest,
             default_scene,
             scenes,
+            animations,
             scene_shader_geometry: HashMap::new(),
+            textures: TextureCache::default(),
+            display: DisplayTracker::default(),
             scene_lights: HashMap::new(),
-            scene_first_camera: None,
+            scene_first_camera: HashMap::new(),
             scene_cameras: HashMap::new(),
         })
     }
 
+    /// Reads only the metadata of a glTF file, without loading any buffers, images, or geometry
+    ///
+    /// This is much faster than opening the entire file and is useful when the contents of the
+    /// file only need to be listed. The returned manifest is the same as the one returned by
+    /// `describe()` after opening the file.
+    pub fn open_metadata(path: &Path) -> Result<Manifest, gltf::Error> {
+        let gltf::Gltf {document, blob: _} = gltf::Gltf::open(path)?;
+        Ok(Manifest::from(&document))
+    }
+
+    /// Returns a summary of the contents of this file
+    pub fn describe(&self) -> &Manifest {
+        &self.manifest
+    }
+
+    /// Returns the duration (in seconds) of the animation with the given name, which is the time
+    /// of its last keyframe across all of its channels, or None if there is no such animation
+    pub fn animation_duration(&self, name: &str) -> Option<f32> {
+        let anim_index = self.find_animation(Some(name)).ok()?;
+        Some(self.animations[anim_index].duration())
+    }
+
     /// Attempts to find the index of a scene with the given name. If name is None, the default
-    /// scene is returned.
+    /// scene is returned. Scenes that share a name can be picked with a `#n` suffix (see
+    /// `match_name`).
     fn find_scene(&self, name: Option<&str>) -> Result<usize, QueryError> {
         match name {
             None => Ok(self.default_scene),
-            // This assumes that scene names are unique. If they are not unique, we might need to
-            // search for all matching scenes and produce an error if there is more than one result
-            Some(name) => self.scenes.iter()
-                .position(|scene| scene.name.as_deref() == Some(name))
-                .ok_or_else(|| QueryError::UnknownScene {name: name.to_string()}),
+            Some(name) => {
+                let names: Vec<_> = self.scenes.iter().map(|scene| scene.name.as_deref()).collect();
+                match match_name(name, &names) {
+                    NameMatch::Unique(index) => Ok(index),
+                    NameMatch::Missing => Err(QueryError::UnknownScene {name: name.to_string()}),
+                    NameMatch::Ambiguous(indexes) => {
+                        Err(QueryError::AmbiguousScene {name: name.to_string(), indexes})
+                    },
+                }
+            },
+        }
+    }
+
+    /// Checks that a scene with the given index exists in this file
+    fn find_scene_by_index(&self, index: usize) -> Result<usize, QueryError> {
+        let count = self.scenes.len();
+        if index < count {
+            Ok(index)
+        } else {
+            Err(QueryError::SceneIndexOutOfRange {index, count})
         }
     }
+
+    /// Attempts to find the index of an animation with the given name. If name is None, the only
+    /// animation in the file is returned.
+    fn find_animation(&self, name: Option<&str>) -> Result<usize, QueryError> {
+        match name {
+            None => match self.animations.len() {
+                0 => Err(QueryError::NoAnimationFound),
+                1 => Ok(0),
+                count => Err(QueryError::AmbiguousAnimation {count}),
+            },
+            Some(name) => self.animations.iter()
+                .position(|anim| anim.name.as_deref() == Some(name))
+                .ok_or_else(|| QueryError::UnknownAnimation {name: name.to_string()}),
+        }
+    }
+
+    /// Finds the animation frame referred to by the given query
+    fn find_animation_frame(&self, query: &AnimationQuery) -> Result<AnimationFrame, QueryError> {
+        let AnimationQuery {name, position} = query;
+        let anim_index = self.find_animation(name.as_deref())?;
+
+        let time = match *position {
+            AnimationPosition::Time(time) => time,
+            AnimationPosition::RelativeTime {start_time, weight} => {
+                let end_time = self.animations[anim_index].duration() * 1000.0;
+                start_time + (end_time - start_time) * weight
+            },
+        };
+
+        // Quantize the time so that the same frame queried repeatedly hits the cache
+        Ok((anim_index, time.max(0.0).round() as u32))
+    }
+
+    /// Finds the indexes of the nodes with the given names in the given scene, along with the
+    /// indexes of all of their descendants
+    fn find_nodes(&self, scene_index: usize, names: &[String]) -> Result<HashSet<usize>, QueryError> {
+        let scene = &self.scenes[scene_index];
+
+        let mut selected = HashSet::new();
+        for name in names {
+            // Every node with a matching name is selected, since node names are not required to
+            // be unique
+            let matches: Vec<_> = scene.roots.iter()
+                .flat_map(|root| root.traverse())
+                .filter(|(_, node)| node.name.as_ref() == Some(name))
+                .collect();
+            if matches.is_empty() {
+                return Err(QueryError::UnknownNode {name: name.clone()});
+            }
+
+            for (_, node) in matches {
+                selected.extend(node.traverse().map(|(_, node)| node.index));
+            }
+        }
+
+        Ok(selected)
+    }
+
+    /// Returns the world transform of every node in the given scene, posed at the given animation
+    /// frame if any and with the given transform overrides applied, in breadth-first order
+    fn posed_nodes(
+        &self,
+        scene_index: usize,
+        frame: Option<AnimationFrame>,
+        overrides: &HashMap<String, Mat4>,
+    ) -> Vec<(Mat4, Arc<Node>)> {
+        let scene = &self.scenes[scene_index];
+        // Animation channels are sampled in seconds
+        let pose = frame.map(|(anim_index, time)| self.animations[anim_index].sample(time as f32 / 1000.0));
+
+        let mut nodes = Vec::new();
+        for root in &scene.roots {
+            let mut traversal = root.traverse_with(pose.as_ref(), overrides);
+            while let Some((parent_trans, node)) = traversal.next() {
+                nodes.push((parent_trans * traversal.local_transform(&node), node));
+            }
+        }
+        nodes
+    }
 }
 
 impl QueryBackend for GltfFile {
     fn query_geometry(&mut self, query: &GeometryQuery, display: &Display) -> Result<Arc<Vec<Arc<ShaderGeometry>>>, QueryError> {
-        let GeometryQuery {models, animation} = query;
+        let GeometryQuery {models, animation, morph_weights, transform_overrides, normals} = query;
 
-        //TODO: Restructure the code in this file to add animation support
+        // Geometry uploaded to another display cannot be drawn with this one
+        if self.display.switch_to(display) {
+            self.scene_shader_geometry.clear();
+            self.textures = TextureCache::default();
+        }
 
         use GeometryFilter::*;
-        let scene_index = match models {
-            Scene {name} => self.find_scene(name.as_deref())?,
+        let (scene_index, node_names) = match models {
+            Scene {name} => (self.find_scene(name.as_deref())?, None),
+            SceneByIndex {index} => (self.find_scene_by_index(*index)?, None),
+            Nodes {names, scene} => (self.find_scene(scene.as_deref())?, Some(names)),
         };
+        let frame = animation.as_ref().map(|anim| self.find_animation_frame(anim)).transpose()?;
+        // The indexes of the nodes to draw, or None if the entire scene should be drawn
+        let selected = node_names.map(|names| self.find_nodes(scene_index, names)).transpose()?;
+        // Overrides are checked the same way as selected nodes so that typos are not ignored
+        for name in transform_overrides.keys() {
+            self.find_nodes(scene_index, std::slice::from_ref(name))?;
+        }
 
-        match self.scene_shader_geometry.get(&scene_index) {
+        // Weights are compared by their bits since floats cannot be used as keys
+        let weight_bits = morph_weights.as_ref()
+            .map(|weights| weights.iter().map(|weight| weight.to_bits()).collect());
+        let mut override_bits: Vec<_> = transform_overrides.iter()
+            .map(|(name, transform)| (name.clone(), transform.into_row_array().map(f32::to_bits)))
+            .collect();
+        override_bits.sort();
+        let geo_key = ((scene_index, frame), node_names.cloned(), weight_bits, override_bits, *normals);
+        match self.scene_shader_geometry.get(&geo_key) {
             Some(scene_geo) => Ok(scene_geo.clone()),
 
             None => {
-                let scene = &self.scenes[scene_index];
+                // Skinned meshes need the world transform of every joint (even the joints outside
+                // of the selected nodes), so all of the world transforms need to be computed
+                // before any geometry is posed
+                let nodes = self.posed_nodes(scene_index, frame, transform_overrides);
+                let world_transforms: HashMap<_, _> = nodes.iter()
+                    .map(|(world_transform, node)| (node.index, *world_transform))
+                    .collect();
+                let selected_nodes = nodes.iter().filter(|(_, node)| match &selected {
+                    Some(selected) => selected.contains(&node.index),
+                    None => true,
+                });
 
                 let mut scene_geo = Vec::new();
-                for (parent_trans, node) in scene.roots.iter().flat_map(|root| root.traverse()) {
-                    let model_transform = parent_trans * node.transform;
-
-                    if let Some(mesh) = node.mesh() {
-                        for geo in &mesh.geometry {
-                            let geo = ShaderGeometry::new(display, geo, model_transform)?;
-                            scene_geo.push(Arc::new(geo));
-                        }
+                for (world_transform, node) in selected_nodes {
+                    let mesh = match node.mesh() {
+                        Some(mesh) => mesh,
+                        None => continue,
+                    };
+
+                    // Weights from the query override those of the node, which override the
+                    // default weights of the mesh
+                    let weights = morph_weights.as_deref()
+                        .or_else(|| node.morph_weights.as_deref())
+                        .unwrap_or(&mesh.morph_weights);
+                    let geometry = morphed_geometry(mesh, weights);
+
+                    match frame {
+                        None => {
+                            for geo in geometry.iter() {
+                                let geo = ShaderGeometry::with_texture_cache(display, geo,
+                                    *world_transform, *normals, &mut self.textures)?;
+                                scene_geo.push(Arc::new(geo));
+                            }
+                        },
+
+                        Some(_) => {
+                            scene_geo.extend(posed_geometry(
+                                display,
+                                &mut self.textures,
+                                &geometry,
+                                node.skin.as_deref(),
+                                *world_transform,
+                                &world_transforms,
+                                *normals,
+                            )?.into_iter().map(Arc::new));
+                        },
                     }
                 }
 
@@ -109,32 +342,37 @@ impl QueryBackend for GltfFile {
                 }
 
                 let scene_geo = Arc::new(scene_geo);
-                self.scene_shader_geometry.insert(scene_index, scene_geo.clone());
+                self.scene_shader_geometry.insert(geo_key, scene_geo.clone());
                 Ok(scene_geo)
             },
         }
     }
 
     fn query_camera(&mut self, query: &CameraQuery) -> Result<Arc<Camera>, QueryError> {
-        use CameraQuery::*;
-        match query {
-            FirstInScene {name} => {
-                let scene_index = self.find_scene(name.as_deref())?;
-
-                match &self.scene_first_camera {
+        let CameraQuery {camera, animation} = query;
+        let frame = animation.as_ref().map(|anim| self.find_animation_frame(anim)).transpose()?;
+
+        use CameraFilter::*;
+        match camera {
+            FirstInScene {..} | FirstInSceneByIndex {..} => {
+                let scene_index = match camera {
+                    FirstInScene {name} => self.find_scene(name.as_deref())?,
+                    FirstInSceneByIndex {index} => self.find_scene_by_index(*index)?,
+                    Named {..} => unreachable!(),
+                };
+
+                match self.scene_first_camera.get(&(scene_index, frame)) {
                     Some(cam) => Ok(cam.clone()),
 
                     None => {
-                        let scene = &self.scenes[scene_index];
-
-                        let mut nodes = scene.roots.iter().flat_map(|root| root.traverse());
-                        let scene_first_camera = nodes.find_map(|(parent_trans, node)| {
-                            let world_transform = parent_trans * node.transform;
-
+                        let nodes = self.posed_nodes(scene_index, frame, &HashMap::new());
+                        let scene_first_camera = nodes.iter().find_map(|(world_transform, node)| {
                             match node.camera() {
                                 Some(cam) => Some(Arc::new(Camera {
                                     view: world_transform.inverted(),
-                                    projection: cam.to_projection(),
+                                    // glTF always uses a right-handed coordinate system
+                                    projection: cam.to_projection(Handedness::Right),
+                                    handedness: Handedness::Right,
                                 })),
 
                                 None => None,
@@ -143,7 +381,7 @@ impl QueryBackend for GltfFile {
 
                         match scene_first_camera {
                             Some(cam) => {
-                                self.scene_first_camera = Some(cam.clone());
+                                self.scene_first_camera.insert((scene_index, frame), cam.clone());
                                 Ok(cam)
                             },
 
@@ -156,39 +394,42 @@ impl QueryBackend for GltfFile {
             Named {name, scene} => {
                 let scene_index = self.find_scene(scene.as_deref())?;
 
-                let cam_key = (scene_index, name.clone());
+                let cam_key = ((scene_index, frame), name.clone());
                 match self.scene_cameras.get(&cam_key) {
                     Some(cam) => Ok(cam.clone()),
 
                     None => {
-                        let scene = &self.scenes[scene_index];
-
-                        let mut nodes = scene.roots.iter().flat_map(|root| root.traverse());
-                        // This code assumes that camera names are unique
-                        let found_camera = nodes.find_map(|(parent_trans, node)| {
-                            let world_transform = parent_trans * node.transform;
-
-                            match node.camera() {
-                                Some(cam) if node.name.as_ref() == Some(name) || cam.name() == Some(name) => {
-                                    Some(Arc::new(Camera {
-                                        view: world_transform.inverted(),
-                                        projection: cam.to_projection(),
-                                    }))
-                                },
-
-                                Some(_) |
-                                None => None,
-                            }
+                        let nodes = self.posed_nodes(scene_index, frame, &HashMap::new());
+                        let cameras: Vec<_> = nodes.iter()
+                            .filter_map(|(world_transform, node)| {
+                                node.camera().map(|cam| (world_transform, node, cam))
+                            })
+                            .collect();
+
+                        // A camera can be referred to by the name of its node or by its own name
+                        let index = match_name_by(name, cameras.len(), |i, name| {
+                            let (_, node, cam) = &cameras[i];
+                            node.name.as_deref() == Some(name) || cam.name() == Some(name)
                         });
-
-                        match found_camera {
-                            Some(cam) => {
-                                self.scene_cameras.insert(cam_key, cam.clone());
-                                Ok(cam)
+                        let index = match index {
+                            NameMatch::Unique(index) => index,
+                            NameMatch::Missing => {
+                                return Err(QueryError::UnknownCamera {name: name.to_string()});
                             },
+                            NameMatch::Ambiguous(indexes) => {
+                                let nodes = indexes.into_iter().map(|i| cameras[i].1.index).collect();
+                                return Err(QueryError::AmbiguousCamera {name: name.to_string(), nodes});
+                            },
+                        };
 
-                            None => Err(QueryError::UnknownCamera {name: name.to_string()}),
-                        }
+                        let (world_transform, _, cam) = cameras[index];
+                        let cam = Arc::new(Camera {
+                            view: world_transform.inverted(),
+                            projection: cam.to_projection(Handedness::Right),
+                            handedness: Handedness::Right,
+                        });
+                        self.scene_cameras.insert(cam_key, cam.clone());
+                        Ok(cam)
                     },
                 }
             },
@@ -199,6 +440,7 @@ impl QueryBackend for GltfFile {
         use LightQuery::*;
         let scene_index = match query {
             Scene {name} => self.find_scene(name.as_deref())?,
+            SceneByIndex {index} => self.find_scene_by_index(*index)?,
         };
 
         match self.scene_lights.get(&scene_index) {
@@ -228,3 +470,339 @@ impl QueryBackend for GltfFile {
         }
     }
 }
+
+/// The result of looking up an item by a name that may be shared by several items
+#[derive(Debug, PartialEq, Eq)]
+enum NameMatch {
+    /// The index of the only matching item
+    Unique(usize),
+    Missing,
+    /// The indexes of every item with the name
+    Ambiguous(Vec<usize>),
+}
+
+/// Finds the index of the item with the given name, where `names` holds the name of each item
+///
+/// See `match_name_by` for how items that share a name are picked.
+fn match_name(name: &str, names: &[Option<&str>]) -> NameMatch {
+    match_name_by(name, names.len(), |i, name| names[i] == Some(name))
+}
+
+/// Finds the index of the item with the given name out of `count` items, where `has_name` checks
+/// whether the item at an index has a name
+///
+/// Names are not required to be unique. If several items have the name, they can be picked
+/// between by adding `#n` to the name, where n is the position of the item among the items with
+/// that name (starting at 1). Items whose name actually ends in `#n` are always matched first.
+fn match_name_by(name: &str, count: usize, has_name: impl Fn(usize, &str) -> bool) -> NameMatch {
+    let find_all = |name| (0..count).filter(|&i| has_name(i, name)).collect::<Vec<_>>();
+
+    let matches = find_all(name);
+    match matches.len() {
+        0 => {},
+        1 => return NameMatch::Unique(matches[0]),
+        _ => return NameMatch::Ambiguous(matches),
+    }
+
+    let position = name.rfind('#').and_then(|hash| {
+        let (base, suffix) = (&name[..hash], &name[hash+1..]);
+        suffix.parse::<usize>().ok().map(|n| (base, n))
+    });
+    match position {
+        Some((base, n)) if n >= 1 => find_all(base).get(n - 1)
+            .map(|&index| NameMatch::Unique(index))
+            .unwrap_or(NameMatch::Missing),
+        _ => NameMatch::Missing,
+    }
+}
+
+/// Returns the geometry of a mesh with the given morph target weights applied, borrowing the
+/// geometry of the mesh if none of the weights would change it
+///
+/// See: https://github.com/KhronosGroup/glTF/tree/master/specification/2.0#morph-targets
+fn morphed_geometry<'a>(mesh: &'a Mesh, weights: &[f32]) -> Cow<'a, [Geometry]> {
+    if mesh.geometry.iter().any(|geo| geo.is_morphed_by(weights)) {
+        Cow::Owned(mesh.geometry.iter().map(|geo| geo.morphed(weights)).collect())
+    } else {
+        Cow::Borrowed(&mesh.geometry)
+    }
+}
+
+/// Creates the geometry of a mesh in an animated pose. If the mesh has a skin, its vertices are
+/// moved by the joints of that skin and the world transform of the mesh is ignored.
+///
+/// See: https://github.com/KhronosGroup/glTF/tree/master/specification/2.0#skins
+fn posed_geometry(
+    display: &Display,
+    textures: &mut TextureCache,
+    geometry: &[Geometry],
+    skin: Option<&Skin>,
+    world_transform: Mat4,
+    world_transforms: &HashMap<usize, Mat4>,
+    normals: NormalMode,
+) -> Result<Vec<ShaderGeometry>, QueryError> {
+    let joint_matrices = skin.map(|skin| skin.joint_matrices(world_transforms));
+
+    geometry.iter().map(|geo| {
+        let geo = match &joint_matrices {
+            // Skinned vertices are already in world space
+            Some(joint_matrices) if geo.is_skinned() => {
+                let skinned = geo.skinned(joint_matrices);
+                ShaderGeometry::with_texture_cache(display, &skinned, Mat4::identity(), normals, textures)?
+            },
+            _ => ShaderGeometry::with_texture_cache(display, geo, world_transform, normals, textures)?,
+        };
+
+        Ok(geo)
+    }).collect()
+}
+
+/// Checks that every buffer and image referenced by the given document exists, and that every
+/// buffer is at least as long as the document says it is
+fn validate_resources(path: &Path, document: &gltf::Document, blob: Option<&[u8]>) -> Result<(), FileError> {
+    // Relative URIs are resolved from the directory of the glTF file
+    let base = path.parent().unwrap_or_else(|| Path::new("./"));
+    let missing = |resource: String, reason: String| FileError::MissingGltfResource {
+        path: path.to_path_buf(),
+        resource,
+        reason,
+    };
+
+    for buffer in document.buffers() {
+        let (resource, len) = match buffer.source() {
+            gltf::buffer::Source::Uri(uri) => {
+                let resource = format!("buffer {} (`{}`)", buffer.index(), uri);
+                match resource_path(base, uri) {
+                    Some(uri_path) => {
+                        let metadata = fs::metadata(&uri_path)
+                            .map_err(|err| missing(resource.clone(), err.to_string()))?;
+                        (resource, metadata.len() as usize)
+                    },
+                    // Embedded data is decoded when the file is imported
+                    None => continue,
+                }
+            },
+
+            gltf::buffer::Source::Bin => {
+                let resource = format!("buffer {} (the binary chunk)", buffer.index());
+                match blob {
+                    Some(blob) => (resource, blob.len()),
+                    None => return Err(missing(resource, "the file has no binary chunk".to_string())),
+                }
+            },
+        };
+
+        if len < buffer.length() {
+            return Err(missing(resource, format!("it is {} bytes long, but {} bytes are needed",
+                len, buffer.length())));
+        }
+    }
+
+    for image in document.images() {
+        if let gltf::image::Source::Uri {uri, ..} = image.source() {
+            if let Some(uri_path) = resource_path(base, uri) {
+                if let Err(err) = fs::metadata(&uri_path) {
+                    return Err(missing(format!("image {} (`{}`)", image.index(), uri), err.to_string()));
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the path of the file that the given URI refers to, or None if the URI does not refer
+/// to a file (e.g. a `data:` URI that embeds its data). This matches the way that URIs are resolved
+/// by `gltf::import`, which reports any unsupported schemes itself.
+fn resource_path(base: &Path, uri: &str) -> Option<PathBuf> {
+    if !uri.contains(':') {
+        Some(base.join(uri))
+    } else {
+        uri.strip_prefix("file://").or_else(|| uri.strip_prefix("file:")).map(PathBuf::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+
+    fn bigboi_path() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("samples/bigboi/gltf/bigboi.gltf")
+    }
+
+    #[test]
+    fn open_metadata_matches_describe() {
+        let path = bigboi_path();
+        let manifest = GltfFile::open_metadata(&path).unwrap();
+        let file = GltfFile::open(&path).unwrap();
+        assert_eq!(&manifest, file.describe());
+
+        let scenes: Vec<_> = manifest.scenes.iter().map(|scene| scene.name.as_deref()).collect();
+        assert_eq!(scenes, &[Some("Scene")]);
+        let cameras: Vec<_> = manifest.cameras.iter().map(|cam| cam.name.as_deref()).collect();
+        assert_eq!(cameras, &[Some("perspective"), Some("front"), Some("right"), Some("left")]);
+
+        // Durations come from the accessor bounds, so they match the loaded keyframes
+        assert!(!manifest.animations.is_empty());
+        for (info, anim) in manifest.animations.iter().zip(&file.animations) {
+            assert_eq!(info.duration, anim.duration());
+        }
+    }
+
+    #[test]
+    fn missing_and_truncated_buffers_are_named() {
+        let dir = env::temp_dir().join("spritec_gltf_resources");
+        fs::create_dir_all(&dir).unwrap();
+        let gltf = |buffer_uri: &str| format!(r#"{{
+            "asset": {{"version": "2.0"}},
+            "scenes": [{{"nodes": []}}],
+            "buffers": [{{"uri": "{}", "byteLength": 12}}],
+            "images": [{{"uri": "missing.png"}}]
+        }}"#, buffer_uri);
+        fs::write(dir.join("short.bin"), [0u8; 4]).unwrap();
+        fs::write(dir.join("full.bin"), [0u8; 12]).unwrap();
+        fs::write(dir.join("missing_buffer.gltf"), gltf("missing.bin")).unwrap();
+        fs::write(dir.join("short_buffer.gltf"), gltf("short.bin")).unwrap();
+        fs::write(dir.join("missing_image.gltf"), gltf("full.bin")).unwrap();
+
+        let resource = |name: &str| match GltfFile::open(&dir.join(name)) {
+            Err(FileError::MissingGltfResource {resource, reason, ..}) => (resource, reason),
+            res => panic!("expected a missing resource error, got {:?}", res.map(|_| ())),
+        };
+        let (missing_buffer, _) = resource("missing_buffer.gltf");
+        let (short_buffer, reason) = resource("short_buffer.gltf");
+        let (missing_image, _) = resource("missing_image.gltf");
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(missing_buffer, "buffer 0 (`missing.bin`)");
+        assert_eq!(short_buffer, "buffer 0 (`short.bin`)");
+        assert_eq!(reason, "it is 4 bytes long, but 12 bytes are needed");
+        assert_eq!(missing_image, "image 0 (`missing.png`)");
+    }
+
+    #[test]
+    fn find_animation_frames() {
+        let file = GltfFile::open(&bigboi_path()).unwrap();
+
+        let end = AnimationQuery {
+            name: None,
+            position: AnimationPosition::RelativeTime {start_time: 0.0, weight: 1.0},
+        };
+        assert_eq!(file.find_animation_frame(&end).unwrap(), (0, 1000));
+
+        let unknown = AnimationQuery {name: Some("run".to_string()), position: AnimationPosition::Time(0.0)};
+        match file.find_animation_frame(&unknown) {
+            Err(QueryError::UnknownAnimation {name}) => assert_eq!(name, "run"),
+            res => panic!("expected an unknown animation error, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn animation_duration_by_name() {
+        let file = GltfFile::open(&bigboi_path()).unwrap();
+        let anim = &file.animations[0];
+        let name = anim.name.as_deref().unwrap();
+        assert_eq!(file.animation_duration(name), Some(anim.duration()));
+        assert_eq!(file.animation_duration("run"), None);
+    }
+
+    #[test]
+    fn camera_posed_by_animation() {
+        let mut file = GltfFile::open(&bigboi_path()).unwrap();
+        let still = file.query_camera(&CameraQuery::first_in_default_scene()).unwrap();
+
+        // The cameras in this file are not animated, so posing them leaves them in place
+        let posed = file.query_camera(&CameraQuery {
+            camera: CameraFilter::FirstInScene {name: None},
+            animation: Some(AnimationQuery {name: None, position: AnimationPosition::Time(500.0)}),
+        }).unwrap();
+        assert_eq!(posed.view, still.view);
+        assert_eq!(file.scene_first_camera.len(), 2);
+
+        let unknown = CameraQuery {
+            camera: CameraFilter::Named {name: "front".to_string(), scene: None},
+            animation: Some(AnimationQuery {name: Some("run".to_string()), position: AnimationPosition::Time(0.0)}),
+        };
+        match file.query_camera(&unknown) {
+            Err(QueryError::UnknownAnimation {name}) => assert_eq!(name, "run"),
+            res => panic!("expected an unknown animation error, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn ambiguous_camera_names_its_nodes() {
+        let dir = env::temp_dir().join("spritec_gltf_cameras");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("cameras.gltf");
+        fs::write(&path, r#"{
+            "asset": {"version": "2.0"},
+            "scenes": [{"nodes": [0, 1, 2]}],
+            "nodes": [
+                {"name": "front", "camera": 0},
+                {"name": "side"},
+                {"name": "front", "camera": 0}
+            ],
+            "cameras": [{"type": "perspective", "perspective": {"yfov": 1.0, "znear": 0.1}}]
+        }"#).unwrap();
+        let mut file = GltfFile::open(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let named = |name: &str| CameraQuery {
+            camera: CameraFilter::Named {name: name.to_string(), scene: None},
+            animation: None,
+        };
+        let err = file.query_camera(&named("front")).unwrap_err();
+        assert!(matches!(&err, QueryError::AmbiguousCamera {nodes, ..} if nodes == &[0, 2]), "{:?}", err);
+        assert_eq!(err.to_string(),
+            "Scene has 2 cameras named `front` (nodes [0, 2]), use `front#1` to `front#2` to pick one");
+        assert!(file.query_camera(&named("front#2")).is_ok());
+    }
+
+    #[test]
+    fn find_nodes_with_descendants() {
+        let file = GltfFile::open(&bigboi_path()).unwrap();
+        let names = |names: &[&str]| -> Vec<String> { names.iter().map(|name| name.to_string()).collect() };
+
+        // The head, and the left upper arm along with its child, the forearm
+        let selected = file.find_nodes(file.default_scene, &names(&["upper_arm.L", "head"])).unwrap();
+        let mut selected: Vec<_> = selected.into_iter().collect();
+        selected.sort();
+        assert_eq!(selected, &[10, 11, 12]);
+
+        match file.find_nodes(file.default_scene, &names(&["head", "tail"])) {
+            Err(QueryError::UnknownNode {name}) => assert_eq!(name, "tail"),
+            res => panic!("expected an unknown node error, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn duplicate_names_are_ambiguous() {
+        let names = [Some("a"), Some("b"), None, Some("b"), Some("c#1")];
+
+        assert_eq!(match_name("a", &names), NameMatch::Unique(0));
+        assert_eq!(match_name("d", &names), NameMatch::Missing);
+        assert_eq!(match_name("b", &names), NameMatch::Ambiguous(vec![1, 3]));
+        // Picks between the items with the same name, counting from 1
+        assert_eq!(match_name("b#1", &names), NameMatch::Unique(1));
+        assert_eq!(match_name("b#2", &names), NameMatch::Unique(3));
+        assert_eq!(match_name("b#3", &names), NameMatch::Missing);
+        assert_eq!(match_name("b#0", &names), NameMatch::Missing);
+        assert_eq!(match_name("a#1", &names), NameMatch::Unique(0));
+        // Names that really end in `#n` are matched as they are
+        assert_eq!(match_name("c#1", &names), NameMatch::Unique(4));
+        assert_eq!(match_name("c", &names), NameMatch::Missing);
+    }
+
+    #[test]
+    fn find_scene_by_index() {
+        let file = GltfFile::open(&bigboi_path()).unwrap();
+        assert_eq!(file.find_scene_by_index(0).unwrap(), 0);
+
+        match file.find_scene_by_index(1) {
+            Err(QueryError::SceneIndexOutOfRange {index: 1, count: 1}) => {},
+            res => panic!("expected a scene index out of range error, got {:?}", res),
+        }
+    }
+}
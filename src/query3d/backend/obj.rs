@@ -1,45 +1,123 @@
-use std::path::Path;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::io::BufReader;
+use std::collections::HashMap;
 
 use rayon::iter::{ParallelIterator, IntoParallelIterator};
 
 use crate::math::Mat4;
-use crate::scene::{Mesh, Material};
-use crate::renderer::{Display, ShaderGeometry, Camera, Light};
-use crate::query3d::{GeometryQuery, GeometryFilter, AnimationQuery, CameraQuery, LightQuery};
+use crate::scene::{Mesh, Material, NormalMode};
+use crate::renderer::{Display, DisplayTracker, ShaderGeometry, Camera, Light};
+use crate::query3d::{GeometryQuery, GeometryFilter, AnimationQuery, CameraQuery, CameraFilter, LightQuery};
 
 use super::{QueryBackend, QueryError};
 
-/// Represents a single OBJ file
+/// Represents one OBJ file, or several OBJ files that are drawn together as a single model
 #[derive(Debug)]
 pub struct ObjFile {
-    // This representation is sufficient for GeometryFilter::Scene, but it will need to
-    // change once we add in more advanced filtering (e.g. by name)
-
+    /// The geometry of every object or group in the file(s), named after the object or group it
+    /// came from so that it can be picked out as a node
     mesh: Mesh,
-    /// The version of this model lazily uploaded to the GPU
-    scene_geometry: Option<Arc<Vec<Arc<ShaderGeometry>>>>,
+    /// The versions of this model lazily uploaded to the GPU, for each way of generating normals
+    scene_geometry: HashMap<NormalMode, Arc<Vec<Arc<ShaderGeometry>>>>,
+    /// The display that `scene_geometry` was uploaded to
+    display: DisplayTracker,
 }
 
 impl ObjFile {
     /// Opens a OBJ file
     pub fn open(path: &Path) -> Result<Self, tobj::LoadError> {
-        let (models, materials) = tobj::load_obj(path)?;
+        Self::open_with_unit_scale(path, 1.0)
+    }
+
+    /// Opens a OBJ file, treating every unit in the file as the given size in meters
+    ///
+    /// OBJ files do not record the units they were authored in. Scaling them on load allows them
+    /// to be rendered alongside glTF files, which are always in meters.
+    pub fn open_with_unit_scale(path: &Path, unit_scale: f32) -> Result<Self, tobj::LoadError> {
+        Self::open_many_with_unit_scale(&[path.to_path_buf()], unit_scale)
+    }
 
-        let materials: Vec<_> = materials.into_par_iter()
-            .map(|mat| Arc::new(Material::from(mat)))
-            .collect();
+    /// Opens several OBJ files as a single model, treating every unit in the files as the given
+    /// size in meters
+    ///
+    /// The geometry of each file is kept as is (in the order the paths are given), so a model that
+    /// was exported as several OBJ files is drawn the same way as if it had been exported as one.
+    /// Each object or group keeps its name and can be selected as a node.
+    pub fn open_many_with_unit_scale(paths: &[PathBuf], unit_scale: f32) -> Result<Self, tobj::LoadError> {
+        let meshes = paths.iter()
+            .map(|path| load_mesh(path))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut mesh = Mesh {
+            name: None,
+            geometry: meshes.into_iter().flat_map(|mesh| mesh.geometry).collect(),
+            morph_weights: Vec::new(),
+        };
+        mesh.scale(unit_scale);
 
         Ok(Self {
-            mesh: Mesh::from_obj(models, &materials),
-            scene_geometry: None,
+            mesh,
+            scene_geometry: HashMap::new(),
+            display: DisplayTracker::default(),
         })
     }
+
+    /// Returns the mesh loaded from this file
+    pub fn mesh(&self) -> &Mesh {
+        &self.mesh
+    }
+
+    /// Returns the geometry of the whole scene with the given normals, uploading it to the given
+    /// display if it has not been uploaded yet
+    fn scene_geometry(
+        &mut self,
+        display: &Display,
+        normals: NormalMode,
+    ) -> Result<Arc<Vec<Arc<ShaderGeometry>>>, QueryError> {
+        if let Some(scene_geometry) = self.scene_geometry.get(&normals) {
+            return Ok(scene_geometry.clone());
+        }
+
+        let scene_geometry = Arc::new(self.mesh.geometry.iter()
+            .map(|geo| {
+                ShaderGeometry::new(display, geo, Mat4::identity(), normals).map(Arc::new)
+            })
+            .collect::<Result<Vec<_>, _>>()?);
+        self.scene_geometry.insert(normals, scene_geometry.clone());
+
+        Ok(scene_geometry)
+    }
+}
+
+/// Loads the geometry and materials of a single OBJ file
+fn load_mesh(path: &Path) -> Result<Mesh, tobj::LoadError> {
+    let file = fs::File::open(path).map_err(|_| tobj::LoadError::OpenFileFailed)?;
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let (models, materials) = tobj::load_obj_buf(&mut BufReader::new(file), |mtl_path| {
+        // A missing MTL file is not an error. Any geometry that would have used its materials
+        // is given the default OBJ material instead.
+        match tobj::load_mtl(&base_dir.join(mtl_path)) {
+            Err(tobj::LoadError::OpenFileFailed) => Ok((Vec::new(), HashMap::new())),
+            result => result,
+        }
+    })?;
+
+    let materials: Vec<_> = materials.into_par_iter()
+        .map(|mat| Arc::new(Material::from_obj(mat, base_dir)))
+        .collect();
+
+    Ok(Mesh::from_obj(models, &materials))
 }
 
 impl QueryBackend for ObjFile {
     fn query_geometry(&mut self, query: &GeometryQuery, display: &Display) -> Result<Arc<Vec<Arc<ShaderGeometry>>>, QueryError> {
-        let GeometryQuery {models, animation} = query;
+        let GeometryQuery {models, animation, morph_weights: _, transform_overrides, normals} = query;
+
+        // Geometry uploaded to another display cannot be drawn with this one
+        if self.display.switch_to(display) {
+            self.scene_geometry.clear();
+        }
 
         // OBJ files do not support animations
         match animation {
@@ -52,43 +130,79 @@ impl QueryBackend for ObjFile {
             _ => {},
         }
 
+        // OBJ files do not contain any named nodes to override the transforms of
+        if let Some(name) = transform_overrides.keys().min() {
+            return Err(QueryError::UnknownNode {name: name.clone()});
+        }
+
         use GeometryFilter::*;
         match models {
-            Scene {name: None} => match &self.scene_geometry {
-                Some(scene_geometry) => Ok(scene_geometry.clone()),
-                None => {
-                    let scene_geometry = Arc::new(self.mesh.geometry.iter()
-                        .map(|geo| {
-                            ShaderGeometry::new(display, geo, Mat4::identity()).map(Arc::new)
-                        })
-                        .collect::<Result<Vec<_>, _>>()?);
-
-                    if scene_geometry.is_empty() {
-                        return Err(QueryError::NoGeometryFound);
-                    }
-
-                    self.scene_geometry = Some(scene_geometry.clone());
-
-                    Ok(scene_geometry)
-                },
+            // OBJ files only contain a single scene
+            Scene {name: None} | SceneByIndex {index: 0} => {
+                let scene_geometry = self.scene_geometry(display, *normals)?;
+                if scene_geometry.is_empty() {
+                    return Err(QueryError::NoGeometryFound);
+                }
+
+                Ok(scene_geometry)
+            },
+            // Each object or group is treated as a node named after it
+            Nodes {names, scene: None} => {
+                let missing = names.iter()
+                    .find(|&name| !self.mesh.geometry.iter().any(|geo| geo.name.as_ref() == Some(name)));
+                if let Some(name) = missing {
+                    return Err(QueryError::UnknownNode {name: name.clone()});
+                }
+
+                // The scene geometry is stored in the same order as the geometry of the mesh
+                let scene_geometry = self.scene_geometry(display, *normals)?;
+                let geometry: Vec<_> = self.mesh.geometry.iter().zip(scene_geometry.iter())
+                    .filter(|(geo, _)| geo.name.as_ref().is_some_and(|name| names.contains(name)))
+                    .map(|(_, shader_geo)| shader_geo.clone())
+                    .collect();
+                if geometry.is_empty() {
+                    return Err(QueryError::NoGeometryFound);
+                }
+
+                Ok(geometry.into())
             },
             // OBJ files do not contain any named scenes
-            Scene {name: Some(name)} => Err(QueryError::UnknownScene {name: name.clone()}),
+            Scene {name: Some(name)} |
+            Nodes {names: _, scene: Some(name)} => Err(QueryError::UnknownScene {name: name.clone()}),
+            &SceneByIndex {index} => Err(QueryError::SceneIndexOutOfRange {index, count: 1}),
         }
     }
 
     fn query_camera(&mut self, query: &CameraQuery) -> Result<Arc<Camera>, QueryError> {
+        let CameraQuery {camera, animation} = query;
+
+        // OBJ files do not support animations
+        match animation {
+            Some(AnimationQuery {name: Some(name), ..}) => {
+                return Err(QueryError::UnknownAnimation {name: name.clone()});
+            },
+            Some(AnimationQuery {name: None, ..}) => {
+                return Err(QueryError::NoAnimationFound);
+            },
+            _ => {},
+        }
+
         // OBJ files do not support cameras
         // This code still does the work to produce useful errors
-        match query {
-            CameraQuery::FirstInScene {name: None} => Err(QueryError::NoCameraFound),
-            CameraQuery::Named {name, scene: None} => Err(QueryError::UnknownCamera {name: name.clone()}),
+        match camera {
+            CameraFilter::FirstInScene {name: None} |
+            CameraFilter::FirstInSceneByIndex {index: 0} => Err(QueryError::NoCameraFound),
+            CameraFilter::Named {name, scene: None} => Err(QueryError::UnknownCamera {name: name.clone()}),
 
             // OBJ files do not contain any named scenes
-            CameraQuery::FirstInScene {name: Some(name)} |
-            CameraQuery::Named {name: _, scene: Some(name)} => Err(QueryError::UnknownScene {
+            CameraFilter::FirstInScene {name: Some(name)} |
+            CameraFilter::Named {name: _, scene: Some(name)} => Err(QueryError::UnknownScene {
                 name: name.clone(),
             }),
+            &CameraFilter::FirstInSceneByIndex {index} => Err(QueryError::SceneIndexOutOfRange {
+                index,
+                count: 1,
+            }),
         }
     }
 
@@ -96,11 +210,87 @@ impl QueryBackend for ObjFile {
         // OBJ files do not support lights
         // This code still does the work to produce useful errors
         match query {
-            LightQuery::Scene {name: None} => Err(QueryError::NoLightsFound),
+            LightQuery::Scene {name: None} |
+            LightQuery::SceneByIndex {index: 0} => Err(QueryError::NoLightsFound),
             // OBJ files do not contain any named scenes
             LightQuery::Scene {name: Some(name)} => Err(QueryError::UnknownScene {
                 name: name.clone(),
             }),
+            &LightQuery::SceneByIndex {index} => Err(QueryError::SceneIndexOutOfRange {
+                index,
+                count: 1,
+            }),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+
+    use approx::{assert_relative_eq, relative_eq};
+
+    use crate::math::{Vec3, Rgba};
+    use crate::query3d::File;
+
+    #[test]
+    fn unit_scale_normalizes_to_meters() {
+        // A triangle that is 1m wide when authored in centimeters
+        let path = env::temp_dir().join("spritec_unit_scale_cm.obj");
+        fs::write(&path, "v 0 0 0\nv 100 0 0\nv 0 50 -100\nf 1 2 3\n").unwrap();
+
+        let file = ObjFile::open_with_unit_scale(&path, 0.01).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let (min, max) = file.mesh().bounds().unwrap();
+        assert_relative_eq!(min, Vec3::new(0.0, 0.0, -1.0));
+        assert_relative_eq!(max, Vec3::new(1.0, 0.5, 0.0));
+    }
+
+    #[test]
+    fn mtl_colors_and_missing_mtl_fallback() {
+        let dir = env::temp_dir().join("spritec_mtl_colors");
+        fs::create_dir_all(&dir).unwrap();
+        let triangle = "v 0 0 0\nv 1 0 0\nv 0 1 0\n";
+        fs::write(dir.join("colors.mtl"), "newmtl red\nKd 1 0 0\nd 0.5\nmap_Kd missing.png\n").unwrap();
+        fs::write(dir.join("colored.obj"), format!("mtllib colors.mtl\n{}usemtl red\nf 1 2 3\n", triangle)).unwrap();
+        fs::write(dir.join("no_mtl.obj"), format!("mtllib missing.mtl\n{}usemtl red\nf 1 2 3\n", triangle)).unwrap();
+
+        let colored = ObjFile::open(&dir.join("colored.obj")).unwrap();
+        let no_mtl = ObjFile::open(&dir.join("no_mtl.obj")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        // The texture could not be loaded, but the diffuse color is still used
+        let material = &colored.mesh().geometry[0].material;
+        assert_eq!(material.diffuse_color, Rgba::new(1.0, 0.0, 0.0, 0.5));
+        assert!(material.base_color_texture.is_none());
+
+        let material = &no_mtl.mesh().geometry[0].material;
+        assert_eq!(material.diffuse_color, Material::obj_default().diffuse_color);
+    }
+
+    #[test]
+    fn directory_of_objs_is_one_model() {
+        let dir = env::temp_dir().join("spritec_obj_directory");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("b_legs.obj"), "o legs\nv 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n").unwrap();
+        fs::write(dir.join("a_body.obj"), "o body\nv 0 1 0\nv 1 1 0\nv 0 2 0\nf 1 2 3\n").unwrap();
+        fs::write(dir.join("notes.txt"), "not a model").unwrap();
+
+        let file = File::open(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let file = match file {
+            Ok(File::Obj(file)) => file,
+            res => panic!("expected an OBJ file, got {:?}", res.map(|_| ())),
+        };
+        // Files are merged in order of their names, and each object keeps its name
+        let names: Vec<_> = file.mesh().geometry.iter().map(|geo| geo.name.as_deref()).collect();
+        assert_eq!(names, &[Some("body"), Some("legs")]);
+        let (min, max) = file.mesh().bounds().unwrap();
+        assert_relative_eq!(min, Vec3::zero());
+        assert_relative_eq!(max, Vec3::new(1.0, 2.0, 0.0));
+    }
+}
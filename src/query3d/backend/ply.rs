@@ -0,0 +1,675 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::str::SplitAsciiWhitespace;
+
+use thiserror::Error;
+
+use crate::math::{self, Vec3, Mat4, Rgba};
+use crate::scene::{Mesh, Geometry, Material, NormalMode};
+use crate::renderer::{Display, DisplayTracker, ShaderGeometry, Camera, Light};
+use crate::query3d::{GeometryQuery, GeometryFilter, AnimationQuery, CameraQuery, CameraFilter, LightQuery};
+
+use super::{QueryBackend, QueryError};
+
+#[derive(Debug, Error)]
+pub enum PlyError {
+    #[error(transparent)]
+    IoError(#[from] io::Error),
+    #[error("Invalid PLY header on line {line}: {message}")]
+    InvalidHeader {line: usize, message: String},
+    #[error("PLY file ended before all of its elements were read")]
+    UnexpectedEof,
+    #[error("Invalid value `{token}` in ASCII PLY file")]
+    InvalidValue {token: String},
+    #[error("PLY vertices must have `x`, `y`, and `z` properties")]
+    MissingPositions,
+    #[error("PLY face refers to vertex {index}, but the file only has {count} vertices")]
+    VertexIndexOutOfRange {index: usize, count: usize},
+}
+
+/// Represents a single PLY file
+///
+/// Only the vertices and faces of the file are loaded. PLY files do not contain any materials,
+/// cameras, or lights, but their vertices may have colors.
+#[derive(Debug)]
+pub struct PlyFile {
+    mesh: Mesh,
+    /// The versions of this model lazily uploaded to the GPU, for each way of generating normals
+    scene_geometry: HashMap<NormalMode, Arc<Vec<Arc<ShaderGeometry>>>>,
+    /// The display that `scene_geometry` was uploaded to
+    display: DisplayTracker,
+}
+
+impl PlyFile {
+    /// Opens an ASCII or binary PLY file
+    pub fn open(path: &Path) -> Result<Self, PlyError> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    /// Loads an ASCII or binary PLY file from its contents
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, PlyError> {
+        let (header, body) = split_header(bytes)?;
+        let header = Header::parse(&header)?;
+
+        let mut reader = match header.format {
+            Format::Ascii => DataReader::Ascii(std::str::from_utf8(body)
+                .map_err(|_| PlyError::InvalidValue {token: "<non-UTF-8 data>".to_string()})?
+                .split_ascii_whitespace()),
+            Format::BinaryLittleEndian => DataReader::Binary {bytes: body, big_endian: false},
+            Format::BinaryBigEndian => DataReader::Binary {bytes: body, big_endian: true},
+        };
+
+        let mut vertices = VertexData::default();
+        let mut faces = Vec::new();
+        for element in &header.elements {
+            match &element.name[..] {
+                "vertex" => vertices = read_vertices(&mut reader, element)?,
+                "face" => faces = read_faces(&mut reader, element)?,
+                // Any other elements (e.g. edges) are skipped
+                _ => for _ in 0..element.count {
+                    for property in &element.properties {
+                        reader.read_property(property)?;
+                    }
+                },
+            }
+        }
+
+        Ok(Self {
+            mesh: Mesh {
+                name: None,
+                geometry: vec![vertices.into_geometry(faces)?],
+                morph_weights: Vec::new(),
+            },
+            scene_geometry: HashMap::new(),
+            display: DisplayTracker::default(),
+        })
+    }
+
+    /// Returns the mesh loaded from this file
+    pub fn mesh(&self) -> &Mesh {
+        &self.mesh
+    }
+}
+
+/// Splits the file into the text of its header and the data of its elements
+fn split_header(bytes: &[u8]) -> Result<(String, &[u8]), PlyError> {
+    const END_HEADER: &[u8] = b"end_header";
+    let end = bytes.windows(END_HEADER.len())
+        .position(|window| window == END_HEADER)
+        .ok_or_else(|| PlyError::InvalidHeader {line: 1, message: "missing `end_header`".to_string()})?;
+    // The data begins on the line after the end of the header
+    let data_start = bytes[end..].iter()
+        .position(|&byte| byte == b'\n')
+        .map(|newline| end + newline + 1)
+        .unwrap_or_else(|| bytes.len());
+
+    Ok((String::from_utf8_lossy(&bytes[..end]).into_owned(), &bytes[data_start..]))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ScalarType {
+    Int8,
+    UInt8,
+    Int16,
+    UInt16,
+    Int32,
+    UInt32,
+    Float32,
+    Float64,
+}
+
+impl ScalarType {
+    fn parse(name: &str) -> Option<Self> {
+        use ScalarType::*;
+        Some(match name {
+            "char" | "int8" => Int8,
+            "uchar" | "uint8" => UInt8,
+            "short" | "int16" => Int16,
+            "ushort" | "uint16" => UInt16,
+            "int" | "int32" => Int32,
+            "uint" | "uint32" => UInt32,
+            "float" | "float32" => Float32,
+            "double" | "float64" => Float64,
+            _ => return None,
+        })
+    }
+
+    /// The number of bytes used to store this type in a binary file
+    fn size(self) -> usize {
+        use ScalarType::*;
+        match self {
+            Int8 | UInt8 => 1,
+            Int16 | UInt16 => 2,
+            Int32 | UInt32 | Float32 => 4,
+            Float64 => 8,
+        }
+    }
+
+    /// Converts a value of this type to a linear color channel between 0.0 and 1.0. Integer colors
+    /// use the full range of their type and are encoded as sRGB, like the colors of an image, so
+    /// they are decoded. Floating point colors are assumed to already be linear.
+    fn to_color_channel(self, value: f64) -> f32 {
+        match self {
+            ScalarType::Float32 | ScalarType::Float64 => value as f32,
+            _ => math::srgb_to_linear(self.to_alpha_channel(value)),
+        }
+    }
+
+    /// Converts a value of this type to an alpha channel between 0.0 and 1.0. Integer values use
+    /// the full range of their type. Unlike colors, alpha is never encoded as sRGB.
+    fn to_alpha_channel(self, value: f64) -> f32 {
+        use ScalarType::*;
+        let max = match self {
+            Int8 => i8::MAX as f64,
+            UInt8 => u8::MAX as f64,
+            Int16 => i16::MAX as f64,
+            UInt16 => u16::MAX as f64,
+            Int32 => i32::MAX as f64,
+            UInt32 => u32::MAX as f64,
+            Float32 | Float64 => 1.0,
+        };
+        (value / max) as f32
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Property {
+    Scalar {name: String, ty: ScalarType},
+    List {name: String, count_ty: ScalarType, item_ty: ScalarType},
+}
+
+impl Property {
+    fn name(&self) -> &str {
+        match self {
+            Property::Scalar {name, ..} | Property::List {name, ..} => name,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Header {
+    format: Format,
+    /// Every element in the file, in the order their data is stored
+    elements: Vec<Element>,
+}
+
+impl Header {
+    fn parse(header: &str) -> Result<Self, PlyError> {
+        let mut format = None;
+        let mut elements: Vec<Element> = Vec::new();
+
+        for (index, line) in header.lines().enumerate() {
+            let invalid = |message: &str| PlyError::InvalidHeader {
+                line: index + 1,
+                message: message.to_string(),
+            };
+            let scalar_type = |name: Option<&str>| name.and_then(ScalarType::parse)
+                .ok_or_else(|| invalid("unknown property type"));
+
+            let tokens: Vec<_> = line.split_whitespace().collect();
+            match tokens[..] {
+                ["ply"] if index == 0 => {},
+                _ if index == 0 => return Err(invalid("file must start with `ply`")),
+                ["format", name, _version] => format = Some(match name {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::BinaryLittleEndian,
+                    "binary_big_endian" => Format::BinaryBigEndian,
+                    _ => return Err(invalid("unknown format")),
+                }),
+                ["comment", ..] | ["obj_info", ..] | [] => {},
+                ["element", name, count] => elements.push(Element {
+                    name: name.to_string(),
+                    count: count.parse().map_err(|_| invalid("invalid element count"))?,
+                    properties: Vec::new(),
+                }),
+                ["property", "list", count_ty, item_ty, name] => {
+                    let property = Property::List {
+                        name: name.to_string(),
+                        count_ty: scalar_type(Some(count_ty))?,
+                        item_ty: scalar_type(Some(item_ty))?,
+                    };
+                    elements.last_mut().ok_or_else(|| invalid("property must follow an element"))?
+                        .properties.push(property);
+                },
+                ["property", ty, name] => {
+                    let property = Property::Scalar {name: name.to_string(), ty: scalar_type(Some(ty))?};
+                    elements.last_mut().ok_or_else(|| invalid("property must follow an element"))?
+                        .properties.push(property);
+                },
+                _ => return Err(invalid("unrecognized line")),
+            }
+        }
+
+        let format = format.ok_or_else(|| PlyError::InvalidHeader {
+            line: 1,
+            message: "missing `format`".to_string(),
+        })?;
+        Ok(Self {format, elements})
+    }
+}
+
+/// Reads the values of elements one at a time
+enum DataReader<'a> {
+    Ascii(SplitAsciiWhitespace<'a>),
+    Binary {bytes: &'a [u8], big_endian: bool},
+}
+
+impl<'a> DataReader<'a> {
+    fn read(&mut self, ty: ScalarType) -> Result<f64, PlyError> {
+        match self {
+            DataReader::Ascii(tokens) => {
+                let token = tokens.next().ok_or(PlyError::UnexpectedEof)?;
+                token.parse().map_err(|_| PlyError::InvalidValue {token: token.to_string()})
+            },
+
+            DataReader::Binary {bytes, big_endian} => {
+                let size = ty.size();
+                if bytes.len() < size {
+                    return Err(PlyError::UnexpectedEof);
+                }
+                let (data, rest) = bytes.split_at(size);
+                *bytes = rest;
+
+                // Converts everything to little endian so only one set of conversions is needed
+                let mut le = [0; 8];
+                le[..size].copy_from_slice(data);
+                if *big_endian {
+                    le[..size].reverse();
+                }
+
+                use ScalarType::*;
+                Ok(match ty {
+                    Int8 => le[0] as i8 as f64,
+                    UInt8 => le[0] as f64,
+                    Int16 => i16::from_le_bytes([le[0], le[1]]) as f64,
+                    UInt16 => u16::from_le_bytes([le[0], le[1]]) as f64,
+                    Int32 => i32::from_le_bytes(le[..4].try_into().unwrap()) as f64,
+                    UInt32 => u32::from_le_bytes(le[..4].try_into().unwrap()) as f64,
+                    Float32 => f32::from_le_bytes(le[..4].try_into().unwrap()) as f64,
+                    Float64 => f64::from_le_bytes(le),
+                })
+            },
+        }
+    }
+
+    /// Reads every value of the given property
+    fn read_property(&mut self, property: &Property) -> Result<Vec<f64>, PlyError> {
+        match *property {
+            Property::Scalar {ty, ..} => Ok(vec![self.read(ty)?]),
+            Property::List {count_ty, item_ty, ..} => {
+                let count = self.read(count_ty)? as usize;
+                (0..count).map(|_| self.read(item_ty)).collect()
+            },
+        }
+    }
+}
+
+/// The properties of every vertex that are used for rendering
+#[derive(Debug, Default)]
+struct VertexData {
+    positions: Vec<Vec3>,
+    normals: Vec<Vec3>,
+    colors: Vec<Rgba>,
+}
+
+fn read_vertices(reader: &mut DataReader, element: &Element) -> Result<VertexData, PlyError> {
+    let has = |names: &[&str]| names.iter()
+        .all(|name| element.properties.iter().any(|property| property.name() == *name));
+    if !has(&["x", "y", "z"]) {
+        return Err(PlyError::MissingPositions);
+    }
+    let has_normals = has(&["nx", "ny", "nz"]);
+    let has_colors = has(&["red", "green", "blue"]);
+
+    let mut data = VertexData::default();
+    for _ in 0..element.count {
+        let mut position = Vec3::zero();
+        let mut normal = Vec3::zero();
+        let mut color = Rgba::white();
+        for property in &element.properties {
+            let value = reader.read_property(property)?;
+            let (ty, value) = match (property, &value[..]) {
+                (&Property::Scalar {ty, ..}, &[value]) => (ty, value),
+                // Lists are never used for vertex data
+                _ => continue,
+            };
+
+            match property.name() {
+                "x" => position.x = value as f32,
+                "y" => position.y = value as f32,
+                "z" => position.z = value as f32,
+                "nx" => normal.x = value as f32,
+                "ny" => normal.y = value as f32,
+                "nz" => normal.z = value as f32,
+                "red" => color.r = ty.to_color_channel(value),
+                "green" => color.g = ty.to_color_channel(value),
+                "blue" => color.b = ty.to_color_channel(value),
+                "alpha" => color.a = ty.to_alpha_channel(value),
+                _ => {},
+            }
+        }
+
+        data.positions.push(position);
+        if has_normals {
+            data.normals.push(normal);
+        }
+        if has_colors {
+            data.colors.push(color);
+        }
+    }
+
+    Ok(data)
+}
+
+/// Reads the vertex indices of each face
+fn read_faces(reader: &mut DataReader, element: &Element) -> Result<Vec<Vec<u32>>, PlyError> {
+    // The count comes from the header, so it cannot be trusted to reserve space up front
+    let mut faces = Vec::new();
+    for _ in 0..element.count {
+        let mut face = Vec::new();
+        for property in &element.properties {
+            let values = reader.read_property(property)?;
+            if let "vertex_indices" | "vertex_index" = property.name() {
+                face = values.into_iter().map(|index| index as u32).collect();
+            }
+        }
+        faces.push(face);
+    }
+
+    Ok(faces)
+}
+
+impl VertexData {
+    /// Creates geometry from these vertices, splitting each face into a fan of triangles
+    fn into_geometry(self, faces: Vec<Vec<u32>>) -> Result<Geometry, PlyError> {
+        let Self {positions, normals, colors} = self;
+
+        let mut indices = Vec::new();
+        for face in faces {
+            if let Some(&index) = face.iter().find(|&&index| index as usize >= positions.len()) {
+                return Err(PlyError::VertexIndexOutOfRange {
+                    index: index as usize,
+                    count: positions.len(),
+                });
+            }
+            for i in 1..face.len().saturating_sub(1) {
+                indices.extend_from_slice(&[face[0], face[i], face[i + 1]]);
+            }
+        }
+
+        let normals = if normals.is_empty() {
+            vertex_normals(&positions, &indices)
+        } else {
+            normals
+        };
+        // Vertex colors are shown as is, rather than being darkened by the default gray
+        let material = if colors.is_empty() {
+            Material::obj_default()
+        } else {
+            Material::default()
+        };
+
+        Ok(Geometry {
+            name: None,
+            indices,
+            positions,
+            normals,
+            tex_coords: Vec::new(),
+            tangents: Vec::new(),
+            colors,
+            joints: Vec::new(),
+            weights: Vec::new(),
+            morph_targets: Vec::new(),
+            material: Arc::new(material),
+        })
+    }
+}
+
+/// Computes a normal for each vertex by averaging the normals of the triangles around it,
+/// weighted by their area
+fn vertex_normals(positions: &[Vec3], indices: &[u32]) -> Vec<Vec3> {
+    let mut normals = vec![Vec3::zero(); positions.len()];
+    for triangle in indices.chunks(3) {
+        let [a, b, c] = [triangle[0] as usize, triangle[1] as usize, triangle[2] as usize];
+        // The length of the cross product is twice the area of the triangle
+        let normal = (positions[b] - positions[a]).cross(positions[c] - positions[a]);
+        for &vertex in &[a, b, c] {
+            normals[vertex] += normal;
+        }
+    }
+
+    normals.into_iter()
+        .map(|normal| normal.try_normalized().unwrap_or_else(Vec3::zero))
+        .collect()
+}
+
+impl QueryBackend for PlyFile {
+    fn query_geometry(&mut self, query: &GeometryQuery, display: &Display) -> Result<Arc<Vec<Arc<ShaderGeometry>>>, QueryError> {
+        let GeometryQuery {models, animation, morph_weights: _, transform_overrides, normals} = query;
+
+        // Geometry uploaded to another display cannot be drawn with this one
+        if self.display.switch_to(display) {
+            self.scene_geometry.clear();
+        }
+
+        // PLY files do not support animations
+        match animation {
+            Some(AnimationQuery {name: Some(name), ..}) => {
+                return Err(QueryError::UnknownAnimation {name: name.clone()});
+            },
+            Some(AnimationQuery {name: None, ..}) => {
+                return Err(QueryError::NoAnimationFound);
+            },
+            _ => {},
+        }
+
+        // PLY files do not contain any named nodes to override the transforms of
+        if let Some(name) = transform_overrides.keys().min() {
+            return Err(QueryError::UnknownNode {name: name.clone()});
+        }
+
+        use GeometryFilter::*;
+        match models {
+            // PLY files only contain a single scene
+            Scene {name: None} | SceneByIndex {index: 0} => match self.scene_geometry.get(normals) {
+                Some(scene_geometry) => Ok(scene_geometry.clone()),
+                None => {
+                    if self.mesh.geometry.iter().all(|geo| geo.indices.is_empty()) {
+                        return Err(QueryError::NoGeometryFound);
+                    }
+
+                    let scene_geometry: Arc<Vec<_>> = self.mesh.geometry.iter()
+                        .map(|geo| {
+                            ShaderGeometry::new(display, geo, Mat4::identity(), *normals).map(Arc::new)
+                        })
+                        .collect::<Result<Vec<_>, _>>()?
+                        .into();
+
+                    self.scene_geometry.insert(*normals, scene_geometry.clone());
+
+                    Ok(scene_geometry)
+                },
+            },
+            // PLY files do not contain any named scenes
+            Scene {name: Some(name)} |
+            Nodes {names: _, scene: Some(name)} => Err(QueryError::UnknownScene {name: name.clone()}),
+            &SceneByIndex {index} => Err(QueryError::SceneIndexOutOfRange {index, count: 1}),
+            // PLY files do not contain any named nodes
+            Nodes {names, scene: None} => match names.first() {
+                Some(name) => Err(QueryError::UnknownNode {name: name.clone()}),
+                None => Err(QueryError::NoGeometryFound),
+            },
+        }
+    }
+
+    fn query_camera(&mut self, query: &CameraQuery) -> Result<Arc<Camera>, QueryError> {
+        let CameraQuery {camera, animation} = query;
+
+        // PLY files do not support animations
+        match animation {
+            Some(AnimationQuery {name: Some(name), ..}) => {
+                return Err(QueryError::UnknownAnimation {name: name.clone()});
+            },
+            Some(AnimationQuery {name: None, ..}) => {
+                return Err(QueryError::NoAnimationFound);
+            },
+            _ => {},
+        }
+
+        // PLY files do not support cameras
+        // This code still does the work to produce useful errors
+        match camera {
+            CameraFilter::FirstInScene {name: None} |
+            CameraFilter::FirstInSceneByIndex {index: 0} => Err(QueryError::NoCameraFound),
+            CameraFilter::Named {name, scene: None} => Err(QueryError::UnknownCamera {name: name.clone()}),
+
+            // PLY files do not contain any named scenes
+            CameraFilter::FirstInScene {name: Some(name)} |
+            CameraFilter::Named {name: _, scene: Some(name)} => Err(QueryError::UnknownScene {
+                name: name.clone(),
+            }),
+            &CameraFilter::FirstInSceneByIndex {index} => Err(QueryError::SceneIndexOutOfRange {
+                index,
+                count: 1,
+            }),
+        }
+    }
+
+    fn query_lights(&mut self, query: &LightQuery) -> Result<Arc<Vec<Arc<Light>>>, QueryError> {
+        // PLY files do not support lights
+        // This code still does the work to produce useful errors
+        match query {
+            LightQuery::Scene {name: None} |
+            LightQuery::SceneByIndex {index: 0} => Err(QueryError::NoLightsFound),
+            // PLY files do not contain any named scenes
+            LightQuery::Scene {name: Some(name)} => Err(QueryError::UnknownScene {
+                name: name.clone(),
+            }),
+            &LightQuery::SceneByIndex {index} => Err(QueryError::SceneIndexOutOfRange {
+                index,
+                count: 1,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use approx::{assert_relative_eq, relative_eq};
+
+    /// A colored quad in the XY plane, without normals
+    const ASCII_QUAD: &str = "ply
+format ascii 1.0
+comment a single quad
+element vertex 4
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+end_header
+0 0 0 255 0 0
+1 0 0 0 128 0
+1 1 0 0 0 255
+0 1 0 255 255 255
+4 0 1 2 3
+";
+
+    #[test]
+    fn ascii_quad_with_colors_and_computed_normals() {
+        let file = PlyFile::from_bytes(ASCII_QUAD.as_bytes()).unwrap();
+        let geo = &file.mesh().geometry[0];
+
+        assert_eq!(geo.indices, vec![0, 1, 2, 0, 2, 3]);
+        assert_relative_eq!(geo.positions[2], Vec3::new(1.0, 1.0, 0.0));
+        assert_eq!(geo.colors[0], Rgba::new(1.0, 0.0, 0.0, 1.0));
+        // 8-bit colors are decoded from sRGB
+        assert_relative_eq!(geo.colors[1].g, 0.21586, epsilon = 1e-5);
+        assert_eq!(geo.colors[3], Rgba::white());
+        for &normal in &geo.normals {
+            assert_relative_eq!(normal, Vec3::unit_z());
+        }
+        assert_eq!(geo.material.diffuse_color, Rgba::white());
+    }
+
+    #[test]
+    fn binary_formats_match_ascii() {
+        let header = |format: &str| format!("ply
+format {} 1.0
+element vertex 3
+property float x
+property float y
+property float z
+property float nx
+property float ny
+property float nz
+element face 1
+property list uchar uint vertex_indices
+end_header
+", format);
+
+        let vertices = [[0.0f32, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+        for &(format, big_endian) in &[("binary_little_endian", false), ("binary_big_endian", true)] {
+            let mut bytes = header(format).into_bytes();
+            for position in &vertices {
+                for &value in position.iter().chain(&[0.0, 0.0, 1.0]) {
+                    let value: f32 = value;
+                    bytes.extend(&if big_endian { value.to_be_bytes() } else { value.to_le_bytes() });
+                }
+            }
+            bytes.push(3);
+            for index in 0..3u32 {
+                bytes.extend(&if big_endian { index.to_be_bytes() } else { index.to_le_bytes() });
+            }
+
+            let file = PlyFile::from_bytes(&bytes).unwrap();
+            let geo = &file.mesh().geometry[0];
+            assert_eq!(geo.indices, vec![0, 1, 2]);
+            assert_relative_eq!(geo.positions[1], Vec3::unit_x());
+            assert_relative_eq!(geo.normals[2], Vec3::unit_z());
+            assert!(geo.colors.is_empty());
+        }
+    }
+
+    #[test]
+    fn element_count_larger_than_file() {
+        // Only one face follows the header, so the file ends long before the claimed count
+        let source = ASCII_QUAD.replace("element face 1", "element face 1000000000000");
+        match PlyFile::from_bytes(source.as_bytes()) {
+            Err(PlyError::UnexpectedEof) => {},
+            result => panic!("expected an end of file error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn invalid_vertex_index() {
+        let source = ASCII_QUAD.replace("4 0 1 2 3", "3 0 1 7");
+        match PlyFile::from_bytes(source.as_bytes()) {
+            Err(PlyError::VertexIndexOutOfRange {index: 7, count: 4}) => {},
+            result => panic!("expected an index error, got {:?}", result),
+        }
+    }
+}
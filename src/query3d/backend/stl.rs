@@ -0,0 +1,427 @@
+use std::io;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::collections::HashMap;
+use std::convert::TryInto;
+
+use thiserror::Error;
+
+use crate::math::{Vec3, Mat4, Radians, Handedness};
+use crate::scene::{Mesh, Geometry, Material, NormalMode, CameraType};
+use crate::renderer::{Display, DisplayTracker, ShaderGeometry, Camera, Light};
+use crate::query3d::{GeometryQuery, GeometryFilter, AnimationQuery, CameraQuery, CameraFilter, LightQuery};
+
+use super::{QueryBackend, QueryError};
+
+/// The size of the header at the start of a binary STL file, including the triangle count
+const BINARY_HEADER_SIZE: usize = 84;
+/// The size of each triangle in a binary STL file: a normal, three vertices, and an attribute
+/// byte count
+const BINARY_TRIANGLE_SIZE: usize = 50;
+
+#[derive(Debug, Error)]
+pub enum StlError {
+    #[error(transparent)]
+    IoError(#[from] io::Error),
+    #[error("Binary STL file is truncated (expected {expected} bytes, got {actual})")]
+    Truncated {expected: usize, actual: usize},
+    #[error("Invalid ASCII STL file: unexpected `{token}` on line {line}")]
+    InvalidAscii {line: usize, token: String},
+}
+
+/// Represents a single STL file
+///
+/// STL files only contain triangles, so the file is loaded as a single mesh with the default gray
+/// material and no cameras or lights.
+#[derive(Debug)]
+pub struct StlFile {
+    mesh: Mesh,
+    /// The versions of this model lazily uploaded to the GPU, for each way of generating normals
+    scene_geometry: HashMap<NormalMode, Arc<Vec<Arc<ShaderGeometry>>>>,
+    /// The display that `scene_geometry` was uploaded to
+    display: DisplayTracker,
+}
+
+impl StlFile {
+    /// Opens a binary or ASCII STL file
+    pub fn open(path: &Path) -> Result<Self, StlError> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    /// Loads a binary or ASCII STL file from its contents
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, StlError> {
+        let triangles = if is_binary(bytes) {
+            parse_binary(bytes)?
+        } else {
+            parse_ascii(&String::from_utf8_lossy(bytes))?
+        };
+
+        Ok(Self {
+            mesh: Mesh {
+                name: None,
+                geometry: vec![triangles_to_geometry(triangles)],
+                morph_weights: Vec::new(),
+            },
+            scene_geometry: HashMap::new(),
+            display: DisplayTracker::default(),
+        })
+    }
+
+    /// Returns the mesh loaded from this file
+    pub fn mesh(&self) -> &Mesh {
+        &self.mesh
+    }
+
+    /// A camera in front of the mesh that is far enough away to see all of it
+    fn default_camera(&self) -> Option<Camera> {
+        let (min, max) = self.mesh.bounds()?;
+        let center = (min + max) / 2.0;
+        // Avoids a degenerate camera for meshes with no area
+        let radius = ((max - min).magnitude() / 2.0).max(f32::EPSILON);
+
+        let field_of_view_y = Radians::from_degrees(45.0);
+        let distance = radius / (field_of_view_y.get_radians() / 2.0).sin();
+        let cam_type = CameraType::Perspective {
+            name: None,
+            // Sprites are usually square
+            aspect_ratio: 1.0,
+            field_of_view_y,
+            near_z: (distance - radius).max(distance * 0.01),
+            far_z: Some(distance + radius),
+        };
+
+        let handedness = Handedness::Right;
+        let eye = center + Vec3::forward_rh() * -distance;
+        Some(Camera {
+            view: handedness.look_at(eye, center, Vec3::up()),
+            projection: cam_type.to_projection(handedness),
+            handedness,
+        })
+    }
+}
+
+/// A triangle with its vertices and the normal of its face
+type Triangle = (Vec3, [Vec3; 3]);
+
+/// Binary files begin with an 80 byte header that may start with "solid", just like an ASCII
+/// file. Binary files can still be recognized because their triangle count matches the size of
+/// the file, or because they contain bytes that never appear in ASCII files.
+fn is_binary(bytes: &[u8]) -> bool {
+    if bytes.len() >= BINARY_HEADER_SIZE {
+        let count = u32::from_le_bytes(bytes[80..84].try_into().unwrap()) as usize;
+        if bytes.len() == BINARY_HEADER_SIZE + count * BINARY_TRIANGLE_SIZE {
+            return true;
+        }
+    }
+
+    let is_text = bytes.iter().all(|&byte| byte != 0 && byte.is_ascii());
+    let starts_with_solid = String::from_utf8_lossy(&bytes[..bytes.len().min(80)])
+        .trim_start()
+        .starts_with("solid");
+    !(is_text && starts_with_solid)
+}
+
+fn parse_binary(bytes: &[u8]) -> Result<Vec<Triangle>, StlError> {
+    // Files too short to contain the triangle count (including empty files) are never ASCII
+    if bytes.len() < BINARY_HEADER_SIZE {
+        return Err(StlError::Truncated {expected: BINARY_HEADER_SIZE, actual: bytes.len()});
+    }
+    let count = u32::from_le_bytes(bytes[80..84].try_into().unwrap()) as usize;
+    let expected = BINARY_HEADER_SIZE + count * BINARY_TRIANGLE_SIZE;
+    if bytes.len() < expected {
+        return Err(StlError::Truncated {expected, actual: bytes.len()});
+    }
+
+    let read_vec3 = |data: &[u8]| {
+        let float = |i: usize| f32::from_le_bytes(data[i * 4..i * 4 + 4].try_into().unwrap());
+        Vec3::new(float(0), float(1), float(2))
+    };
+
+    Ok(bytes[BINARY_HEADER_SIZE..expected].chunks(BINARY_TRIANGLE_SIZE).map(|data| {
+        let normal = read_vec3(&data[0..12]);
+        (normal, [read_vec3(&data[12..24]), read_vec3(&data[24..36]), read_vec3(&data[36..48])])
+    }).collect())
+}
+
+fn parse_ascii(source: &str) -> Result<Vec<Triangle>, StlError> {
+    let mut triangles = Vec::new();
+    let mut normal = Vec3::zero();
+    let mut vertices = Vec::new();
+
+    for (index, line) in source.lines().enumerate() {
+        let invalid = |token: &str| StlError::InvalidAscii {line: index + 1, token: token.to_string()};
+        let mut tokens = line.split_whitespace();
+        let read_vec3 = |tokens: &mut std::str::SplitWhitespace| -> Result<Vec3, StlError> {
+            let mut component = || {
+                let token = tokens.next().unwrap_or("");
+                token.parse::<f32>().map_err(|_| invalid(token))
+            };
+            Ok(Vec3::new(component()?, component()?, component()?))
+        };
+
+        match tokens.next() {
+            Some("facet") => {
+                match tokens.next() {
+                    Some("normal") => {},
+                    token => return Err(invalid(token.unwrap_or(""))),
+                }
+                normal = read_vec3(&mut tokens)?;
+                vertices.clear();
+            },
+            Some("vertex") => vertices.push(read_vec3(&mut tokens)?),
+            Some("endfacet") => match vertices[..] {
+                [a, b, c] => triangles.push((normal, [a, b, c])),
+                _ => return Err(invalid("endfacet")),
+            },
+            Some("solid") | Some("outer") | Some("endloop") | Some("endsolid") | None => {},
+            Some(token) => return Err(invalid(token)),
+        }
+    }
+
+    Ok(triangles)
+}
+
+/// STL files are typically Z-up, so every position and normal is rotated to be Y-up like glTF.
+/// Each triangle gets its own vertices so that the normal of its face can be used directly.
+fn triangles_to_geometry(triangles: Vec<Triangle>) -> Geometry {
+    let to_y_up = |Vec3 {x, y, z}: Vec3| Vec3::new(x, z, -y);
+
+    let mut positions = Vec::with_capacity(triangles.len() * 3);
+    let mut normals = Vec::with_capacity(triangles.len() * 3);
+    for (normal, vertices) in triangles {
+        let [a, b, c] = vertices;
+        // Many exporters leave the normal as zero, so compute it from the counter-clockwise
+        // winding order of the vertices instead
+        let normal = if normal.magnitude_squared() > 0.0 {
+            normal.normalized()
+        } else {
+            (b - a).cross(c - a).try_normalized().unwrap_or_else(Vec3::zero)
+        };
+
+        positions.extend(vertices.iter().copied().map(to_y_up));
+        normals.extend_from_slice(&[to_y_up(normal); 3]);
+    }
+
+    Geometry {
+        name: None,
+        indices: (0..positions.len() as u32).collect(),
+        positions,
+        normals,
+        // STL files only store triangles
+        tex_coords: Vec::new(),
+        tangents: Vec::new(),
+        colors: Vec::new(),
+        joints: Vec::new(),
+        weights: Vec::new(),
+        morph_targets: Vec::new(),
+        material: Arc::new(Material::obj_default()),
+    }
+}
+
+impl QueryBackend for StlFile {
+    fn query_geometry(&mut self, query: &GeometryQuery, display: &Display) -> Result<Arc<Vec<Arc<ShaderGeometry>>>, QueryError> {
+        let GeometryQuery {models, animation, morph_weights: _, transform_overrides, normals} = query;
+
+        // Geometry uploaded to another display cannot be drawn with this one
+        if self.display.switch_to(display) {
+            self.scene_geometry.clear();
+        }
+
+        // STL files do not support animations
+        match animation {
+            Some(AnimationQuery {name: Some(name), ..}) => {
+                return Err(QueryError::UnknownAnimation {name: name.clone()});
+            },
+            Some(AnimationQuery {name: None, ..}) => {
+                return Err(QueryError::NoAnimationFound);
+            },
+            _ => {},
+        }
+
+        // STL files do not contain any named nodes to override the transforms of
+        if let Some(name) = transform_overrides.keys().min() {
+            return Err(QueryError::UnknownNode {name: name.clone()});
+        }
+
+        use GeometryFilter::*;
+        match models {
+            // STL files only contain a single scene
+            Scene {name: None} | SceneByIndex {index: 0} => match self.scene_geometry.get(normals) {
+                Some(scene_geometry) => Ok(scene_geometry.clone()),
+                None => {
+                    if self.mesh.geometry.iter().all(|geo| geo.indices.is_empty()) {
+                        return Err(QueryError::NoGeometryFound);
+                    }
+
+                    let scene_geometry: Arc<Vec<_>> = self.mesh.geometry.iter()
+                        .map(|geo| {
+                            ShaderGeometry::new(display, geo, Mat4::identity(), *normals).map(Arc::new)
+                        })
+                        .collect::<Result<Vec<_>, _>>()?
+                        .into();
+
+                    self.scene_geometry.insert(*normals, scene_geometry.clone());
+
+                    Ok(scene_geometry)
+                },
+            },
+            // STL files do not contain any named scenes
+            Scene {name: Some(name)} |
+            Nodes {names: _, scene: Some(name)} => Err(QueryError::UnknownScene {name: name.clone()}),
+            &SceneByIndex {index} => Err(QueryError::SceneIndexOutOfRange {index, count: 1}),
+            // STL files do not contain any named nodes
+            Nodes {names, scene: None} => match names.first() {
+                Some(name) => Err(QueryError::UnknownNode {name: name.clone()}),
+                None => Err(QueryError::NoGeometryFound),
+            },
+        }
+    }
+
+    fn query_camera(&mut self, query: &CameraQuery) -> Result<Arc<Camera>, QueryError> {
+        let CameraQuery {camera, animation} = query;
+
+        // STL files do not support animations
+        match animation {
+            Some(AnimationQuery {name: Some(name), ..}) => {
+                return Err(QueryError::UnknownAnimation {name: name.clone()});
+            },
+            Some(AnimationQuery {name: None, ..}) => {
+                return Err(QueryError::NoAnimationFound);
+            },
+            _ => {},
+        }
+
+        // STL files do not support cameras, so a camera that can see the whole mesh is provided
+        // when the query does not ask for a specific camera
+        match camera {
+            CameraFilter::FirstInScene {name: None} |
+            CameraFilter::FirstInSceneByIndex {index: 0} => self.default_camera()
+                .map(Arc::new)
+                .ok_or(QueryError::NoCameraFound),
+            CameraFilter::Named {name, scene: None} => Err(QueryError::UnknownCamera {name: name.clone()}),
+
+            // STL files do not contain any named scenes
+            CameraFilter::FirstInScene {name: Some(name)} |
+            CameraFilter::Named {name: _, scene: Some(name)} => Err(QueryError::UnknownScene {
+                name: name.clone(),
+            }),
+            &CameraFilter::FirstInSceneByIndex {index} => Err(QueryError::SceneIndexOutOfRange {
+                index,
+                count: 1,
+            }),
+        }
+    }
+
+    fn query_lights(&mut self, query: &LightQuery) -> Result<Arc<Vec<Arc<Light>>>, QueryError> {
+        // STL files do not support lights
+        // This code still does the work to produce useful errors
+        match query {
+            LightQuery::Scene {name: None} |
+            LightQuery::SceneByIndex {index: 0} => Err(QueryError::NoLightsFound),
+            // STL files do not contain any named scenes
+            LightQuery::Scene {name: Some(name)} => Err(QueryError::UnknownScene {
+                name: name.clone(),
+            }),
+            &LightQuery::SceneByIndex {index} => Err(QueryError::SceneIndexOutOfRange {
+                index,
+                count: 1,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use approx::{assert_relative_eq, relative_eq};
+
+    use crate::math::Vec4;
+
+    const ASCII_TRIANGLE: &str = "solid triangle
+  facet normal 0 0 1
+    outer loop
+      vertex 0 0 0
+      vertex 1 0 0
+      vertex 0 1 0
+    endloop
+  endfacet
+endsolid triangle
+";
+
+    fn binary_triangle(normal: [f32; 3]) -> Vec<u8> {
+        // Binary files may also start with "solid", so this must not be mistaken for ASCII
+        let mut bytes = b"solid binary".to_vec();
+        bytes.resize(80, 0);
+        bytes.extend(&1u32.to_le_bytes());
+        for value in normal.iter().chain(&[0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0]) {
+            bytes.extend(&value.to_le_bytes());
+        }
+        bytes.extend(&0u16.to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn ascii_and_binary_match() {
+        let ascii = StlFile::from_bytes(ASCII_TRIANGLE.as_bytes()).unwrap();
+        let binary = StlFile::from_bytes(&binary_triangle([0.0, 0.0, 1.0])).unwrap();
+
+        for file in &[ascii, binary] {
+            let geo = &file.mesh().geometry[0];
+            assert_eq!(geo.indices, vec![0, 1, 2]);
+            // Z-up positions are converted to Y-up
+            assert_relative_eq!(geo.positions[2], Vec3::new(0.0, 0.0, -1.0));
+            assert_relative_eq!(geo.normals[0], Vec3::new(0.0, 1.0, 0.0));
+        }
+    }
+
+    #[test]
+    fn missing_normals_are_computed() {
+        let file = StlFile::from_bytes(&binary_triangle([0.0, 0.0, 0.0])).unwrap();
+        for &normal in &file.mesh().geometry[0].normals {
+            assert_relative_eq!(normal, Vec3::new(0.0, 1.0, 0.0));
+        }
+    }
+
+    #[test]
+    fn truncated_binary_and_invalid_ascii() {
+        let mut bytes = binary_triangle([0.0, 0.0, 1.0]);
+        bytes[80] = 2;
+        match StlFile::from_bytes(&bytes) {
+            Err(StlError::Truncated {expected: 184, actual: 134}) => {},
+            result => panic!("expected a truncated file error, got {:?}", result),
+        }
+
+        // Too short to hold the triangle count
+        for &short in &[&[][..], &[0; 10][..]] {
+            match StlFile::from_bytes(short) {
+                Err(StlError::Truncated {expected: 84, actual}) => assert_eq!(actual, short.len()),
+                result => panic!("expected a truncated file error, got {:?}", result),
+            }
+        }
+
+        match StlFile::from_bytes(b"solid broken\n  facet normal 0 0 x\n") {
+            Err(StlError::InvalidAscii {line: 2, token}) => assert_eq!(token, "x"),
+            result => panic!("expected an invalid file error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn default_camera_sees_mesh() {
+        let mut file = StlFile::from_bytes(ASCII_TRIANGLE.as_bytes()).unwrap();
+        let camera = file.query_camera(&CameraQuery::first_in_default_scene()).unwrap();
+
+        let (min, max) = file.mesh().bounds().unwrap();
+        for &corner in &[min, max] {
+            let clip = camera.projection * camera.view * Vec4::from_point(corner);
+            let ndc = Vec3::from(clip) / clip.w;
+            assert!(ndc.x.abs() <= 1.0 && ndc.y.abs() <= 1.0 && ndc.z.abs() <= 1.0);
+        }
+
+        match file.query_lights(&LightQuery::Scene {name: None}) {
+            Err(QueryError::NoLightsFound) => {},
+            result => panic!("expected no lights, got {:?}", result),
+        }
+    }
+}
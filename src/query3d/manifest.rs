@@ -0,0 +1,84 @@
+/// A summary of the contents of a 3D file
+///
+/// This only describes what is available in the file. It does not contain any of the geometry,
+/// so it is cheap to produce and to keep around.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Manifest {
+    /// The scenes in the file, in the order they are stored in the file
+    pub scenes: Vec<SceneInfo>,
+    /// The cameras in the file, in the order they are stored in the file
+    pub cameras: Vec<CameraInfo>,
+    /// The lights in the file, in the order they are stored in the file
+    pub lights: Vec<LightInfo>,
+    /// The animations in the file, in the order they are stored in the file
+    pub animations: Vec<AnimationInfo>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SceneInfo {
+    /// The name of the scene (if any)
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CameraInfo {
+    /// The name of the camera (if any)
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LightInfo {
+    /// The name of the light (if any)
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnimationInfo {
+    /// The name of the animation (if any)
+    pub name: Option<String>,
+    /// The time of the last keyframe in the animation, in seconds
+    pub duration: f32,
+}
+
+impl Manifest {
+    /// The manifest of a file format that can only store a single unnamed scene, with no cameras,
+    /// lights, or animations (e.g. OBJ or STL)
+    pub fn single_scene() -> Self {
+        Self {
+            scenes: vec![SceneInfo {name: None}],
+            ..Self::default()
+        }
+    }
+}
+
+impl<'a> From<&'a gltf::Document> for Manifest {
+    fn from(document: &'a gltf::Document) -> Self {
+        let name_of = |name: Option<&str>| name.map(|name| name.to_string());
+
+        Self {
+            scenes: document.scenes()
+                .map(|scene| SceneInfo {name: name_of(scene.name())})
+                .collect(),
+            cameras: document.cameras()
+                .map(|cam| CameraInfo {name: name_of(cam.name())})
+                .collect(),
+            lights: document.lights().map(|lights| {
+                lights.map(|light| LightInfo {name: name_of(light.name())}).collect()
+            }).unwrap_or_default(),
+            animations: document.animations()
+                .map(|anim| AnimationInfo {name: name_of(anim.name()), duration: duration(&anim)})
+                .collect(),
+        }
+    }
+}
+
+/// Returns the time of the last keyframe in the given animation, in seconds
+///
+/// The glTF spec requires the keyframe times of every animation sampler to have a maximum value,
+/// so this can be found without reading any of the buffers in the file.
+fn duration(anim: &gltf::Animation) -> f32 {
+    anim.samplers()
+        .filter_map(|sampler| sampler.input().max())
+        .filter_map(|max| max.get(0).and_then(|time| time.as_f64()))
+        .fold(0.0, |duration, time| duration.max(time as f32))
+}
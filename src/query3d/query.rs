@@ -1,7 +1,22 @@
+use std::collections::HashMap;
+
+use crate::math::Mat4;
+use crate::scene::NormalMode;
+
 #[derive(Debug, Clone)]
 pub struct GeometryQuery {
     pub models: GeometryFilter,
     pub animation: Option<AnimationQuery>,
+    /// The weight of each morph target (blend shape) to apply to every mesh, or None if the
+    /// weights from the file should be used. Meshes without morph targets are unaffected.
+    pub morph_weights: Option<Vec<f32>>,
+    /// A transform to apply to each node with the given name, on top of its local transform from
+    /// the file (or from the animation). The children of a node inherit its override. Nodes that
+    /// are not in the map use the transform from the file.
+    pub transform_overrides: HashMap<String, Mat4>,
+    /// The way that the normals of the geometry are generated. Flat normals give every triangle a
+    /// single flat shade, which suits low-poly models.
+    pub normals: NormalMode,
 }
 
 #[derive(Debug, Clone)]
@@ -11,6 +26,23 @@ pub enum GeometryFilter {
         /// The name of the scene to look in or None if the default scene should be used
         name: Option<String>,
     },
+    /// Returns all the geometry in the scene with the given index
+    ///
+    /// Useful when scenes are unnamed and so cannot be selected by name
+    SceneByIndex {
+        /// The index of the scene in the file
+        index: usize,
+    },
+    /// Returns the geometry of the nodes with the given names and of all of their descendants
+    ///
+    /// Useful for rendering a single part of a model. Each node keeps the world transform that it
+    /// has in the scene.
+    Nodes {
+        /// The names of the nodes to return the geometry of
+        names: Vec<String>,
+        /// The name of the scene to look in or None if the default scene should be used
+        scene: Option<String>,
+    },
 }
 
 impl GeometryFilter {
@@ -45,12 +77,33 @@ pub enum AnimationPosition {
 }
 
 #[derive(Debug, Clone)]
-pub enum CameraQuery {
+pub struct CameraQuery {
+    pub camera: CameraFilter,
+    /// The animation to pose the camera with, or None if the camera should be used as loaded
+    pub animation: Option<AnimationQuery>,
+}
+
+impl CameraQuery {
+    pub fn first_in_default_scene() -> Self {
+        CameraQuery {
+            camera: CameraFilter::FirstInScene {name: None},
+            animation: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum CameraFilter {
     /// Returns the first camera in the given scene
     FirstInScene {
         /// The name of the scene to look in or None if the default scene should be used
         name: Option<String>,
     },
+    /// Returns the first camera in the scene with the given index
+    FirstInSceneByIndex {
+        /// The index of the scene in the file
+        index: usize,
+    },
     /// Returns the camera with the given name
     Named {
         /// The name of the camera to look for
@@ -60,12 +113,6 @@ pub enum CameraQuery {
     },
 }
 
-impl CameraQuery {
-    pub fn first_in_default_scene() -> Self {
-        CameraQuery::FirstInScene {name: None}
-    }
-}
-
 #[derive(Debug, Clone)]
 pub enum LightQuery {
     /// Returns all the lights in the given scene
@@ -73,6 +120,11 @@ pub enum LightQuery {
         /// The name of the scene to look in or None if the default scene should be used
         name: Option<String>,
     },
+    /// Returns all the lights in the scene with the given index
+    SceneByIndex {
+        /// The index of the scene in the file
+        index: usize,
+    },
 }
 
 impl LightQuery {
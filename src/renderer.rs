@@ -5,10 +5,20 @@ mod rendered_image;
 mod job;
 mod light;
 mod camera;
+mod atlas;
+mod orbit;
+mod png;
+mod gif;
+mod depth;
+mod manifest;
+mod shadow;
+mod ground;
+mod stats;
 
 mod layout;
 mod shader;
 mod imageops;
+mod edges;
 
 pub use thread_render_context::*;
 pub use shader_geometry::*;
@@ -17,15 +27,55 @@ pub use rendered_image::*;
 pub use job::*;
 pub use light::*;
 pub use camera::*;
+pub use atlas::*;
+pub use orbit::*;
+pub use png::*;
+pub use self::gif::*;
+pub use depth::*;
+pub use manifest::*;
+pub use shadow::*;
+pub use ground::*;
+pub use stats::*;
+pub use layout::LayoutError;
+pub use edges::EdgeThresholds;
 
-use std::sync::Arc;
+use std::cell::Cell;
 
-use glium::{Surface, framebuffer::SimpleFrameBuffer};
+use glium::{Surface, IndexBuffer, framebuffer::SimpleFrameBuffer};
 
-use crate::math::{Rgba, Rgb, Mat4};
+use crate::math::{Rgba, Vec3, Mat4, Handedness};
 
 use shader::cel::CelUniforms;
+use shader::flat::FlatUniforms;
+use shader::mask::MaskUniforms;
 use shader::outline::OutlineUniforms;
+use shader::normals::NormalsUniforms;
+use shader::shadow::ShadowUniforms;
+use shader::ground::GroundUniforms;
+
+/// The distance (in normalized device coordinates) that wireframe lines are moved towards the
+/// camera, large enough to keep them in front of their own surfaces despite rounding
+const WIREFRAME_DEPTH_BIAS: f32 = 1e-4;
+
+/// The parameters used to draw the inverted hull outline of a model with a camera of the given
+/// handedness
+fn hull_draw_parameters(handedness: Handedness) -> glium::DrawParameters<'static> {
+    glium::DrawParameters {
+        depth: glium::Depth {
+            test: glium::draw_parameters::DepthTest::IfLess,
+            write: true,
+            ..Default::default()
+        },
+        // Enabling backface culling, but flipping the test so that *only* the back faces will
+        // be rendered. Without this, the slightly larger outline mesh would always render over
+        // the regular cel shaded mesh.
+        backface_culling: match handedness {
+            Handedness::Right => glium::draw_parameters::BackfaceCullingMode::CullCounterClockwise,
+            Handedness::Left => glium::draw_parameters::BackfaceCullingMode::CullClockwise,
+        },
+        ..Default::default()
+    }
+}
 
 /// A renderer that allows you to draw models
 pub struct Renderer<'a> {
@@ -33,6 +83,8 @@ pub struct Renderer<'a> {
     display: &'a Display,
     shaders: &'a Shaders,
     target: SimpleFrameBuffer<'a>,
+    /// The statistics of the render context, updated with every draw call
+    stats: &'a Cell<RenderStats>,
 }
 
 impl<'a> Renderer<'a> {
@@ -41,6 +93,14 @@ impl<'a> Renderer<'a> {
         &self.display
     }
 
+    /// Records a draw call of the given triangles in the statistics of the render context
+    fn count_draw(&self, indices: &IndexBuffer<u32>) {
+        let mut stats = self.stats.get();
+        stats.draw_calls += 1;
+        stats.triangles += indices.len() as u64 / 3;
+        self.stats.set(stats);
+    }
+
     /// Clears the screen and resets the depth buffer
     pub fn clear(&mut self, background: Rgba) {
         self.target.clear_color_and_depth(background.into_tuple(), 1.0);
@@ -50,11 +110,11 @@ impl<'a> Renderer<'a> {
     pub fn render(
         &mut self,
         geometry: &ShaderGeometry,
-        lights: &[Arc<Light>],
-        ambient_light: Rgb,
-        view: Mat4,
-        projection: Mat4,
+        lighting: Lighting,
+        camera: &Camera,
         outline: &Outline,
+        rim: Option<Rim>,
+        specular: Option<Specular>,
     ) -> Result<(), glium::DrawError> {
         let cel_params = glium::DrawParameters {
             depth: glium::Depth {
@@ -67,45 +127,435 @@ impl<'a> Renderer<'a> {
             // https://github.com/glium/glium/blob/125be3580ccfb4e3924005aa5b092069c050a922/book/tuto-11-backface-culling.md#backface-culling-in-glium
             ..Default::default()
         };
-        let outline_params = glium::DrawParameters {
+        let outline_params = hull_draw_parameters(camera.handedness);
+
+        let ShaderGeometry {
+            mesh,
+            material,
+            base_color_texture,
+            emissive_texture,
+            normal_texture,
+            model_transform,
+            model_inverse_transpose,
+            bounds: _,
+        } = geometry;
+        let MeshBuffers {indices, positions, normals, tex_coords, tangents, colors} = &**mesh;
+        let model_transform = *model_transform;
+        let model_inverse_transpose = *model_inverse_transpose;
+        let &Camera {view, projection, handedness: _} = camera;
+        let mvp = projection * view * model_transform;
+        // The camera is at the origin of its own coordinate system
+        let eye_position = view.inverted().mul_point(Vec3::zero());
+
+        let Lighting {lights, ambient_light, key_light, bands, shadow, color_space, exposure, gamma} = lighting;
+        let cel_uniforms = shader::cel::Cel::from(CelUniforms {
+            mvp,
+            model_transform,
+            model_inverse_transpose,
+            lights,
+            ambient_light,
+            key_light,
+            bands,
+            material: &*material,
+            base_color_texture: base_color_texture.as_deref(),
+            emissive_texture: emissive_texture.as_deref(),
+            normal_texture: normal_texture.as_deref(),
+            shadow,
+            eye_position,
+            rim,
+            specular,
+            color_space,
+            exposure,
+            gamma,
+        });
+
+        self.count_draw(indices);
+        self.target.draw((positions, normals, tex_coords, tangents, colors), indices, &self.shaders.cel,
+            &cel_uniforms, &cel_params)?;
+
+        // Edge detected outlines are drawn over the finished image using a separate normals pass
+        let &Outline {mode, color: outline_color} = outline;
+        if let Some(thickness) = mode.hull_thickness() {
+            let outline_uniforms = shader::outline::Outline::from(OutlineUniforms {
+                mvp,
+                outline_thickness: thickness,
+                outline_color,
+            });
+
+            self.count_draw(indices);
+            self.target.draw((positions, normals), indices, &self.shaders.outline,
+                &outline_uniforms, &outline_params)?;
+        }
+
+        Ok(())
+    }
+
+    /// Draw the given model using smooth diffuse lighting, with no cel shading bands, outline, rim
+    /// light, or specular highlights
+    pub fn render_flat(
+        &mut self,
+        geometry: &ShaderGeometry,
+        lighting: Lighting,
+        camera: &Camera,
+    ) -> Result<(), glium::DrawError> {
+        let params = glium::DrawParameters {
             depth: glium::Depth {
                 test: glium::draw_parameters::DepthTest::IfLess,
                 write: true,
                 ..Default::default()
             },
-            // Enabling backface culling, but flipping the test so that *only* the back faces will
-            // be rendered. Without this, the slightly larger outline mesh would always render over
-            // the regular cel shaded mesh.
-            backface_culling: glium::draw_parameters::BackfaceCullingMode::CullCounterClockwise,
             ..Default::default()
         };
 
-        let ShaderGeometry {indices, positions, normals, material, model_transform} = geometry;
+        let ShaderGeometry {
+            mesh,
+            material,
+            base_color_texture,
+            emissive_texture,
+            // Normal textures are only applied by the cel shader
+            normal_texture: _,
+            model_transform,
+            model_inverse_transpose,
+            bounds: _,
+        } = geometry;
+        let MeshBuffers {indices, positions, normals, tex_coords, tangents, colors} = &**mesh;
         let model_transform = *model_transform;
+        let &Camera {view, projection, handedness: _} = camera;
         let mvp = projection * view * model_transform;
-        let model_inverse_transpose = model_transform.inverted().transposed();
 
-        let cel_uniforms = shader::cel::Cel::from(CelUniforms {
+        let Lighting {
+            lights,
+            ambient_light,
+            key_light: _,
+            bands: _,
+            shadow: _,
+            color_space,
+            exposure,
+            gamma,
+        } = lighting;
+        let flat_uniforms = shader::flat::Flat::from(FlatUniforms {
             mvp,
             model_transform,
-            model_inverse_transpose,
+            model_inverse_transpose: *model_inverse_transpose,
             lights,
             ambient_light,
-            material: &*material,
+            material,
+            base_color_texture: base_color_texture.as_deref(),
+            emissive_texture: emissive_texture.as_deref(),
+            color_space,
+            exposure,
+            gamma,
         });
 
-        self.target.draw((positions, normals), indices, &self.shaders.cel,
-            &cel_uniforms, &cel_params)?;
+        self.count_draw(indices);
+        self.target.draw((positions, normals, tex_coords, tangents, colors), indices, &self.shaders.flat,
+            &flat_uniforms, &params)
+    }
+
+    /// Draw the silhouette of the given model in solid white, ignoring lighting and color. Only
+    /// the alpha of the material is used, so that cut out parts of the surface are left empty.
+    pub fn render_mask(
+        &mut self,
+        geometry: &ShaderGeometry,
+        camera: &Camera,
+    ) -> Result<(), glium::DrawError> {
+        let params = glium::DrawParameters {
+            depth: glium::Depth {
+                test: glium::draw_parameters::DepthTest::IfLess,
+                write: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let ShaderGeometry {
+            mesh,
+            material,
+            base_color_texture,
+            emissive_texture: _,
+            normal_texture: _,
+            model_transform,
+            model_inverse_transpose,
+            bounds: _,
+        } = geometry;
+        let MeshBuffers {indices, positions, normals, tex_coords, tangents, colors} = &**mesh;
+        let model_transform = *model_transform;
+        let &Camera {view, projection, handedness: _} = camera;
+        let mvp = projection * view * model_transform;
 
-        let outline_uniforms = shader::outline::Outline::from(OutlineUniforms {
+        let mask_uniforms = shader::mask::Mask::from(MaskUniforms {
             mvp,
-            outline_thickness: outline.thickness,
-            outline_color: outline.color,
+            model_transform,
+            model_inverse_transpose: *model_inverse_transpose,
+            material,
+            base_color_texture: base_color_texture.as_deref(),
+            mask_color: Rgba::white(),
+        });
+
+        self.count_draw(indices);
+        self.target.draw((positions, normals, tex_coords, tangents, colors), indices, &self.shaders.mask,
+            &mask_uniforms, &params)
+    }
+
+    /// Draw the given model into the depth buffer only, so that geometry drawn afterwards is hidden
+    /// behind it without the model itself being visible
+    pub fn render_depth_only(
+        &mut self,
+        geometry: &ShaderGeometry,
+        camera: &Camera,
+    ) -> Result<(), glium::DrawError> {
+        let params = glium::DrawParameters {
+            depth: glium::Depth {
+                test: glium::draw_parameters::DepthTest::IfLess,
+                write: true,
+                ..Default::default()
+            },
+            color_mask: (false, false, false, false),
+            ..Default::default()
+        };
+
+        let &ShaderGeometry {ref mesh, model_transform, ..} = geometry;
+        let MeshBuffers {indices, positions, normals, ..} = &**mesh;
+        let &Camera {view, projection, handedness: _} = camera;
+        let depth_uniforms = shader::outline::Outline::from(OutlineUniforms {
+            mvp: projection * view * model_transform,
+            outline_thickness: 0.0,
+            outline_color: Rgba::zero(),
         });
 
+        self.count_draw(indices);
         self.target.draw((positions, normals), indices, &self.shaders.outline,
-            &outline_uniforms, &outline_params)?;
+            &depth_uniforms, &params)
+    }
 
-        Ok(())
+    /// Draw the edges of every triangle of the given model as lines of the given color and width
+    /// (in pixels). If `depth_test` is true, only the edges of surfaces that are not hidden behind
+    /// geometry that was already drawn are visible.
+    ///
+    /// Widths other than 1 are not supported by every driver (see `Wireframe::thickness`).
+    pub fn render_wireframe(
+        &mut self,
+        geometry: &ShaderGeometry,
+        camera: &Camera,
+        color: Rgba,
+        width: f32,
+        depth_test: bool,
+    ) -> Result<(), glium::DrawError> {
+        let params = glium::DrawParameters {
+            depth: glium::Depth {
+                test: if depth_test {
+                    glium::draw_parameters::DepthTest::IfLessOrEqual
+                } else {
+                    glium::draw_parameters::DepthTest::Overwrite
+                },
+                write: false,
+                ..Default::default()
+            },
+            polygon_mode: glium::PolygonMode::Line,
+            line_width: Some(width),
+            ..Default::default()
+        };
+
+        let &ShaderGeometry {ref mesh, model_transform, ..} = geometry;
+        let MeshBuffers {indices, positions, normals, ..} = &**mesh;
+        let &Camera {view, projection, handedness: _} = camera;
+        // Moves every line slightly towards the camera (by a constant amount of depth) so that
+        // lines are not hidden by the very surfaces that they are the edges of
+        let depth_bias = Mat4::new(
+            1.0, 0.0, 0.0, 0.0,
+            0.0, 1.0, 0.0, 0.0,
+            0.0, 0.0, 1.0, -WIREFRAME_DEPTH_BIAS,
+            0.0, 0.0, 0.0, 1.0,
+        );
+        // The outline shader draws the geometry in a single color when it is not expanded
+        let wireframe_uniforms = shader::outline::Outline::from(OutlineUniforms {
+            mvp: depth_bias * projection * view * model_transform,
+            outline_thickness: 0.0,
+            outline_color: color,
+        });
+
+        self.count_draw(indices);
+        self.target.draw((positions, normals), indices, &self.shaders.outline,
+            &wireframe_uniforms, &params)
+    }
+
+    /// Draw the given ground plane (uploaded as geometry centered beneath the model) and its
+    /// contact shadow. This should be drawn before the model.
+    pub fn render_ground(
+        &mut self,
+        plane: &ShaderGeometry,
+        ground: &GroundPlane,
+        camera: &Camera,
+    ) -> Result<(), glium::DrawError> {
+        let params = glium::DrawParameters {
+            depth: glium::Depth {
+                test: glium::draw_parameters::DepthTest::IfLess,
+                // A plane with only a shadow is drawn like a decal that never hides anything
+                write: ground.color.is_some(),
+                ..Default::default()
+            },
+            // The plane and its shadow may be partially transparent. The alpha of the pixels is
+            // combined so that the shadow can be seen over a transparent background.
+            blend: glium::Blend {
+                color: glium::BlendingFunction::Addition {
+                    source: glium::LinearBlendingFactor::SourceAlpha,
+                    destination: glium::LinearBlendingFactor::OneMinusSourceAlpha,
+                },
+                alpha: glium::BlendingFunction::Addition {
+                    source: glium::LinearBlendingFactor::One,
+                    destination: glium::LinearBlendingFactor::OneMinusSourceAlpha,
+                },
+                constant_value: (0.0, 0.0, 0.0, 0.0),
+            },
+            ..Default::default()
+        };
+
+        let &ShaderGeometry {ref mesh, model_transform, ..} = plane;
+        let MeshBuffers {indices, positions, ..} = &**mesh;
+        let &Camera {view, projection, handedness: _} = camera;
+        let ground_uniforms = shader::ground::Ground::from(GroundUniforms {
+            mvp: projection * view * model_transform,
+            ground,
+        });
+
+        self.count_draw(indices);
+        self.target.draw(positions, indices, &self.shaders.ground, &ground_uniforms, &params)
+    }
+
+    /// Draw the depth of the given model as seen from a light, where `light_space` transforms
+    /// world coordinates into the clip space of the light
+    pub(crate) fn render_shadow_depth(
+        &mut self,
+        geometry: &ShaderGeometry,
+        light_space: Mat4,
+    ) -> Result<(), glium::DrawError> {
+        let params = glium::DrawParameters {
+            depth: glium::Depth {
+                test: glium::draw_parameters::DepthTest::IfLess,
+                write: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let &ShaderGeometry {ref mesh, model_transform, ..} = geometry;
+        let MeshBuffers {indices, positions, ..} = &**mesh;
+        let shadow_uniforms = shader::shadow::Shadow::from(ShadowUniforms {
+            mvp: light_space * model_transform,
+        });
+
+        self.count_draw(indices);
+        self.target.draw(positions, indices, &self.shaders.shadow, &shadow_uniforms, &params)
+    }
+
+    /// Draw the normal and the depth (the distance along the view direction of the camera) of each
+    /// pixel of the given model
+    ///
+    /// The renderer should be cleared to transparent black first so that pixels where nothing is
+    /// drawn have a normal of zero length.
+    pub fn render_normals(
+        &mut self,
+        geometry: &ShaderGeometry,
+        camera: &Camera,
+    ) -> Result<(), glium::DrawError> {
+        let params = glium::DrawParameters {
+            depth: glium::Depth {
+                test: glium::draw_parameters::DepthTest::IfLess,
+                write: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let &ShaderGeometry {ref mesh, model_transform, model_inverse_transpose, ..} = geometry;
+        let MeshBuffers {indices, positions, normals, ..} = &**mesh;
+        let &Camera {view, projection, handedness: _} = camera;
+        let model_view = view * model_transform;
+        let mvp = projection * model_view;
+
+        let normals_uniforms = shader::normals::Normals::from(NormalsUniforms {
+            mvp,
+            model_view,
+            model_inverse_transpose,
+        });
+
+        self.count_draw(indices);
+        self.target.draw((positions, normals), indices, &self.shaders.normals,
+            &normals_uniforms, &params)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+
+    use glium::draw_parameters::{BackfaceCullingMode, DepthTest};
+
+    use crate::math::Vec4;
+    use crate::scene::{Geometry, Material};
+
+    #[test]
+    fn hull_draws_back_faces_outside_silhouette() {
+        let params = hull_draw_parameters(Handedness::Right);
+        assert_eq!(params.depth.test, DepthTest::IfLess);
+        assert!(params.depth.write);
+        // Front faces are wound clockwise in a left-handed coordinate system
+        let left = hull_draw_parameters(Handedness::Left);
+        assert_eq!(left.backface_culling, BackfaceCullingMode::CullClockwise);
+
+        let radius = 1.0;
+        let sphere = Geometry::uv_sphere(radius, 32, 16, Arc::new(Material::default()));
+        let thickness = OutlineMode::InvertedHull {thickness: 0.25}.hull_thickness()
+            .expect("expected a hull to be drawn");
+        let eye = Vec3::new(0.0, 0.0, 5.0);
+        let mvp = Handedness::Right.perspective_no(0.8, 1.0, 0.1, 100.0)
+            * Handedness::Right.look_at(eye, Vec3::zero(), Vec3::up());
+
+        // The position of each vertex after it is pushed out by the outline vertex shader, and then
+        // in normalized device coordinates
+        let to_ndc = |position: Vec3| {
+            let clip = mvp * Vec4::from_point(position);
+            Vec3::from(clip) / clip.w
+        };
+        let hull_positions: Vec<_> = sphere.positions.iter().zip(&sphere.normals)
+            .map(|(&position, &normal)| position + normal * thickness)
+            .collect();
+        let hull: Vec<_> = hull_positions.iter().map(|&position| to_ndc(position)).collect();
+
+        // Emulates the culling of the draw parameters for a right-handed camera
+        let culled = |signed_area: f32| match params.backface_culling {
+            BackfaceCullingMode::CullingDisabled => false,
+            BackfaceCullingMode::CullCounterClockwise => signed_area > 0.0,
+            BackfaceCullingMode::CullClockwise => signed_area < 0.0,
+        };
+        let mut drawn = Vec::new();
+        for triangle in sphere.indices.chunks(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| hull[triangle[i] as usize]);
+            // Triangles with (almost) no area at the poles of the sphere do not cover any pixels
+            let signed_area = (b.x - a.x) * (c.y - a.y) - (c.x - a.x) * (b.y - a.y);
+            if signed_area.abs() > 1e-9 && !culled(signed_area) {
+                drawn.push(triangle);
+            }
+        }
+        assert!(!drawn.is_empty(), "expected some of the hull to be drawn");
+
+        // Only the faces of the hull that point away from the camera are drawn
+        for triangle in &drawn {
+            let [a, b, c] = [0, 1, 2].map(|i| hull_positions[triangle[i] as usize]);
+            // The sphere is wound counter-clockwise when viewed from outside, so this points out
+            let normal = (b - a).cross(c - a);
+            assert!(normal.dot(a - eye) > 0.0, "a front face of the hull was drawn at {:?}", a);
+        }
+
+        // The drawn part of the hull reaches past the silhouette of the sphere, which forms a ring
+        // of the outline color around it
+        let extent = |points: &mut dyn Iterator<Item=Vec3>| {
+            points.map(|point| point.x.hypot(point.y)).fold(0.0, f32::max)
+        };
+        let sphere_extent = extent(&mut sphere.positions.iter().map(|&position| to_ndc(position)));
+        let hull_extent = extent(&mut drawn.iter().flat_map(|triangle| triangle.iter().map(|&i| hull[i as usize])));
+        assert!(hull_extent > sphere_extent, "hull {} did not reach past the sphere {}", hull_extent, sphere_extent);
     }
 }
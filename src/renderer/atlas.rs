@@ -0,0 +1,162 @@
+use std::num::NonZeroU32;
+
+use image::{RgbaImage, GenericImageView};
+use serde::Serialize;
+
+use super::{
+    Size,
+    layout::{LayoutNode, PackedLayout},
+    imageops::{copy, trim},
+};
+
+/// A single image containing many frames, each trimmed down to its visible pixels and then packed
+/// tightly together
+#[derive(Debug, Clone)]
+pub struct Atlas {
+    /// The packed image containing every frame
+    pub image: RgbaImage,
+    /// The location of every frame in the packed image
+    pub manifest: AtlasManifest,
+}
+
+/// Describes where each frame is stored in an atlas image
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AtlasManifest {
+    /// The width of the atlas image (in pixels)
+    pub width: u32,
+    /// The height of the atlas image (in pixels)
+    pub height: u32,
+    /// Every frame in the atlas, in the order the frames were given
+    pub frames: Vec<AtlasFrame>,
+}
+
+/// The area of an atlas image that contains a single trimmed frame
+///
+/// A frame that did not contain any visible pixels is stored as a zero-size area.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AtlasFrame {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    /// The position of the center of the original (untrimmed) frame, relative to the top-left
+    /// corner of the trimmed frame (in pixels). Drawing each frame with its pivot at the same
+    /// point keeps the frames aligned in the same way as they were rendered.
+    pub pivot: (f32, f32),
+}
+
+impl Atlas {
+    /// Trims each of the given frames and packs them into an image that is at most `max_width`
+    /// pixels wide. If any trimmed frame is wider than `max_width`, the width of the widest trimmed
+    /// frame is used instead.
+    pub fn pack(frames: &[RgbaImage], max_width: NonZeroU32) -> Self {
+        // Each trimmed frame and its offset in the original frame
+        let trimmed: Vec<_> = frames.iter().map(|frame| {
+            trim(frame).map(|(offset, size)| {
+                let sub_image = frame.view(offset.x, offset.y, size.width.get(), size.height.get());
+                (offset, sub_image.to_image())
+            })
+        }).collect();
+
+        // Only the frames with visible pixels take up space in the atlas
+        let cells: Vec<_> = trimmed.iter().flatten().map(|(_, image)| LayoutNode::Empty {
+            size: image_size(image),
+            background: None,
+        }).collect();
+        let packed = if cells.is_empty() {
+            None
+        } else {
+            let max_width = cells.iter()
+                .map(|cell| cell.size().width)
+                .fold(max_width, |max_width, width| max_width.max(width));
+            let packed = PackedLayout::new(cells, max_width, None, 0)
+                .expect("bug: max width was not increased to fit every frame");
+            Some(packed)
+        };
+
+        let (width, height) = packed.as_ref()
+            .map(|packed| (packed.width.get(), packed.height.get()))
+            .unwrap_or((0, 0));
+        let mut image = RgbaImage::new(width, height);
+        let mut targets = packed.into_iter().flat_map(|packed| packed.cells).map(|(target, _)| target);
+
+        let frames = frames.iter().zip(trimmed).map(|(frame, trimmed)| {
+            let center = (frame.width() as f32 / 2.0, frame.height() as f32 / 2.0);
+            match trimmed {
+                Some((offset, sub_image)) => {
+                    let target = targets.next().expect("bug: missing target for packed frame");
+                    copy(&sub_image, &mut image, (target.x, target.y));
+
+                    AtlasFrame {
+                        x: target.x,
+                        y: target.y,
+                        width: sub_image.width(),
+                        height: sub_image.height(),
+                        pivot: (center.0 - offset.x as f32, center.1 - offset.y as f32),
+                    }
+                },
+
+                None => AtlasFrame {x: 0, y: 0, width: 0, height: 0, pivot: center},
+            }
+        }).collect();
+
+        Self {
+            image,
+            manifest: AtlasManifest {width, height, frames},
+        }
+    }
+}
+
+impl AtlasManifest {
+    /// Serializes this manifest as JSON
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+}
+
+fn image_size(image: &RgbaImage) -> Size {
+    Size {
+        width: NonZeroU32::new(image.width()).expect("bug: trimmed image had zero width"),
+        height: NonZeroU32::new(image.height()).expect("bug: trimmed image had zero height"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pack_animation_frames() {
+        let red = image::Rgba([255, 0, 0, 255]);
+        // Four 8x8 frames with a square moving across them. The last frame is empty.
+        let frames: Vec<_> = [(0, 0, 4), (2, 2, 3), (4, 1, 2), (0, 0, 0)].iter()
+            .map(|&(x, y, size)| RgbaImage::from_fn(8, 8, |px, py| {
+                if px >= x && px < x + size && py >= y && py < y + size {
+                    red
+                } else {
+                    image::Rgba([0, 0, 0, 0])
+                }
+            }))
+            .collect();
+
+        let Atlas {image, manifest} = Atlas::pack(&frames, NonZeroU32::new(16).unwrap());
+        assert_eq!(manifest.frames.len(), 4);
+        assert_eq!((manifest.width, manifest.height), image.dimensions());
+
+        let expected_sizes = [(4, 4), (3, 3), (2, 2), (0, 0)];
+        for (frame, &(width, height)) in manifest.frames.iter().zip(&expected_sizes) {
+            assert_eq!((frame.width, frame.height), (width, height));
+            for x in frame.x..frame.x + frame.width {
+                for y in frame.y..frame.y + frame.height {
+                    assert_eq!(*image.get_pixel(x, y), red);
+                }
+            }
+        }
+
+        // The pivot is the center of the untrimmed frame
+        assert_eq!(manifest.frames[1].pivot, (2.0, 2.0));
+        assert_eq!(manifest.frames[3].pivot, (4.0, 4.0));
+
+        assert!(manifest.to_json().unwrap().contains("\"pivot\""));
+    }
+}
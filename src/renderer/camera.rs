@@ -1,4 +1,8 @@
-use crate::math::Mat4;
+use std::num::NonZeroU32;
+
+use crate::math::{Mat4, Vec3, FrustumPlanes, Handedness};
+
+use super::Size;
 
 #[derive(Debug, Clone)]
 pub struct Camera {
@@ -6,4 +10,548 @@ pub struct Camera {
     pub view: Mat4,
     /// The projection matrix of this camera
     pub projection: Mat4,
+    /// The handedness of the coordinate system that the view and projection matrices were built
+    /// for. This determines which faces of the geometry are considered front faces.
+    pub handedness: Handedness,
+}
+
+impl Camera {
+    /// Creates an orthographic camera that looks down at the world origin from the angle used for
+    /// isometric game sprites. The viewing volume is `scale` world units across.
+    ///
+    /// The camera is turned 45° about the y-axis so that it looks along the diagonal between the
+    /// positive x and z axes, and is then tilted down towards the ground:
+    ///
+    /// * isometric (`dimetric` is false): tilted down by atan(1/√2) ≈ 35.264°, so that all three
+    ///   axes are foreshortened equally and a square on the ground is drawn √3 times as wide as it
+    ///   is tall
+    /// * dimetric (`dimetric` is true): tilted down by 30°, so that a square on the ground is drawn
+    ///   exactly twice as wide as it is tall (the "2:1 isometric" used in pixel art)
+    ///
+    /// The y-axis always points straight up in the rendered image. The value of `scale` must be
+    /// greater than zero.
+    pub fn isometric(dimetric: bool, scale: f32) -> Self {
+        assert!(scale > 0.0, "the scale of an isometric camera must be greater than zero");
+
+        let pitch = if dimetric {
+            30.0f32.to_radians()
+        } else {
+            (1.0 / 2.0f32.sqrt()).atan()
+        };
+        let yaw = 45.0f32.to_radians();
+
+        // The camera is far enough away that a scene centered on the origin is not clipped
+        let distance = 100.0;
+        let eye = Vec3::new(
+            pitch.cos() * yaw.sin(),
+            pitch.sin(),
+            pitch.cos() * yaw.cos(),
+        ) * distance;
+
+        let handedness = Handedness::Right;
+        let half_size = scale / 2.0;
+        Camera {
+            view: handedness.look_at(eye, Vec3::zero(), Vec3::up()),
+            // OpenGL clip planes are -1 to 1, thus we use the _no method
+            projection: handedness.orthographic_no(FrustumPlanes {
+                left: -half_size,
+                right: half_size,
+                bottom: -half_size,
+                top: half_size,
+                near: 0.1,
+                far: distance * 2.0,
+            }),
+            handedness,
+        }
+    }
+
+    /// The width of the view of this camera divided by its height
+    pub fn aspect_ratio(&self) -> f32 {
+        // Perspective and orthographic projections both scale the x and y axes by factors whose
+        // ratio is the aspect ratio
+        (self.projection[(1, 1)] / self.projection[(0, 0)]).abs()
+    }
+
+    /// Returns a copy of this camera that is moved (and zoomed, for an orthographic camera) so
+    /// that the given world space bounds fit entirely within its view. The camera keeps its
+    /// orientation and is centered on the bounds.
+    ///
+    /// `margin` is the extra space left on every side, as a fraction of the size of the bounds.
+    /// `aspect_ratio` is the width of the rendered image divided by its height.
+    ///
+    /// An orthographic camera is resized so that the bounds fill its viewing volume. A perspective
+    /// camera keeps its vertical field of view and is moved along its view direction until a
+    /// sphere around the bounds fits in its view. The near and far planes of both kinds of camera
+    /// are moved to enclose the bounds.
+    pub fn auto_frame(&self, (min, max): (Vec3, Vec3), aspect_ratio: f32, margin: f32) -> Camera {
+        assert!(aspect_ratio > 0.0, "the aspect ratio must be greater than zero");
+        assert!(margin >= 0.0, "the margin must not be negative");
+
+        let &Camera {mut view, projection, handedness} = self;
+        let forward_z = handedness.forward_z();
+        // Each margin adds to both sides of the bounds
+        let padding = 1.0 + 2.0 * margin;
+        // Keeps the viewing volume from collapsing when the bounds are a single point
+        let min_extent = 1e-3;
+
+        // Move the camera along its own x and y axes so that it looks at the center of the bounds
+        let center = view.mul_point((min + max) / 2.0);
+        view[(0, 3)] -= center.x;
+        view[(1, 3)] -= center.y;
+
+        // An orthographic projection has no perspective divide, so w is always 1.0
+        let is_orthographic = projection[(3, 0)] == 0.0 && projection[(3, 1)] == 0.0
+            && projection[(3, 2)] == 0.0;
+
+        let projection = if is_orthographic {
+            let corners: Vec<_> = (0..8).map(|i| view.mul_point(Vec3::new(
+                if i & 1 == 0 { min.x } else { max.x },
+                if i & 2 == 0 { min.y } else { max.y },
+                if i & 4 == 0 { min.z } else { max.z },
+            ))).collect();
+            let max_abs = |component: fn(&Vec3) -> f32| {
+                corners.iter().map(|corner| component(corner).abs()).fold(0.0, f32::max) * padding
+            };
+
+            let half_height = max_abs(|corner| corner.y)
+                .max(max_abs(|corner| corner.x) / aspect_ratio)
+                .max(min_extent);
+            let half_width = half_height * aspect_ratio;
+
+            // The distance of each corner in front of the camera
+            let depths = corners.iter().map(|corner| corner.z * forward_z);
+            let near = depths.clone().fold(f32::INFINITY, f32::min);
+            let far = depths.fold(f32::NEG_INFINITY, f32::max);
+            let depth_padding = ((far - near) * margin).max(min_extent);
+
+            // OpenGL clip planes are -1 to 1, thus we use the _no method
+            handedness.orthographic_no(FrustumPlanes {
+                left: -half_width,
+                right: half_width,
+                bottom: -half_height,
+                top: half_height,
+                near: near - depth_padding,
+                far: far + depth_padding,
+            })
+
+        } else {
+            // The (1, 1) entry of a perspective projection is 1 / tan(fov_y / 2)
+            let tan_half_fov_y = 1.0 / projection[(1, 1)];
+            let tan_half_fov_x = tan_half_fov_y * aspect_ratio;
+            let half_fov = tan_half_fov_y.min(tan_half_fov_x).atan();
+
+            // The distance at which the sphere around the bounds touches the sides of the view
+            let radius = ((max - min).magnitude() / 2.0 * padding).max(min_extent);
+            let distance = radius / half_fov.sin();
+            view[(2, 3)] += forward_z * distance - center.z;
+
+            handedness.perspective_no(
+                2.0 * tan_half_fov_y.atan(),
+                aspect_ratio,
+                // Keep the near plane in front of the camera to preserve depth precision
+                (distance - radius).max(distance * 1e-3),
+                distance + radius,
+            )
+        };
+
+        Camera {view, projection, handedness}
+    }
+}
+
+/// Adjusts a camera to position the rendered geometry within the frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Framing {
+    /// Keeps the world origin at the center of the frame, regardless of the geometry being drawn.
+    ///
+    /// The camera keeps its orientation and its distance from the origin, but is moved so that it
+    /// looks directly at the origin. An orthographic projection is used so that one world unit is
+    /// always the same number of pixels. This keeps a model from sliding around between frames of
+    /// an animation, even as the bounds of the model change.
+    OriginCentered {
+        /// The number of pixels that one world unit should take up in the rendered image
+        ///
+        /// The value must be greater than zero.
+        pixels_per_unit: f32,
+    },
+
+    /// Aligns an orthographic camera with the pixel grid of the rendered image so that the
+    /// features of a model always start and end on pixel boundaries. This prevents a model from
+    /// shimmering between frames as it moves by fractions of a pixel.
+    ///
+    /// The projection is rescaled so that one world unit is exactly a whole number of pixels, and
+    /// the camera is moved along its own x and y axes (by less than a pixel) so that the world
+    /// origin lands on a pixel boundary. The near and far planes of the camera are kept.
+    ///
+    /// Pixels are measured in the final image. If the image is supersampled, it is rendered with
+    /// `pixels_per_unit * factor` pixels per unit and downscaled afterwards, so the snapping still
+    /// lines up with the pixels of the final image while edges are smoothed.
+    ///
+    /// The size of a perspective projection depends on the distance from the camera, so it can't
+    /// be aligned with the pixel grid. Perspective cameras are left unchanged.
+    PixelSnapped {
+        /// The number of pixels that one world unit should take up in the rendered image
+        pixels_per_unit: NonZeroU32,
+    },
+
+    /// Moves the camera so that all of the geometry being drawn fits in the frame, without
+    /// changing the direction that the camera faces (see `Camera::auto_frame`)
+    ///
+    /// Useful for rendering a model without knowing its size or position ahead of time. The
+    /// camera is unchanged if there is no geometry.
+    Fit {
+        /// The extra space left around the geometry on every side, as a fraction of the size of
+        /// the geometry
+        ///
+        /// The value must not be negative.
+        margin: f32,
+    },
+}
+
+impl Framing {
+    /// Returns a new camera with this framing applied to the given camera, for a frame of the
+    /// given size. The bounds are the world space bounds of the geometry being drawn, if any.
+    pub fn apply(self, camera: &Camera, size: Size, bounds: Option<(Vec3, Vec3)>) -> Camera {
+        use Framing::*;
+        match self {
+            OriginCentered {pixels_per_unit} => {
+                assert!(pixels_per_unit > 0.0, "pixels per unit must be greater than zero");
+
+                // Move the camera along its own x and y axes so that the origin is in the center
+                // of its view. The z-coordinate is the distance to the origin (negated for
+                // right-handed cameras, which look down the negative z-axis).
+                let &Camera {mut view, projection: _, handedness} = camera;
+                view[(0, 3)] = 0.0;
+                view[(1, 3)] = 0.0;
+                let distance = handedness.forward_z() * view[(2, 3)];
+                assert!(distance > 0.0, "the world origin must be in front of the camera");
+
+                let Size {width, height} = size;
+                let half_width = width.get() as f32 / pixels_per_unit / 2.0;
+                let half_height = height.get() as f32 / pixels_per_unit / 2.0;
+                // OpenGL clip planes are -1 to 1, thus we use the _no method
+                let projection = handedness.orthographic_no(FrustumPlanes {
+                    left: -half_width,
+                    right: half_width,
+                    bottom: -half_height,
+                    top: half_height,
+                    // Place the origin in the middle of the clipping planes
+                    near: 0.0,
+                    far: distance * 2.0,
+                });
+
+                Camera {view, projection, handedness}
+            },
+
+            PixelSnapped {pixels_per_unit} => {
+                let &Camera {mut view, mut projection, handedness} = camera;
+                // An orthographic projection has no perspective divide, so w is always 1.0
+                let is_orthographic = projection[(3, 0)] == 0.0 && projection[(3, 1)] == 0.0
+                    && projection[(3, 2)] == 0.0;
+                if !is_orthographic {
+                    return camera.clone();
+                }
+
+                let pixels_per_unit = pixels_per_unit.get() as f32;
+                let Size {width, height} = size;
+                let (width, height) = (width.get() as f32, height.get() as f32);
+
+                // Scale so that the visible area is exactly the size of the frame, in units
+                projection[(0, 0)] = 2.0 * pixels_per_unit / width;
+                projection[(1, 1)] = 2.0 * pixels_per_unit / height;
+                projection[(0, 3)] = 0.0;
+                projection[(1, 3)] = 0.0;
+
+                // Move the origin to the closest pixel boundary. The center of the frame is only
+                // on a pixel boundary if the frame has an even size, so the distance is measured
+                // from the edge of the frame.
+                let snap = |offset: f32, frame_size: f32| {
+                    let pixel = (frame_size / 2.0 + offset * pixels_per_unit).round();
+                    (pixel - frame_size / 2.0) / pixels_per_unit
+                };
+                view[(0, 3)] = snap(view[(0, 3)], width);
+                view[(1, 3)] = snap(view[(1, 3)], height);
+
+                Camera {view, projection, handedness}
+            },
+
+            Fit {margin} => match bounds {
+                Some(bounds) => {
+                    let Size {width, height} = size;
+                    camera.auto_frame(bounds, width.get() as f32 / height.get() as f32, margin)
+                },
+                None => camera.clone(),
+            },
+        }
+    }
+}
+
+/// The way that the view of a camera is fitted to an image with a different aspect ratio
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AspectMode {
+    /// The view is stretched to fill the entire image
+    #[default]
+    Stretch,
+    /// The view keeps the aspect ratio of the camera and is centered in the image. The rest of
+    /// the image is filled with bars of the background (letterboxing or pillarboxing).
+    Letterbox,
+}
+
+/// Returns the offset and size of the largest area with the given aspect ratio that is centered
+/// in an image of the given size. Each dimension of the area is always at least one pixel.
+pub(in super) fn letterbox(size: Size, aspect_ratio: f32) -> ((u32, u32), Size) {
+    assert!(aspect_ratio > 0.0, "the aspect ratio must be greater than zero");
+
+    let Size {width, height} = size;
+    let (width, height) = (width.get(), height.get());
+    let (area_width, area_height) = if width as f32 / height as f32 > aspect_ratio {
+        // Bars on the left and right (pillarboxing)
+        ((height as f32 * aspect_ratio).round() as u32, height)
+    } else {
+        // Bars on the top and bottom (letterboxing)
+        (width, (width as f32 / aspect_ratio).round() as u32)
+    };
+    let area_width = area_width.clamp(1, width);
+    let area_height = area_height.clamp(1, height);
+
+    let offset = ((width - area_width) / 2, (height - area_height) / 2);
+    // Safe because both dimensions were clamped to at least one
+    let area = unsafe { Size {
+        width: NonZeroU32::new_unchecked(area_width),
+        height: NonZeroU32::new_unchecked(area_height),
+    } };
+    (offset, area)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use approx::{assert_relative_eq, relative_eq};
+
+    use crate::math::{Vec3, Vec4, Radians};
+    use crate::scene::CameraType;
+
+    #[test]
+    fn origin_centered_keeps_origin_fixed() {
+        let size = Size {
+            width: NonZeroU32::new(64).unwrap(),
+            height: NonZeroU32::new(32).unwrap(),
+        };
+        let framing = Framing::OriginCentered {pixels_per_unit: 8.0};
+
+        // Two cameras with the same orientation that were aimed at different parts of the model
+        for &target in &[Vec3::new(0.5, 1.0, 0.0), Vec3::new(-2.0, 0.3, 0.0)] {
+            let eye = target + Vec3::new(0.0, 0.0, 10.0);
+            let camera = Camera {
+                view: Mat4::look_at_rh(eye, target, Vec3::up()),
+                projection: Mat4::identity(),
+                handedness: Handedness::Right,
+            };
+            let Camera {view, projection, ..} = framing.apply(&camera, size, None);
+            let mvp = projection * view;
+
+            // The origin is always at the center of the frame
+            let origin = mvp * Vec4::new(0.0, 0.0, 0.0, 1.0);
+            assert_relative_eq!(origin.x, 0.0, epsilon = 1e-6);
+            assert_relative_eq!(origin.y, 0.0, epsilon = 1e-6);
+
+            // One unit to the right is exactly pixels_per_unit pixels from the center
+            let right = mvp * Vec4::new(1.0, 0.0, 0.0, 1.0);
+            let pixels = right.x * size.width.get() as f32 / 2.0;
+            assert_relative_eq!(pixels, 8.0, epsilon = 1e-4);
+        }
+    }
+
+    #[test]
+    fn auto_frame_fits_bounds() {
+        let bounds = (Vec3::new(2.0, -1.0, 0.0), Vec3::new(6.0, 3.0, 1.0));
+        let margin = 0.1;
+        let eye = Vec3::new(10.0, 10.0, 10.0);
+
+        let orthographic = Camera {
+            view: Mat4::look_at_rh(eye, Vec3::zero(), Vec3::up()),
+            projection: Handedness::Right.orthographic_no(FrustumPlanes {
+                left: -1.0,
+                right: 1.0,
+                bottom: -1.0,
+                top: 1.0,
+                near: 0.1,
+                far: 2.0,
+            }),
+            handedness: Handedness::Right,
+        };
+        let perspective = Camera {
+            projection: Handedness::Right.perspective_no(40.0f32.to_radians(), 1.0, 0.1, 2.0),
+            ..orthographic.clone()
+        };
+
+        for camera in &[orthographic, perspective] {
+            let Camera {view, projection, ..} = camera.auto_frame(bounds, 2.0, margin);
+            let to_ndc = |p: Vec3| {
+                let clip = projection * view * Vec4::from_point(p);
+                Vec3::from(clip) / clip.w
+            };
+
+            let (min, max) = bounds;
+            let mut largest = 0.0f32;
+            for i in 0..8 {
+                let corner = Vec3::new(
+                    if i & 1 == 0 { min.x } else { max.x },
+                    if i & 2 == 0 { min.y } else { max.y },
+                    if i & 4 == 0 { min.z } else { max.z },
+                );
+                let ndc = to_ndc(corner);
+                assert!(ndc.x.abs() < 1.0 && ndc.y.abs() < 1.0 && ndc.z.abs() < 1.0,
+                    "corner {:?} was outside of the view at {:?}", corner, ndc);
+                largest = largest.max(ndc.x.abs()).max(ndc.y.abs());
+            }
+            // The bounds are not much smaller than the view
+            assert!(largest > 0.5, "bounds only filled {} of the view", largest);
+
+            // The center of the bounds is in the center of the view
+            let center = to_ndc((min + max) / 2.0);
+            assert_relative_eq!(center.x, 0.0, epsilon = 1e-4);
+            assert_relative_eq!(center.y, 0.0, epsilon = 1e-4);
+        }
+    }
+
+    #[test]
+    fn left_handed_camera_is_not_mirrored() {
+        let cam_type = CameraType::Perspective {
+            name: None,
+            aspect_ratio: 1.0,
+            field_of_view_y: Radians::from_degrees(40.0),
+            near_z: 0.1,
+            far_z: Some(100.0),
+        };
+        // An "L" shape: a point up and a point to the right of the corner, in world space
+        let corner = Vec3::new(0.0, 0.0, 0.0);
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let right = Vec3::new(1.0, 0.0, 0.0);
+
+        // The same shot described in both coordinate systems. The z-axis points the other way in
+        // a left-handed coordinate system, so the camera position is mirrored.
+        for &(handedness, eye) in &[
+            (Handedness::Right, Vec3::new(0.0, 0.0, 10.0)),
+            (Handedness::Left, Vec3::new(0.0, 0.0, -10.0)),
+        ] {
+            let mvp = cam_type.to_projection(handedness)
+                * handedness.look_at(eye, Vec3::zero(), Vec3::up());
+            let to_ndc = |p: Vec3| {
+                let clip = mvp * Vec4::from_point(p);
+                Vec3::from(clip) / clip.w
+            };
+
+            let corner = to_ndc(corner);
+            // Same orientation in the rendered image regardless of handedness
+            assert!(to_ndc(right).x > corner.x, "{:?} camera mirrored the x-axis", handedness);
+            assert!(to_ndc(up).y > corner.y, "{:?} camera mirrored the y-axis", handedness);
+            // The point is within the clipping planes
+            assert!(corner.z > -1.0 && corner.z < 1.0);
+        }
+    }
+
+    #[test]
+    fn isometric_ground_aspect_ratio() {
+        for &(dimetric, expected_ratio) in &[(false, 3.0f32.sqrt()), (true, 2.0)] {
+            let Camera {view, projection, ..} = Camera::isometric(dimetric, 10.0);
+            let to_ndc = |p: Vec3| Vec3::from(projection * view * Vec4::from_point(p));
+
+            // A unit square on the ground is drawn as a diamond
+            let left = to_ndc(Vec3::new(0.0, 0.0, 1.0));
+            let right = to_ndc(Vec3::new(1.0, 0.0, 0.0));
+            let top = to_ndc(Vec3::new(0.0, 0.0, 0.0));
+            let bottom = to_ndc(Vec3::new(1.0, 0.0, 1.0));
+            let ratio = (right.x - left.x) / (top.y - bottom.y);
+            assert_relative_eq!(ratio, expected_ratio, epsilon = 1e-4);
+
+            // Vertical lines stay vertical
+            assert_relative_eq!(to_ndc(Vec3::up()).x, to_ndc(Vec3::zero()).x, epsilon = 1e-6);
+        }
+    }
+
+    #[test]
+    fn pixel_snapped_origin_on_pixel_boundary() {
+        // An odd width puts the center of the frame in the middle of a pixel
+        let size = Size {
+            width: NonZeroU32::new(33).unwrap(),
+            height: NonZeroU32::new(32).unwrap(),
+        };
+        let framing = Framing::PixelSnapped {pixels_per_unit: NonZeroU32::new(16).unwrap()};
+
+        for &offset in &[0.0, 0.01, 0.37, -1.23] {
+            let eye = Vec3::new(offset, offset * 2.0, 10.0);
+            let camera = Camera {
+                view: Mat4::look_at_rh(eye, eye - Vec3::unit_z(), Vec3::up()),
+                projection: Handedness::Right.orthographic_no(FrustumPlanes {
+                    left: -1.0,
+                    right: 1.0,
+                    bottom: -1.0,
+                    top: 1.0,
+                    near: 0.1,
+                    far: 100.0,
+                }),
+                handedness: Handedness::Right,
+            };
+            let Camera {view, projection, ..} = framing.apply(&camera, size, None);
+            let mvp = projection * view;
+            // The position of a world space point in pixels from the bottom-left of the frame
+            let to_pixels = |point: Vec3| {
+                let ndc = mvp * Vec4::from_point(point);
+                (
+                    (ndc.x + 1.0) / 2.0 * size.width.get() as f32,
+                    (ndc.y + 1.0) / 2.0 * size.height.get() as f32,
+                )
+            };
+
+            let (x, y) = to_pixels(Vec3::zero());
+            assert_relative_eq!(x, x.round(), epsilon = 1e-3);
+            assert_relative_eq!(y, y.round(), epsilon = 1e-3);
+            // The camera moved by less than a pixel
+            assert!((x - (16.5 - offset * 16.0)).abs() <= 0.5 + 1e-3);
+
+            // One world unit is exactly pixels_per_unit pixels
+            let (right, up) = to_pixels(Vec3::new(1.0, 1.0, 0.0));
+            assert_relative_eq!(right - x, 16.0, epsilon = 1e-3);
+            assert_relative_eq!(up - y, 16.0, epsilon = 1e-3);
+        }
+
+        // Perspective cameras cannot be snapped, so they are left as they are
+        let camera = Camera {
+            view: Mat4::look_at_rh(Vec3::new(0.3, 0.0, 10.0), Vec3::zero(), Vec3::up()),
+            projection: Handedness::Right.perspective_no(40.0f32.to_radians(), 1.0, 0.1, 100.0),
+            handedness: Handedness::Right,
+        };
+        let snapped = framing.apply(&camera, size, None);
+        assert_eq!(snapped.view, camera.view);
+        assert_eq!(snapped.projection, camera.projection);
+    }
+
+    #[test]
+    fn letterbox_centers_camera_view() {
+        let size = |width, height| Size {
+            width: NonZeroU32::new(width).unwrap(),
+            height: NonZeroU32::new(height).unwrap(),
+        };
+        let camera = Camera {
+            view: Mat4::identity(),
+            projection: Mat4::orthographic_rh_no(FrustumPlanes {
+                left: -2.0,
+                right: 2.0,
+                bottom: -1.0,
+                top: 1.0,
+                near: 0.1,
+                far: 10.0,
+            }),
+            handedness: Handedness::Right,
+        };
+        assert_relative_eq!(camera.aspect_ratio(), 2.0);
+
+        // A square image gets bars on the top and bottom
+        assert_eq!(letterbox(size(64, 64), camera.aspect_ratio()), ((0, 16), size(64, 32)));
+        // A wide image gets bars on the left and right
+        assert_eq!(letterbox(size(100, 20), camera.aspect_ratio()), ((30, 0), size(40, 20)));
+        // A matching image is unchanged
+        assert_eq!(letterbox(size(50, 25), camera.aspect_ratio()), ((0, 0), size(50, 25)));
+    }
 }
@@ -0,0 +1,183 @@
+use std::num::NonZeroU32;
+use std::path::Path;
+
+use image::{DynamicImage, ImageBuffer, ImageFormat, ImageResult, Luma};
+
+use crate::math::{Mat4, Vec4};
+
+use super::edges::NormalDepthBuffer;
+
+/// An image with a single depth value for each pixel. Depths range from 0.0 at the near plane of
+/// the camera to 1.0 at the far plane. Pixels where nothing was drawn have a depth of 1.0.
+pub type DepthImage = ImageBuffer<Luma<f32>, Vec<f32>>;
+
+/// The way that the depth of each pixel is stored in a depth image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DepthMode {
+    /// The depth as stored in the depth buffer, after the projection of the camera has been
+    /// applied. Perspective cameras use more of the range for depths close to the camera.
+    Normalized,
+    /// The distance in front of the camera along its view direction, scaled so that the near
+    /// plane is 0.0 and the far plane is 1.0
+    ///
+    /// Cameras with an infinite far plane use the farthest depth that was drawn as the far plane
+    /// instead, so the depths of the geometry still cover the full range.
+    Linear,
+}
+
+/// Converts the depths in the given buffer into a depth image using the projection matrix of the
+/// camera that they were rendered with
+pub(in super) fn to_depth_image(buffer: &NormalDepthBuffer, projection: Mat4, mode: DepthMode) -> DepthImage {
+    // The depth in front of the camera of the points on the near and far planes
+    let unproject = |ndc_z: f32| {
+        let point = projection.inverted() * Vec4::new(0.0, 0.0, ndc_z, 1.0);
+        -point.z / point.w
+    };
+    let near = unproject(-1.0);
+    let far = unproject(1.0);
+    // Every depth would be 0.0 if the far plane is infinitely far away
+    let far = if far.is_finite() {
+        far
+    } else {
+        (0..buffer.height).flat_map(|y| (0..buffer.width).map(move |x| (x, y)))
+            .filter_map(|(x, y)| buffer.get(x, y))
+            .fold(near, |far, (_, depth)| far.max(depth))
+    };
+
+    DepthImage::from_fn(buffer.width, buffer.height, |x, y| {
+        let depth = match buffer.get(x, y) {
+            Some((_, depth)) => match mode {
+                DepthMode::Normalized => {
+                    // The camera looks down the negative z-axis of its own coordinate system
+                    let clip = projection * Vec4::new(0.0, 0.0, -depth, 1.0);
+                    clip.z / clip.w * 0.5 + 0.5
+                },
+                // Everything drawn is on the near plane if there is no range of depths at all
+                DepthMode::Linear if far <= near => 0.0,
+                DepthMode::Linear => (depth - near) / (far - near),
+            },
+            None => 1.0,
+        };
+
+        Luma([depth.clamp(0.0, 1.0)])
+    })
+}
+
+/// Shrinks the given depth image by the given (x, y) factors, keeping the nearest depth in each
+/// block of pixels so that edges stay sharp
+pub(in super) fn downscale_depth(depth: &DepthImage, factor: (NonZeroU32, NonZeroU32)) -> DepthImage {
+    let (factor_x, factor_y) = (factor.0.get(), factor.1.get());
+    let (width, height) = depth.dimensions();
+
+    DepthImage::from_fn(width / factor_x, height / factor_y, |x, y| {
+        let nearest = (0..factor_y).flat_map(|oy| (0..factor_x).map(move |ox| (ox, oy)))
+            .map(|(ox, oy)| depth.get_pixel(x * factor_x + ox, y * factor_y + oy)[0])
+            .fold(1.0, f32::min);
+        Luma([nearest])
+    })
+}
+
+/// Scales the given depth image up, with no interpolation of any kind
+pub(in super) fn scale_depth(depth: &DepthImage, scale: NonZeroU32) -> DepthImage {
+    let scale = scale.get();
+    let (width, height) = depth.dimensions();
+
+    DepthImage::from_fn(width * scale, height * scale, |x, y| *depth.get_pixel(x / scale, y / scale))
+}
+
+/// Converts a depth image into a 16-bit grayscale image, with the nearest depths in black
+pub fn depth_to_gray16(depth: &DepthImage) -> DynamicImage {
+    let (width, height) = depth.dimensions();
+    let image = ImageBuffer::from_fn(width, height, |x, y| {
+        Luma([(depth.get_pixel(x, y)[0] * u16::MAX as f32).round() as u16])
+    });
+    DynamicImage::ImageLuma16(image)
+}
+
+/// Writes a depth image to the given path as a 16-bit grayscale PNG file, regardless of the
+/// extension of the path
+pub fn write_depth_png(depth: &DepthImage, path: &Path) -> ImageResult<()> {
+    depth_to_gray16(depth).save_with_format(path, ImageFormat::Png)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use approx::{assert_relative_eq, relative_eq};
+
+    use crate::math::FrustumPlanes;
+
+    /// A 3x1 buffer with depths of 2.0 and 6.0 followed by a pixel where nothing was drawn
+    fn buffer() -> NormalDepthBuffer {
+        NormalDepthBuffer {
+            width: 3,
+            height: 1,
+            pixels: vec![[0.0, 0.0, 1.0, 2.0], [0.0, 0.0, 1.0, 6.0], [0.0; 4]],
+        }
+    }
+
+    #[test]
+    fn linear_depth_between_near_and_far() {
+        let projection = Mat4::perspective_rh_no(std::f32::consts::FRAC_PI_2, 1.0, 1.0, 11.0);
+        let depth = to_depth_image(&buffer(), projection, DepthMode::Linear);
+        assert_relative_eq!(depth.get_pixel(0, 0)[0], 0.1, epsilon = 1e-5);
+        assert_relative_eq!(depth.get_pixel(1, 0)[0], 0.5, epsilon = 1e-5);
+        assert_eq!(depth.get_pixel(2, 0)[0], 1.0);
+
+        // Normalized depths grow faster near the camera with a perspective projection
+        let normalized = to_depth_image(&buffer(), projection, DepthMode::Normalized);
+        assert!(normalized.get_pixel(0, 0)[0] > 0.1);
+        assert!(normalized.get_pixel(0, 0)[0] < normalized.get_pixel(1, 0)[0]);
+    }
+
+    #[test]
+    fn linear_depth_with_infinite_far_plane() {
+        use crate::math::{Handedness, Radians};
+        use crate::scene::CameraType;
+
+        let camera = CameraType::Perspective {
+            name: None,
+            aspect_ratio: 1.0,
+            field_of_view_y: Radians::from_degrees(90.0),
+            near_z: 1.0,
+            far_z: None,
+        };
+        let projection = camera.to_projection(Handedness::Right);
+
+        // The farthest depth that was drawn is used as the far plane
+        let depth = to_depth_image(&buffer(), projection, DepthMode::Linear);
+        assert_relative_eq!(depth.get_pixel(0, 0)[0], 0.2, epsilon = 1e-5);
+        assert_relative_eq!(depth.get_pixel(1, 0)[0], 1.0, epsilon = 1e-5);
+        assert_eq!(depth.get_pixel(2, 0)[0], 1.0);
+    }
+
+    #[test]
+    fn orthographic_depth_modes_match() {
+        let projection = Mat4::orthographic_rh_no(FrustumPlanes {
+            left: -1.0,
+            right: 1.0,
+            bottom: -1.0,
+            top: 1.0,
+            near: 1.0,
+            far: 11.0,
+        });
+        let linear = to_depth_image(&buffer(), projection, DepthMode::Linear);
+        let normalized = to_depth_image(&buffer(), projection, DepthMode::Normalized);
+        for (linear, normalized) in linear.pixels().zip(normalized.pixels()) {
+            assert_relative_eq!(linear[0], normalized[0], epsilon = 1e-5);
+        }
+    }
+
+    #[test]
+    fn downscale_keeps_nearest_depth() {
+        let depth = DepthImage::from_raw(2, 2, vec![1.0, 0.25, 0.5, 1.0]).unwrap();
+        let two = NonZeroU32::new(2).unwrap();
+        let downscaled = downscale_depth(&depth, (two, two));
+        assert_eq!(downscaled.into_raw(), vec![0.25]);
+
+        // Each column is its own block when only the rows are combined
+        let downscaled = downscale_depth(&depth, (NonZeroU32::new(1).unwrap(), two));
+        assert_eq!(downscaled.into_raw(), vec![0.5, 0.25]);
+    }
+}
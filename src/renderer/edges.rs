@@ -0,0 +1,193 @@
+use image::RgbaImage;
+
+use crate::math::{Rgba, Vec3};
+
+use super::imageops::{composite_over, to_pixel};
+
+/// The surface normal and depth of every pixel in a rendered image. The depth is the distance in
+/// front of the camera, measured along its view direction.
+///
+/// A normal of zero length means that nothing was drawn at that pixel.
+#[derive(Debug, Clone)]
+pub struct NormalDepthBuffer {
+    pub width: u32,
+    pub height: u32,
+    /// The normal (xyz) and depth (w) of each pixel, one row at a time starting from the top
+    pub pixels: Vec<[f32; 4]>,
+}
+
+impl NormalDepthBuffer {
+    /// Returns the normal and depth of the pixel at the given position, or None if nothing was
+    /// drawn there
+    pub fn get(&self, x: u32, y: u32) -> Option<(Vec3, f32)> {
+        let [nx, ny, nz, depth] = self.pixels[(y * self.width + x) as usize];
+        let normal = Vec3::new(nx, ny, nz);
+        // Drawn normals are always normalized
+        if normal.magnitude_squared() > 0.5 {
+            Some((normal, depth))
+        } else {
+            None
+        }
+    }
+}
+
+/// The thresholds used to decide if two neighboring pixels are separated by an edge
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EdgeThresholds {
+    /// The largest difference in depth, as a fraction of the nearer depth
+    pub depth: f32,
+    /// The largest angle between the normals, in radians
+    pub normal: f32,
+}
+
+impl Default for EdgeThresholds {
+    /// Outlines the silhouette, parts that overlap by at least a tenth of their depth, and creases
+    /// sharper than 45°
+    fn default() -> Self {
+        Self {
+            depth: 0.1,
+            normal: 45.0f32.to_radians(),
+        }
+    }
+}
+
+/// Finds the pixels of the given buffer that lie on an edge. The result has one value for each
+/// pixel in the buffer, in the same order.
+///
+/// A pixel is on an edge if it is nearer to the camera than one of its neighbors and either
+/// nothing was drawn at that neighbor (a silhouette), the depth changes too quickly (an
+/// overlap), or the normals point in sufficiently different directions (a crease). Only the nearer
+/// pixel is marked so that each edge is a single pixel wide.
+pub fn detect_edges(buffer: &NormalDepthBuffer, thresholds: EdgeThresholds) -> Vec<bool> {
+    let &NormalDepthBuffer {width, height, ..} = buffer;
+    let min_cos = thresholds.normal.cos();
+
+    let mut edges = Vec::with_capacity(buffer.pixels.len());
+    for y in 0..height {
+        for x in 0..width {
+            let (normal, depth) = match buffer.get(x, y) {
+                Some(pixel) => pixel,
+                None => {
+                    edges.push(false);
+                    continue;
+                },
+            };
+
+            let neighbors = [
+                (x.checked_sub(1), Some(y)),
+                (Some(x + 1).filter(|&x| x < width), Some(y)),
+                (Some(x), y.checked_sub(1)),
+                (Some(x), Some(y + 1).filter(|&y| y < height)),
+            ];
+            let is_edge = neighbors.iter().any(|&neighbor| match neighbor {
+                (Some(nx), Some(ny)) => match buffer.get(nx, ny) {
+                    Some((other_normal, other_depth)) => depth <= other_depth && (
+                        other_depth - depth > thresholds.depth * depth.abs()
+                        || normal.dot(other_normal) < min_cos
+                    ),
+                    None => true,
+                },
+                // Geometry cut off by the edge of the image is not outlined
+                _ => false,
+            });
+            edges.push(is_edge);
+        }
+    }
+
+    edges
+}
+
+/// Draws the given edges over the image in the given color. Each edge pixel is expanded into a
+/// rectangle that is `thickness` pixels wide and tall, given as (x, y).
+pub fn draw_edges(image: &mut RgbaImage, edges: &[bool], thickness: (u32, u32), color: Rgba) {
+    let (width, height) = image.dimensions();
+    assert_eq!(edges.len(), (width * height) as usize, "bug: edges must match the image size");
+
+    // Even thicknesses extend one pixel further to the right and bottom
+    let before = |thickness: u32| (thickness.saturating_sub(1) / 2) as i64;
+    let after = |thickness: u32| (thickness / 2) as i64;
+    let (before_x, after_x) = (before(thickness.0), after(thickness.0));
+    let (before_y, after_y) = (before(thickness.1), after(thickness.1));
+
+    let pixel = to_pixel(color);
+    let mut outline = RgbaImage::new(width, height);
+    for (index, _) in edges.iter().enumerate().filter(|&(_, &edge)| edge) {
+        let x = (index as u32 % width) as i64;
+        let y = (index as u32 / width) as i64;
+        for oy in (y - before_y).max(0)..=(y + after_y).min(height as i64 - 1) {
+            for ox in (x - before_x).max(0)..=(x + after_x).min(width as i64 - 1) {
+                outline.put_pixel(ox as u32, oy as u32, pixel);
+            }
+        }
+    }
+
+    composite_over(&outline, image);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const THRESHOLDS: EdgeThresholds = EdgeThresholds {depth: 0.1, normal: 0.5};
+
+    fn normal_depth_buffer(width: u32, height: u32, pixel: impl Fn(u32, u32) -> [f32; 4]) -> NormalDepthBuffer {
+        let pixels = (0..height).flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| pixel(x, y))
+            .collect();
+        NormalDepthBuffer {width, height, pixels}
+    }
+
+    #[test]
+    fn edges_on_silhouette_and_crease() {
+        // A 3x3 square of geometry surrounded by background, with a crease between its first and
+        // second columns
+        let buffer = normal_depth_buffer(5, 5, |x, y| match (x, y) {
+            (1, 1..=3) => [1.0, 0.0, 0.0, 2.0],
+            (2..=3, 1..=3) => [0.0, 0.0, 1.0, 2.0],
+            _ => [0.0, 0.0, 0.0, 0.0],
+        });
+        let edges = detect_edges(&buffer, THRESHOLDS);
+
+        let marked: Vec<String> = edges.chunks(5)
+            .map(|row| row.iter().map(|&edge| if edge { '#' } else { '.' }).collect())
+            .collect();
+        assert_eq!(marked, vec![
+            ".....",
+            ".###.",
+            ".###.",
+            ".###.",
+            ".....",
+        ]);
+
+        // Without the crease, the middle of the square is not an edge
+        let flat = normal_depth_buffer(5, 5, |x, y| match (x, y) {
+            (1..=3, 1..=3) => [0.0, 0.0, 1.0, 2.0],
+            _ => [0.0, 0.0, 0.0, 0.0],
+        });
+        assert!(!detect_edges(&flat, THRESHOLDS)[2 * 5 + 2]);
+    }
+
+    #[test]
+    fn only_nearer_pixel_is_marked_at_overlap() {
+        let buffer = normal_depth_buffer(4, 1, |x, _| if x < 2 {
+            [0.0, 0.0, 1.0, 1.0]
+        } else {
+            [0.0, 0.0, 1.0, 5.0]
+        });
+        let edges = detect_edges(&buffer, THRESHOLDS);
+        assert_eq!(edges, vec![false, true, false, false]);
+    }
+
+    #[test]
+    fn edges_are_drawn_with_thickness() {
+        let mut edges = vec![false; 25];
+        edges[2 * 5 + 2] = true;
+
+        let mut image = RgbaImage::new(5, 5);
+        draw_edges(&mut image, &edges, (3, 3), Rgba::black());
+        let drawn = image.pixels().filter(|pixel| pixel[3] == 255).count();
+        assert_eq!(drawn, 9);
+        assert_eq!(image.get_pixel(1, 1)[3], 255);
+        assert_eq!(image.get_pixel(0, 0)[3], 0);
+    }
+}
@@ -0,0 +1,185 @@
+use std::io::{self, Write};
+use std::fs;
+use std::path::Path;
+use std::num::NonZeroU32;
+use std::convert::TryFrom;
+use std::collections::HashMap;
+
+use gif::SetParameter;
+use thiserror::Error;
+use image::{DynamicImage, RgbaImage};
+
+/// The palette index reserved for transparent pixels. The rest of the palette is shared by
+/// every frame.
+const TRANSPARENT_INDEX: u8 = 255;
+
+/// Pixels with an alpha value below this are written as transparent. GIF images cannot store
+/// partially transparent pixels.
+const ALPHA_THRESHOLD: u8 = 128;
+
+#[derive(Debug, Error)]
+pub enum GifError {
+    #[error(transparent)]
+    IoError(#[from] io::Error),
+    #[error("Cannot create a GIF image with no frames")]
+    NoFrames,
+    #[error("The size of a GIF image must be at most 65535x65535 pixels (got {width}x{height})")]
+    TooLarge {width: u32, height: u32},
+    #[error("Every frame of a GIF image must be the same size (expected {expected:?}, got {actual:?})")]
+    FrameSizeMismatch {expected: (u32, u32), actual: (u32, u32)},
+}
+
+/// Encodes the given frames as a looping animated GIF that plays at the given frame rate
+///
+/// Every frame must be the same size. All of the frames share a single global palette. If the
+/// frames use too many colors to fit in that palette, their colors are approximated. Pixels that
+/// are mostly transparent (e.g. a background with zero alpha) are written as fully transparent.
+pub fn encode_gif<W: Write>(frames: &[DynamicImage], fps: NonZeroU32, writer: W) -> Result<(), GifError> {
+    let frames: Vec<RgbaImage> = frames.iter().map(|frame| frame.to_rgba()).collect();
+    let (width, height) = frames.first().ok_or(GifError::NoFrames)?.dimensions();
+    if let Some(frame) = frames.iter().find(|frame| frame.dimensions() != (width, height)) {
+        return Err(GifError::FrameSizeMismatch {
+            expected: (width, height),
+            actual: frame.dimensions(),
+        });
+    }
+    let (gif_width, gif_height) = match (u16::try_from(width), u16::try_from(height)) {
+        (Ok(gif_width), Ok(gif_height)) => (gif_width, gif_height),
+        _ => return Err(GifError::TooLarge {width, height}),
+    };
+
+    let palette = Palette::new(&frames);
+    let mut encoder = gif::Encoder::new(writer, gif_width, gif_height, &palette.color_map())?;
+    encoder.set(gif::Repeat::Infinite)?;
+
+    // The delay between frames is in units of 10 ms
+    let delay = (100.0 / fps.get() as f32).round().max(1.0) as u16;
+    for frame in &frames {
+        let pixels: Vec<u8> = frame.pixels().map(|pixel| {
+            let [r, g, b, a] = pixel.0;
+            if a < ALPHA_THRESHOLD {
+                TRANSPARENT_INDEX
+            } else {
+                palette.index_of([r, g, b])
+            }
+        }).collect();
+
+        let mut gif_frame = gif::Frame::from_indexed_pixels(gif_width, gif_height, &pixels,
+            Some(TRANSPARENT_INDEX));
+        gif_frame.delay = delay;
+        // Each frame replaces the previous one entirely, including its transparent areas
+        gif_frame.dispose = gif::DisposalMethod::Background;
+        encoder.write_frame(&gif_frame)?;
+    }
+
+    Ok(())
+}
+
+/// Writes the given frames to the given path as a looping animated GIF, regardless of the
+/// extension of the path
+pub fn write_gif(frames: &[DynamicImage], fps: NonZeroU32, path: &Path) -> Result<(), GifError> {
+    let file = io::BufWriter::new(fs::File::create(path)?);
+    encode_gif(frames, fps, file)
+}
+
+/// The colors shared by every frame of a GIF image
+enum Palette {
+    /// Every visible color fits in the palette, so no colors need to be changed
+    Exact {
+        colors: Vec<[u8; 3]>,
+        indexes: HashMap<[u8; 3], u8>,
+    },
+    /// The visible colors are reduced to the closest colors that fit in the palette
+    Quantized(color_quant::NeuQuant),
+}
+
+impl Palette {
+    /// Computes a palette for the visible pixels of every frame, leaving one palette entry free
+    /// for transparency
+    fn new(frames: &[RgbaImage]) -> Self {
+        let visible_colors = || frames.iter()
+            .flat_map(|frame| frame.pixels())
+            .filter(|pixel| pixel.0[3] >= ALPHA_THRESHOLD)
+            .map(|pixel| [pixel.0[0], pixel.0[1], pixel.0[2]]);
+
+        let mut colors = Vec::new();
+        let mut indexes = HashMap::new();
+        for color in visible_colors() {
+            if indexes.contains_key(&color) {
+                continue;
+            }
+            if colors.len() == TRANSPARENT_INDEX as usize {
+                let pixels: Vec<u8> = visible_colors().flat_map(|[r, g, b]| vec![r, g, b, 255]).collect();
+                // A sample factor of 10 is the recommended trade-off between speed and quality
+                return Palette::Quantized(color_quant::NeuQuant::new(10, TRANSPARENT_INDEX as usize, &pixels));
+            }
+            indexes.insert(color, colors.len() as u8);
+            colors.push(color);
+        }
+
+        Palette::Exact {colors, indexes}
+    }
+
+    /// The RGB values of each color in the palette, padded so that every index is valid
+    fn color_map(&self) -> Vec<u8> {
+        let mut color_map = match self {
+            Palette::Exact {colors, ..} => colors.iter().flat_map(|color| color.to_vec()).collect(),
+            Palette::Quantized(quantizer) => quantizer.color_map_rgb(),
+        };
+        color_map.resize(256 * 3, 0);
+        color_map
+    }
+
+    /// Returns the index of the palette color used for the given visible color
+    fn index_of(&self, [r, g, b]: [u8; 3]) -> u8 {
+        match self {
+            Palette::Exact {indexes, ..} => indexes[&[r, g, b]],
+            Palette::Quantized(quantizer) => quantizer.index_of(&[r, g, b, 255]) as u8,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use image::{AnimationDecoder, gif::GifDecoder};
+
+    #[test]
+    fn gif_frames_round_trip() {
+        let frames: Vec<_> = [[255, 0, 0, 255], [0, 0, 255, 255]].iter().map(|&color| {
+            // A transparent background with a single opaque pixel
+            let mut image = RgbaImage::new(4, 3);
+            image.put_pixel(1, 1, image::Rgba(color));
+            DynamicImage::ImageRgba8(image)
+        }).collect();
+
+        let mut bytes = Vec::new();
+        encode_gif(&frames, NonZeroU32::new(10).unwrap(), &mut bytes).unwrap();
+        assert_eq!(&bytes[..6], b"GIF89a");
+
+        let decoded = GifDecoder::new(&bytes[..]).unwrap().into_frames().collect_frames().unwrap();
+        assert_eq!(decoded.len(), 2);
+        for (frame, expected) in decoded.iter().zip(&frames) {
+            // 10 fps is 100 ms per frame
+            assert_eq!(frame.delay().numer_denom_ms(), (100, 1));
+
+            let image = frame.buffer();
+            assert_eq!(image.dimensions(), (4, 3));
+            assert_eq!(image.get_pixel(0, 0).0[3], 0);
+            assert_eq!(image.get_pixel(1, 1), expected.to_rgba().get_pixel(1, 1));
+        }
+    }
+
+    #[test]
+    fn gif_frames_must_match() {
+        let frames = vec![
+            DynamicImage::ImageRgba8(RgbaImage::new(4, 3)),
+            DynamicImage::ImageRgba8(RgbaImage::new(3, 4)),
+        ];
+        match encode_gif(&frames, NonZeroU32::new(10).unwrap(), Vec::new()) {
+            Err(GifError::FrameSizeMismatch {expected: (4, 3), actual: (3, 4)}) => {},
+            result => panic!("expected a frame size error, got {:?}", result),
+        }
+    }
+}
@@ -0,0 +1,38 @@
+use crate::math::Rgba;
+
+/// A flat ground plane drawn beneath the geometry of a rendered image
+///
+/// This is much cheaper than `Shadows` and is useful for making sprites look like they are
+/// standing on something. The plane is centered beneath the geometry.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GroundPlane {
+    /// The height of the plane along the y-axis, in world coordinates
+    pub height: f32,
+    /// The color of the plane, or None if only the contact shadow should be drawn
+    pub color: Option<Rgba>,
+    /// The shadow drawn on the plane directly beneath the geometry, or None if no shadow should
+    /// be drawn
+    pub shadow: Option<ContactShadow>,
+}
+
+/// A soft circular shadow on a ground plane
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ContactShadow {
+    /// The radius of the shadow, in world units. This must be greater than zero.
+    pub radius: f32,
+    /// The fraction of the radius over which the edge of the shadow fades out. A value of 0.0
+    /// gives the shadow a hard edge and a value of 1.0 fades it out all the way from its center.
+    pub softness: f32,
+    /// The color of the shadow at its center. The alpha component determines how dark it is.
+    pub color: Rgba,
+}
+
+impl Default for ContactShadow {
+    fn default() -> Self {
+        Self {
+            radius: 0.5,
+            softness: 0.5,
+            color: Rgba::new(0.0, 0.0, 0.0, 0.5),
+        }
+    }
+}
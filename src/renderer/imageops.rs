@@ -1,7 +1,13 @@
 //! Additional image utilities that extend the ones provided by the image crate
 
+use std::num::NonZeroU32;
+
 use image::RgbaImage;
 
+use crate::math::Rgba;
+
+use super::{Size, DownscaleFilter, layout::LayoutOffset};
+
 /// Scales the given source image to fit into the target image.
 ///
 /// The target image dimensions must be a multiple of the source image dimensions. No interpolation
@@ -38,6 +44,62 @@ pub fn scale_to_fit(source: &RgbaImage, target: &mut RgbaImage) {
     }
 }
 
+/// Scales the given source image to fit into the target image, blending the four nearest source
+/// pixels of each target pixel.
+///
+/// The target image dimensions must be a multiple of the source image dimensions. Colors are
+/// blended with premultiplied alpha so that transparent pixels do not darken the edges of the
+/// opaque pixels next to them.
+pub fn scale_bilinear(source: &RgbaImage, target: &mut RgbaImage) {
+    let (source_width, source_height) = source.dimensions();
+    let (target_width, target_height) = target.dimensions();
+
+    let scale_x = target_width / source_width;
+    let scale_y = target_height / source_height;
+    assert_eq!(source_width * scale_x, target_width);
+    assert_eq!(source_height * scale_y, target_height);
+
+    let premultiplied = |x: u32, y: u32| {
+        let image::Rgba([r, g, b, a]) = *source.get_pixel(x, y);
+        let alpha = a as f32 / 255.0;
+        [r as f32 / 255.0 * alpha, g as f32 / 255.0 * alpha, b as f32 / 255.0 * alpha, alpha]
+    };
+    // The source pixels on either side of the given target pixel and the weight of the second one
+    let neighbors = |target: u32, scale: u32, source_len: u32| {
+        // Pixel centers are lined up so that the image does not shift as it is scaled
+        let pos = ((target as f32 + 0.5) / scale as f32 - 0.5).max(0.0);
+        let first = (pos.floor() as u32).min(source_len - 1);
+        let second = (first + 1).min(source_len - 1);
+        (first, second, pos - first as f32)
+    };
+
+    for y in 0..target_height {
+        let (y0, y1, weight_y) = neighbors(y, scale_y, source_height);
+        for x in 0..target_width {
+            let (x0, x1, weight_x) = neighbors(x, scale_x, source_width);
+
+            let mut blended = [0.0; 4];
+            let taps = [
+                (x0, y0, (1.0 - weight_x) * (1.0 - weight_y)),
+                (x1, y0, weight_x * (1.0 - weight_y)),
+                (x0, y1, (1.0 - weight_x) * weight_y),
+                (x1, y1, weight_x * weight_y),
+            ];
+            for &(sx, sy, weight) in &taps {
+                let pixel = premultiplied(sx, sy);
+                for (value, channel) in blended.iter_mut().zip(&pixel) {
+                    *value += channel * weight;
+                }
+            }
+
+            let [r, g, b, a] = blended;
+            let unpremultiply = |value: f32| if a > 0.0 { (value / a).clamp(0.0, 1.0) } else { 0.0 };
+            let color = Rgba {r: unpremultiply(r), g: unpremultiply(g), b: unpremultiply(b), a: a.clamp(0.0, 1.0)};
+            target.put_pixel(x, y, to_pixel(color));
+        }
+    }
+}
+
 /// Copy the entire source buffer into the given target buffer starting at the given offset.
 pub fn copy(source: &RgbaImage, target: &mut RgbaImage, (offset_x, offset_y): (u32, u32)) {
     let source_width = source.width();
@@ -57,3 +119,437 @@ pub fn copy(source: &RgbaImage, target: &mut RgbaImage, (offset_x, offset_y): (u
         }
     }
 }
+
+/// Draws the entire source buffer over the given target buffer starting at the given offset,
+/// blending the two using the alpha of each source pixel
+pub fn copy_over(source: &RgbaImage, target: &mut RgbaImage, (offset_x, offset_y): (u32, u32)) {
+    assert!(offset_x + source.width() <= target.width());
+    assert!(offset_y + source.height() <= target.height());
+
+    for (x, y, src) in source.enumerate_pixels() {
+        let dest = target.get_pixel_mut(x + offset_x, y + offset_y);
+        *dest = blend_over(*src, *dest);
+    }
+}
+
+/// Draws the source image over the target image (which must be the same size), blending the two
+/// using the alpha of each source pixel
+pub fn composite_over(source: &RgbaImage, target: &mut RgbaImage) {
+    assert_eq!(source.dimensions(), target.dimensions(), "images must be the same size");
+
+    for (src, dest) in source.pixels().zip(target.pixels_mut()) {
+        *dest = blend_over(*src, *dest);
+    }
+}
+
+/// The "over" operator for colors that are not premultiplied
+fn blend_over(src: image::Rgba<u8>, dest: image::Rgba<u8>) -> image::Rgba<u8> {
+    let src_alpha = src[3] as f32 / 255.0;
+    let dest_alpha = dest[3] as f32 / 255.0;
+    let alpha = src_alpha + dest_alpha * (1.0 - src_alpha);
+    if alpha == 0.0 {
+        return image::Rgba([0, 0, 0, 0]);
+    }
+
+    let mut blended = [0; 4];
+    for channel in 0..3 {
+        let color = (src[channel] as f32 * src_alpha
+            + dest[channel] as f32 * dest_alpha * (1.0 - src_alpha)) / alpha;
+        blended[channel] = color.round() as u8;
+    }
+    blended[3] = (alpha * 255.0).round() as u8;
+    image::Rgba(blended)
+}
+
+/// Fill an area of the given size in the target buffer with a single color, starting at the given
+/// offset.
+pub fn fill(target: &mut RgbaImage, color: image::Rgba<u8>, (offset_x, offset_y): (u32, u32), size: Size) {
+    let Size {width, height} = size;
+    let width = width.get();
+    let height = height.get();
+
+    assert!(offset_x + width <= target.width());
+    assert!(offset_y + height <= target.height());
+
+    for x in offset_x..offset_x + width {
+        for y in offset_y..offset_y + height {
+            target.put_pixel(x, y, color);
+        }
+    }
+}
+
+/// Extrudes the edge pixels of the area of the given size starting at the given offset outward by
+/// the given number of pixels. Each pixel around the area is set to the color of the closest pixel
+/// on the edge of the area. Pixels that would be outside of the target buffer are skipped.
+pub fn extrude(target: &mut RgbaImage, (offset_x, offset_y): (u32, u32), size: Size, amount: u32) {
+    let Size {width, height} = size;
+    let width = width.get();
+    let height = height.get();
+
+    assert!(offset_x + width <= target.width());
+    assert!(offset_y + height <= target.height());
+
+    let start_x = offset_x.saturating_sub(amount);
+    let start_y = offset_y.saturating_sub(amount);
+    let end_x = (offset_x + width + amount).min(target.width());
+    let end_y = (offset_y + height + amount).min(target.height());
+
+    for x in start_x..end_x {
+        for y in start_y..end_y {
+            let inside_x = x >= offset_x && x < offset_x + width;
+            let inside_y = y >= offset_y && y < offset_y + height;
+            if inside_x && inside_y {
+                continue;
+            }
+
+            // The closest pixel within the area
+            let edge_x = x.max(offset_x).min(offset_x + width - 1);
+            let edge_y = y.max(offset_y).min(offset_y + height - 1);
+            let pixel = *target.get_pixel(edge_x, edge_y);
+            target.put_pixel(x, y, pixel);
+        }
+    }
+}
+
+/// Scales the given image down by the given (x, y) factors using the given filter.
+///
+/// The image dimensions must be a multiple of the factors. Colors are filtered with premultiplied
+/// alpha so that transparent pixels do not darken the edges of the opaque pixels next to them.
+pub fn downscale(source: &RgbaImage, factor: (NonZeroU32, NonZeroU32), filter: DownscaleFilter) -> RgbaImage {
+    let (factor_x, factor_y) = (factor.0.get(), factor.1.get());
+    let (width, height) = source.dimensions();
+    assert_eq!(width % factor_x, 0, "image width must be a multiple of the downscale factor");
+    assert_eq!(height % factor_y, 0, "image height must be a multiple of the downscale factor");
+    let (target_width, target_height) = (width / factor_x, height / factor_y);
+
+    if filter == DownscaleFilter::Dominant {
+        return downscale_dominant(source, (factor_x, factor_y));
+    }
+
+    // Premultiplied RGBA values between 0.0 and 1.0, stored row by row
+    let pixels: Vec<[f32; 4]> = source.pixels().map(|&image::Rgba([r, g, b, a])| {
+        let alpha = a as f32 / 255.0;
+        [r as f32 / 255.0 * alpha, g as f32 / 255.0 * alpha, b as f32 / 255.0 * alpha, alpha]
+    }).collect();
+
+    // The filter is separable, so the image is filtered horizontally and then vertically
+    let weights = filter_weights(filter, factor_x, width);
+    let mut horizontal = vec![[0.0; 4]; (target_width * height) as usize];
+    for y in 0..height {
+        for (x, taps) in weights.iter().enumerate() {
+            let row = (y * width) as usize;
+            horizontal[(y * target_width) as usize + x] = apply_taps(taps, |i| pixels[row + i]);
+        }
+    }
+
+    let weights = filter_weights(filter, factor_y, height);
+    let mut target = RgbaImage::new(target_width, target_height);
+    for x in 0..target_width {
+        for (y, taps) in weights.iter().enumerate() {
+            let [r, g, b, a] = apply_taps(taps, |i| horizontal[i * target_width as usize + x as usize]);
+
+            // Sharp filters can overshoot, so the values are clamped before converting back
+            let a = a.clamp(0.0, 1.0);
+            let unpremultiply = |value: f32| if a > 0.0 { (value / a).clamp(0.0, 1.0) } else { 0.0 };
+            let color = Rgba {r: unpremultiply(r), g: unpremultiply(g), b: unpremultiply(b), a};
+            target.put_pixel(x, y as u32, to_pixel(color));
+        }
+    }
+
+    target
+}
+
+/// Scales the given image down by replacing each block of pixels with its most common color
+///
+/// Every fully transparent pixel counts as the same color, no matter what its color channels are.
+/// Ties go to the more opaque color so that thin outlines are not lost to a transparent background,
+/// and then to the color that appears first in the block.
+fn downscale_dominant(source: &RgbaImage, (factor_x, factor_y): (u32, u32)) -> RgbaImage {
+    let (width, height) = source.dimensions();
+    RgbaImage::from_fn(width / factor_x, height / factor_y, |x, y| {
+        // Each color in the block and the number of times it appears, in the order that the
+        // colors first appear. Blocks are small, so a linear search is fast enough.
+        let mut counts: Vec<(image::Rgba<u8>, u32)> = Vec::new();
+        for j in 0..factor_y {
+            for i in 0..factor_x {
+                let mut pixel = *source.get_pixel(x * factor_x + i, y * factor_y + j);
+                if pixel[3] == 0 {
+                    pixel = image::Rgba([0, 0, 0, 0]);
+                }
+
+                match counts.iter_mut().find(|(color, _)| *color == pixel) {
+                    Some((_, count)) => *count += 1,
+                    None => counts.push((pixel, 1)),
+                }
+            }
+        }
+
+        // max_by_key returns the last maximum, so the colors are searched in reverse to prefer
+        // the color that appears first
+        counts.into_iter().rev()
+            .max_by_key(|&(color, count)| (count, color[3]))
+            .map(|(color, _)| color)
+            .expect("bug: a block always has at least one pixel")
+    })
+}
+
+/// Computes the source pixels and normalized weights that contribute to each target pixel when a
+/// row (or column) of the given length is scaled down by the given factor
+fn filter_weights(filter: DownscaleFilter, factor: u32, len: u32) -> Vec<Vec<(usize, f32)>> {
+    use DownscaleFilter::*;
+    let factor = factor as f32;
+    // The filter extends this many target pixels in each direction from the center
+    let (support, kernel): (f32, fn(f32) -> f32) = match filter {
+        Box => (0.5, |_| 1.0),
+        Lanczos3 => (3.0, |x| sinc(x) * sinc(x / 3.0)),
+        Dominant => unreachable!("bug: the dominant color filter cannot be separated into weights"),
+    };
+
+    (0..(len as f32 / factor) as u32).map(|target| {
+        // The center of the target pixel in source pixel coordinates
+        let center = (target as f32 + 0.5) * factor;
+        let start = (center - support * factor).floor().max(0.0) as u32;
+        let end = ((center + support * factor).ceil() as u32).min(len);
+
+        let mut taps: Vec<_> = (start..end).filter_map(|source| {
+            // Distance from the center, measured in target pixels
+            let distance = (source as f32 + 0.5 - center) / factor;
+            if distance.abs() < support {
+                Some((source as usize, kernel(distance)))
+            } else {
+                None
+            }
+        }).collect();
+
+        let total: f32 = taps.iter().map(|&(_, weight)| weight).sum();
+        for (_, weight) in &mut taps {
+            *weight /= total;
+        }
+        taps
+    }).collect()
+}
+
+fn apply_taps(taps: &[(usize, f32)], pixel: impl Fn(usize) -> [f32; 4]) -> [f32; 4] {
+    taps.iter().fold([0.0; 4], |mut acc, &(index, weight)| {
+        for (acc, value) in acc.iter_mut().zip(pixel(index).iter()) {
+            *acc += value * weight;
+        }
+        acc
+    })
+}
+
+fn sinc(x: f32) -> f32 {
+    if x == 0.0 {
+        1.0
+    } else {
+        let x = x * std::f32::consts::PI;
+        x.sin() / x
+    }
+}
+
+/// Finds the smallest area of the image that contains every pixel that is not fully transparent.
+///
+/// Returns the offset and size of that area, or None if every pixel is fully transparent.
+pub fn trim(image: &RgbaImage) -> Option<(LayoutOffset, Size)> {
+    let visible = image.enumerate_pixels().filter(|(_, _, pixel)| pixel[3] != 0);
+    let (min_x, min_y, max_x, max_y) = visible.fold(None, |bounds, (x, y, _)| match bounds {
+        None => Some((x, y, x, y)),
+        Some((min_x, min_y, max_x, max_y)) => {
+            Some((min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y)))
+        },
+    })?;
+
+    // This code is safe because max >= min, so adding 1 always produces a non-zero value
+    let size = unsafe { Size {
+        width: NonZeroU32::new_unchecked(max_x - min_x + 1),
+        height: NonZeroU32::new_unchecked(max_y - min_y + 1),
+    } };
+    Some((LayoutOffset {x: min_x, y: min_y}, size))
+}
+
+/// Resizes the given image to the given dimensions. A new image is only allocated if the
+/// dimensions are different, so the pixels of a reused image are left as they were.
+pub fn resize_buffer(image: &mut RgbaImage, (width, height): (u32, u32)) {
+    if image.dimensions() != (width, height) {
+        *image = RgbaImage::new(width, height);
+    }
+}
+
+/// Multiplies the color channels of every pixel by its alpha channel, producing an image with
+/// premultiplied alpha. Fully transparent pixels become transparent black.
+pub fn premultiply_alpha(image: &mut RgbaImage) {
+    for pixel in image.pixels_mut() {
+        let alpha = pixel[3] as u32;
+        for channel in &mut pixel.0[..3] {
+            *channel = ((*channel as u32 * alpha + 127) / 255) as u8;
+        }
+    }
+}
+
+/// Converts a color with components between 0.0 and 1.0 into a pixel that can be stored in an
+/// image. Components outside of that range are clamped.
+pub fn to_pixel(color: Rgba) -> image::Rgba<u8> {
+    let to_u8 = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+    let Rgba {r, g, b, a} = color;
+    image::Rgba([to_u8(r), to_u8(g), to_u8(b), to_u8(a)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extrude_fills_gutter_with_edge_color() {
+        let sprite = Size {
+            width: NonZeroU32::new(2).unwrap(),
+            height: NonZeroU32::new(2).unwrap(),
+        };
+        let red = to_pixel(Rgba::red());
+
+        // A solid 2x2 sprite in the middle of a 2 pixel gutter
+        let mut image = RgbaImage::new(6, 6);
+        fill(&mut image, red, (2, 2), sprite);
+        extrude(&mut image, (2, 2), sprite, 1);
+
+        let transparent = image::Rgba([0, 0, 0, 0]);
+        for (x, y, &pixel) in image.enumerate_pixels() {
+            let expected = if (1..5).contains(&x) && (1..5).contains(&y) { red } else { transparent };
+            assert_eq!(pixel, expected, "pixel ({}, {}) had the wrong color", x, y);
+        }
+    }
+
+    #[test]
+    fn lanczos_downscale_is_sharper_than_box() {
+        // Vertical stripes that are 3 target pixels wide and do not line up with the 4x4 blocks
+        // of subpixels being combined
+        let factor = NonZeroU32::new(4).unwrap();
+        let source = RgbaImage::from_fn(64, 4, |x, _| {
+            if (x + 2) / 12 % 2 == 0 {
+                image::Rgba([255, 255, 255, 255])
+            } else {
+                image::Rgba([0, 0, 0, 255])
+            }
+        });
+
+        // The sum of squared differences between neighboring pixels
+        let high_frequency_energy = |image: &RgbaImage| -> f32 {
+            (1..image.width()).map(|x| {
+                let diff = image.get_pixel(x, 0)[0] as f32 - image.get_pixel(x - 1, 0)[0] as f32;
+                diff * diff
+            }).sum()
+        };
+
+        let box_image = downscale(&source, (factor, factor), DownscaleFilter::Box);
+        let lanczos_image = downscale(&source, (factor, factor), DownscaleFilter::Lanczos3);
+        assert_eq!(box_image.dimensions(), (16, 1));
+        assert_eq!(lanczos_image.dimensions(), (16, 1));
+        assert!(high_frequency_energy(&lanczos_image) > high_frequency_energy(&box_image));
+    }
+
+    #[test]
+    fn downscale_uses_premultiplied_alpha() {
+        // Half of each 2x2 block is opaque red and the other half is transparent black
+        let source = RgbaImage::from_fn(2, 2, |x, _| {
+            if x == 0 { image::Rgba([255, 0, 0, 255]) } else { image::Rgba([0, 0, 0, 0]) }
+        });
+
+        let (one, two) = (NonZeroU32::new(1).unwrap(), NonZeroU32::new(2).unwrap());
+        for &filter in &[DownscaleFilter::Box, DownscaleFilter::Lanczos3] {
+            let image = downscale(&source, (two, two), filter);
+            // The color stays red instead of being darkened by the transparent pixels
+            assert_eq!(*image.get_pixel(0, 0), image::Rgba([255, 0, 0, 128]));
+
+            // Only combining the columns keeps every row
+            let image = downscale(&source, (two, one), filter);
+            assert_eq!(image.dimensions(), (1, 2));
+            assert_eq!(*image.get_pixel(0, 1), image::Rgba([255, 0, 0, 128]));
+        }
+    }
+
+    #[test]
+    fn dominant_downscale_keeps_hard_edges() {
+        let red = image::Rgba([255, 0, 0, 255]);
+        let blue = image::Rgba([0, 0, 255, 255]);
+        // A 6x4 image of 3x2 blocks. The left blocks are mostly red, the top right block is mostly
+        // transparent (with different hidden colors), and the bottom right block is half blue.
+        let source = RgbaImage::from_fn(6, 4, |x, y| match (x / 3, y / 2) {
+            (0, _) if (x, y % 2) == (2, 1) => blue,
+            (0, _) => red,
+            (1, 0) if (x, y) == (3, 0) => red,
+            (1, 0) => image::Rgba([x as u8, y as u8, 0, 0]),
+            _ if (x + y) % 2 == 0 => blue,
+            _ => image::Rgba([0, 0, 0, 0]),
+        });
+
+        let (two, three) = (NonZeroU32::new(2).unwrap(), NonZeroU32::new(3).unwrap());
+        let image = downscale(&source, (three, two), DownscaleFilter::Dominant);
+        assert_eq!(image.dimensions(), (2, 2));
+        // Only existing colors are used, with no blending at the edges
+        assert_eq!(*image.get_pixel(0, 0), red);
+        assert_eq!(*image.get_pixel(0, 1), red);
+        // Every transparent pixel counts as the same color
+        assert_eq!(*image.get_pixel(1, 0), image::Rgba([0, 0, 0, 0]));
+        // A tie between a color and transparency keeps the color
+        assert_eq!(*image.get_pixel(1, 1), blue);
+    }
+
+    #[test]
+    fn bilinear_scale_blends_neighbors() {
+        let mut source = RgbaImage::new(2, 1);
+        source.put_pixel(0, 0, image::Rgba([0, 0, 0, 255]));
+        source.put_pixel(1, 0, image::Rgba([200, 200, 200, 255]));
+
+        let mut target = RgbaImage::new(4, 2);
+        scale_bilinear(&source, &mut target);
+
+        // The outer pixels keep the color of the edge, and the inner pixels are blended
+        let row: Vec<u8> = (0..4).map(|x| target.get_pixel(x, 0)[0]).collect();
+        assert_eq!(row, vec![0, 50, 150, 200]);
+        assert_eq!(target.get_pixel(1, 1), target.get_pixel(1, 0));
+    }
+
+    #[test]
+    fn composite_blends_with_alpha() {
+        let source = RgbaImage::from_fn(3, 1, |x, _| match x {
+            0 => image::Rgba([255, 0, 0, 255]),
+            1 => image::Rgba([255, 0, 0, 128]),
+            _ => image::Rgba([0, 0, 0, 0]),
+        });
+        let mut target = RgbaImage::from_pixel(3, 1, image::Rgba([0, 0, 255, 255]));
+
+        composite_over(&source, &mut target);
+        assert_eq!(*target.get_pixel(0, 0), image::Rgba([255, 0, 0, 255]));
+        assert_eq!(*target.get_pixel(1, 0), image::Rgba([128, 0, 127, 255]));
+        assert_eq!(*target.get_pixel(2, 0), image::Rgba([0, 0, 255, 255]));
+    }
+
+    #[test]
+    fn premultiply_scales_color_by_alpha() {
+        let mut image = RgbaImage::from_raw(3, 1, vec![
+            255, 128, 0, 255,
+            255, 128, 0, 128,
+            255, 255, 255, 0,
+        ]).unwrap();
+        premultiply_alpha(&mut image);
+        assert_eq!(image.into_raw(), vec![
+            255, 128, 0, 255,
+            128, 64, 0, 128,
+            0, 0, 0, 0,
+        ]);
+    }
+
+    #[test]
+    fn resize_buffer_reuses_same_size() {
+        let mut image = RgbaImage::new(4, 2);
+        image.put_pixel(1, 1, to_pixel(Rgba::red()));
+        let data = image.as_ptr();
+
+        resize_buffer(&mut image, (4, 2));
+        assert_eq!(image.as_ptr(), data);
+        assert_eq!(*image.get_pixel(1, 1), to_pixel(Rgba::red()));
+
+        resize_buffer(&mut image, (2, 4));
+        assert_eq!(image.dimensions(), (2, 4));
+        assert_eq!(*image.get_pixel(1, 1), image::Rgba([0, 0, 0, 0]));
+    }
+}
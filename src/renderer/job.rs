@@ -1,32 +1,353 @@
 use std::num::NonZeroU32;
+use std::convert::TryFrom;
+use std::time::Instant;
 
-use image::RgbaImage;
+use image::{RgbaImage, DynamicImage, imageops::flip_vertical_in_place};
+use crate::query3d::{AnimationQuery, AnimationPosition};
 
 use super::{
     RenderNode,
+    RenderCamera,
+    FileQuery,
     ThreadRenderContext,
     DrawLayoutError,
-    layout::LayoutNode,
+    DepthImage,
+    SheetManifest,
+    RenderStats,
+    layout::{LayoutNode, LayoutError},
+    depth::scale_depth,
+    imageops::premultiply_alpha,
+    stats::elapsed_ms,
 };
 
 #[derive(Debug)]
 pub struct RenderJob {
-    /// A scale factor to apply to the generated image. The image is scaled without interpolation.
-    /// The value must be greater than zero.
+    /// A scale factor to apply to the generated image. The value must be greater than zero.
     pub scale: NonZeroU32,
+    /// The way that pixels are filled in when the generated image is scaled
+    pub scale_mode: ScaleMode,
+    /// If true, the rows of the generated image are stored from the bottom to the top, so that the
+    /// origin is at the bottom-left corner (e.g. for uploading to an OpenGL texture)
+    pub flip_y: bool,
+    /// If true, the color channels of the generated image are multiplied by its alpha channel
+    /// (premultiplied alpha), as expected by many game engines when compositing sprites
+    ///
+    /// A transparent background is unaffected, since its pixels become transparent black either
+    /// way. Partially transparent backgrounds are darkened in proportion to their alpha. Pixel
+    /// formats without an alpha channel keep the darkened colors, as if the image had been drawn
+    /// over black.
+    pub premultiply: bool,
+    /// The pixel format of the generated image
+    pub format: PixelFormat,
     /// The root node of the tree that describes the image to render
     pub root: RenderNode,
 }
 
 impl RenderJob {
-    pub fn execute(self, ctx: &mut ThreadRenderContext) -> Result<RgbaImage, DrawLayoutError> {
-        let Self {scale, root} = self;
+    pub fn execute(self, ctx: &mut ThreadRenderContext) -> Result<DynamicImage, DrawLayoutError> {
+        self.execute_with_depth(ctx).map(|(image, _)| image)
+    }
+
+    /// Renders this job, also returning how long each stage of rendering took
+    pub fn execute_with_stats(
+        self,
+        ctx: &mut ThreadRenderContext,
+    ) -> Result<(DynamicImage, RenderStats), DrawLayoutError> {
+        // Discard anything that was rendered with the context before this job
+        ctx.take_stats();
+        let image = self.execute(ctx)?;
+        Ok((image, ctx.take_stats()))
+    }
+
+    /// Returns the area of the generated image that each rendered image in this job is drawn into,
+    /// without rendering anything
+    pub fn manifest(&self) -> Result<SheetManifest, LayoutError> {
+        let layout = LayoutNode::try_from(self.root.clone())?;
+        Ok(SheetManifest::from_layout(layout, self.scale, self.flip_y))
+    }
+
+    /// Renders this job, also returning a depth image of the same size if any of the rendered
+    /// images in it have a depth mode set
+    pub fn execute_with_depth(
+        self,
+        ctx: &mut ThreadRenderContext,
+    ) -> Result<(DynamicImage, Option<DepthImage>), DrawLayoutError> {
+        let format = self.format;
+        // An empty image does not allocate, so the generated image is only allocated once
+        let mut image = RgbaImage::new(0, 0);
+        let depth = self.draw_into(&mut image, ctx)?;
+
+        let start = Instant::now();
+        let image = format.convert(image);
+        ctx.record_stats(|stats| stats.draw_ms += elapsed_ms(start));
+
+        Ok((image, depth))
+    }
+
+    /// Renders this job into the given buffer, which is only reallocated if its size is not
+    /// already the size of the generated image. Reusing the same buffer for every frame of a live
+    /// preview avoids allocating a new image for each frame.
+    ///
+    /// The buffer always holds 8-bit RGBA pixels, so the pixel format of this job is not used. No
+    /// depth image is produced.
+    pub fn execute_into(
+        self,
+        buffer: &mut RgbaImage,
+        ctx: &mut ThreadRenderContext,
+    ) -> Result<(), DrawLayoutError> {
+        self.draw_into(buffer, ctx).map(|_| ())
+    }
+
+    /// Draws this job into the given buffer with 8-bit RGBA pixels, returning its depth image (if
+    /// any). The buffer is resized to the size of the generated image if needed.
+    fn draw_into(
+        self,
+        buffer: &mut RgbaImage,
+        ctx: &mut ThreadRenderContext,
+    ) -> Result<Option<DepthImage>, DrawLayoutError> {
+        let Self {scale, scale_mode, flip_y, premultiply, format: _, root} = self;
+
+        let start = Instant::now();
+        let layout = LayoutNode::try_from(root)?;
+        ctx.record_stats(|stats| stats.parse_ms += elapsed_ms(start));
+
+        let (image, depth) = ctx.draw_with_depth(layout)?;
+        let start = Instant::now();
+        ctx.scale_into(&image, scale, scale_mode, buffer)?;
+        let mut depth = depth.map(|depth| scale_depth(&depth, scale));
+
+        // Flipping after scaling moves whole rows, so every scaled pixel stays a complete block
+        if flip_y {
+            flip_vertical_in_place(buffer);
+            if let Some(depth) = &mut depth {
+                flip_vertical_in_place(depth);
+            }
+        }
+
+        if premultiply {
+            premultiply_alpha(buffer);
+        }
+
+        ctx.record_stats(|stats| stats.draw_ms += elapsed_ms(start));
+
+        Ok(depth)
+    }
+
+    /// Renders `frame_count` frames of this job, with every image posed at evenly spaced times
+    /// (`1 / fps` seconds apart) from the start of its animation
+    ///
+    /// Each image uses the animation named in its geometry query, or the default animation if no
+    /// animation is named. Geometry that was already resolved cannot be posed, so it is drawn the
+    /// same way in every frame. Camera queries that have an animation are posed at the same times.
+    pub fn execute_animation(
+        self,
+        ctx: &mut ThreadRenderContext,
+        frame_count: NonZeroU32,
+        fps: NonZeroU32,
+    ) -> Result<Vec<DynamicImage>, DrawLayoutError> {
+        let times = (0..frame_count.get()).map(|frame| frame as f32 / fps.get() as f32);
+        self.execute_at_times(ctx, times)
+    }
+
+    /// Renders the frames of an animation that is `duration` seconds long at the given frame rate,
+    /// with the frames ordered according to the given playback mode (see `playback_times`)
+    ///
+    /// Images are posed the same way as `execute_animation`.
+    pub fn execute_playback(
+        self,
+        ctx: &mut ThreadRenderContext,
+        duration: f32,
+        fps: NonZeroU32,
+        mode: PlaybackMode,
+    ) -> Result<Vec<DynamicImage>, DrawLayoutError> {
+        self.execute_at_times(ctx, playback_times(duration, fps, mode))
+    }
+
+    /// Renders one frame of this job for each of the given times (in seconds)
+    fn execute_at_times(
+        self,
+        ctx: &mut ThreadRenderContext,
+        times: impl IntoIterator<Item=f32>,
+    ) -> Result<Vec<DynamicImage>, DrawLayoutError> {
+        let Self {scale, scale_mode, flip_y, premultiply, format, root} = self;
+
+        times.into_iter().map(|time| {
+            let mut root = root.clone();
+            // Animation times are in milliseconds
+            set_animation_time(&mut root, time * 1000.0);
+
+            Self {scale, scale_mode, flip_y, premultiply, format, root}.execute(ctx)
+        }).collect()
+    }
+}
 
-        let layout = LayoutNode::from(root);
+/// The order that the frames of an animation are played in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PlaybackMode {
+    /// The animation is played from start to end once, including both its first and last frame
+    #[default]
+    Once,
+    /// The animation repeats seamlessly, so the last frame leads back into the first one without
+    /// showing the same pose twice
+    Loop,
+    /// The animation is played forward and then backward, without repeating the frames at either
+    /// end when the sequence repeats
+    PingPong,
+}
+
+/// Returns the times (in seconds) to sample an animation that is `duration` seconds long at, so
+/// that it plays at the given frame rate in the given playback mode
+///
+/// * `Once` samples every `1 / fps` seconds from 0, followed by a final frame at `duration`
+/// * `Loop` samples the animation at evenly spaced times from 0 up to but not including
+///   `duration`, which is where the animation returns to its first pose. The spacing is as close
+///   to `1 / fps` as possible while still fitting a whole number of frames into the animation.
+/// * `PingPong` samples the same times as `Once`, followed by the same times in reverse without
+///   the first and last frame
+///
+/// An animation with no duration is always sampled once at time 0.
+pub fn playback_times(duration: f32, fps: NonZeroU32, mode: PlaybackMode) -> Vec<f32> {
+    let fps = fps.get() as f32;
+    if duration.is_nan() || duration <= 0.0 {
+        return vec![0.0];
+    }
+
+    // A small tolerance keeps a duration that is a multiple of the frame duration from gaining an
+    // extra frame due to rounding
+    let frames = (duration * fps - 1e-3).ceil().max(1.0) as u32;
+    let once = || (0..frames).map(|frame| frame as f32 / fps).chain(Some(duration));
+
+    use PlaybackMode::*;
+    match mode {
+        Once => once().collect(),
+        Loop => {
+            let frames = (duration * fps).round().max(1.0) as u32;
+            (0..frames).map(|frame| frame as f32 * duration / frames as f32).collect()
+        },
+        PingPong => {
+            let forward: Vec<_> = once().collect();
+            let backward = forward[1..forward.len() - 1].iter().rev().copied();
+            forward.iter().copied().chain(backward).collect()
+        },
+    }
+}
+
+/// Poses every image in the given tree at the given time (in ms) in its animation
+fn set_animation_time(node: &mut RenderNode, time: f32) {
+    match node {
+        RenderNode::RenderedImage(image) => {
+            for query in image.geometry.queries_mut() {
+                let name = query.animation.take().and_then(|animation| animation.name);
+                query.animation = Some(AnimationQuery {
+                    name,
+                    position: AnimationPosition::Time(time),
+                });
+            }
+
+            // Cameras are only animated if they were queried with an animation, so that a camera
+            // from a file with an unrelated animation keeps its original position
+            if let RenderCamera::Query(FileQuery {query, ..}) = &mut image.camera {
+                if let Some(animation) = &mut query.animation {
+                    animation.position = AnimationPosition::Time(time);
+                }
+            }
+        },
+
+        RenderNode::Layout(layout) => {
+            for node in &mut layout.nodes {
+                set_animation_time(node, time);
+            }
+        },
+
+        RenderNode::Empty {..} => {},
+    }
+}
+
+/// The way that the pixels of an image are filled in when it is scaled up
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScaleMode {
+    /// Each pixel is copied into a square block of pixels, which keeps pixel art crisp
+    #[default]
+    Nearest,
+    /// Each pixel is blended with its neighbors, which smooths out images that are not pixel art
+    Bilinear,
+}
+
+/// The pixel format of a generated image
+///
+/// Images are always rendered with RGBA colors. The rendered image is converted to this format
+/// once rendering is complete.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PixelFormat {
+    /// 8-bit red, green, blue, and alpha channels
+    #[default]
+    Rgba8,
+    /// 8-bit red, green, and blue channels. The alpha channel is discarded.
+    Rgb8,
+    /// A single 8-bit luminance (brightness) channel. The alpha channel is discarded.
+    Gray8,
+}
+
+impl PixelFormat {
+    /// The number of 8-bit channels stored for each pixel in this format
+    pub fn channels(self) -> u8 {
+        use PixelFormat::*;
+        match self {
+            Rgba8 => 4,
+            Rgb8 => 3,
+            Gray8 => 1,
+        }
+    }
+
+    /// Converts the given RGBA image into this format
+    pub fn convert(self, image: RgbaImage) -> DynamicImage {
+        let image = DynamicImage::ImageRgba8(image);
+
+        use PixelFormat::*;
+        match self {
+            Rgba8 => image,
+            Rgb8 => DynamicImage::ImageRgb8(image.to_rgb()),
+            Gray8 => DynamicImage::ImageLuma8(image.to_luma()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn convert_pixel_formats() {
+        let mut image = RgbaImage::new(3, 2);
+        image.put_pixel(0, 0, image::Rgba([255, 0, 0, 255]));
+        image.put_pixel(2, 1, image::Rgba([10, 200, 30, 128]));
 
-        let image = ctx.draw(layout)?;
-        let image = ctx.scale(&image, scale)?;
+        for &format in &[PixelFormat::Rgba8, PixelFormat::Rgb8, PixelFormat::Gray8] {
+            let converted = format.convert(image.clone());
+            let bytes = converted.to_bytes();
+            assert_eq!(bytes.len(), 3 * 2 * format.channels() as usize);
+        }
+
+        let rgb = PixelFormat::Rgb8.convert(image.clone()).to_rgb();
+        assert_eq!(rgb.get_pixel(2, 1).0, [10, 200, 30]);
+
+        let gray = PixelFormat::Gray8.convert(image.clone()).to_luma();
+        let expected = DynamicImage::ImageRgba8(image).to_luma();
+        // Pure red is not pure black or white
+        let red_luma = gray.get_pixel(0, 0).0[0];
+        assert!(red_luma > 0 && red_luma < 255);
+        assert_eq!(gray.into_raw(), expected.into_raw());
+    }
 
-        Ok(image)
+    #[test]
+    fn playback_modes() {
+        let fps = NonZeroU32::new(4).unwrap();
+        assert_eq!(playback_times(1.0, fps, PlaybackMode::Once), &[0.0, 0.25, 0.5, 0.75, 1.0]);
+        assert_eq!(playback_times(0.9, fps, PlaybackMode::Once), &[0.0, 0.25, 0.5, 0.75, 0.9]);
+        // The end of a loop is the same pose as its start
+        assert_eq!(playback_times(1.0, fps, PlaybackMode::Loop), &[0.0, 0.25, 0.5, 0.75]);
+        assert_eq!(playback_times(1.0, fps, PlaybackMode::PingPong),
+            &[0.0, 0.25, 0.5, 0.75, 1.0, 0.75, 0.5, 0.25]);
+        assert_eq!(playback_times(0.0, fps, PlaybackMode::Loop), &[0.0]);
     }
 }
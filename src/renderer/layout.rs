@@ -1,27 +1,54 @@
 use std::num::NonZeroU32;
+use std::convert::TryFrom;
 
-use super::{RenderedImage, RenderNode, RenderLayout, LayoutType, Size};
+use image::RgbaImage;
+use thiserror::Error;
+
+use crate::math::Rgba;
+
+use super::{RenderedImage, RenderNode, RenderLayout, LayoutType, FillOrder, CellAlignment, Size};
+use super::imageops::{fill, copy, copy_over, to_pixel};
 
 #[derive(Debug)]
 pub enum LayoutNode {
     RenderedImage(RenderedImage),
     Grid(GridLayout),
+    Packed(PackedLayout),
     /// An empty slot, used to create a gap/empty cell in the layout
-    Empty {size: Size},
+    Empty {size: Size, background: Option<Rgba>},
 }
 
-impl From<RenderNode> for LayoutNode {
-    fn from(node: RenderNode) -> Self {
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum LayoutError {
+    #[error("A layout must contain at least one node")]
+    NoCells,
+    #[error("A grid layout with {cells} node(s) cannot have {cols} columns")]
+    TooManyColumns {cols: u32, cells: usize},
+    #[error("A node that is {width} pixels wide does not fit in a packed layout that is at most {max_width} pixels wide")]
+    CellTooWide {width: u32, max_width: u32},
+}
+
+impl TryFrom<RenderNode> for LayoutNode {
+    type Error = LayoutError;
+
+    fn try_from(node: RenderNode) -> Result<Self, Self::Error> {
         use RenderNode::*;
         use LayoutType::*;
-        match node {
+        Ok(match node {
             RenderedImage(image) => LayoutNode::RenderedImage(image),
-            Layout(RenderLayout {nodes, layout: Grid {cols}}) => {
-                let layout_nodes = nodes.into_iter().map(Into::into).collect();
-                LayoutNode::Grid(GridLayout::new(layout_nodes, cols))
+            Layout(RenderLayout {nodes, layout: Grid {cols, fill, spacing, padding, align}, background, bleed}) => {
+                let layout_nodes = nodes.into_iter().map(LayoutNode::try_from).collect::<Result<_, _>>()?;
+                let grid = GridLayout::new(layout_nodes, cols, fill, background, bleed)?
+                    .with_spacing(spacing, padding)
+                    .with_alignment(align);
+                LayoutNode::Grid(grid)
             },
-            Empty {size} => LayoutNode::Empty {size},
-        }
+            Layout(RenderLayout {nodes, layout: Packed {width}, background, bleed}) => {
+                let layout_nodes = nodes.into_iter().map(LayoutNode::try_from).collect::<Result<_, _>>()?;
+                LayoutNode::Packed(PackedLayout::new(layout_nodes, width, background, bleed)?)
+            },
+            Empty {size, background} => LayoutNode::Empty {size, background},
+        })
     }
 }
 
@@ -32,7 +59,20 @@ impl LayoutNode {
         match self {
             RenderedImage(image) => image.size,
             Grid(grid) => grid.size(),
-            Empty {size} => *size,
+            Packed(packed) => packed.size(),
+            Empty {size, ..} => *size,
+        }
+    }
+
+    /// The color that this node fills its layout cell with instead of the background of the
+    /// layout, if any. This is the only place where a node overrides the background of its cell.
+    pub fn cell_background(&self) -> Option<Rgba> {
+        use LayoutNode::*;
+
+        match self {
+            RenderedImage(image) => image.cell_background,
+            Empty {background, ..} => *background,
+            Grid(_) | Packed(_) => None,
         }
     }
 
@@ -42,6 +82,69 @@ impl LayoutNode {
             current: 0,
         }
     }
+
+    /// Returns each node of this layout along with the offset it is drawn at and the cell that it
+    /// is drawn into
+    pub fn iter_cells(self) -> impl Iterator<Item = (LayoutOffset, LayoutCell, LayoutNode)> {
+        use LayoutNode::*;
+
+        let (grid_cell, background) = match &self {
+            Grid(grid) => (Some((grid.cell_size(), grid.align)), grid.background),
+            Packed(packed) => (None, packed.background),
+            RenderedImage(_) | Empty {..} => (None, None),
+        };
+
+        self.iter_targets().map(move |(offset, node)| {
+            // Cells in a packed layout are only as large as the node drawn into them. Nodes in a
+            // grid may be aligned away from the top-left corner of their cell.
+            let (cell_offset, size) = match grid_cell {
+                Some((cell_size, align)) => {
+                    let (align_x, align_y) = align.offset(cell_size, node.size());
+                    (LayoutOffset {x: offset.x - align_x, y: offset.y - align_y}, cell_size)
+                },
+                None => (offset, node.size()),
+            };
+            let cell = LayoutCell {
+                offset: cell_offset,
+                size,
+                background: node.cell_background().or(background),
+            };
+
+            (offset, cell, node)
+        })
+    }
+}
+
+/// The area of a layout that a node is drawn into
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LayoutCell {
+    /// The top-left corner of the cell
+    pub offset: LayoutOffset,
+    /// The size of the cell, which may be larger than the node drawn into it
+    pub size: Size,
+    /// The color that the whole cell is filled with before its node is drawn, or None to leave
+    /// the cell transparent. This is the cell background of the node if it has one (see
+    /// `LayoutNode::cell_background`), and the background of the layout otherwise.
+    pub background: Option<Rgba>,
+}
+
+impl LayoutCell {
+    /// Fills this cell of the given image with its background, if it has one
+    pub fn fill_background(&self, target: &mut RgbaImage) {
+        if let Some(background) = self.background {
+            fill(target, to_pixel(background), (self.offset.x, self.offset.y), self.size);
+        }
+    }
+
+    /// Draws the image of the node in this cell into the given image at the given offset. The
+    /// background of the cell shows through wherever the drawn image is transparent.
+    pub fn draw_image(&self, image: &RgbaImage, offset: LayoutOffset, target: &mut RgbaImage) {
+        match self.background {
+            Some(_) => copy_over(image, target, (offset.x, offset.y)),
+            // Nothing could show through, so the image is copied as it is
+            None => copy(image, target, (offset.x, offset.y)),
+        }
+    }
 }
 
 /// A fully-computed grid layout
@@ -52,11 +155,33 @@ pub struct GridLayout {
     pub cell_height: NonZeroU32,
     pub rows: NonZeroU32,
     pub cols: NonZeroU32,
+    /// The order in which the cells are placed in the grid
+    pub fill: FillOrder,
+    /// The color to fill each cell with before it is drawn, or None to leave the cells transparent
+    pub background: Option<Rgba>,
+    /// The number of pixels to extrude the edges of each cell into the gutter around it
+    pub bleed: u32,
+    /// The number of transparent pixels between neighboring cells
+    pub spacing: u32,
+    /// The number of transparent pixels around the outside of the grid
+    pub padding: u32,
+    /// The position of each node within its cell, when the node is smaller than the cell
+    pub align: CellAlignment,
 }
 
 impl GridLayout {
-    pub fn new(cells: Vec<LayoutNode>, cols: NonZeroU32) -> Self {
-        assert!(!cells.is_empty(), "zero-cell grid layouts are not supported");
+    /// Creates a grid with the given number of columns. Columns past the last cell are left empty
+    /// (see `with_full_columns` to disallow that).
+    pub fn new(
+        cells: Vec<LayoutNode>,
+        cols: NonZeroU32,
+        fill: FillOrder,
+        background: Option<Rgba>,
+        bleed: u32,
+    ) -> Result<Self, LayoutError> {
+        if cells.is_empty() {
+            return Err(LayoutError::NoCells);
+        }
 
         let Size {width: cell_width, height: cell_height} = cells.iter().fold(
             Size::min_value(),
@@ -68,7 +193,36 @@ impl GridLayout {
         // Safe because cells.len() > 0 and we always add 1 in the end of the calculation of rows
         let rows = unsafe { NonZeroU32::new_unchecked(rows) };
 
-        Self {cells, cell_width, cell_height, rows, cols}
+        Ok(Self {cells, cell_width, cell_height, rows, cols, fill, background, bleed, spacing: 0, padding: 0, align: CellAlignment::default()})
+    }
+
+    /// Requires every column of this grid to contain at least one cell, so a grid with more
+    /// columns than cells is an error instead of having empty columns
+    pub fn with_full_columns(self) -> Result<Self, LayoutError> {
+        if self.cols.get() as usize > self.cells.len() {
+            return Err(LayoutError::TooManyColumns {cols: self.cols.get(), cells: self.cells.len()});
+        }
+
+        Ok(self)
+    }
+
+    /// Leaves the given number of transparent pixels between neighboring cells and around the
+    /// outside of the grid
+    pub fn with_spacing(self, spacing: u32, padding: u32) -> Self {
+        Self {spacing, padding, ..self}
+    }
+
+    /// Positions each node that is smaller than its cell using the given alignment
+    pub fn with_alignment(self, align: CellAlignment) -> Self {
+        Self {align, ..self}
+    }
+
+    /// Returns the size of every cell in this layout
+    pub fn cell_size(&self) -> Size {
+        Size {
+            width: self.cell_width,
+            height: self.cell_height,
+        }
     }
 
     /// Returns the total size of the image generated by this layout
@@ -81,14 +235,167 @@ impl GridLayout {
 
     /// The total width of the image generated by this layout
     pub fn width(&self) -> NonZeroU32 {
+        let cell_width = self.cell_width.get() + 2 * self.bleed;
+        let cols = self.cols.get();
+        let width = cell_width * cols + self.spacing * (cols - 1) + 2 * self.padding;
         // Safe because multiplying two non-zero values cannot be zero
-        unsafe { NonZeroU32::new_unchecked(self.cell_width.get() * self.cols.get()) }
+        unsafe { NonZeroU32::new_unchecked(width) }
     }
 
     /// The total height of the image generated by this layout
     pub fn height(&self) -> NonZeroU32 {
+        let cell_height = self.cell_height.get() + 2 * self.bleed;
+        let rows = self.rows.get();
+        let height = cell_height * rows + self.spacing * (rows - 1) + 2 * self.padding;
         // Safe because multiplying two non-zero values cannot be zero
-        unsafe { NonZeroU32::new_unchecked(self.cell_height.get() * self.rows.get()) }
+        unsafe { NonZeroU32::new_unchecked(height) }
+    }
+}
+
+/// A fully-computed packed layout
+///
+/// Cells are placed using a "skyline" bin-packing algorithm: the top edge of the packed cells is
+/// tracked as a list of horizontal segments and each cell is placed wherever its bottom edge would
+/// end up the highest (closest to the top of the image). Cells are placed tallest first, which
+/// tends to produce more compact results. Cells with the same size are placed in the order they
+/// were given, so the same cells always produce the same layout.
+#[derive(Debug)]
+pub struct PackedLayout {
+    /// Each cell and the offset it is drawn at, in the order the cells were given
+    pub cells: Vec<(LayoutOffset, LayoutNode)>,
+    pub width: NonZeroU32,
+    pub height: NonZeroU32,
+    /// The color to fill each cell with before it is drawn, or None to leave the cells transparent
+    pub background: Option<Rgba>,
+    /// The number of pixels to extrude the edges of each cell into the gutter around it
+    pub bleed: u32,
+}
+
+impl PackedLayout {
+    /// Packs the given cells into an image that is at most `max_width` pixels wide. Every cell
+    /// (including the gutter around it) must fit within that width.
+    pub fn new(
+        cells: Vec<LayoutNode>,
+        max_width: NonZeroU32,
+        background: Option<Rgba>,
+        bleed: u32,
+    ) -> Result<Self, LayoutError> {
+        if cells.is_empty() {
+            return Err(LayoutError::NoCells);
+        }
+
+        let max_width = max_width.get();
+        // Pack each cell together with the gutter around it
+        let sizes: Vec<_> = cells.iter().map(|cell| {
+            let Size {width, height} = cell.size();
+            // Safe because adding to a non-zero value cannot produce zero
+            unsafe { Size {
+                width: NonZeroU32::new_unchecked(width.get() + 2 * bleed),
+                height: NonZeroU32::new_unchecked(height.get() + 2 * bleed),
+            } }
+        }).collect();
+        if let Some(size) = sizes.iter().find(|size| size.width.get() > max_width) {
+            return Err(LayoutError::CellTooWide {width: size.width.get(), max_width});
+        }
+
+        // Place the tallest cells first, using the width to break ties
+        let mut order: Vec<_> = (0..sizes.len()).collect();
+        order.sort_by_key(|&i| std::cmp::Reverse((sizes[i].height, sizes[i].width)));
+
+        let mut skyline = Skyline::new(max_width);
+        let mut offsets = vec![LayoutOffset {x: 0, y: 0}; sizes.len()];
+        for i in order {
+            offsets[i] = skyline.insert(sizes[i]);
+        }
+
+        let (width, height) = offsets.iter().zip(&sizes).fold((1, 1), |(width, height), (offset, size)| {
+            (width.max(offset.x + size.width.get()), height.max(offset.y + size.height.get()))
+        });
+        // Safe because both values start at 1 and only ever increase
+        let width = unsafe { NonZeroU32::new_unchecked(width) };
+        let height = unsafe { NonZeroU32::new_unchecked(height) };
+
+        // Move each cell inside of its gutter
+        let cells = offsets.into_iter()
+            .map(|LayoutOffset {x, y}| LayoutOffset {x: x + bleed, y: y + bleed})
+            .zip(cells)
+            .collect();
+        Ok(Self {cells, width, height, background, bleed})
+    }
+
+    /// Returns the total size of the image generated by this layout
+    pub fn size(&self) -> Size {
+        Size {
+            width: self.width,
+            height: self.height,
+        }
+    }
+}
+
+/// The top edge of the cells packed so far, stored as segments ordered from left to right
+#[derive(Debug)]
+struct Skyline {
+    /// Each segment is (x, y, width) where y is the first free row above that segment
+    segments: Vec<(u32, u32, u32)>,
+}
+
+impl Skyline {
+    fn new(width: u32) -> Self {
+        Self {segments: vec![(0, 0, width)]}
+    }
+
+    /// Finds the position for a cell of the given size that keeps its bottom edge as high as
+    /// possible, then raises the skyline to cover the cell
+    fn insert(&mut self, size: Size) -> LayoutOffset {
+        let (width, height) = (size.width.get(), size.height.get());
+
+        // (bottom edge, x, y, index of first segment)
+        let mut best: Option<(u32, u32, u32, usize)> = None;
+        for (start, &(x, _, _)) in self.segments.iter().enumerate() {
+            let y = match self.fit(start, width) {
+                Some(y) => y,
+                None => continue,
+            };
+            let candidate = (y + height, x, y, start);
+            if best.map(|best| (candidate.0, candidate.1) < (best.0, best.1)).unwrap_or(true) {
+                best = Some(candidate);
+            }
+        }
+        // The first segment always starts at x = 0 and every cell fits in the total width
+        let (_, x, y, start) = best.expect("bug: cell did not fit in skyline");
+
+        // Replace every segment covered by the cell with a single segment on top of the cell
+        let right = x + width;
+        let mut end = start;
+        while end < self.segments.len() && self.segments[end].0 < right {
+            end += 1;
+        }
+        let (last_x, last_y, last_width) = self.segments[end - 1];
+        let last_right = last_x + last_width;
+        let mut replacement = vec![(x, y + height, width)];
+        if last_right > right {
+            replacement.push((right, last_y, last_right - right));
+        }
+        self.segments.splice(start..end, replacement);
+
+        LayoutOffset {x, y}
+    }
+
+    /// Returns the y position at which a cell of the given width would rest if its left edge was
+    /// placed at the start of the given segment, or None if the cell would go past the right edge
+    fn fit(&self, start: usize, width: u32) -> Option<u32> {
+        let x = self.segments[start].0;
+        let right = x + width;
+        let &(last_x, _, last_width) = self.segments.last()?;
+        if right > last_x + last_width {
+            return None;
+        }
+
+        let y = self.segments[start..].iter()
+            .take_while(|&&(seg_x, _, _)| seg_x < right)
+            .map(|&(_, seg_y, _)| seg_y)
+            .max()?;
+        Some(y)
     }
 }
 
@@ -120,7 +427,19 @@ impl Iterator for LayoutTargetIter {
             },
 
             Some(Grid(grid)) => {
-                let GridLayout {mut cells, cell_width, cell_height, rows, cols} = grid;
+                let GridLayout {
+                    mut cells,
+                    cell_width,
+                    cell_height,
+                    rows,
+                    cols,
+                    fill,
+                    background,
+                    bleed,
+                    spacing,
+                    padding,
+                    align,
+                } = grid;
 
                 // Stop once there are no more cells to yield
                 if cells.is_empty() {
@@ -129,16 +448,36 @@ impl Iterator for LayoutTargetIter {
 
                 let current = self.current;
 
-                let row = current / cols.get();
-                let col = current % cols.get();
-                let target = LayoutOffset {
-                    x: col * cell_width.get(),
-                    y: row * cell_height.get(),
+                let (row, col) = match fill {
+                    FillOrder::RowMajor => (current / cols.get(), current % cols.get()),
+                    FillOrder::ColumnMajor => {
+                        // The same cells are filled as in row-major order, so when the last row is
+                        // not full, only the columns on its left are full height
+                        let (rows, cols) = (rows.get(), cols.get());
+                        let total = current + cells.len() as u32;
+                        let full_cols = total - (rows - 1) * cols;
+                        let full_cells = full_cols * rows;
+                        if current < full_cells {
+                            (current % rows, current / rows)
+                        } else {
+                            let index = current - full_cells;
+                            (index % (rows - 1), full_cols + index / (rows - 1))
+                        }
+                    },
+                };
+                // Each cell is surrounded by a gutter for the bleed, and separated from the next
+                // cell by the spacing
+                let cell = LayoutOffset {
+                    x: padding + col * (cell_width.get() + 2 * bleed + spacing) + bleed,
+                    y: padding + row * (cell_height.get() + 2 * bleed + spacing) + bleed,
                 };
 
                 self.current += 1;
 
                 let node = cells.remove(0);
+                let cell_size = Size {width: cell_width, height: cell_height};
+                let (align_x, align_y) = align.offset(cell_size, node.size());
+                let target = LayoutOffset {x: cell.x + align_x, y: cell.y + align_y};
                 // Reconstruct the node with the remaining cells
                 self.node = Some(LayoutNode::Grid(GridLayout {
                     cells,
@@ -146,6 +485,33 @@ impl Iterator for LayoutTargetIter {
                     cell_height,
                     rows,
                     cols,
+                    fill,
+                    background,
+                    bleed,
+                    spacing,
+                    padding,
+                    align,
+                }));
+
+                Some((target, node))
+            },
+
+            Some(Packed(packed)) => {
+                let PackedLayout {mut cells, width, height, background, bleed} = packed;
+
+                // Stop once there are no more cells to yield
+                if cells.is_empty() {
+                    return None;
+                }
+
+                let (target, node) = cells.remove(0);
+                // Reconstruct the node with the remaining cells
+                self.node = Some(LayoutNode::Packed(PackedLayout {
+                    cells,
+                    width,
+                    height,
+                    background,
+                    bleed,
                 }));
 
                 Some((target, node))
@@ -153,3 +519,312 @@ impl Iterator for LayoutTargetIter {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn empty_cell(width: u32, height: u32) -> LayoutNode {
+        LayoutNode::Empty {
+            size: Size {
+                width: NonZeroU32::new(width).unwrap(),
+                height: NonZeroU32::new(height).unwrap(),
+            },
+            background: None,
+        }
+    }
+
+    /// A 2x2 image with the given cell background. The image is never rendered by these tests.
+    fn rendered_image(cell_background: Option<Rgba>) -> LayoutNode {
+        use std::sync::Arc;
+
+        use crate::math::{Mat4, Rgb, Handedness};
+        use crate::renderer::{
+            AspectMode,
+            Background,
+            Camera,
+            RenderCamera,
+            RenderLights,
+            RenderGeometry,
+            CelBands,
+            ShadingMode,
+            ColorSpace,
+            Outline,
+            OutlineMode,
+        };
+
+        LayoutNode::RenderedImage(RenderedImage {
+            size: Size {
+                width: NonZeroU32::new(2).unwrap(),
+                height: NonZeroU32::new(2).unwrap(),
+            },
+            // The background of the image itself does not affect the background of its cell
+            background: Background::Color(Rgba::white()),
+            cell_background,
+            camera: RenderCamera::Camera(Arc::new(Camera {
+                view: Mat4::identity(),
+                projection: Mat4::identity(),
+                handedness: Handedness::Right,
+            })),
+            framing: None,
+            aspect: AspectMode::Stretch,
+            lights: RenderLights::Lights(Arc::new(Vec::new())),
+            ambient_light: Rgb::white(),
+            key_light: None,
+            bands: CelBands::default(),
+            shading: ShadingMode::Cel,
+            color_space: ColorSpace::Linear,
+            exposure: 1.0,
+            gamma: 1.0,
+            geometry: RenderGeometry::Geometry(Arc::new(Vec::new())),
+            outline: Outline {
+                mode: OutlineMode::InvertedHull {thickness: 0.0},
+                color: Rgba::black(),
+            },
+            rim: None,
+            specular: None,
+            shadows: None,
+            ground: None,
+            light_gizmos: false,
+            wireframe: None,
+            supersample: None,
+            depth: None,
+        })
+    }
+
+    #[test]
+    fn packed_cells_do_not_overlap() {
+        let cells = vec![
+            empty_cell(64, 32),
+            empty_cell(16, 48),
+            empty_cell(32, 32),
+            empty_cell(40, 10),
+            empty_cell(24, 24),
+        ];
+        let total_area: u32 = cells.iter()
+            .map(|cell| cell.size().width.get() * cell.size().height.get())
+            .sum();
+
+        let layout = LayoutNode::Packed(PackedLayout::new(cells, NonZeroU32::new(96).unwrap(), None, 0).unwrap());
+        let Size {width, height} = layout.size();
+        assert!(width.get() <= 96);
+
+        let rects: Vec<_> = layout.iter_targets().map(|(offset, node)| {
+            let size = node.size();
+            (offset.x, offset.y, offset.x + size.width.get(), offset.y + size.height.get())
+        }).collect();
+        assert_eq!(rects.len(), 5);
+
+        for (i, &(left, top, right, bottom)) in rects.iter().enumerate() {
+            assert!(right <= width.get() && bottom <= height.get());
+            for &(other_left, other_top, other_right, other_bottom) in &rects[i+1..] {
+                let overlaps = left < other_right && other_left < right
+                    && top < other_bottom && other_top < bottom;
+                assert!(!overlaps, "cells overlap: {:?}", rects);
+            }
+        }
+
+        // The packed image should not waste more space than the cells themselves take up
+        assert!(width.get() * height.get() <= total_area * 2);
+    }
+
+    #[test]
+    fn packed_layout_is_deterministic() {
+        let cells = || vec![
+            empty_cell(16, 16),
+            empty_cell(32, 8),
+            empty_cell(16, 16),
+            empty_cell(16, 16),
+            empty_cell(8, 24),
+        ];
+        let offsets = || {
+            let layout = LayoutNode::Packed(PackedLayout::new(cells(), NonZeroU32::new(40).unwrap(), None, 0).unwrap());
+            layout.iter_targets().map(|(offset, _)| (offset.y, offset.x)).collect::<Vec<_>>()
+        };
+
+        let first = offsets();
+        assert_eq!(first, offsets());
+
+        // Cells with the same size are placed in reading order (top to bottom, then left to right)
+        // in the order they were given
+        assert!(first[0] < first[2] && first[2] < first[3], "unexpected order: {:?}", first);
+    }
+
+    #[test]
+    fn invalid_layouts_are_errors() {
+        let cols = |cols| NonZeroU32::new(cols).unwrap();
+
+        let grid = GridLayout::new(Vec::new(), cols(1), FillOrder::RowMajor, None, 0);
+        assert_eq!(grid.err(), Some(LayoutError::NoCells));
+        let grid = GridLayout::new(vec![empty_cell(8, 8)], cols(2), FillOrder::RowMajor, None, 0)
+            .and_then(GridLayout::with_full_columns);
+        assert_eq!(grid.err(), Some(LayoutError::TooManyColumns {cols: 2, cells: 1}));
+
+        // Extra columns are left empty unless every column is required to have a cell
+        let grid = GridLayout::new(vec![empty_cell(8, 8)], cols(2), FillOrder::RowMajor, None, 0).unwrap();
+        assert_eq!((grid.width().get(), grid.height().get()), (16, 8));
+
+        // The gutter for the bleed counts towards the width of each cell
+        let packed = PackedLayout::new(vec![empty_cell(8, 8)], cols(10), None, 2);
+        assert_eq!(packed.err(), Some(LayoutError::CellTooWide {width: 12, max_width: 10}));
+
+        // Errors in nested layouts are returned instead of panicking
+        let node = RenderNode::Layout(RenderLayout {
+            nodes: vec![RenderNode::Layout(RenderLayout {
+                nodes: Vec::new(),
+                layout: LayoutType::Packed {width: cols(10)},
+                background: None,
+                bleed: 0,
+            })],
+            layout: LayoutType::Packed {width: cols(10)},
+            background: None,
+            bleed: 0,
+        });
+        assert_eq!(LayoutNode::try_from(node).err(), Some(LayoutError::NoCells));
+    }
+
+    #[test]
+    fn grid_fill_order() {
+        let cells = || (0..5).map(|_| empty_cell(10, 20)).collect::<Vec<_>>();
+        let offsets = |fill| {
+            let layout = LayoutNode::Grid(GridLayout::new(cells(), NonZeroU32::new(3).unwrap(), fill, None, 0).unwrap());
+            let size = layout.size();
+            let offsets: Vec<_> = layout.iter_targets().map(|(offset, _)| (offset.x, offset.y)).collect();
+            (size, offsets)
+        };
+
+        let (row_major_size, row_major) = offsets(FillOrder::RowMajor);
+        assert_eq!(row_major, &[(0, 0), (10, 0), (20, 0), (0, 20), (10, 20)]);
+
+        // Column-major grids have the same size, but are filled top to bottom first
+        let (col_major_size, col_major) = offsets(FillOrder::ColumnMajor);
+        assert_eq!(col_major, &[(0, 0), (0, 20), (10, 0), (10, 20), (20, 0)]);
+        assert_eq!(row_major_size, col_major_size);
+
+        // Every column is used, even if only the first column is full
+        let cells = (0..4).map(|_| empty_cell(10, 20)).collect();
+        let layout = LayoutNode::Grid(GridLayout::new(cells, NonZeroU32::new(3).unwrap(), FillOrder::ColumnMajor, None, 0).unwrap());
+        let col_major: Vec<_> = layout.iter_targets().map(|(offset, _)| (offset.x, offset.y)).collect();
+        assert_eq!(col_major, &[(0, 0), (0, 20), (10, 0), (20, 0)]);
+    }
+
+    #[test]
+    fn grid_fills_per_cell_backgrounds() {
+        let red = Rgba::red();
+        let blue = Rgba::blue();
+        let gray = Rgba::new(0.5, 0.5, 0.5, 1.0);
+        let cell = |width, height, background| LayoutNode::Empty {
+            size: Size {
+                width: NonZeroU32::new(width).unwrap(),
+                height: NonZeroU32::new(height).unwrap(),
+            },
+            background,
+        };
+
+        // A 2x2 grid where the top-right and bottom-left cells override the background of the
+        // grid. The bottom-left node is smaller than its cell, but the whole cell is filled.
+        let cells = vec![cell(2, 2, None), cell(2, 2, Some(red)), cell(1, 1, Some(blue)), cell(2, 2, None)];
+        let grid = GridLayout::new(cells, NonZeroU32::new(2).unwrap(), FillOrder::RowMajor, Some(gray), 0)
+            .unwrap()
+            .with_alignment(CellAlignment::Center);
+        let layout = LayoutNode::Grid(grid);
+
+        let Size {width, height} = layout.size();
+        let mut image = RgbaImage::new(width.get(), height.get());
+        for (_, cell, _) in layout.iter_cells() {
+            cell.fill_background(&mut image);
+        }
+
+        for (x, y, &pixel) in image.enumerate_pixels() {
+            let expected = match (x / 2, y / 2) {
+                (1, 0) => red,
+                (0, 1) => blue,
+                _ => gray,
+            };
+            assert_eq!(pixel, to_pixel(expected), "pixel ({}, {}) had the wrong color", x, y);
+        }
+    }
+
+    #[test]
+    fn rendered_images_override_cell_background() {
+        let gray = Rgba::new(0.5, 0.5, 0.5, 1.0);
+        let cells = vec![rendered_image(None), rendered_image(Some(Rgba::red())), empty_cell(2, 2)];
+        let grid = GridLayout::new(cells, NonZeroU32::new(3).unwrap(), FillOrder::RowMajor, Some(gray), 0).unwrap();
+        let backgrounds: Vec<_> = LayoutNode::Grid(grid).iter_cells()
+            .map(|(_, cell, _)| cell.background)
+            .collect();
+        assert_eq!(backgrounds, &[Some(gray), Some(Rgba::red()), Some(gray)]);
+    }
+
+    #[test]
+    fn transparent_images_show_cell_background() {
+        let gray = Rgba::new(0.5, 0.5, 0.5, 1.0);
+        let red = Rgba::red();
+        let blue = Rgba::blue();
+        let empty = LayoutNode::Empty {
+            size: Size {
+                width: NonZeroU32::new(2).unwrap(),
+                height: NonZeroU32::new(2).unwrap(),
+            },
+            background: Some(blue),
+        };
+        let cells = vec![rendered_image(None), rendered_image(Some(red)), empty, rendered_image(Some(blue))];
+        let grid = GridLayout::new(cells, NonZeroU32::new(2).unwrap(), FillOrder::RowMajor, Some(gray), 0).unwrap();
+        let layout = LayoutNode::Grid(grid);
+
+        // Each rendered image is transparent except for its top-left pixel
+        let mut rendered = RgbaImage::new(2, 2);
+        rendered.put_pixel(0, 0, to_pixel(Rgba::white()));
+
+        let Size {width, height} = layout.size();
+        let mut image = RgbaImage::new(width.get(), height.get());
+        for (offset, cell, node) in layout.iter_cells() {
+            cell.fill_background(&mut image);
+            if let LayoutNode::RenderedImage(_) = node {
+                cell.draw_image(&rendered, offset, &mut image);
+            }
+        }
+
+        for (x, y, &pixel) in image.enumerate_pixels() {
+            let background = match (x / 2, y / 2) {
+                (0, 0) => gray,
+                (1, 0) => red,
+                _ => blue,
+            };
+            let is_empty_cell = (x / 2, y / 2) == (0, 1);
+            let expected = if (x % 2, y % 2) == (0, 0) && !is_empty_cell { Rgba::white() } else { background };
+            assert_eq!(pixel, to_pixel(expected), "pixel ({}, {}) had the wrong color", x, y);
+        }
+    }
+
+    #[test]
+    fn grid_spacing_and_padding() {
+        let cells = (0..6).map(|_| empty_cell(10, 20)).collect();
+        let grid = GridLayout::new(cells, NonZeroU32::new(4).unwrap(), FillOrder::RowMajor, None, 0).unwrap()
+            .with_spacing(2, 3);
+        assert_eq!(grid.width().get(), 4*10 + 3*2 + 2*3);
+        assert_eq!(grid.height().get(), 2*20 + 2 + 2*3);
+
+        let offsets: Vec<_> = LayoutNode::Grid(grid).iter_targets()
+            .map(|(offset, _)| (offset.x, offset.y))
+            .collect();
+        assert_eq!(offsets[..2], [(3, 3), (15, 3)]);
+        assert_eq!(offsets[5], (15, 25));
+    }
+
+    #[test]
+    fn grid_cell_alignment() {
+        let cells = || vec![empty_cell(20, 30), empty_cell(10, 10)];
+        let offsets = |align| {
+            let grid = GridLayout::new(cells(), NonZeroU32::new(2).unwrap(), FillOrder::RowMajor, None, 0).unwrap()
+                .with_alignment(align);
+            LayoutNode::Grid(grid).iter_targets()
+                .map(|(offset, _)| (offset.x, offset.y))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(offsets(CellAlignment::TopLeft), &[(0, 0), (20, 0)]);
+        assert_eq!(offsets(CellAlignment::Center), &[(0, 0), (25, 10)]);
+        assert_eq!(offsets(CellAlignment::BottomCenter), &[(0, 0), (25, 20)]);
+    }
+}
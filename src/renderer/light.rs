@@ -1,7 +1,9 @@
 use std::sync::Arc;
 
-use crate::scene::LightType;
-use crate::math::Mat4;
+use crate::scene::{LightType, Geometry, Material};
+use crate::math::{Mat4, Vec3, Quaternion, Rgb, Rgba};
+
+use super::{CelBands, ColorSpace, ShadowMap};
 
 #[derive(Debug, Clone)]
 pub struct Light {
@@ -10,3 +12,264 @@ pub struct Light {
     /// The world transform of the light
     pub world_transform: Mat4,
 }
+
+/// The lights used to shade the geometry being drawn
+#[derive(Debug, Clone, Copy)]
+pub struct Lighting<'a> {
+    pub lights: &'a [Arc<Light>],
+    /// The ambient light in the scene
+    pub ambient_light: Rgb,
+    /// The index of the light that determines where the cel shading bands are drawn, or None if
+    /// the light from every light should be added together before it is split into bands. When a
+    /// key light is used, all other lights add a smooth fill.
+    pub key_light: Option<usize>,
+    /// The bands used to split the light into flat areas of color
+    pub bands: &'a CelBands,
+    /// The shadows cast by one of the lights, or None if no shadows should be drawn
+    pub shadow: Option<&'a ShadowMap>,
+    /// The color space that the lighting is computed in
+    pub color_space: ColorSpace,
+    /// The factor that the lit color is multiplied by before it is written to the image
+    pub exposure: f32,
+    /// The gamma that the lit color is adjusted with after it is encoded
+    pub gamma: f32,
+}
+
+impl<'a> Lighting<'a> {
+    /// Returns lighting that only has a white ambient light, so every surface is drawn in its own
+    /// flat color without any bands or shadows. This is used to draw the gizmos of the lights.
+    pub fn unlit(self) -> Self {
+        Self {
+            lights: &[],
+            ambient_light: Rgb::white(),
+            key_light: None,
+            shadow: None,
+            ..self
+        }
+    }
+}
+
+impl Light {
+    /// Creates a directional light that shines in the given direction. The direction does not need
+    /// to be normalized, but it must not be zero.
+    pub fn directional(direction: Vec3, color: Rgb, intensity: f32) -> Self {
+        assert!(direction.magnitude_squared() > 0.0, "the direction of a light must not be zero");
+
+        // Lights shine down their local negative z-axis
+        let rotation = Quaternion::rotation_from_to_3d(-Vec3::unit_z(), direction);
+        Self {
+            data: Arc::new(LightType::Directional {color, intensity}),
+            world_transform: Mat4::from(rotation),
+        }
+    }
+
+    /// The shape of the geometry that visualizes the position and direction of this light
+    ///
+    /// Point lights are drawn as spheres. Spot and directional lights are drawn as cones that
+    /// open in the direction the light is travelling.
+    pub fn gizmo_shape(&self) -> GizmoShape {
+        use LightType::*;
+        match *self.data {
+            Point {..} => GizmoShape::Sphere,
+            Directional {..} | Spot {..} => GizmoShape::Cone,
+        }
+    }
+
+    /// Returns the transform that places the geometry of the gizmo shape of this light in the
+    /// world, sized to be roughly `size` world units across
+    pub fn gizmo_transform(&self, size: f32) -> Mat4 {
+        use LightType::*;
+        let scale = match *self.data {
+            Point {..} => Vec3::broadcast(size),
+            Directional {..} => Vec3::new(size / 4.0, size / 4.0, size),
+            Spot {outer_cone_angle, ..} => {
+                // The spread of the cone matches the outer cone angle of the light, but is
+                // clamped to keep the gizmo from becoming too flat to see
+                let angle = outer_cone_angle.get_radians().min(60.0f32.to_radians());
+                Vec3::new(size * angle.tan(), size * angle.tan(), size)
+            },
+        };
+
+        self.world_transform * Mat4::scaling_3d(scale)
+    }
+
+    /// The material that the gizmo of this light is drawn with, which has the color of the light
+    pub fn gizmo_material(&self) -> Material {
+        use LightType::*;
+        let color = match *self.data {
+            Point {color, ..} | Directional {color, ..} | Spot {color, ..} => color,
+        };
+
+        Material {
+            diffuse_color: Rgba::from_opaque(color),
+            ..Material::default()
+        }
+    }
+}
+
+/// The shape of the gizmo that visualizes a light. The geometry of each shape is the same for
+/// every light, so it only needs to be uploaded once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GizmoShape {
+    Sphere,
+    Cone,
+}
+
+impl GizmoShape {
+    /// Generates the geometry of this shape at unit scale: a sphere one unit across, or a cone
+    /// with a radius and length of one unit. The geometry should be drawn with the gizmo transform
+    /// and material of a light.
+    pub fn geometry(self) -> Geometry {
+        let material = Arc::new(Material::default());
+        match self {
+            GizmoShape::Sphere => Geometry::uv_sphere(0.5, 12, 8, material),
+            // Opens along the negative z-axis, which is the direction that lights shine in
+            GizmoShape::Cone => Geometry::cone(1.0, 1.0, 12, material),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use approx::{assert_relative_eq, relative_eq};
+
+    use crate::math::Vec4;
+
+    #[test]
+    fn directional_light_shines_in_direction() {
+        for &direction in &[Vec3::new(1.0, -2.0, 0.5), Vec3::unit_z(), -Vec3::unit_z()] {
+            let light = Light::directional(direction, Rgb::white(), 1.0);
+            let shines = Vec3::from(light.world_transform * Vec4::new(0.0, 0.0, -1.0, 0.0));
+            assert_relative_eq!(shines, direction.normalized(), epsilon = 1e-5);
+        }
+    }
+
+    #[test]
+    fn point_light_gizmo_surrounds_light() {
+        let position = Vec3::new(1.0, 2.0, -3.0);
+        let light = Light {
+            data: Arc::new(LightType::Point {color: Rgb::red(), intensity: 1.0, range: None}),
+            world_transform: Mat4::translation_3d(position),
+        };
+
+        assert_eq!(light.gizmo_material().diffuse_color, Rgba::red());
+        let gizmo = light.gizmo_shape().geometry();
+        assert!(gizmo.indices.iter().all(|&index| (index as usize) < gizmo.positions.len()));
+
+        // The light's position is inside of the gizmo once it is placed in the world
+        let transform = light.gizmo_transform(0.5);
+        let world_positions = gizmo.positions.iter().map(|&pos| transform.mul_point(pos));
+        let (min, max) = crate::math::bounds(world_positions).unwrap();
+        assert!(min.x < position.x && min.y < position.y && min.z < position.z);
+        assert!(max.x > position.x && max.y > position.y && max.z > position.z);
+    }
+
+    #[test]
+    fn gizmo_at_light_screen_position() {
+        use crate::math::{Degrees, Handedness};
+
+        let eye = Vec3::new(0.0, 1.0, 5.0);
+        let view_projection = Handedness::Right.perspective_no(0.8, 1.0, 0.1, 100.0)
+            * Handedness::Right.look_at(eye, Vec3::zero(), Vec3::up());
+        // The position in normalized device coordinates, with the depth left out
+        let to_screen = |position: Vec3| {
+            let clip = view_projection * Vec4::from_point(position);
+            Vec3::new(clip.x / clip.w, clip.y / clip.w, 0.0)
+        };
+
+        let spot = LightType::Spot {
+            color: Rgb::white(),
+            intensity: 1.0,
+            range: None,
+            inner_cone_angle: Degrees::from_degrees(10.0).into(),
+            outer_cone_angle: Degrees::from_degrees(30.0).into(),
+        };
+        let lights = vec![
+            Light {
+                data: Arc::new(LightType::Point {color: Rgb::white(), intensity: 1.0, range: None}),
+                world_transform: Mat4::translation_3d(Vec3::new(1.0, 0.5, -1.0)),
+            },
+            Light {
+                data: Arc::new(spot),
+                world_transform: Mat4::translation_3d(Vec3::new(-1.5, 2.0, 0.0)) * Mat4::rotation_x(-1.2),
+            },
+            Light::directional(Vec3::new(1.0, -1.0, -1.0), Rgb::white(), 1.0),
+        ];
+
+        for light in &lights {
+            let light_position = to_screen(light.world_transform.mul_point(Vec3::zero()));
+            let transform = light.gizmo_transform(0.25);
+            let gizmo = light.gizmo_shape().geometry();
+            let (min, max) = crate::math::bounds(gizmo.positions.iter()
+                .map(|&position| to_screen(transform.mul_point(position)))).unwrap();
+
+            // The gizmo covers the point on the screen where the light is, and is small enough to
+            // not hide the rest of the image
+            let epsilon = Vec3::broadcast(1e-5);
+            assert!(light_position.partial_cmpge(&(min - epsilon)).reduce_and()
+                && light_position.partial_cmple(&(max + epsilon)).reduce_and(),
+                "{:?} at {:?} is not over its light at {:?}", light.data, (min, max), light_position);
+            let extent = max - min;
+            assert!(extent.x < 0.2 && extent.y < 0.2, "{:?} is too large on the screen", light.data);
+        }
+    }
+
+    #[test]
+    fn gizmo_drawn_in_light_color() {
+        use glium::uniforms::{Uniforms, UniformValue};
+
+        use crate::renderer::shader::cel::{Cel, CelUniforms};
+
+        let light = Arc::new(Light::directional(Vec3::new(1.0, -1.0, 0.0), Rgb::new(0.0, 1.0, 0.5), 2.0));
+        let material = light.gizmo_material();
+        let bands = CelBands::default();
+        let lights = vec![light];
+        let lighting = Lighting {
+            lights: &lights,
+            ambient_light: Rgb::black(),
+            key_light: Some(0),
+            bands: &bands,
+            shadow: None,
+            color_space: ColorSpace::default(),
+            exposure: 1.0,
+            gamma: 1.0,
+        };
+
+        let Lighting {lights, ambient_light, key_light, bands, shadow, color_space, exposure, gamma} = lighting.unlit();
+        let cel = Cel::from(CelUniforms {
+            mvp: Mat4::identity(),
+            model_transform: Mat4::identity(),
+            model_inverse_transpose: Mat4::identity(),
+            lights,
+            ambient_light,
+            key_light,
+            bands,
+            material: &material,
+            base_color_texture: None,
+            emissive_texture: None,
+            normal_texture: None,
+            shadow,
+            eye_position: Vec3::zero(),
+            rim: None,
+            specular: None,
+            color_space,
+            exposure,
+            gamma,
+        });
+
+        // With no lights and a white ambient light, the cel shader draws the diffuse color of the
+        // material as is, which is the color of the light
+        let mut values = Vec::new();
+        cel.visit_values(|name, value| values.push((name.to_string(), value)));
+        let value = |name: &str| values.iter()
+            .find(|(uniform_name, _)| uniform_name == name)
+            .map(|&(_, value)| value)
+            .unwrap_or_else(|| panic!("{} uniform was not set", name));
+        assert!(matches!(value("num_lights"), UniformValue::SignedInt(0)));
+        assert!(matches!(value("key_light"), UniformValue::SignedInt(-1)));
+        assert!(matches!(value("ambient_light"), UniformValue::Vec3([1.0, 1.0, 1.0])));
+        assert!(matches!(value("material.diffuse_color"), UniformValue::Vec4([0.0, 1.0, 0.5, 1.0])));
+    }
+}
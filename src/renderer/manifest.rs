@@ -0,0 +1,201 @@
+use std::num::NonZeroU32;
+
+use serde_json::json;
+
+use super::{
+    Size,
+    layout::{LayoutNode, LayoutOffset},
+};
+
+/// Describes where each rendered image is drawn in the image generated by a layout, so that the
+/// image can be sliced back into its frames (e.g. by a game engine)
+#[derive(Debug, Clone, PartialEq)]
+pub struct SheetManifest {
+    /// The width of the generated image (in pixels)
+    pub width: u32,
+    /// The height of the generated image (in pixels)
+    pub height: u32,
+    /// Every rendered image in the layout, in the order that the images are drawn
+    pub frames: Vec<SheetFrame>,
+}
+
+/// The area of the generated image that a single rendered image is drawn into
+#[derive(Debug, Clone, PartialEq)]
+pub struct SheetFrame {
+    /// The name of the frame, which is its index in `SheetManifest::frames`
+    pub name: String,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl SheetManifest {
+    /// Computes the manifest of the given layout. The offsets come from the same iteration that is
+    /// used to draw the layout, so they always match the drawn pixels.
+    ///
+    /// Frames are scaled up by `scale`. If `flip_y` is true, the rows of the image are assumed to
+    /// be stored from the bottom to the top, so each frame is flipped to match.
+    pub(in super) fn from_layout(layout: LayoutNode, scale: NonZeroU32, flip_y: bool) -> Self {
+        let scale = scale.get();
+        let Size {width, height} = layout.size();
+        let (width, height) = (width.get() * scale, height.get() * scale);
+
+        let mut targets = Vec::new();
+        collect_targets(layout, LayoutOffset {x: 0, y: 0}, &mut targets);
+
+        let frames = targets.into_iter().enumerate().map(|(i, (offset, size))| {
+            let frame_height = size.height.get() * scale;
+            let y = offset.y * scale;
+            SheetFrame {
+                name: i.to_string(),
+                x: offset.x * scale,
+                y: if flip_y { height - y - frame_height } else { y },
+                width: size.width.get() * scale,
+                height: frame_height,
+            }
+        }).collect();
+
+        Self {width, height, frames}
+    }
+
+    /// Serializes this manifest as JSON in the "JSON (Array)" format used by TexturePacker, which
+    /// many game engines are able to import
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        let frames: Vec<_> = self.frames.iter().map(|frame| {
+            let &SheetFrame {ref name, x, y, width: w, height: h} = frame;
+            json!({
+                "filename": name,
+                "frame": {"x": x, "y": y, "w": w, "h": h},
+                "rotated": false,
+                "trimmed": false,
+                "spriteSourceSize": {"x": 0, "y": 0, "w": w, "h": h},
+                "sourceSize": {"w": w, "h": h},
+            })
+        }).collect();
+
+        serde_json::to_string_pretty(&json!({
+            "frames": frames,
+            "meta": {
+                "app": "spritec",
+                "size": {"w": self.width, "h": self.height},
+                "scale": "1",
+            },
+        }))
+    }
+}
+
+/// Adds the offset and size of every rendered image in the given node to `targets`. Nested layouts
+/// are drawn relative to the offset of their cell.
+fn collect_targets(node: LayoutNode, origin: LayoutOffset, targets: &mut Vec<(LayoutOffset, Size)>) {
+    for (offset, node) in node.iter_targets() {
+        let offset = LayoutOffset {x: origin.x + offset.x, y: origin.y + offset.y};
+
+        use LayoutNode::*;
+        match node {
+            RenderedImage(image) => targets.push((offset, image.size)),
+            Grid(_) | Packed(_) => collect_targets(node, offset, targets),
+            // Empty cells are gaps in the layout, not frames
+            Empty {..} => {},
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+
+    use crate::math::{Mat4, Rgb, Rgba, Handedness};
+    use crate::renderer::{
+        RenderedImage,
+        AspectMode,
+        Background,
+        Camera,
+        RenderCamera,
+        RenderLights,
+        RenderGeometry,
+        CelBands,
+        ShadingMode,
+        ColorSpace,
+        Outline,
+        OutlineMode,
+        FillOrder,
+        layout::GridLayout,
+    };
+
+    fn size(width: u32, height: u32) -> Size {
+        Size {
+            width: NonZeroU32::new(width).unwrap(),
+            height: NonZeroU32::new(height).unwrap(),
+        }
+    }
+
+    fn rendered_image(width: u32, height: u32) -> LayoutNode {
+        LayoutNode::RenderedImage(RenderedImage {
+            size: size(width, height),
+            background: Background::default(),
+            cell_background: None,
+            camera: RenderCamera::Camera(Arc::new(Camera {
+                view: Mat4::identity(),
+                projection: Mat4::identity(),
+                handedness: Handedness::Right,
+            })),
+            framing: None,
+            aspect: AspectMode::Stretch,
+            lights: RenderLights::Lights(Arc::new(Vec::new())),
+            ambient_light: Rgb::white(),
+            key_light: None,
+            bands: CelBands::default(),
+            shading: ShadingMode::Cel,
+            color_space: ColorSpace::Linear,
+            exposure: 1.0,
+            gamma: 1.0,
+            geometry: RenderGeometry::Geometry(Arc::new(Vec::new())),
+            outline: Outline {
+                mode: OutlineMode::InvertedHull {thickness: 0.0},
+                color: Rgba::black(),
+            },
+            rim: None,
+            specular: None,
+            shadows: None,
+            ground: None,
+            light_gizmos: false,
+            wireframe: None,
+            supersample: None,
+            depth: None,
+        })
+    }
+
+    #[test]
+    fn manifest_matches_grid_and_skips_empty_cells() {
+        // A column of three cells with the middle one left empty
+        let layout = || {
+            let cells = vec![
+                rendered_image(10, 20),
+                LayoutNode::Empty {size: size(10, 20), background: None},
+                rendered_image(10, 20),
+            ];
+            let grid = GridLayout::new(cells, NonZeroU32::new(1).unwrap(), FillOrder::RowMajor, None, 0).unwrap()
+                .with_spacing(1, 0);
+            LayoutNode::Grid(grid)
+        };
+
+        let manifest = SheetManifest::from_layout(layout(), NonZeroU32::new(1).unwrap(), false);
+        assert_eq!((manifest.width, manifest.height), (10, 62));
+        let rects: Vec<_> = manifest.frames.iter()
+            .map(|frame| (frame.name.as_str(), frame.x, frame.y, frame.width, frame.height))
+            .collect();
+        assert_eq!(rects, &[("0", 0, 0, 10, 20), ("1", 0, 42, 10, 20)]);
+
+        // Scaling and flipping move every frame along with the pixels
+        let manifest = SheetManifest::from_layout(layout(), NonZeroU32::new(2).unwrap(), true);
+        let rects: Vec<_> = manifest.frames.iter().map(|frame| (frame.x, frame.y)).collect();
+        assert_eq!(rects, &[(0, 84), (0, 0)]);
+
+        let json: serde_json::Value = serde_json::from_str(&manifest.to_json().unwrap()).unwrap();
+        assert_eq!(json["frames"][1]["frame"]["h"], 40);
+        assert_eq!(json["meta"]["size"]["w"], 20);
+    }
+}
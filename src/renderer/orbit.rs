@@ -0,0 +1,217 @@
+use std::f32::consts::PI;
+use std::num::NonZeroU32;
+use std::sync::Arc;
+
+use crate::math::Mat4;
+use crate::query3d::QueryError;
+
+use super::{
+    RenderedImage,
+    RenderCamera,
+    Camera,
+    RenderNode,
+    RenderLayout,
+    LayoutType,
+    FillOrder,
+    CellAlignment,
+};
+
+/// Returns `count` copies of the given camera, rotated around the world y-axis in equal steps of
+/// exactly `360 / count` degrees. The first camera is the same as the given camera.
+///
+/// The cameras orbit the world origin in the same direction as `OrbitFrames`. Models that are not
+/// centered on the origin can be kept in view by using `Framing::Fit` with each camera.
+pub fn turntable_cameras(base: &Camera, count: u32) -> Vec<Camera> {
+    (0..count).map(|step| orbit_camera(base, step, count)).collect()
+}
+
+/// Returns a single row grid of `count` copies of the given image, each drawn with the camera
+/// rotated a further step around the world y-axis (see `turntable_cameras`). This produces a
+/// sprite sheet of the model from every direction.
+pub fn turntable_grid(image: RenderedImage, count: NonZeroU32) -> Result<RenderNode, QueryError> {
+    let camera = image.camera.fetch_camera()?;
+    let nodes = turntable_cameras(&camera, count.get()).into_iter()
+        .map(|camera| RenderNode::RenderedImage(RenderedImage {
+            camera: RenderCamera::Camera(Arc::new(camera)),
+            ..image.clone()
+        }))
+        .collect();
+
+    Ok(RenderNode::Layout(RenderLayout {
+        nodes,
+        layout: LayoutType::Grid {
+            cols: count,
+            fill: FillOrder::RowMajor,
+            spacing: 0,
+            padding: 0,
+            align: CellAlignment::default(),
+        },
+        background: None,
+        bleed: 0,
+    }))
+}
+
+/// Rotates the given camera `step` steps of a full rotation split into `steps` steps
+fn orbit_camera(camera: &Camera, step: u32, steps: u32) -> Camera {
+    let angle = step as f32 / steps as f32 * 2.0 * PI;
+
+    // Moving the camera around the world is the same as rotating the world the other way around
+    // the camera
+    Camera {
+        view: camera.view * Mat4::rotation_y(-angle),
+        ..camera.clone()
+    }
+}
+
+/// An iterator over copies of an image with the camera rotated around the world origin
+///
+/// The camera orbits counter-clockwise (when viewed from above) around the world y-axis, taking
+/// equal steps to complete one full rotation. Every frame shares the same camera, lights, and
+/// geometry as the original image, so nothing is queried from the file again.
+#[derive(Debug, Clone)]
+pub struct OrbitFrames {
+    image: RenderedImage,
+    camera: Arc<Camera>,
+    steps: u32,
+    current: u32,
+}
+
+impl OrbitFrames {
+    /// Creates an orbit around the given image. The image should already be resolved (see
+    /// `RenderedImage::resolve`), otherwise its geometry and lights will be queried from the file
+    /// every time a frame is drawn.
+    pub fn new(image: RenderedImage, steps: NonZeroU32) -> Result<Self, QueryError> {
+        let camera = image.camera.fetch_camera()?;
+
+        Ok(Self {
+            image,
+            camera,
+            steps: steps.get(),
+            current: 0,
+        })
+    }
+}
+
+impl Iterator for OrbitFrames {
+    type Item = RenderedImage;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.current >= self.steps {
+            return None;
+        }
+
+        let camera = orbit_camera(&self.camera, self.current, self.steps);
+        self.current += 1;
+
+        Some(RenderedImage {
+            camera: RenderCamera::Camera(Arc::new(camera)),
+            ..self.image.clone()
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = (self.steps - self.current) as usize;
+        (remaining, Some(remaining))
+    }
+}
+
+impl ExactSizeIterator for OrbitFrames {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::math::{Vec3, Rgb, Rgba, Handedness};
+    use crate::renderer::{Size, AspectMode, Background, RenderLights, RenderGeometry, Outline, OutlineMode, CelBands, ShadingMode, ColorSpace};
+
+    #[test]
+    fn orbit_shares_resolved_geometry() {
+        let geometry = Arc::new(Vec::new());
+        let image = RenderedImage {
+            size: Size {
+                width: NonZeroU32::new(8).unwrap(),
+                height: NonZeroU32::new(8).unwrap(),
+            },
+            background: Background::default(),
+            cell_background: None,
+            camera: RenderCamera::Camera(Arc::new(Camera {
+                view: Mat4::look_at_rh(Vec3::new(0.0, 2.0, 10.0), Vec3::zero(), Vec3::up()),
+                projection: Mat4::identity(),
+                handedness: Handedness::Right,
+            })),
+            framing: None,
+            aspect: AspectMode::Stretch,
+            lights: RenderLights::Lights(Arc::new(Vec::new())),
+            ambient_light: Rgb::white(),
+            key_light: None,
+            bands: CelBands::default(),
+            shading: ShadingMode::Cel,
+            color_space: ColorSpace::Linear,
+            exposure: 1.0,
+            gamma: 1.0,
+            geometry: RenderGeometry::Geometry(geometry.clone()),
+            outline: Outline {
+                mode: OutlineMode::InvertedHull {thickness: 0.0},
+                color: Rgba::black(),
+            },
+            rim: None,
+            specular: None,
+            shadows: None,
+            ground: None,
+            light_gizmos: false,
+            wireframe: None,
+            supersample: None,
+            depth: None,
+        };
+
+        let frames: Vec<_> = OrbitFrames::new(image, NonZeroU32::new(8).unwrap()).unwrap().collect();
+        assert_eq!(frames.len(), 8);
+
+        let eyes: Vec<_> = frames.iter().map(|frame| {
+            // Every frame reuses the geometry that was resolved once up front
+            match &frame.geometry {
+                RenderGeometry::Geometry(geo) => assert!(Arc::ptr_eq(geo, &geometry)),
+                RenderGeometry::Query(_) | RenderGeometry::Queries(_) | RenderGeometry::Instances {..} => {
+                    panic!("orbit frame re-queried its geometry")
+                },
+            }
+
+            let camera = frame.camera.fetch_camera().unwrap();
+            camera.view.inverted().mul_point(Vec3::zero())
+        }).collect();
+
+        for (i, eye) in eyes.iter().enumerate() {
+            // The camera stays the same distance from the origin and at the same height
+            assert!((eye.magnitude() - eyes[0].magnitude()).abs() < 1e-4);
+            assert!((eye.y - 2.0).abs() < 1e-4);
+            // Every frame is from a different position
+            for other in &eyes[i+1..] {
+                assert!(eye.distance(*other) > 1.0);
+            }
+        }
+    }
+
+    #[test]
+    fn turntable_steps_evenly_around_y_axis() {
+        let base = Camera {
+            view: Mat4::look_at_rh(Vec3::new(0.0, 0.0, 10.0), Vec3::zero(), Vec3::up()),
+            projection: Mat4::identity(),
+            handedness: Handedness::Right,
+        };
+        assert!(turntable_cameras(&base, 0).is_empty());
+
+        let eyes: Vec<_> = turntable_cameras(&base, 4).iter()
+            .map(|camera| camera.view.inverted().mul_point(Vec3::zero()))
+            .collect();
+        // Each step is a quarter turn counter-clockwise when viewed from above
+        let expected = [
+            Vec3::new(0.0, 0.0, 10.0),
+            Vec3::new(10.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -10.0),
+            Vec3::new(-10.0, 0.0, 0.0),
+        ];
+        for (eye, expected) in eyes.iter().zip(&expected) {
+            assert!(eye.distance(*expected) < 1e-4, "expected {:?}, got {:?}", expected, eye);
+        }
+    }
+}
@@ -0,0 +1,47 @@
+use std::path::Path;
+
+use image::{DynamicImage, ImageFormat, ImageOutputFormat, ImageResult};
+
+/// Encodes a generated image as an RGBA8 PNG file, converting from the pixel format of the image
+pub fn to_png_bytes(image: &DynamicImage) -> ImageResult<Vec<u8>> {
+    let image = DynamicImage::ImageRgba8(image.to_rgba());
+    let mut bytes = Vec::new();
+    image.write_to(&mut bytes, ImageOutputFormat::Png)?;
+    Ok(bytes)
+}
+
+/// Writes a generated image to the given path as a PNG file, regardless of the extension of the
+/// path
+pub fn write_png(image: &DynamicImage, path: &Path) -> ImageResult<()> {
+    image.save_with_format(path, ImageFormat::Png)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use image::{ColorType, GrayImage, RgbaImage};
+
+    #[test]
+    fn png_bytes_round_trip() {
+        let mut image = RgbaImage::new(3, 2);
+        image.put_pixel(1, 0, image::Rgba([10, 20, 30, 40]));
+        let image = DynamicImage::ImageRgba8(image);
+
+        let bytes = to_png_bytes(&image).unwrap();
+        // Every PNG file starts with the same signature
+        assert_eq!(&bytes[..8], b"\x89PNG\r\n\x1a\n");
+
+        let decoded = image::load_from_memory(&bytes).unwrap();
+        assert_eq!(decoded.to_rgba().into_raw(), image.to_rgba().into_raw());
+    }
+
+    #[test]
+    fn png_bytes_are_rgba8() {
+        let image = DynamicImage::ImageLuma8(GrayImage::from_raw(2, 1, vec![0, 200]).unwrap());
+
+        let decoded = image::load_from_memory(&to_png_bytes(&image).unwrap()).unwrap();
+        assert_eq!(decoded.color(), ColorType::Rgba8);
+        assert_eq!(decoded.to_rgba().into_raw(), vec![0, 0, 0, 255, 200, 200, 200, 255]);
+    }
+}
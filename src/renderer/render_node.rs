@@ -1,29 +1,91 @@
 use std::num::NonZeroU32;
 
+use crate::math::Rgba;
+
 use super::{RenderedImage, Size};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum RenderNode {
     RenderedImage(RenderedImage),
     Layout(RenderLayout),
     /// An empty slot, used to create a gap/empty cell in the layout
-    Empty {size: Size},
+    Empty {
+        size: Size,
+        /// The color to fill the slot with, or None if the background of the layout should be
+        /// used for this slot
+        background: Option<Rgba>,
+    },
 }
 
 /// Lays out one or more nodes in the given configuration
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct RenderLayout {
     pub nodes: Vec<RenderNode>,
     pub layout: LayoutType,
+    /// The color to fill each cell of the layout with before the cell is drawn, or None if the
+    /// cells should be left transparent
+    pub background: Option<Rgba>,
+    /// The number of pixels to extrude the edge pixels of each cell outward by. This reserves a
+    /// gutter of that size around every cell so that colors from neighboring cells do not bleed
+    /// into each other when the image is sampled with filtering (e.g. as a texture atlas).
+    pub bleed: u32,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum LayoutType {
-  /// All renders are placed in a regular grid with the given number of columns
-  Grid { cols: NonZeroU32 },
+  /// All renders are placed in a regular grid with the given number of columns, filled in the
+  /// given order
+  Grid {
+    cols: NonZeroU32,
+    fill: FillOrder,
+    /// The number of transparent pixels between neighboring cells
+    spacing: u32,
+    /// The number of transparent pixels around the outside of the grid
+    padding: u32,
+    /// The position of each render within its cell, when the render is smaller than the cell
+    align: CellAlignment,
+  },
+
+  /// Tightly packs all sprites into an image of width at most the given value. The packing is not
+  /// guaranteed to be a regular grid.
+  Packed { width: NonZeroU32 },
+}
+
+/// The order in which the cells of a grid are filled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FillOrder {
+    /// Each row is filled from left to right before moving down to the next row
+    #[default]
+    RowMajor,
+    /// Each column is filled from top to bottom before moving right to the next column. The
+    /// filled cells are the same as in row-major order, so if the last row is not full, the
+    /// columns to the right of its last cell are one cell shorter.
+    ColumnMajor,
+}
+
+/// The position of a render within a grid cell that is larger than the render
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CellAlignment {
+    #[default]
+    TopLeft,
+    TopCenter,
+    Center,
+    BottomCenter,
+}
+
+impl CellAlignment {
+    /// Returns the (x, y) offset from the top-left corner of a cell of size `cell` at which a
+    /// node of size `node` should be drawn
+    pub fn offset(self, cell: Size, node: Size) -> (u32, u32) {
+        let extra_width = cell.width.get().saturating_sub(node.width.get());
+        let extra_height = cell.height.get().saturating_sub(node.height.get());
 
-  //TODO: This is an example of a layout we could have in the future
-  // Tightly packs all sprites into an image of width at most the given value. The packing is not
-  // guaranteed to be a regular grid.
-  //Packed { width: NonZeroU32 },
+        use CellAlignment::*;
+        match self {
+            TopLeft => (0, 0),
+            TopCenter => (extra_width / 2, 0),
+            Center => (extra_width / 2, extra_height / 2),
+            BottomCenter => (extra_width / 2, extra_height),
+        }
+    }
 }
@@ -1,32 +1,104 @@
 use std::num::NonZeroU32;
 use std::sync::{Arc, Mutex};
+use std::path::Path;
+use std::collections::HashMap;
 
-use crate::math::{Rgb, Rgba};
+use image::RgbaImage;
+use thiserror::Error;
+use crate::math::{Rgb, Rgba, Mat4};
 
 use crate::query3d::{GeometryQuery, LightQuery, CameraQuery, File, QueryError, QueryBackend};
 
-use super::{Camera, Light};
+use super::{Camera, Framing, AspectMode, Light, Display, ShaderGeometry, DepthMode, Shadows, GroundPlane};
+use super::shader::cel::MAX_BANDS;
 
 /// An image that will be rendered using the given information
 #[derive(Debug, Clone)]
 pub struct RenderedImage {
     /// The size at which to render the generated image
     pub size: Size,
-    /// The background color of the generated image
-    pub background: Rgba,
+    /// What to draw behind the geometry in the generated image
+    pub background: Background,
+    /// The color to fill the layout cell that this image is drawn into with, or None if the
+    /// background of the layout should be used. This works the same way as the background of
+    /// `RenderNode::Empty`, and has no effect outside of a layout.
+    ///
+    /// The whole cell is filled before the image is drawn into it, so `background` is drawn on top
+    /// wherever the image covers the cell. The cell background only shows through where the image
+    /// is smaller than its cell or where `background` is transparent.
+    pub cell_background: Option<Rgba>,
     /// The camera perspective from which to render each frame
     pub camera: RenderCamera,
+    /// Adjusts the camera to position the geometry within the image, or None if the camera should
+    /// be used as is
+    pub framing: Option<Framing>,
+    /// The way that the view of the camera is fitted to the image when their aspect ratios differ
+    pub aspect: AspectMode,
     /// The lights to use to light the rendered scene
     pub lights: RenderLights,
-    /// The ambient light in the scene
+    /// The ambient light in the scene. This is the only ambient light added to the geometry, so
+    /// black leaves any surface that no light reaches completely dark.
     pub ambient_light: Rgb,
+    /// The index of the light that determines where the cel shading bands are drawn, or None if
+    /// the light from every light should be added together before it is split into bands. When a
+    /// key light is used, all other lights add a smooth fill.
+    pub key_light: Option<usize>,
+    /// The bands used to split the light on each surface into flat areas of color
+    pub bands: CelBands,
+    /// The technique used to shade the geometry
+    pub shading: ShadingMode,
+    /// The color space that the lighting is computed in
+    pub color_space: ColorSpace,
+    /// The factor that the lit color of the geometry is multiplied by before it is encoded and
+    /// written to the image. 1.0 leaves the color unchanged.
+    pub exposure: f32,
+    /// The gamma of the lit color of the geometry: once the color is encoded, each component is
+    /// raised to the power of `1 / gamma` and clamped between 0 and 1. Values greater than 1.0
+    /// brighten the midtones, and 1.0 leaves the color unchanged. Must be greater than zero.
+    pub gamma: f32,
     /// The geometry to draw in the rendered image
-    pub geometry: FileQuery<GeometryQuery>,
+    pub geometry: RenderGeometry,
     /// The outline to use when drawing the geometry
     pub outline: Outline,
+    /// The rim light to add around the edges of the geometry, or None if no rim light should be
+    /// drawn
+    pub rim: Option<Rim>,
+    /// The specular highlight to add to the geometry for each light, or None if no specular
+    /// highlights should be drawn
+    pub specular: Option<Specular>,
+    /// The shadows cast by the primary directional light, or None if no shadows should be drawn.
+    /// Only used with cel shading.
+    pub shadows: Option<Shadows>,
+    /// The ground plane drawn beneath the geometry, or None if no ground plane should be drawn
+    pub ground: Option<GroundPlane>,
+    /// Debugging option that draws a small shape at the position of each light so the lighting
+    /// setup of the scene can be seen
+    pub light_gizmos: bool,
+    /// Debugging option that draws the edges of every triangle of the geometry, or None if no
+    /// wireframe should be drawn
+    pub wireframe: Option<Wireframe>,
+    /// Renders the image at a higher resolution and then scales it down to reduce aliasing, or
+    /// None if the image should be rendered at its actual size
+    pub supersample: Option<Supersample>,
+    /// Also produces a depth image for this image, stored in the given way, or None if no depth
+    /// image is needed
+    pub depth: Option<DepthMode>,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+impl RenderedImage {
+    /// Fetches the camera, lights, and geometry of this image so that they do not need to be
+    /// queried from the file again when this image (or a copy of it) is drawn
+    pub fn resolve(self, display: &Display) -> Result<Self, QueryError> {
+        Ok(Self {
+            camera: RenderCamera::Camera(self.camera.fetch_camera()?),
+            lights: RenderLights::Lights(self.lights.fetch_lights()?),
+            geometry: RenderGeometry::Geometry(self.geometry.fetch_geometry(display)?),
+            ..self
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Size {
     pub width: NonZeroU32,
     pub height: NonZeroU32,
@@ -52,16 +124,318 @@ impl Size {
             height: unsafe { NonZeroU32::new_unchecked(height.get().max(other.height.get())) },
         }
     }
+
+    /// The width of this size divided by its height
+    pub fn aspect_ratio(self) -> f32 {
+        self.width.get() as f32 / self.height.get() as f32
+    }
+
+    /// Multiplies both dimensions by the given factor, rounding to the nearest pixel. Each
+    /// dimension is always at least one pixel, no matter how small the factor is.
+    ///
+    /// The factor must be greater than zero.
+    pub fn scaled(self, factor: f32) -> Self {
+        assert!(factor > 0.0, "a size must be scaled by a factor greater than zero");
+
+        let scale = |value: NonZeroU32| {
+            let scaled = (value.get() as f32 * factor).round().max(1.0) as u32;
+            // Safe because the value was clamped to at least one
+            unsafe { NonZeroU32::new_unchecked(scaled) }
+        };
+        Self {
+            width: scale(self.width),
+            height: scale(self.height),
+        }
+    }
+
+    /// Returns the largest size with the same aspect ratio as this size that fits within the given
+    /// bounds. The result may be larger than this size if the bounds are larger in both
+    /// dimensions. Rounding may change the aspect ratio slightly, and each dimension is always at
+    /// least one pixel.
+    pub fn fit_within(self, bounds: Self) -> Self {
+        let factor = (bounds.width.get() as f32 / self.width.get() as f32)
+            .min(bounds.height.get() as f32 / self.height.get() as f32);
+        let Self {width, height} = self.scaled(factor);
+
+        // Guards against floating point error pushing a dimension past the bounds
+        Self {
+            width: width.min(bounds.width),
+            height: height.min(bounds.height),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Outline {
-    /// The outline thickness to use when drawing the generated image
+    /// The technique used to draw the outline
+    pub mode: OutlineMode,
+    /// The color of the outline to draw
+    pub color: Rgba,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutlineMode {
+    /// Draws only the back faces of the geometry, pushed outward along their normals, behind the
+    /// front faces of the regular geometry. Only the part of the expanded "hull" that pokes out
+    /// from behind the geometry remains visible, which produces an outline that follows the
+    /// silhouette of the geometry.
+    InvertedHull {
+        /// The distance (in model units) that each vertex is pushed along its normal. A value of
+        /// zero disables the outline.
+        ///
+        /// The value must not be negative.
+        thickness: f32,
+    },
+    /// Renders the normal and the depth of every pixel in a second pass, then draws an outline
+    /// wherever neighboring pixels differ by too much. Unlike the inverted hull, this also outlines
+    /// creases and overlapping parts inside the silhouette of the geometry.
+    EdgeDetect {
+        /// The width of the outline (in pixels of the final image). A value of zero disables
+        /// the outline.
+        thickness: u32,
+        /// Neighboring pixels are on an edge if their distances in front of the camera differ by
+        /// more than this fraction of the nearer distance
+        depth_threshold: f32,
+        /// Neighboring pixels are on an edge if the angle between their normals (in radians) is
+        /// larger than this
+        normal_threshold: f32,
+    },
+}
+
+impl OutlineMode {
+    /// Returns the distance that the inverted hull is pushed out from the geometry, or None if no
+    /// inverted hull should be drawn
+    pub fn hull_thickness(self) -> Option<f32> {
+        match self {
+            OutlineMode::InvertedHull {thickness} if thickness > 0.0 => Some(thickness),
+            _ => None,
+        }
+    }
+}
+
+/// A light added to the edges of the geometry that face away from the camera, based on the
+/// fresnel effect. The amount of light also depends on the index of refraction of each material.
+///
+/// This is added after the cel shading, so it is not split into bands.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rim {
+    /// The color of the rim light
+    pub color: Rgb,
+    /// Controls how quickly the rim light fades as surfaces face towards the camera. Larger
+    /// values produce a thinner rim. A value of zero disables the rim light.
     ///
     /// The value must not be negative.
-    pub thickness: f32,
-    /// The color of the outline to draw
+    pub power: f32,
+}
+
+/// The edges of every triangle of the geometry drawn as lines, used to debug the topology of a
+/// mesh
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Wireframe {
+    /// The color of the lines
     pub color: Rgba,
+    /// The width of the lines (in pixels of the image, before it is scaled)
+    ///
+    /// The value must be greater than zero. Lines are drawn by the driver, which is only required
+    /// to support a width of 1 in OpenGL core profiles. Many drivers draw wider lines one pixel
+    /// wide, so this should only be relied on for debugging.
+    pub thickness: f32,
+    /// If true, the lines are drawn over the shaded geometry. If false, the geometry is not shaded
+    /// and only the lines are drawn.
+    pub overlay: bool,
+    /// If true, only the edges of surfaces that can be seen are drawn. If false, the edges of
+    /// every surface are drawn, including the ones that are hidden behind other surfaces.
+    pub depth_test: bool,
+}
+
+/// A highlight added where a light reflects off of a surface towards the camera
+///
+/// The highlight is not split into the cel shading bands. Lights that are banded produce a
+/// highlight that is either fully lit or not lit at all. Other lights produce a smooth highlight.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Specular {
+    /// The color of the highlight
+    pub color: Rgb,
+    /// Controls the size of the highlight. Larger values produce a smaller highlight.
+    ///
+    /// The value must be greater than zero.
+    pub power: f32,
+    /// The brightness of the highlight. A value of zero disables the highlight.
+    pub intensity: f32,
+}
+
+/// The technique used to shade the surfaces of the geometry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ShadingMode {
+    /// Splits the light on each surface into cel shading bands, with an outline, rim light, and
+    /// specular highlights if they are configured
+    #[default]
+    Cel,
+    /// Applies the light on each surface smoothly with a single diffuse term. The cel shading
+    /// bands, outline, rim light, and specular highlights are all ignored.
+    Flat,
+    /// Draws the silhouette of the geometry in solid white over a transparent background, ignoring
+    /// lighting and color. Parts of the geometry cut out by the alpha of their material are not
+    /// part of the silhouette. The background, ground plane, light gizmos, outline, rim light,
+    /// specular highlights, and shadows are all ignored.
+    Mask,
+}
+
+/// The color space that the lighting of the geometry is computed in
+///
+/// Textures are stored as sRGB, so in linear space each texel is decoded with the inverse of the
+/// sRGB transfer function before lighting is applied:
+///
+/// ```text
+/// linear = srgb / 12.92                        if srgb <= 0.04045
+/// linear = ((srgb + 0.055) / 1.055) ^ 2.4      otherwise
+/// ```
+///
+/// and the lit color is encoded with the sRGB transfer function before it is written to the image:
+///
+/// ```text
+/// srgb = linear * 12.92                        if linear <= 0.0031308
+/// srgb = 1.055 * linear ^ (1 / 2.4) - 0.055    otherwise
+/// ```
+///
+/// Material colors (e.g. the glTF base color factor), vertex colors, and light colors are already
+/// linear, so they are never converted. (8-bit vertex colors in PLY files are decoded from sRGB
+/// when the file is loaded.) Colors that are not lit (the background, outline, and
+/// ground) are written to the image as is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorSpace {
+    /// Lighting is computed in linear space, which is physically correct
+    #[default]
+    Linear,
+    /// Lighting is computed directly on the stored sRGB values of textures and the result is
+    /// written to the image without being encoded. This is the naive behavior of many simple
+    /// renderers, which makes lit surfaces look darker and more saturated.
+    ///
+    /// This is not the output of older versions of spritec, which lit the decoded texels in linear
+    /// space and then encoded the result with a gamma of 2.2. `Linear` is much closer to that
+    /// output, since the sRGB transfer function is close to a gamma of 2.2.
+    Srgb,
+}
+
+/// A single cel shading band
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CelBand {
+    /// The band is used for surfaces that receive more than this amount of light (between 0.0
+    /// and 1.0)
+    pub threshold: f32,
+    /// The amount that the color of the light is multiplied by in this band
+    pub multiplier: f32,
+}
+
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum CelBandsError {
+    #[error("At least one cel shading band is required")]
+    NoBands,
+    #[error("Only up to {max} cel shading bands are supported, but {count} were given")]
+    TooManyBands {count: usize, max: usize},
+    #[error("Cel shading band thresholds must be sorted from largest to smallest")]
+    UnsortedThresholds,
+}
+
+/// The bands that the light on each surface is split into to produce the cel shaded look
+///
+/// Each surface uses the first band with a threshold below the amount of light it receives.
+/// Surfaces that receive no more light than any of the thresholds use the last band.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CelBands {
+    bands: Vec<CelBand>,
+}
+
+impl Default for CelBands {
+    fn default() -> Self {
+        let band = |threshold, multiplier| CelBand {threshold, multiplier};
+        Self {
+            bands: vec![band(0.95, 1.0), band(0.5, 0.7), band(0.05, 0.35), band(0.0, 0.1)],
+        }
+    }
+}
+
+impl CelBands {
+    /// Creates cel shading bands from a list of bands sorted by threshold, from largest to
+    /// smallest
+    pub fn new(bands: Vec<CelBand>) -> Result<Self, CelBandsError> {
+        if bands.is_empty() {
+            return Err(CelBandsError::NoBands);
+        }
+        if bands.len() > MAX_BANDS {
+            return Err(CelBandsError::TooManyBands {count: bands.len(), max: MAX_BANDS});
+        }
+        if bands.windows(2).any(|pair| pair[0].threshold < pair[1].threshold) {
+            return Err(CelBandsError::UnsortedThresholds);
+        }
+
+        Ok(Self {bands})
+    }
+
+    /// Returns the bands, sorted by threshold from largest to smallest
+    pub fn bands(&self) -> &[CelBand] {
+        &self.bands
+    }
+}
+
+/// What to draw behind the geometry of a rendered image
+#[derive(Debug, Clone, PartialEq)]
+pub enum Background {
+    /// Fills the image with a single color
+    Color(Rgba),
+    /// An image that is stretched to the size of the rendered image. The geometry is drawn over
+    /// the image, so the image shows through any transparent parts of the geometry.
+    Image(Arc<BackgroundImage>),
+}
+
+impl Background {
+    /// Decodes the given image file to use as a background. The file is only read once, no matter
+    /// how many images are drawn over it.
+    pub fn open_image(path: &Path) -> Result<Self, image::ImageError> {
+        Ok(Background::Image(Arc::new(BackgroundImage::new(image::open(path)?.to_rgba()))))
+    }
+}
+
+/// A decoded background image, along with a copy of it stretched to each size that it has been
+/// drawn at
+#[derive(Debug)]
+pub struct BackgroundImage {
+    image: RgbaImage,
+    resized: Mutex<HashMap<Size, Arc<RgbaImage>>>,
+}
+
+impl PartialEq for BackgroundImage {
+    fn eq(&self, other: &Self) -> bool {
+        self.image.dimensions() == other.image.dimensions() && *self.image == *other.image
+    }
+}
+
+impl BackgroundImage {
+    pub fn new(image: RgbaImage) -> Self {
+        Self {image, resized: Mutex::default()}
+    }
+
+    /// Returns the image stretched to the given size, reusing the stretched copy if it has
+    /// already been drawn at that size
+    pub fn resized(&self, size: Size) -> Arc<RgbaImage> {
+        let mut resized = self.resized.lock().expect("bug: background image cache was poisoned");
+        resized.entry(size).or_insert_with(|| {
+            Arc::new(image::imageops::resize(&self.image, size.width.get(), size.height.get(),
+                image::imageops::FilterType::Triangle))
+        }).clone()
+    }
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Background::Color(Rgba::zero())
+    }
+}
+
+impl From<Rgba> for Background {
+    fn from(color: Rgba) -> Self {
+        Background::Color(color)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -83,6 +457,64 @@ impl RenderCamera {
     }
 }
 
+#[derive(Debug, Clone)]
+pub enum RenderGeometry {
+    Geometry(Arc<Vec<Arc<ShaderGeometry>>>),
+    Query(FileQuery<GeometryQuery>),
+    /// Combines the geometry from several queries (possibly on different files) so that it can be
+    /// drawn together. The geometry from each file keeps the transforms from that file's scene.
+    Queries(Vec<FileQuery<GeometryQuery>>),
+    /// Draws the geometry from a single query once for each of the given transforms, which are
+    /// applied on top of the transforms from the file's scene. The file is only queried once and
+    /// every instance shares the same buffers on the GPU.
+    Instances {
+        query: FileQuery<GeometryQuery>,
+        transforms: Arc<Vec<Mat4>>,
+    },
+}
+
+impl RenderGeometry {
+    pub fn fetch_geometry(&self, display: &Display) -> Result<Arc<Vec<Arc<ShaderGeometry>>>, QueryError> {
+        use RenderGeometry::*;
+        match self {
+            Geometry(geo) => Ok(geo.clone()),
+            Query(file_query) => Self::fetch_query(file_query, display),
+            Queries(file_queries) => {
+                let geometry = file_queries.iter()
+                    .map(|file_query| Self::fetch_query(file_query, display))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let geometry: Vec<_> = geometry.iter().flat_map(|geo| geo.iter().cloned()).collect();
+                Ok(geometry.into())
+            },
+            Instances {query, transforms} => {
+                let geometry = Self::fetch_query(query, display)?;
+                let instances: Vec<_> = transforms.iter()
+                    .flat_map(|&transform| geometry.iter().map(move |geo| geo.instance(transform)))
+                    .map(Arc::new)
+                    .collect();
+                Ok(instances.into())
+            },
+        }
+    }
+
+    /// Returns the queries used to fetch this geometry, if any
+    pub fn queries_mut(&mut self) -> impl Iterator<Item = &mut GeometryQuery> {
+        use RenderGeometry::*;
+        let file_queries = match self {
+            Geometry(_) => &mut [],
+            Query(file_query) | Instances {query: file_query, ..} => std::slice::from_mut(file_query),
+            Queries(file_queries) => &mut file_queries[..],
+        };
+        file_queries.iter_mut().map(|FileQuery {query, ..}| query)
+    }
+
+    fn fetch_query(file_query: &FileQuery<GeometryQuery>, display: &Display) -> Result<Arc<Vec<Arc<ShaderGeometry>>>, QueryError> {
+        let FileQuery {query, file} = file_query;
+        let mut file = file.lock().expect("bug: file lock was poisoned");
+        file.query_geometry(query, display)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum RenderLights {
     Lights(Arc<Vec<Arc<Light>>>),
@@ -107,3 +539,90 @@ pub struct FileQuery<Q> {
     pub query: Q,
     pub file: Arc<Mutex<File>>,
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Supersample {
+    /// The image is rendered at this many times its width and height, given as (x, y). Sprites
+    /// that will be stretched along one axis can use a larger factor along that axis.
+    pub factor: (NonZeroU32, NonZeroU32),
+    /// The filter used to scale the rendered image back down to its actual size
+    pub filter: DownscaleFilter,
+}
+
+/// The filter used to combine pixels when an image is scaled down
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DownscaleFilter {
+    /// Averages each block of pixels. Fast, but produces slightly soft edges.
+    #[default]
+    Box,
+    /// A windowed sinc filter with 3 lobes. Produces sharper edges than `Box`, but can produce
+    /// faint halos next to high contrast edges.
+    Lanczos3,
+    /// Uses the most common color in each block of pixels, so no new colors are created and edges
+    /// stay hard. Suited to pixel art, but thin details can disappear if they cover less of a
+    /// block than the colors around them.
+    Dominant,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cel_bands_must_be_sorted_descending() {
+        let band = |threshold| CelBand {threshold, multiplier: 1.0};
+
+        assert!(CelBands::new(vec![band(0.5), band(0.0)]).is_ok());
+        assert_eq!(CelBands::new(vec![band(0.0), band(0.5)]), Err(CelBandsError::UnsortedThresholds));
+        assert_eq!(CelBands::new(Vec::new()), Err(CelBandsError::NoBands));
+        assert_eq!(CelBands::new(vec![band(0.0); MAX_BANDS + 1]), Err(CelBandsError::TooManyBands {
+            count: MAX_BANDS + 1,
+            max: MAX_BANDS,
+        }));
+
+        // The default bands are valid
+        let default_bands = CelBands::default();
+        assert_eq!(CelBands::new(default_bands.bands().to_vec()), Ok(default_bands));
+    }
+
+    #[test]
+    fn size_scaling_keeps_aspect_ratio() {
+        let size = |width, height| Size {
+            width: NonZeroU32::new(width).unwrap(),
+            height: NonZeroU32::new(height).unwrap(),
+        };
+
+        assert_eq!(size(64, 32).aspect_ratio(), 2.0);
+        assert_eq!(size(64, 32).scaled(1.5), size(96, 48));
+        // Neither dimension is ever rounded down to zero
+        assert_eq!(size(64, 2).scaled(0.1), size(6, 1));
+
+        assert_eq!(size(64, 32).fit_within(size(32, 32)), size(32, 16));
+        assert_eq!(size(64, 32).fit_within(size(256, 64)), size(128, 64));
+        assert_eq!(size(100, 1).fit_within(size(10, 10)), size(10, 1));
+    }
+
+    #[test]
+    fn hull_thickness_only_for_inverted_hull() {
+        assert_eq!(OutlineMode::InvertedHull {thickness: 0.25}.hull_thickness(), Some(0.25));
+        // A thickness of zero skips the outline pass entirely
+        assert_eq!(OutlineMode::InvertedHull {thickness: 0.0}.hull_thickness(), None);
+        let edge_detect = OutlineMode::EdgeDetect {thickness: 1, depth_threshold: 0.1, normal_threshold: 0.5};
+        assert_eq!(edge_detect.hull_thickness(), None);
+    }
+
+    #[test]
+    fn background_image_resized_once_per_size() {
+        let background = BackgroundImage::new(RgbaImage::from_pixel(4, 2, image::Rgba([10, 20, 30, 255])));
+        let size = |width, height| Size {
+            width: NonZeroU32::new(width).unwrap(),
+            height: NonZeroU32::new(height).unwrap(),
+        };
+
+        let first = background.resized(size(8, 4));
+        assert_eq!(first.dimensions(), (8, 4));
+        assert_eq!(first.get_pixel(3, 1).0, [10, 20, 30, 255]);
+        assert!(Arc::ptr_eq(&first, &background.resized(size(8, 4))));
+        assert_eq!(background.resized(size(2, 2)).dimensions(), (2, 2));
+    }
+}
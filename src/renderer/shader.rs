@@ -1,6 +1,12 @@
 mod nested_uniforms;
 mod light_uniform;
 mod material_uniform;
+mod texture_sampler;
 
 pub mod cel;
+pub mod flat;
+pub mod mask;
 pub mod outline;
+pub mod normals;
+pub mod shadow;
+pub mod ground;
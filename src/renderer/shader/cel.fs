@@ -56,6 +56,14 @@ struct Light {
 
 struct Material {
     vec4 diffuse_color;
+    // The light emitted by the material, which is not affected by lighting
+    vec3 emissive_color;
+    // Fragments with an alpha less than this value are discarded. Zero keeps
+    // every fragment.
+    float alpha_cutoff;
+    // The fraction of light that the material reflects when viewed head on,
+    // computed from its index of refraction
+    float reflectance;
 };
 
 // Light parameters
@@ -63,30 +71,227 @@ struct Material {
 uniform int num_lights;
 uniform Light lights[MAX_LIGHTS];
 uniform vec3 ambient_light;
+// The index of the light that determines where the cel shading bands are. All
+// other lights add smooth fill light. If -1, the light from every light is
+// added together before it is split into bands.
+uniform int key_light;
+
+// Cel shading bands, sorted by threshold from largest to smallest. Each
+// surface uses the multiplier of the first band whose threshold is less than
+// the light intensity. If the intensity is not greater than any threshold, the
+// last band is used.
+#define MAX_BANDS 8
+uniform int num_bands;
+uniform float band_thresholds[MAX_BANDS];
+uniform float band_multipliers[MAX_BANDS];
 
 // Material data
 uniform Material material;
+// If true, the diffuse color of the material is multiplied by the color
+// sampled from base_color_texture
+uniform bool use_base_color_texture;
+uniform sampler2D base_color_texture;
+// If true, the emissive color of the material is multiplied by the color
+// sampled from emissive_texture
+uniform bool use_emissive_texture;
+uniform sampler2D emissive_texture;
+// If true, the normal of the surface is perturbed by the tangent-space normal
+// sampled from normal_texture. The texture is never decoded from sRGB.
+uniform bool use_normal_texture;
+uniform sampler2D normal_texture;
+// The x and y components of the sampled normal are multiplied by this value
+uniform float normal_scale;
+
+// If true, textures are decoded from sRGB (by the sampler) and lighting is
+// computed in linear space, then the result is encoded as sRGB. If false,
+// lighting is computed on the stored sRGB values and the result is written as
+// is.
+uniform bool linear_lighting;
+
+// The lit color is multiplied by this value before it is encoded. 1.0 leaves
+// the color unchanged.
+uniform float exposure;
+// The encoded color is raised to the power of 1 / gamma. 1.0 leaves the color
+// unchanged.
+uniform float gamma;
+
+// The position of the camera in world coordinates
+uniform vec3 eye_position;
+
+// Shadow parameters
+// The index of the light that casts shadows, or -1 if no light casts shadows
+uniform int shadow_light;
+// Transforms world coordinates into the clip space of the shadow light
+uniform mat4 light_space;
+// The depth of the closest surface to the shadow light, as seen from the light
+uniform sampler2D shadow_map;
+// How far behind the closest surface a point must be before it is in shadow
+uniform float shadow_bias;
+
+// Specular highlight parameters
+// The color of the specular highlight
+uniform vec3 specular_color;
+// Larger values produce a smaller highlight. Must be greater than zero.
+uniform float specular_power;
+// The brightness of the specular highlight. Set to 0.0 to disable specular
+// highlights.
+uniform float specular_intensity;
+
+// Rim light parameters
+// The color of the rim light. Set to black to disable the rim light.
+uniform vec3 rim_color;
+// Larger values produce a thinner rim. Must be greater than zero (the rim light
+// is disabled with a black rim color when the configured power is zero).
+uniform float rim_power;
 
 // This is assumed to be normalized
 in vec3 v_normal;
 in vec3 v_position;
+in vec2 v_tex_coord;
+// Zero if the geometry has no tangents
+in vec4 v_tangent;
+// White if the geometry has no vertex colors
+in vec4 v_color;
 
 out vec4 frag_color;
 
-// https://github.com/KhronosGroup/glTF-Sample-Viewer/blob/a18868cfe652bab4c084c751c80a6cfb55ae0f2f/src/shaders/metallic-roughness.frag#L199-L208
+// Point and spot lights fall off with the inverse square of the distance to
+// the light. If the light has a range, the falloff is smoothly windowed so that
+// it reaches zero at that range.
+//
+// https://github.com/KhronosGroup/glTF/tree/master/extensions/2.0/Khronos/KHR_lights_punctual#range-property
 float range_attenuation(float distance, float range) {
+    float inverse_square = 1.0 / pow(distance, 2);
     if (range <= 0.0) {
         // range is unlimited
+        return inverse_square;
+    }
+
+    return max(min(1.0 - pow(distance / range, 4), 1.0), 0.0) * inverse_square;
+}
+
+// Encodes a linear color with the sRGB transfer function
+// https://en.wikipedia.org/wiki/SRGB#Transfer_function_(%22gamma%22)
+vec3 linear_to_srgb(vec3 linear) {
+    linear = clamp(linear, 0.0, 1.0);
+    vec3 low = linear * 12.92;
+    vec3 high = 1.055 * pow(linear, vec3(1.0 / 2.4)) - 0.055;
+    return mix(high, low, vec3(lessThanEqual(linear, vec3(0.0031308))));
+}
+
+// Returns the color of the given texel. The sampler always decodes textures
+// from sRGB, so the texel is encoded again if lighting should use the stored
+// values.
+vec4 texel_color(vec4 texel) {
+    if (!linear_lighting) {
+        texel.rgb = linear_to_srgb(texel.rgb);
+    }
+    return texel;
+}
+
+// Returns the color of the surface before any lighting is applied. The alpha
+// component is only used for the alpha cutoff.
+vec4 base_color() {
+    vec4 color = material.diffuse_color * v_color;
+    if (use_base_color_texture) {
+        color *= texel_color(texture(base_color_texture, v_tex_coord));
+    }
+    return color;
+}
+
+// Returns the light emitted by the surface
+vec3 emissive_color() {
+    vec3 color = material.emissive_color;
+    if (use_emissive_texture) {
+        color *= texel_color(texture(emissive_texture, v_tex_coord)).rgb;
+    }
+    return color;
+}
+
+// Returns the normal of the surface in the world coordinate system. If there is
+// a normal texture, the normal is perturbed by it in tangent space, so the
+// bumps in the texture are split into cel shading bands like the rest of the
+// surface.
+//
+// https://github.com/KhronosGroup/glTF/tree/92f59a0dbefe2d54cff38dba103cd70462cc778b/specification/2.0#materialnormaltextureinfo
+vec3 surface_normal() {
+    vec3 normal = normalize(v_normal);
+    if (!use_normal_texture || dot(v_tangent.xyz, v_tangent.xyz) == 0.0) {
+        return normal;
+    }
+
+    // Interpolation can make the tangent no longer perpendicular to the normal
+    vec3 tangent = normalize(v_tangent.xyz - normal * dot(normal, v_tangent.xyz));
+    vec3 bitangent = cross(normal, tangent) * v_tangent.w;
+
+    vec3 tangent_normal = texture(normal_texture, v_tex_coord).rgb * 2.0 - 1.0;
+    tangent_normal.xy *= normal_scale;
+    return normalize(mat3(tangent, bitangent, normal) * tangent_normal);
+}
+
+// Returns the multiplier of the cel shading band for the given light intensity
+float band_multiplier(float light_intensity) {
+    for (int i = 0; i < num_bands; i++) {
+        if (light_intensity > band_thresholds[i]) {
+            return band_multipliers[i];
+        }
+    }
+
+    return band_multipliers[num_bands - 1];
+}
+
+// Computes the specular highlight added by a light using the Blinn-Phong model.
+// The highlight is computed separately from the diffuse light so that it does
+// not change the cel shading bands. If banded is true, the highlight is either
+// fully lit or not lit at all.
+//
+// Both position and normal should be in the world coordinate system.
+vec3 specular_highlight(Light light, bool banded, vec3 surface_to_light, float attenuation, vec3 position, vec3 normal) {
+    // Surfaces facing away from the light cannot have a highlight
+    if (specular_intensity <= 0.0 || dot(normal, surface_to_light) <= 0.0) {
+        return vec3(0.0);
+    }
+
+    // https://en.wikipedia.org/wiki/Blinn%E2%80%93Phong_reflection_model
+    vec3 surface_to_eye = normalize(eye_position - position);
+    vec3 halfway = normalize(surface_to_light + surface_to_eye);
+    float amount = pow(max(dot(normalize(normal), halfway), 0.0), specular_power) * attenuation;
+    if (banded) {
+        amount = step(0.5, amount);
+    }
+
+    return specular_color * light.color * specular_intensity * amount;
+}
+
+// Returns 0.0 if the given light is blocked from reaching the given position by
+// another surface, or 1.0 otherwise. Shadows are hard so they fit in with the
+// flat areas of color produced by cel shading.
+//
+// The position should be in the world coordinate system.
+float shadow_visibility(int light_index, vec3 position) {
+    if (light_index != shadow_light) {
         return 1.0;
     }
 
-    return max(min(1.0 - pow(distance / range, 4), 1.0), 0.0) / pow(distance, 2);
+    // Orthographic projections do not need a perspective divide
+    vec3 coord = vec3(light_space * vec4(position, 1.0)) * 0.5 + 0.5;
+    // Surfaces outside of the shadow map are not shadowed
+    if (any(lessThan(coord, vec3(0.0))) || any(greaterThan(coord, vec3(1.0)))) {
+        return 1.0;
+    }
+
+    float closest_depth = texture(shadow_map, coord.xy).r;
+    return coord.z - shadow_bias > closest_depth ? 0.0 : 1.0;
 }
 
-// Uses the lighting model to compute the color of a point on a surface.
+// Uses the lighting model to compute how much of the given light reaches a
+// point on a surface, before it is split into cel shading bands. The direction
+// from the surface to the light and the attenuation of the light are also
+// returned so that they can be used to compute specular highlights. The index
+// of the light in the lights array is used to look up its shadows.
 //
 // Both position and normal should be in the world coordinate system.
-vec3 apply_light(Light light, vec3 position, vec3 normal) {
+float light_intensity(Light light, int light_index, vec3 position, vec3 normal, out vec3 surface_to_light, out float attenuation) {
     // The lighting model implemented here is designed around supporting the
     // glTF punctual lights extension. The calculations performed conform to
     // that spec. Some features found in other lighting implementations may be
@@ -98,8 +303,6 @@ vec3 apply_light(Light light, vec3 position, vec3 normal) {
     // * https://www.tomdalling.com/blog/modern-opengl/07-more-lighting-ambient-specular-attenuation-gamma/
     // * https://www.tomdalling.com/blog/modern-opengl/08-even-more-lighting-directional-lights-spotlights-multiple-lights/
 
-    vec3 surface_to_light;
-    float attenuation = 1.0;
     if (light.position.w == 0.0) {
         // Directional light
 
@@ -109,8 +312,9 @@ vec3 apply_light(Light light, vec3 position, vec3 normal) {
 
     } else {
         // Point / spot light
-        surface_to_light = normalize(light.position.xyz - position);
-        float distance_to_light = length(surface_to_light);
+        vec3 to_light = light.position.xyz - position;
+        float distance_to_light = length(to_light);
+        surface_to_light = to_light / distance_to_light;
         attenuation = range_attenuation(distance_to_light, light.range);
 
         if (light.light_angle_scale != 0.0) {
@@ -125,50 +329,124 @@ vec3 apply_light(Light light, vec3 position, vec3 normal) {
         }
     }
 
+    // Shadowed surfaces are lit as if they face away from the light, which also
+    // removes their specular highlights
+    attenuation *= shadow_visibility(light_index, position);
+
     // Calculate diffuse light amount
     // max() is used to bottom out at zero if the dot product is negative
-    float diffuse_intensity = max(dot(v_normal, surface_to_light), 0.0);
+    float diffuse_intensity = max(dot(normalize(normal), surface_to_light), 0.0);
 
-    // Calculate what would normally be the final color, including texturing and
-    // diffuse lighting
-    float light_intensity = diffuse_intensity;
-    light_intensity *= attenuation;
-    // Discards the material alpha component
-    vec3 color = vec3(material.diffuse_color) * light.color;
+    return diffuse_intensity * attenuation;
+}
 
-    // A Cel/Toon shader implementation
-    // Discretises the color to produce a "toon" effect
-    // Initial version based on this article: http://rbwhitaker.wikidot.com/toon-shader
+// Computes the color of a point on a surface with the given base color, lit by
+// a single light. If banded is false, the light is applied smoothly instead of
+// being split into cel shading bands.
+//
+// Both position and normal should be in the world coordinate system.
+vec3 apply_light(Light light, int light_index, bool banded, vec3 color, vec3 position, vec3 normal) {
+    vec3 surface_to_light;
+    float attenuation;
+    float intensity = light_intensity(light, light_index, position, normal, surface_to_light, attenuation);
+    color *= light.color;
 
-    if (light_intensity > 0.95) {
-       // Leave the color as-is
-       // e.g. color *= 1.0;
+    vec3 diffuse;
+    if (banded) {
+        // A Cel/Toon shader implementation
+        // Discretises the color to produce a "toon" effect
+        // Initial version based on this article: http://rbwhitaker.wikidot.com/toon-shader
+        diffuse = color * band_multiplier(intensity);
+    } else {
+        diffuse = color * intensity;
+    }
 
-    } else if (light_intensity > 0.5) {
-       color *= 0.7;
+    return diffuse + specular_highlight(light, banded, surface_to_light, attenuation, position, normal);
+}
 
-    } else if (light_intensity > 0.05) {
-       color *= 0.35;
+// Computes the color of a point on a surface with the given base color, lit by
+// every light. The intensities of all the lights are added up (and clamped)
+// before the total is split into cel shading bands, so overlapping lights do
+// not create extra bands. The surface is tinted by the colors of the lights,
+// weighted by how much light each one contributes.
+//
+// Both position and normal should be in the world coordinate system.
+vec3 apply_lights_combined(vec3 color, vec3 position, vec3 normal) {
+    float total_intensity = 0.0;
+    vec3 weighted_color = vec3(0.0);
+    vec3 average_color = vec3(0.0);
+    vec3 specular = vec3(0.0);
+    for (int i = 0; i < num_lights; i++) {
+        Light light = lights[i];
+        vec3 surface_to_light;
+        float attenuation;
+        float intensity = light_intensity(light, i, position, normal, surface_to_light, attenuation);
 
-    } else {
-       color *= 0.1;
+        total_intensity += intensity;
+        weighted_color += light.color * intensity;
+        average_color += light.color / float(num_lights);
+        specular += specular_highlight(light, true, surface_to_light, attenuation, position, normal);
     }
 
-    return color;
+    // Surfaces that no light reaches still get the darkest band, tinted by all
+    // of the lights equally
+    vec3 light_color = total_intensity > 0.0 ? weighted_color / total_intensity : average_color;
+    float banded = band_multiplier(min(total_intensity, 1.0));
+
+    return color * light_color * banded + specular;
+}
+
+// Computes the amount of rim light to add to a point on a surface using Schlick's approximation of
+// the fresnel effect. Surfaces at grazing angles to the camera receive the most light.
+//
+// Both position and normal should be in the world coordinate system.
+vec3 rim_light(vec3 position, vec3 normal) {
+    // https://en.wikipedia.org/wiki/Schlick%27s_approximation
+    float r0 = material.reflectance;
+
+    vec3 surface_to_eye = normalize(eye_position - position);
+    float cos_theta = max(dot(normalize(normal), surface_to_eye), 0.0);
+    float fresnel = r0 + (1.0 - r0) * pow(1.0 - cos_theta, rim_power);
+
+    return rim_color * fresnel;
 }
 
 void main() {
-    // Discards the material alpha component
-    vec3 final_color = vec3(material.diffuse_color) * ambient_light;
-    for (int i = 0; i < num_lights; i++) {
-        Light light = lights[i];
-        final_color += apply_light(light, v_position, v_normal);
+    vec4 base = base_color();
+    // Cutout parts of the surface are not drawn, so whatever is behind them shows
+    // through instead
+    if (base.a < material.alpha_cutoff) {
+        discard;
     }
+    vec3 color = base.rgb;
+    vec3 normal = surface_normal();
+    // Ambient light is only added once, no matter how many lights there are
+    vec3 final_color = color * ambient_light;
+    if (key_light < 0) {
+        if (num_lights > 0) {
+            final_color += apply_lights_combined(color, v_position, normal);
+        }
+    } else {
+        for (int i = 0; i < num_lights; i++) {
+            Light light = lights[i];
+            final_color += apply_light(light, i, key_light == i, color, v_position, normal);
+        }
+    }
+
+    // Added after the cel shading so that the rim light is not split into bands
+    final_color += rim_light(v_position, normal);
+    // Emitted light is not affected by the light model, so it is also added after
+    // the cel shading
+    final_color += emissive_color();
 
-    // Gamma correction -- apply at the very end
-    // Technique from: https://learnopengl.com/Advanced-Lighting/Gamma-Correction
-    float gamma = 2.2;
-    final_color = pow(final_color, vec3(1.0/gamma));
+    final_color *= exposure;
+
+    // Gamma correction with the sRGB transfer function -- apply at the very end
+    if (linear_lighting) {
+        final_color = linear_to_srgb(final_color);
+    }
+    // The gamma of the output only changes the encoded color
+    final_color = pow(clamp(final_color, 0.0, 1.0), vec3(1.0 / gamma));
 
     frag_color = vec4(final_color, 1.0);
 }
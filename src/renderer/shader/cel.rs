@@ -1,19 +1,33 @@
 use std::sync::Arc;
 
-use glium::uniforms::{Uniforms, UniformValue};
+use glium::texture::{SrgbTexture2d, Texture2d, DepthTexture2d};
+use glium::uniforms::{
+    Uniforms,
+    UniformValue,
+    SamplerBehavior,
+    SamplerWrapFunction,
+    MagnifySamplerFilter,
+    MinifySamplerFilter,
+};
 
-use crate::math::{Mat4, Rgb};
+use crate::math::{Mat4, Vec3, Rgb};
 use crate::scene::Material;
-use crate::renderer::Light;
+use crate::renderer::{Light, Rim, Specular, CelBands, CelBand, ShadowMap, ColorSpace};
 
 use super::nested_uniforms::NestedUniforms;
 pub use super::light_uniform::LightUniform;
 pub use super::material_uniform::MaterialUniform;
+use super::texture_sampler::texture_sampler;
 
 /// The maximum supported number of lights
 ///
+/// This value must match the corresponding value in the cel and flat shaders
+pub const MAX_LIGHTS: usize = 10;
+
+/// The maximum supported number of cel shading bands
+///
 /// This value must match the corresponding value in the cel shaders
-const MAX_LIGHTS: usize = 10;
+pub const MAX_BANDS: usize = 8;
 
 pub struct CelUniforms<'a> {
     pub mvp: Mat4,
@@ -21,21 +35,61 @@ pub struct CelUniforms<'a> {
     pub model_inverse_transpose: Mat4,
     pub lights: &'a [Arc<Light>],
     pub ambient_light: Rgb,
+    /// The index of the light that determines the cel shading bands, or None if the light from
+    /// every light should be added together and then banded
+    pub key_light: Option<usize>,
+    pub bands: &'a CelBands,
     pub material: &'a Material,
+    /// The texture multiplied with the diffuse color of the material, if any
+    pub base_color_texture: Option<&'a SrgbTexture2d>,
+    /// The texture multiplied with the emissive color of the material, if any
+    pub emissive_texture: Option<&'a SrgbTexture2d>,
+    /// The tangent-space normal texture of the material, if any
+    pub normal_texture: Option<&'a Texture2d>,
+    /// The shadows cast by one of the lights, if any
+    pub shadow: Option<&'a ShadowMap>,
+    /// The position of the camera in world coordinates
+    pub eye_position: Vec3,
+    pub rim: Option<Rim>,
+    pub specular: Option<Specular>,
+    pub color_space: ColorSpace,
+    pub exposure: f32,
+    pub gamma: f32,
 }
 
 /// This struct must match the uniforms in the cel shaders
-pub struct Cel {
+pub struct Cel<'a> {
     mvp: UniformValue<'static>,
     model_transform: UniformValue<'static>,
     model_inverse_transpose: UniformValue<'static>,
     num_lights: UniformValue<'static>,
     lights: Vec<LightUniform>,
     ambient_light: UniformValue<'static>,
+    key_light: UniformValue<'static>,
+    num_bands: UniformValue<'static>,
+    band_thresholds: Vec<UniformValue<'static>>,
+    band_multipliers: Vec<UniformValue<'static>>,
     material: MaterialUniform,
+    base_color_texture: Option<(&'a SrgbTexture2d, SamplerBehavior)>,
+    emissive_texture: Option<(&'a SrgbTexture2d, SamplerBehavior)>,
+    normal_texture: Option<(&'a Texture2d, SamplerBehavior)>,
+    normal_scale: UniformValue<'static>,
+    shadow_light: UniformValue<'static>,
+    light_space: UniformValue<'static>,
+    shadow_bias: UniformValue<'static>,
+    shadow_map: Option<&'a DepthTexture2d>,
+    eye_position: UniformValue<'static>,
+    rim_color: UniformValue<'static>,
+    rim_power: UniformValue<'static>,
+    specular_color: UniformValue<'static>,
+    specular_power: UniformValue<'static>,
+    specular_intensity: UniformValue<'static>,
+    linear_lighting: UniformValue<'static>,
+    exposure: UniformValue<'static>,
+    gamma: UniformValue<'static>,
 }
 
-impl Uniforms for Cel {
+impl<'t> Uniforms for Cel<'t> {
     fn visit_values<'a, F: FnMut(&str, UniformValue<'a>)>(&'a self, mut visit: F) {
         let Self {
             mvp,
@@ -44,7 +98,28 @@ impl Uniforms for Cel {
             num_lights,
             lights,
             ambient_light,
+            key_light,
+            num_bands,
+            band_thresholds,
+            band_multipliers,
             material,
+            base_color_texture,
+            emissive_texture,
+            normal_texture,
+            normal_scale,
+            shadow_light,
+            light_space,
+            shadow_bias,
+            shadow_map,
+            eye_position,
+            rim_color,
+            rim_power,
+            specular_color,
+            specular_power,
+            specular_intensity,
+            linear_lighting,
+            exposure,
+            gamma,
         } = self;
 
         visit("mvp", *mvp);
@@ -55,11 +130,52 @@ impl Uniforms for Cel {
             light.visit_nested_index("lights", i, &mut visit);
         }
         visit("ambient_light", *ambient_light);
+        visit("key_light", *key_light);
+        visit("num_bands", *num_bands);
+        for (i, (&threshold, &multiplier)) in band_thresholds.iter().zip(band_multipliers).enumerate() {
+            visit(&format!("band_thresholds[{}]", i), threshold);
+            visit(&format!("band_multipliers[{}]", i), multiplier);
+        }
         material.visit_nested("material", &mut visit);
+        visit("use_base_color_texture", UniformValue::Bool(base_color_texture.is_some()));
+        if let &Some((texture, sampler)) = base_color_texture {
+            visit("base_color_texture", UniformValue::SrgbTexture2d(texture, Some(sampler)));
+        }
+        visit("use_emissive_texture", UniformValue::Bool(emissive_texture.is_some()));
+        if let &Some((texture, sampler)) = emissive_texture {
+            visit("emissive_texture", UniformValue::SrgbTexture2d(texture, Some(sampler)));
+        }
+        visit("use_normal_texture", UniformValue::Bool(normal_texture.is_some()));
+        if let &Some((texture, sampler)) = normal_texture {
+            visit("normal_texture", UniformValue::Texture2d(texture, Some(sampler)));
+        }
+        visit("normal_scale", *normal_scale);
+        visit("shadow_light", *shadow_light);
+        visit("light_space", *light_space);
+        visit("shadow_bias", *shadow_bias);
+        if let Some(shadow_map) = shadow_map {
+            // Surfaces outside of the shadow map are never in shadow, so nothing should wrap
+            let sampler = SamplerBehavior {
+                wrap_function: (SamplerWrapFunction::Clamp, SamplerWrapFunction::Clamp, SamplerWrapFunction::Clamp),
+                magnify_filter: MagnifySamplerFilter::Nearest,
+                minify_filter: MinifySamplerFilter::Nearest,
+                ..Default::default()
+            };
+            visit("shadow_map", UniformValue::DepthTexture2d(shadow_map, Some(sampler)));
+        }
+        visit("eye_position", *eye_position);
+        visit("rim_color", *rim_color);
+        visit("rim_power", *rim_power);
+        visit("specular_color", *specular_color);
+        visit("specular_power", *specular_power);
+        visit("specular_intensity", *specular_intensity);
+        visit("linear_lighting", *linear_lighting);
+        visit("exposure", *exposure);
+        visit("gamma", *gamma);
     }
 }
 
-impl<'a> From<CelUniforms<'a>> for Cel {
+impl<'a> From<CelUniforms<'a>> for Cel<'a> {
     fn from(cel_uniforms: CelUniforms<'a>) -> Self {
         let CelUniforms {
             mvp,
@@ -67,10 +183,46 @@ impl<'a> From<CelUniforms<'a>> for Cel {
             model_inverse_transpose,
             lights,
             ambient_light,
+            key_light,
+            bands,
             material,
+            base_color_texture,
+            emissive_texture,
+            normal_texture,
+            shadow,
+            eye_position,
+            rim,
+            specular,
+            color_space,
+            exposure,
+            gamma,
         } = cel_uniforms;
 
+        // A black rim color disables the rim light entirely. A power of zero would add the same
+        // amount of light to every surface instead of only the edges, so it is also disabled.
+        let Rim {color: rim_color, power: rim_power} = rim.filter(|rim| rim.power > 0.0).unwrap_or(Rim {
+            color: Rgb::black(),
+            power: 1.0,
+        });
+
+        // An intensity of zero disables specular highlights entirely
+        let Specular {
+            color: specular_color,
+            power: specular_power,
+            intensity: specular_intensity,
+        } = specular.unwrap_or(Specular {
+            color: Rgb::white(),
+            power: 1.0,
+            intensity: 0.0,
+        });
+
         assert!(lights.len() <= MAX_LIGHTS, "Only up to {} lights can be rendered at any given time", MAX_LIGHTS);
+        if let Some(key_light) = key_light {
+            assert!(key_light < lights.len(), "bug: key light index {} is out of range", key_light);
+        }
+        if let Some(shadow) = shadow {
+            assert!(shadow.light < lights.len(), "Shadow light index {} is out of range", shadow.light);
+        }
 
         Self {
             mvp: UniformValue::Mat4(mvp.into_col_arrays()),
@@ -82,7 +234,213 @@ impl<'a> From<CelUniforms<'a>> for Cel {
                 LightUniform::new(data, *world_transform)
             }).collect(),
             ambient_light: UniformValue::Vec3(ambient_light.into_array()),
+            // -1 means that the lights are added together and then banded
+            key_light: UniformValue::SignedInt(key_light.map(|index| index as i32).unwrap_or(-1)),
+            num_bands: UniformValue::SignedInt(bands.bands().len() as i32),
+            band_thresholds: bands.bands().iter()
+                .map(|&CelBand {threshold, ..}| UniformValue::Float(threshold))
+                .collect(),
+            band_multipliers: bands.bands().iter()
+                .map(|&CelBand {multiplier, ..}| UniformValue::Float(multiplier))
+                .collect(),
             material: MaterialUniform::new(material),
+            base_color_texture: base_color_texture.zip(material.base_color_texture.as_deref())
+                .map(|(texture, source)| (texture, texture_sampler(source))),
+            emissive_texture: emissive_texture.zip(material.emissive_texture.as_deref())
+                .map(|(texture, source)| (texture, texture_sampler(source))),
+            normal_texture: normal_texture.zip(material.normal_texture.as_deref())
+                .map(|(texture, source)| (texture, texture_sampler(source))),
+            normal_scale: UniformValue::Float(material.normal_scale),
+            // -1 means that no light casts shadows
+            shadow_light: UniformValue::SignedInt(shadow.map(|shadow| shadow.light as i32).unwrap_or(-1)),
+            light_space: UniformValue::Mat4(shadow.map(|shadow| shadow.light_space).unwrap_or_else(Mat4::identity)
+                .into_col_arrays()),
+            shadow_bias: UniformValue::Float(shadow.map(|shadow| shadow.bias).unwrap_or(0.0)),
+            shadow_map: shadow.map(|shadow| &shadow.depth),
+            eye_position: UniformValue::Vec3(eye_position.into_array()),
+            rim_color: UniformValue::Vec3(rim_color.into_array()),
+            rim_power: UniformValue::Float(rim_power),
+            specular_color: UniformValue::Vec3(specular_color.into_array()),
+            specular_power: UniformValue::Float(specular_power),
+            specular_intensity: UniformValue::Float(specular_intensity),
+            linear_lighting: UniformValue::Bool(color_space == ColorSpace::Linear),
+            exposure: UniformValue::Float(exposure),
+            gamma: UniformValue::Float(gamma),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::scene::LightType;
+
+    /// Returns the name and value of every uniform (other than matrices and textures) of a cel
+    /// shader with the given lights, ambient light, key light and rim light
+    fn uniform_values(
+        lights: &[Arc<Light>],
+        ambient_light: Rgb,
+        key_light: Option<usize>,
+        rim: Option<Rim>,
+    ) -> Vec<(String, UniformValue<'static>)> {
+        let material = Material::default();
+        let bands = CelBands::default();
+
+        let cel = Cel::from(CelUniforms {
+            mvp: Mat4::identity(),
+            model_transform: Mat4::identity(),
+            model_inverse_transpose: Mat4::identity(),
+            lights,
+            ambient_light,
+            key_light,
+            bands: &bands,
+            material: &material,
+            base_color_texture: None,
+            emissive_texture: None,
+            normal_texture: None,
+            shadow: None,
+            eye_position: Vec3::zero(),
+            rim,
+            specular: None,
+            color_space: ColorSpace::default(),
+            exposure: 1.0,
+            gamma: 1.0,
+        });
+
+        let mut values = Vec::new();
+        cel.visit_values(|name, uniform| match uniform {
+            UniformValue::SignedInt(x) => values.push((name.to_string(), UniformValue::SignedInt(x))),
+            UniformValue::Float(x) => values.push((name.to_string(), UniformValue::Float(x))),
+            UniformValue::Vec3(x) => values.push((name.to_string(), UniformValue::Vec3(x))),
+            UniformValue::Vec4(x) => values.push((name.to_string(), UniformValue::Vec4(x))),
+            UniformValue::Bool(x) => values.push((name.to_string(), UniformValue::Bool(x))),
+            _ => {},
+        });
+        values
+    }
+
+    fn directional_light(world_transform: Mat4) -> Arc<Light> {
+        Arc::new(Light {
+            data: Arc::new(LightType::Directional {color: Rgb::white(), intensity: 1.0}),
+            world_transform,
+        })
+    }
+
+    /// Returns the value of the uniform with the given name, for a cel shader with two lights and
+    /// the given key light and rim light
+    fn uniform_value(key_light: Option<usize>, rim: Option<Rim>, name: &str) -> UniformValue<'static> {
+        let lights = vec![directional_light(Mat4::identity()); 2];
+        uniform_values(&lights, Rgb::black(), key_light, rim).into_iter()
+            .find(|(uniform_name, _)| uniform_name == name)
+            .map(|(_, value)| value)
+            .unwrap_or_else(|| panic!("{} uniform was not set", name))
+    }
+
+    fn key_light_uniform(key_light: Option<usize>) -> i32 {
+        match uniform_value(key_light, None, "key_light") {
+            UniformValue::SignedInt(index) => index,
+            _ => panic!("expected key_light to be an int"),
+        }
+    }
+
+    fn rim_color_uniform(rim: Option<Rim>) -> [f32; 3] {
+        match uniform_value(None, rim, "rim_color") {
+            UniformValue::Vec3(color) => color,
+            _ => panic!("expected rim_color to be a vec3"),
+        }
+    }
+
+    #[test]
+    fn key_light_selects_banded_light() {
+        // Only the key light is banded
+        assert_eq!(key_light_uniform(Some(1)), 1);
+        // Every light is banded together by default
+        assert_eq!(key_light_uniform(None), -1);
+    }
+
+    #[test]
+    fn rim_power_of_zero_disables_rim() {
+        let rim = |power| Some(Rim {color: Rgb::white(), power});
+
+        assert_eq!(rim_color_uniform(rim(2.0)), [1.0; 3]);
+        assert_eq!(rim_color_uniform(rim(0.0)), [0.0; 3]);
+        assert_eq!(rim_color_uniform(None), [0.0; 3]);
+    }
+
+    #[test]
+    fn opposing_lights_reach_every_face() {
+        // Two directional lights shining in opposite directions, neither of which is parallel to
+        // any of the faces of a cube
+        let light_transform = Mat4::rotation_y(0.8) * Mat4::rotation_x(-0.6);
+        let lights = vec![
+            directional_light(light_transform),
+            directional_light(light_transform * Mat4::rotation_x(std::f32::consts::PI)),
+        ];
+        let ambient_light = Rgb::white() * 0.2;
+        let values = uniform_values(&lights, ambient_light, None, None);
+        let find = |name: &str| values.iter()
+            .find(|(uniform_name, _)| uniform_name == name)
+            .map(|(_, value)| *value)
+            .unwrap_or_else(|| panic!("{} uniform was not set", name));
+        let int = |name: &str| match find(name) {
+            UniformValue::SignedInt(x) => x,
+            _ => panic!("expected {} to be an int", name),
+        };
+        let float = |name: &str| match find(name) {
+            UniformValue::Float(x) => x,
+            _ => panic!("expected {} to be a float", name),
+        };
+
+        // Both lights are added together before they are banded, and the ambient light is only
+        // passed in once
+        assert_eq!(int("key_light"), -1);
+        assert_eq!(int("num_lights"), 2);
+        assert_eq!(values.iter().filter(|(name, _)| name == "ambient_light").count(), 1);
+        assert!(matches!(find("ambient_light"), UniformValue::Vec3(color) if color == ambient_light.into_array()));
+
+        // Directional lights are passed as the direction that the light travels in
+        let directions: Vec<_> = (0..int("num_lights")).map(|i| match find(&format!("lights[{}].position", i)) {
+            UniformValue::Vec4([x, y, z, w]) if w == 0.0 => Vec3::new(x, y, z).normalized(),
+            _ => panic!("expected light {} to be a directional light", i),
+        }).collect();
+        let bands: Vec<_> = (0..int("num_bands"))
+            .map(|i| (float(&format!("band_thresholds[{}]", i)), float(&format!("band_multipliers[{}]", i))))
+            .collect();
+        let band_multiplier = |intensity: f32| bands.iter()
+            .find(|&&(threshold, _)| intensity > threshold)
+            .unwrap_or_else(|| bands.last().unwrap()).1;
+        let darkest = bands.last().unwrap().1;
+
+        // The total light on each face of a cube, clamped before it is banded. Each face only
+        // gets light from the light that travels towards it, and is brighter than the darkest
+        // band that surfaces no light reaches get.
+        let axes = [Vec3::unit_x(), Vec3::unit_y(), Vec3::unit_z()];
+        for normal in axes.iter().flat_map(|&axis| vec![axis, -axis]) {
+            let intensities: Vec<_> = directions.iter()
+                .map(|&direction| normal.dot(-direction).max(0.0))
+                .collect();
+            assert_eq!(intensities.iter().filter(|&&intensity| intensity > 0.0).count(), 1,
+                "face with normal {:?} is not lit by exactly one light: {:?}", normal, intensities);
+
+            let total = intensities.iter().sum::<f32>().min(1.0);
+            assert!(band_multiplier(total) > darkest, "face with normal {:?} is not lit by either light", normal);
+        }
+    }
+
+    #[test]
+    fn specular_disabled_by_default() {
+        match uniform_value(None, None, "specular_intensity") {
+            UniformValue::Float(intensity) => assert_eq!(intensity, 0.0),
+            _ => panic!("expected specular_intensity to be a float"),
+        }
+    }
+
+    #[test]
+    fn lighting_in_linear_space_by_default() {
+        match uniform_value(None, None, "linear_lighting") {
+            UniformValue::Bool(linear) => assert!(linear),
+            _ => panic!("expected linear_lighting to be a bool"),
         }
     }
 }
@@ -10,16 +10,31 @@ uniform mat4 model_inverse_transpose;
 
 in vec3 position;
 in vec3 normal;
+in vec2 tex_coord;
+// The w component is the handedness of the tangent space. All zero if the
+// geometry has no tangents.
+in vec4 tangent;
+in vec4 color;
 
 // The normal, in the world coordinate system
 out vec3 v_normal;
 // The position, in the world coordinate system
 out vec3 v_position;
+// The texture coordinate, interpolated across the surface
+out vec2 v_tex_coord;
+// The tangent, in the world coordinate system, with its handedness in w
+out vec4 v_tangent;
+// The vertex color, interpolated across the surface
+out vec4 v_color;
 
 void main() {
     // Transform normals to preserve orthogonality after non-uniform transformations.
     v_normal = mat3(model_inverse_transpose) * normal;
     v_position = vec3(model_transform * vec4(position, 1.0));
+    v_tex_coord = tex_coord;
+    // Tangents lie along the surface, so they are transformed like positions
+    v_tangent = vec4(mat3(model_transform) * tangent.xyz, tangent.w);
+    v_color = color;
 
     // Transforms the position to screen space
     gl_Position = mvp * vec4(position, 1.0);
@@ -0,0 +1,173 @@
+#version 140
+
+// A directional, point or spot light. See cel.fs for a description of each
+// field. This struct must match the one in the cel shader.
+struct Light {
+    vec4 position;
+    vec3 color;
+    float range;
+    vec3 cone_direction;
+    float light_angle_scale;
+    float light_angle_offset;
+};
+
+struct Material {
+    vec4 diffuse_color;
+    // The light emitted by the material, which is not affected by lighting
+    vec3 emissive_color;
+    // Fragments with an alpha less than this value are discarded. Zero keeps
+    // every fragment.
+    float alpha_cutoff;
+    // The reflectance of the material when viewed head on (unused by this shader)
+    float reflectance;
+};
+
+// Light parameters
+#define MAX_LIGHTS 10
+uniform int num_lights;
+uniform Light lights[MAX_LIGHTS];
+uniform vec3 ambient_light;
+
+// Material data
+uniform Material material;
+// If true, the diffuse color of the material is multiplied by the color
+// sampled from base_color_texture
+uniform bool use_base_color_texture;
+uniform sampler2D base_color_texture;
+// If true, the emissive color of the material is multiplied by the color
+// sampled from emissive_texture
+uniform bool use_emissive_texture;
+uniform sampler2D emissive_texture;
+
+// If true, textures are decoded from sRGB (by the sampler) and lighting is
+// computed in linear space, then the result is encoded as sRGB. If false,
+// lighting is computed on the stored sRGB values and the result is written as
+// is.
+uniform bool linear_lighting;
+
+// The lit color is multiplied by this value before it is encoded. 1.0 leaves
+// the color unchanged.
+uniform float exposure;
+// The encoded color is raised to the power of 1 / gamma. 1.0 leaves the color
+// unchanged.
+uniform float gamma;
+
+// This is assumed to be normalized
+in vec3 v_normal;
+in vec3 v_position;
+in vec2 v_tex_coord;
+// White if the geometry has no vertex colors
+in vec4 v_color;
+
+out vec4 frag_color;
+
+// Point and spot lights fall off with the inverse square of the distance to
+// the light, windowed so that it reaches zero at the range of the light (if
+// any).
+//
+// https://github.com/KhronosGroup/glTF/tree/master/extensions/2.0/Khronos/KHR_lights_punctual#range-property
+float range_attenuation(float distance, float range) {
+    float inverse_square = 1.0 / pow(distance, 2);
+    if (range <= 0.0) {
+        // range is unlimited
+        return inverse_square;
+    }
+
+    return max(min(1.0 - pow(distance / range, 4), 1.0), 0.0) * inverse_square;
+}
+
+// Encodes a linear color with the sRGB transfer function
+// https://en.wikipedia.org/wiki/SRGB#Transfer_function_(%22gamma%22)
+vec3 linear_to_srgb(vec3 linear) {
+    linear = clamp(linear, 0.0, 1.0);
+    vec3 low = linear * 12.92;
+    vec3 high = 1.055 * pow(linear, vec3(1.0 / 2.4)) - 0.055;
+    return mix(high, low, vec3(lessThanEqual(linear, vec3(0.0031308))));
+}
+
+// Returns the color of the given texel. The sampler always decodes textures
+// from sRGB, so the texel is encoded again if lighting should use the stored
+// values.
+vec4 texel_color(vec4 texel) {
+    if (!linear_lighting) {
+        texel.rgb = linear_to_srgb(texel.rgb);
+    }
+    return texel;
+}
+
+// Returns the color of the surface before any lighting is applied. The alpha
+// component is only used for the alpha cutoff.
+vec4 base_color() {
+    vec4 color = material.diffuse_color * v_color;
+    if (use_base_color_texture) {
+        color *= texel_color(texture(base_color_texture, v_tex_coord));
+    }
+    return color;
+}
+
+// Returns the light emitted by the surface
+vec3 emissive_color() {
+    vec3 color = material.emissive_color;
+    if (use_emissive_texture) {
+        color *= texel_color(texture(emissive_texture, v_tex_coord)).rgb;
+    }
+    return color;
+}
+
+// Computes how much of the given light reaches a point on a surface, using the
+// same lighting model as the cel shader.
+//
+// Both position and normal should be in the world coordinate system.
+float light_intensity(Light light, vec3 position, vec3 normal) {
+    vec3 surface_to_light;
+    float attenuation;
+    if (light.position.w == 0.0) {
+        // Directional light
+        surface_to_light = -normalize(light.position.xyz);
+        attenuation = 1.0;
+
+    } else {
+        // Point / spot light
+        vec3 to_light = light.position.xyz - position;
+        float distance_to_light = length(to_light);
+        surface_to_light = to_light / distance_to_light;
+        attenuation = range_attenuation(distance_to_light, light.range);
+
+        if (light.light_angle_scale != 0.0) {
+            // https://github.com/KhronosGroup/glTF/tree/92f59a0dbefe2d54cff38dba103cd70462cc778b/extensions/2.0/Khronos/KHR_lights_punctual#inner-and-outer-cone-angles
+            float cd = dot(light.cone_direction, -surface_to_light);
+            float angular_attenuation = clamp(cd * light.light_angle_scale + light.light_angle_offset, 0.0, 1.0);
+            attenuation *= angular_attenuation * angular_attenuation;
+        }
+    }
+
+    return max(dot(normalize(normal), surface_to_light), 0.0) * attenuation;
+}
+
+void main() {
+    vec4 base = base_color();
+    // Cutout parts of the surface are not drawn, so whatever is behind them shows
+    // through instead
+    if (base.a < material.alpha_cutoff) {
+        discard;
+    }
+    vec3 color = base.rgb;
+    vec3 final_color = color * ambient_light;
+    // A single smooth diffuse term for each light, with no bands or highlights
+    for (int i = 0; i < num_lights; i++) {
+        Light light = lights[i];
+        final_color += color * light.color * light_intensity(light, v_position, v_normal);
+    }
+    final_color += emissive_color();
+
+    final_color *= exposure;
+
+    // Gamma correction with the sRGB transfer function -- apply at the very end
+    if (linear_lighting) {
+        final_color = linear_to_srgb(final_color);
+    }
+    // The gamma of the output only changes the encoded color
+    final_color = pow(clamp(final_color, 0.0, 1.0), vec3(1.0 / gamma));
+
+    frag_color = vec4(final_color, 1.0);
+}
@@ -0,0 +1,127 @@
+use std::sync::Arc;
+
+use glium::texture::SrgbTexture2d;
+use glium::uniforms::{Uniforms, UniformValue, SamplerBehavior};
+
+use crate::math::{Mat4, Rgb};
+use crate::scene::Material;
+use crate::renderer::{Light, ColorSpace};
+
+use super::nested_uniforms::NestedUniforms;
+use super::light_uniform::LightUniform;
+use super::material_uniform::MaterialUniform;
+use super::texture_sampler::texture_sampler;
+use super::cel::MAX_LIGHTS;
+
+pub struct FlatUniforms<'a> {
+    pub mvp: Mat4,
+    pub model_transform: Mat4,
+    pub model_inverse_transpose: Mat4,
+    pub lights: &'a [Arc<Light>],
+    pub ambient_light: Rgb,
+    pub material: &'a Material,
+    /// The texture multiplied with the diffuse color of the material, if any
+    pub base_color_texture: Option<&'a SrgbTexture2d>,
+    /// The texture multiplied with the emissive color of the material, if any
+    pub emissive_texture: Option<&'a SrgbTexture2d>,
+    pub color_space: ColorSpace,
+    pub exposure: f32,
+    pub gamma: f32,
+}
+
+/// This struct must match the uniforms in the flat shader
+pub struct Flat<'a> {
+    mvp: UniformValue<'static>,
+    model_transform: UniformValue<'static>,
+    model_inverse_transpose: UniformValue<'static>,
+    num_lights: UniformValue<'static>,
+    lights: Vec<LightUniform>,
+    ambient_light: UniformValue<'static>,
+    material: MaterialUniform,
+    base_color_texture: Option<(&'a SrgbTexture2d, SamplerBehavior)>,
+    emissive_texture: Option<(&'a SrgbTexture2d, SamplerBehavior)>,
+    linear_lighting: UniformValue<'static>,
+    exposure: UniformValue<'static>,
+    gamma: UniformValue<'static>,
+}
+
+impl<'t> Uniforms for Flat<'t> {
+    fn visit_values<'a, F: FnMut(&str, UniformValue<'a>)>(&'a self, mut visit: F) {
+        let Self {
+            mvp,
+            model_transform,
+            model_inverse_transpose,
+            num_lights,
+            lights,
+            ambient_light,
+            material,
+            base_color_texture,
+            emissive_texture,
+            linear_lighting,
+            exposure,
+            gamma,
+        } = self;
+
+        visit("mvp", *mvp);
+        visit("model_transform", *model_transform);
+        visit("model_inverse_transpose", *model_inverse_transpose);
+        visit("num_lights", *num_lights);
+        for (i, light) in lights.iter().enumerate() {
+            light.visit_nested_index("lights", i, &mut visit);
+        }
+        visit("ambient_light", *ambient_light);
+        material.visit_nested("material", &mut visit);
+        // Sampled the same way as the cel shader so that both modes show the same texels
+        visit("use_base_color_texture", UniformValue::Bool(base_color_texture.is_some()));
+        if let &Some((texture, sampler)) = base_color_texture {
+            visit("base_color_texture", UniformValue::SrgbTexture2d(texture, Some(sampler)));
+        }
+        visit("use_emissive_texture", UniformValue::Bool(emissive_texture.is_some()));
+        if let &Some((texture, sampler)) = emissive_texture {
+            visit("emissive_texture", UniformValue::SrgbTexture2d(texture, Some(sampler)));
+        }
+        visit("linear_lighting", *linear_lighting);
+        visit("exposure", *exposure);
+        visit("gamma", *gamma);
+    }
+}
+
+impl<'a> From<FlatUniforms<'a>> for Flat<'a> {
+    fn from(flat_uniforms: FlatUniforms<'a>) -> Self {
+        let FlatUniforms {
+            mvp,
+            model_transform,
+            model_inverse_transpose,
+            lights,
+            ambient_light,
+            material,
+            base_color_texture,
+            emissive_texture,
+            color_space,
+            exposure,
+            gamma,
+        } = flat_uniforms;
+
+        assert!(lights.len() <= MAX_LIGHTS, "Only up to {} lights can be rendered at any given time", MAX_LIGHTS);
+
+        Self {
+            mvp: UniformValue::Mat4(mvp.into_col_arrays()),
+            model_transform: UniformValue::Mat4(model_transform.into_col_arrays()),
+            model_inverse_transpose: UniformValue::Mat4(model_inverse_transpose.into_col_arrays()),
+            num_lights: UniformValue::SignedInt(lights.len() as i32),
+            lights: lights.iter().map(|light| {
+                let Light {data, world_transform} = &**light;
+                LightUniform::new(data, *world_transform)
+            }).collect(),
+            ambient_light: UniformValue::Vec3(ambient_light.into_array()),
+            material: MaterialUniform::new(material),
+            base_color_texture: base_color_texture.zip(material.base_color_texture.as_deref())
+                .map(|(texture, source)| (texture, texture_sampler(source))),
+            emissive_texture: emissive_texture.zip(material.emissive_texture.as_deref())
+                .map(|(texture, source)| (texture, texture_sampler(source))),
+            linear_lighting: UniformValue::Bool(color_space == ColorSpace::Linear),
+            exposure: UniformValue::Float(exposure),
+            gamma: UniformValue::Float(gamma),
+        }
+    }
+}
@@ -0,0 +1,31 @@
+#version 140
+
+// The color of the plane. Transparent if only the shadow should be drawn.
+uniform vec4 plane_color;
+
+// Contact shadow parameters
+// The color of the shadow at its center. Transparent if there is no shadow.
+uniform vec4 shadow_color;
+// The radius of the shadow in world units
+uniform float shadow_radius;
+// The fraction of the radius over which the shadow fades out
+uniform float shadow_softness;
+
+in vec2 v_offset;
+
+out vec4 color;
+
+void main() {
+    // Fully dark up to the soft edge, then fading out to nothing at the radius
+    float distance = length(v_offset) / shadow_radius;
+    float amount = 1.0 - smoothstep(1.0 - shadow_softness, 1.0, distance);
+    float shadow_alpha = shadow_color.a * amount;
+
+    // Composites the shadow over the plane
+    float alpha = shadow_alpha + plane_color.a * (1.0 - shadow_alpha);
+    if (alpha <= 0.0) {
+        discard;
+    }
+    vec3 rgb = (shadow_color.rgb * shadow_alpha + plane_color.rgb * plane_color.a * (1.0 - shadow_alpha)) / alpha;
+    color = vec4(rgb, alpha);
+}
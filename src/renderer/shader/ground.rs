@@ -0,0 +1,55 @@
+use glium::uniforms::{Uniforms, UniformValue};
+
+use crate::math::{Mat4, Rgba};
+use crate::renderer::{GroundPlane, ContactShadow};
+
+pub struct GroundUniforms<'a> {
+    pub mvp: Mat4,
+    pub ground: &'a GroundPlane,
+}
+
+/// This struct must match the uniforms in the ground shaders
+pub struct Ground {
+    mvp: UniformValue<'static>,
+    plane_color: UniformValue<'static>,
+    shadow_color: UniformValue<'static>,
+    shadow_radius: UniformValue<'static>,
+    shadow_softness: UniformValue<'static>,
+}
+
+impl Uniforms for Ground {
+    fn visit_values<'a, F: FnMut(&str, UniformValue<'a>)>(&'a self, mut visit: F) {
+        let &Self {mvp, plane_color, shadow_color, shadow_radius, shadow_softness} = self;
+
+        visit("mvp", mvp);
+        visit("plane_color", plane_color);
+        visit("shadow_color", shadow_color);
+        visit("shadow_radius", shadow_radius);
+        visit("shadow_softness", shadow_softness);
+    }
+}
+
+impl<'a> From<GroundUniforms<'a>> for Ground {
+    fn from(ground_uniforms: GroundUniforms<'a>) -> Self {
+        let GroundUniforms {mvp, ground} = ground_uniforms;
+        let &GroundPlane {height: _, color, shadow} = ground;
+
+        // A transparent shadow draws nothing. The radius of a missing shadow must still not be
+        // zero since the shader divides by it.
+        let ContactShadow {radius, softness, color: shadow_color} = shadow
+            .unwrap_or(ContactShadow {
+                radius: 1.0,
+                softness: 0.0,
+                color: Rgba::zero(),
+            });
+
+        Self {
+            mvp: UniformValue::Mat4(mvp.into_col_arrays()),
+            // A transparent plane only shows the shadow
+            plane_color: UniformValue::Vec4(color.unwrap_or_else(Rgba::zero).into_array()),
+            shadow_color: UniformValue::Vec4(shadow_color.into_array()),
+            shadow_radius: UniformValue::Float(radius),
+            shadow_softness: UniformValue::Float(softness.clamp(0.0, 1.0)),
+        }
+    }
+}
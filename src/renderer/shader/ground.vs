@@ -0,0 +1,17 @@
+#version 140
+
+// The Model View Projection matrix
+uniform mat4 mvp;
+
+in vec3 position;
+
+// The position on the plane, relative to the point directly beneath the
+// geometry
+out vec2 v_offset;
+
+void main() {
+    v_offset = position.xz;
+
+    // Transforms the position to screen space
+    gl_Position = mvp * vec4(position, 1.0);
+}
@@ -37,7 +37,8 @@ impl LightUniform {
     pub fn new(light: &LightType, world_transform: Mat4) -> Self {
         // scale is ignored by all the different light types
         let Transforms {scale: _, rotation, translation: pos} = world_transform.decompose();
-        let direction = rotation * Vec3 {x: 0.0, y: 0.0, z: -1.0};
+        // Normalized since the shader compares this with other unit vectors to find the cone angle
+        let direction = (rotation * Vec3 {x: 0.0, y: 0.0, z: -1.0}).normalized();
 
         use LightType::*;
         match light {
@@ -79,3 +80,71 @@ impl LightUniform {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use approx::{assert_relative_eq, relative_eq};
+
+    use crate::math::{Rgb, Radians};
+
+    #[test]
+    fn point_light_positioned_at_world_translation() {
+        let light = LightType::Point {color: Rgb::white(), intensity: 2.0, range: Some(10.0)};
+        let world_transform = Mat4::translation_3d(Vec3::new(1.0, 2.0, 3.0)) * Mat4::rotation_y(1.0);
+        let uniform = LightUniform::new(&light, world_transform);
+
+        match uniform.position {
+            UniformValue::Vec4([x, y, z, w]) => {
+                assert_eq!([x.round(), y.round(), z.round()], [1.0, 2.0, 3.0]);
+                // A w of 1.0 tells the shader that this light has a position and falls off
+                // with distance
+                assert_eq!(w, 1.0);
+            },
+            _ => panic!("expected the light position to be a vec4"),
+        }
+        match uniform.color {
+            UniformValue::Vec3(color) => assert_eq!(color, [2.0; 3]),
+            _ => panic!("expected the light color to be a vec3"),
+        }
+    }
+
+    #[test]
+    fn spot_light_cone_falloff() {
+        let light = LightType::Spot {
+            color: Rgb::white(),
+            intensity: 1.0,
+            range: None,
+            inner_cone_angle: Radians::from_radians(0.2),
+            outer_cone_angle: Radians::from_radians(0.4),
+        };
+        // Points the light down the -y axis
+        let world_transform = Mat4::rotation_x(-std::f32::consts::FRAC_PI_2);
+        let uniform = LightUniform::new(&light, world_transform);
+
+        let direction = match uniform.cone_direction {
+            UniformValue::Vec3(direction) => Vec3::from(direction),
+            _ => panic!("expected the cone direction to be a vec3"),
+        };
+        assert_relative_eq!(direction, Vec3::new(0.0, -1.0, 0.0), epsilon = 1e-6);
+
+        let (scale, offset) = match (uniform.light_angle_scale, uniform.light_angle_offset) {
+            (UniformValue::Float(scale), UniformValue::Float(offset)) => (scale, offset),
+            _ => panic!("expected the cone angle scale and offset to be floats"),
+        };
+        // The shader clamps `cos(angle) * scale + offset` to [0, 1], where the angle is between
+        // the cone direction and the direction to the surface
+        let cone = |angle: f32| angle.cos() * scale + offset;
+
+        // Full intensity anywhere within the inner cone
+        assert!(cone(0.0) >= 1.0);
+        assert_relative_eq!(cone(0.2), 1.0, epsilon = 1e-4);
+        // No light at or beyond the outer cone
+        assert_relative_eq!(cone(0.4), 0.0, epsilon = 1e-4);
+        assert!(cone(0.6) <= 0.0);
+        // Strictly fading out between the two cones
+        let falloff: Vec<_> = (0..=10).map(|i| cone(0.2 + 0.02 * i as f32)).collect();
+        assert!(falloff.windows(2).all(|pair| pair[1] < pair[0]), "falloff is not monotonic: {:?}", falloff);
+    }
+}
@@ -0,0 +1,45 @@
+#version 140
+
+struct Material {
+    vec4 diffuse_color;
+    // The light emitted by the material (unused by this shader)
+    vec3 emissive_color;
+    // Fragments with an alpha less than this value are discarded. Zero keeps
+    // every fragment.
+    float alpha_cutoff;
+    // The reflectance of the material when viewed head on (unused by this shader)
+    float reflectance;
+};
+
+// Material data, only used for the alpha cutoff
+uniform Material material;
+// If true, the alpha of the material is multiplied by the alpha sampled from
+// base_color_texture
+uniform bool use_base_color_texture;
+uniform sampler2D base_color_texture;
+
+// The color written wherever the geometry covers a pixel
+uniform vec4 mask_color;
+
+in vec3 v_normal;
+in vec3 v_position;
+in vec2 v_tex_coord;
+// White if the geometry has no vertex colors
+in vec4 v_color;
+
+out vec4 frag_color;
+
+void main() {
+    // Computed the same way as the other shaders so that the silhouette matches
+    // the shaded geometry exactly
+    float alpha = material.diffuse_color.a * v_color.a;
+    if (use_base_color_texture) {
+        alpha *= texture(base_color_texture, v_tex_coord).a;
+    }
+    // Cutout parts of the surface are not part of the silhouette
+    if (alpha < material.alpha_cutoff) {
+        discard;
+    }
+
+    frag_color = mask_color;
+}
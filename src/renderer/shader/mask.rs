@@ -0,0 +1,76 @@
+use glium::texture::SrgbTexture2d;
+use glium::uniforms::{Uniforms, UniformValue, SamplerBehavior};
+
+use crate::math::{Mat4, Rgba};
+use crate::scene::Material;
+
+use super::nested_uniforms::NestedUniforms;
+use super::material_uniform::MaterialUniform;
+use super::texture_sampler::texture_sampler;
+
+pub struct MaskUniforms<'a> {
+    pub mvp: Mat4,
+    pub model_transform: Mat4,
+    pub model_inverse_transpose: Mat4,
+    pub material: &'a Material,
+    /// The texture whose alpha is multiplied with the alpha of the material, if any
+    pub base_color_texture: Option<&'a SrgbTexture2d>,
+    pub mask_color: Rgba,
+}
+
+/// This struct must match the uniforms in the mask shader
+pub struct Mask<'a> {
+    mvp: UniformValue<'static>,
+    model_transform: UniformValue<'static>,
+    model_inverse_transpose: UniformValue<'static>,
+    material: MaterialUniform,
+    base_color_texture: Option<(&'a SrgbTexture2d, SamplerBehavior)>,
+    mask_color: UniformValue<'static>,
+}
+
+impl<'t> Uniforms for Mask<'t> {
+    fn visit_values<'a, F: FnMut(&str, UniformValue<'a>)>(&'a self, mut visit: F) {
+        let Self {
+            mvp,
+            model_transform,
+            model_inverse_transpose,
+            material,
+            base_color_texture,
+            mask_color,
+        } = self;
+
+        visit("mvp", *mvp);
+        visit("model_transform", *model_transform);
+        visit("model_inverse_transpose", *model_inverse_transpose);
+        material.visit_nested("material", &mut visit);
+        // Sampled the same way as the cel shader so that cutouts line up with the shaded image
+        visit("use_base_color_texture", UniformValue::Bool(base_color_texture.is_some()));
+        if let &Some((texture, sampler)) = base_color_texture {
+            visit("base_color_texture", UniformValue::SrgbTexture2d(texture, Some(sampler)));
+        }
+        visit("mask_color", *mask_color);
+    }
+}
+
+impl<'a> From<MaskUniforms<'a>> for Mask<'a> {
+    fn from(mask_uniforms: MaskUniforms<'a>) -> Self {
+        let MaskUniforms {
+            mvp,
+            model_transform,
+            model_inverse_transpose,
+            material,
+            base_color_texture,
+            mask_color,
+        } = mask_uniforms;
+
+        Self {
+            mvp: UniformValue::Mat4(mvp.into_col_arrays()),
+            model_transform: UniformValue::Mat4(model_transform.into_col_arrays()),
+            model_inverse_transpose: UniformValue::Mat4(model_inverse_transpose.into_col_arrays()),
+            material: MaterialUniform::new(material),
+            base_color_texture: base_color_texture.zip(material.base_color_texture.as_deref())
+                .map(|(texture, source)| (texture, texture_sampler(source))),
+            mask_color: UniformValue::Vec4(mask_color.into_array()),
+        }
+    }
+}
@@ -5,21 +5,71 @@ use crate::scene::Material;
 /// This struct must match the `Material` struct in our shaders
 pub struct MaterialUniform {
     diffuse_color: UniformValue<'static>,
+    emissive_color: UniformValue<'static>,
+    alpha_cutoff: UniformValue<'static>,
+    reflectance: UniformValue<'static>,
 }
 
 impl Uniforms for MaterialUniform {
     fn visit_values<'a, F: FnMut(&str, UniformValue<'a>)>(&'a self, mut visit: F) {
-        let &Self {diffuse_color} = self;
+        let &Self {diffuse_color, emissive_color, alpha_cutoff, reflectance} = self;
         visit("diffuse_color", diffuse_color);
+        visit("emissive_color", emissive_color);
+        visit("alpha_cutoff", alpha_cutoff);
+        visit("reflectance", reflectance);
     }
 }
 
 impl MaterialUniform {
     pub fn new(material: &Material) -> Self {
-        let &Material {diffuse_color} = material;
+        let &Material {
+            diffuse_color,
+            base_color_texture: _,
+            emissive_color,
+            emissive_texture: _,
+            normal_texture: _,
+            normal_scale: _,
+            alpha_cutoff,
+            ior,
+        } = material;
 
         Self {
             diffuse_color: UniformValue::Vec4(diffuse_color.into_array()),
+            emissive_color: UniformValue::Vec3(emissive_color.into_array()),
+            // Alpha is never less than zero, so a cutoff of zero keeps every fragment
+            alpha_cutoff: UniformValue::Float(alpha_cutoff.unwrap_or(0.0)),
+            reflectance: UniformValue::Float(reflectance(ior)),
         }
     }
 }
+
+/// Returns the fraction of light reflected by a surface with the given index of refraction when it
+/// is viewed head on. This is the base reflectance (R0) in Schlick's approximation, which the rim
+/// light adds to at grazing angles.
+///
+/// glTF materials always have an index of refraction of 1.5 (a reflectance of 0.04), since the
+/// gltf crate does not expose the KHR_materials_ior extension.
+///
+/// See: https://en.wikipedia.org/wiki/Schlick%27s_approximation
+fn reflectance(ior: f32) -> f32 {
+    let r = (ior - 1.0) / (ior + 1.0);
+    r * r
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use approx::{assert_relative_eq, relative_eq};
+
+    #[test]
+    fn reflectance_from_index_of_refraction() {
+        // The default index of refraction reflects a small amount of light, so the rim light
+        // is never zero, even for surfaces that face the camera
+        assert_relative_eq!(reflectance(Material::default().ior), 0.04);
+        // Water
+        assert_relative_eq!(reflectance(1.33), 0.02006, epsilon = 1e-5);
+        // A material with the same index of refraction as air reflects nothing head on
+        assert_eq!(reflectance(1.0), 0.0);
+    }
+}
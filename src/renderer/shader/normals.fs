@@ -0,0 +1,12 @@
+#version 140
+
+in vec3 v_normal;
+in float v_depth;
+
+out vec4 color;
+
+void main() {
+    // The interpolated normal is not guaranteed to be normalized. Normalizing it also means that
+    // only the pixels where nothing was drawn have a normal of zero length.
+    color = vec4(normalize(v_normal), v_depth);
+}
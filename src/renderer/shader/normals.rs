@@ -0,0 +1,38 @@
+use glium::uniforms::{Uniforms, UniformValue};
+
+use crate::math::Mat4;
+
+pub struct NormalsUniforms {
+    pub mvp: Mat4,
+    pub model_view: Mat4,
+    pub model_inverse_transpose: Mat4,
+}
+
+/// This struct must match the uniforms in the normals shaders
+pub struct Normals {
+    mvp: UniformValue<'static>,
+    model_view: UniformValue<'static>,
+    model_inverse_transpose: UniformValue<'static>,
+}
+
+impl Uniforms for Normals {
+    fn visit_values<'a, F: FnMut(&str, UniformValue<'a>)>(&'a self, mut visit: F) {
+        let &Self {mvp, model_view, model_inverse_transpose} = self;
+
+        visit("mvp", mvp);
+        visit("model_view", model_view);
+        visit("model_inverse_transpose", model_inverse_transpose);
+    }
+}
+
+impl From<NormalsUniforms> for Normals {
+    fn from(normals_uniforms: NormalsUniforms) -> Self {
+        let NormalsUniforms {mvp, model_view, model_inverse_transpose} = normals_uniforms;
+
+        Self {
+            mvp: UniformValue::Mat4(mvp.into_col_arrays()),
+            model_view: UniformValue::Mat4(model_view.into_col_arrays()),
+            model_inverse_transpose: UniformValue::Mat4(model_inverse_transpose.into_col_arrays()),
+        }
+    }
+}
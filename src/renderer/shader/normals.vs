@@ -0,0 +1,27 @@
+#version 140
+
+// The Model View Projection matrix
+uniform mat4 mvp;
+// The model view matrix, used to find the depth of each vertex from the camera
+uniform mat4 model_view;
+// The transpose of the inverse of the model matrix, used for
+// transforming the vertex's normal
+uniform mat4 model_inverse_transpose;
+
+in vec3 position;
+in vec3 normal;
+
+// The normal, in the world coordinate system
+out vec3 v_normal;
+// The distance in front of the camera, along its view direction
+out float v_depth;
+
+void main() {
+    // Transform normals to preserve orthogonality after non-uniform transformations.
+    v_normal = mat3(model_inverse_transpose) * normal;
+    // The camera looks down the negative z-axis of its own coordinate system
+    v_depth = -(model_view * vec4(position, 1.0)).z;
+
+    // Transforms the position to screen space
+    gl_Position = mvp * vec4(position, 1.0);
+}
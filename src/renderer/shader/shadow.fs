@@ -0,0 +1,5 @@
+#version 140
+
+void main() {
+    // Only the depth of each fragment is written to the shadow map
+}
@@ -0,0 +1,31 @@
+use glium::uniforms::{Uniforms, UniformValue};
+
+use crate::math::Mat4;
+
+pub struct ShadowUniforms {
+    /// Transforms the model into the clip space of the light casting the shadow
+    pub mvp: Mat4,
+}
+
+/// This struct must match the uniforms in the shadow shaders
+pub struct Shadow {
+    mvp: UniformValue<'static>,
+}
+
+impl Uniforms for Shadow {
+    fn visit_values<'a, F: FnMut(&str, UniformValue<'a>)>(&'a self, mut visit: F) {
+        let &Self {mvp} = self;
+
+        visit("mvp", mvp);
+    }
+}
+
+impl From<ShadowUniforms> for Shadow {
+    fn from(shadow_uniforms: ShadowUniforms) -> Self {
+        let ShadowUniforms {mvp} = shadow_uniforms;
+
+        Self {
+            mvp: UniformValue::Mat4(mvp.into_col_arrays()),
+        }
+    }
+}
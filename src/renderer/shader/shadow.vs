@@ -0,0 +1,10 @@
+#version 140
+
+// Transforms the model into the clip space of the light casting the shadow
+uniform mat4 mvp;
+
+in vec3 position;
+
+void main() {
+    gl_Position = mvp * vec4(position, 1.0);
+}
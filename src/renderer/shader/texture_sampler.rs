@@ -0,0 +1,68 @@
+use glium::uniforms::{SamplerBehavior, SamplerWrapFunction, MagnifySamplerFilter, MinifySamplerFilter};
+
+use crate::scene::{Texture, WrapMode, FilterMode};
+
+/// Returns the sampler used to sample the given texture of a material
+///
+/// The texture is filtered and wrapped the way that it specifies. Textures are sampled before
+/// any lighting is applied, so bilinear filtering happens before the cel shading bands.
+pub fn texture_sampler(texture: &Texture) -> SamplerBehavior {
+    let (wrap_s, wrap_t) = texture.wrap;
+    // Mipmaps are never sampled, so minification uses the same filter as magnification
+    let (magnify_filter, minify_filter) = match texture.filter {
+        FilterMode::Nearest => (MagnifySamplerFilter::Nearest, MinifySamplerFilter::Nearest),
+        FilterMode::Bilinear => (MagnifySamplerFilter::Linear, MinifySamplerFilter::Linear),
+    };
+
+    SamplerBehavior {
+        wrap_function: (wrap_function(wrap_s), wrap_function(wrap_t), SamplerWrapFunction::Repeat),
+        magnify_filter,
+        minify_filter,
+        ..Default::default()
+    }
+}
+
+fn wrap_function(wrap: WrapMode) -> SamplerWrapFunction {
+    match wrap {
+        WrapMode::Repeat => SamplerWrapFunction::Repeat,
+        WrapMode::ClampToEdge => SamplerWrapFunction::Clamp,
+        WrapMode::MirroredRepeat => SamplerWrapFunction::Mirror,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use image::RgbaImage;
+
+    #[test]
+    fn wrap_modes_map_to_sampler_wrap_functions() {
+        let mut texture = Texture::new(RgbaImage::new(1, 1));
+
+        // Repeat is the default, as specified by glTF
+        let (wrap_s, wrap_t, _) = texture_sampler(&texture).wrap_function;
+        assert_eq!((wrap_s, wrap_t), (SamplerWrapFunction::Repeat, SamplerWrapFunction::Repeat));
+
+        // Each direction is wrapped separately
+        texture.wrap = (WrapMode::ClampToEdge, WrapMode::MirroredRepeat);
+        let (wrap_s, wrap_t, _) = texture_sampler(&texture).wrap_function;
+        assert_eq!((wrap_s, wrap_t), (SamplerWrapFunction::Clamp, SamplerWrapFunction::Mirror));
+    }
+
+    #[test]
+    fn filter_modes_map_to_sampler_filters() {
+        let mut texture = Texture::new(RgbaImage::new(1, 1));
+
+        // Nearest is the default, so the texels of pixel art stay crisp
+        let sampler = texture_sampler(&texture);
+        assert_eq!(sampler.magnify_filter, MagnifySamplerFilter::Nearest);
+        assert_eq!(sampler.minify_filter, MinifySamplerFilter::Nearest);
+
+        texture.filter = FilterMode::Bilinear;
+        let sampler = texture_sampler(&texture);
+        assert_eq!(sampler.magnify_filter, MagnifySamplerFilter::Linear);
+        // Mipmaps are never generated, so minification must not sample them
+        assert_eq!(sampler.minify_filter, MinifySamplerFilter::Linear);
+    }
+}
@@ -1,5 +1,7 @@
+use std::rc::{Rc, Weak};
 use std::borrow::Cow;
 use std::sync::Arc;
+use std::collections::HashMap;
 
 use glium::{
     VertexBuffer,
@@ -7,11 +9,13 @@ use glium::{
     VertexFormat,
     index::{self, PrimitiveType},
     vertex::{self, AttributeType},
+    texture::{self, SrgbTexture2d, Texture2d, RawImage2d},
+    backend::{Context, Facade},
 };
 use thiserror::Error;
 
-use crate::math::{Vec3, Mat4};
-use crate::scene::{Geometry, Material};
+use crate::math::{self, Vec2, Vec3, Vec4, Mat4, Rgba};
+use crate::scene::{Geometry, Material, Texture, NormalMode};
 use crate::renderer::Display;
 
 #[derive(Debug, Error)]
@@ -19,17 +23,126 @@ use crate::renderer::Display;
 pub enum ShaderGeometryError {
     IndexBufferCreationError(#[from] index::BufferCreationError),
     VertexBufferCreationError(#[from] vertex::BufferCreationError),
+    TextureCreationError(#[from] texture::TextureCreationError),
 }
 
-/// Geometry stored on the GPU
+/// The vertices and indices of a mesh stored on the GPU, which may be shared by several instances
+/// of the same geometry
 #[derive(Debug)]
-pub struct ShaderGeometry {
+pub struct MeshBuffers {
     pub indices: IndexBuffer<u32>,
     pub positions: VertexBuffer<Vec3>,
     pub normals: VertexBuffer<Vec3>,
+    /// The texture coordinates of each vertex (all zero if the geometry has no texture
+    /// coordinates)
+    pub tex_coords: VertexBuffer<Vec2>,
+    /// The tangent of each vertex (all zero if the geometry has no tangents)
+    pub tangents: VertexBuffer<Vec4>,
+    /// The color of each vertex (all white if the geometry has no vertex colors)
+    pub colors: VertexBuffer<Rgba>,
+}
+
+/// Geometry stored on the GPU
+#[derive(Debug)]
+pub struct ShaderGeometry {
+    pub mesh: Rc<MeshBuffers>,
     pub material: Arc<Material>,
+    /// The base color texture of the material, uploaded to the GPU
+    pub base_color_texture: Option<Rc<SrgbTexture2d>>,
+    /// The emissive texture of the material, uploaded to the GPU
+    pub emissive_texture: Option<Rc<SrgbTexture2d>>,
+    /// The normal texture of the material, uploaded to the GPU without any sRGB decoding
+    pub normal_texture: Option<Rc<Texture2d>>,
     /// The world transform of this geometry
     pub model_transform: Mat4,
+    /// The transpose of the inverse of the world transform, used to transform normals so that
+    /// they stay perpendicular to the surface, even under non-uniform scaling
+    pub model_inverse_transpose: Mat4,
+    /// The (min, max) corners of the axis-aligned box in world space that contains every vertex
+    /// of this geometry, or None if the geometry has no vertices
+    pub bounds: Option<(Vec3, Vec3)>,
+}
+
+/// Remembers which display a cache of GPU resources was uploaded to
+///
+/// Resources uploaded to one display (OpenGL context) cannot be used with another. Caches should
+/// be cleared whenever they are used with a different display than the last one.
+#[derive(Debug, Default)]
+pub struct DisplayTracker {
+    /// The context of the last display. Holding a weak reference keeps the address of the context
+    /// from being reused by another context, even after the display is dropped.
+    context: Option<Weak<Context>>,
+}
+
+impl DisplayTracker {
+    /// Records that resources are about to be used with the given display. Returns true if it is
+    /// not the same display that was previously recorded (or if there was none), in which case
+    /// any cached resources must be discarded.
+    pub fn switch_to(&mut self, display: &Display) -> bool {
+        let context = display.get_context();
+        let same_display = self.context.as_ref()
+            .is_some_and(|last| Weak::ptr_eq(last, &Rc::downgrade(context)));
+        if !same_display {
+            self.context = Some(Rc::downgrade(context));
+        }
+        !same_display
+    }
+}
+
+/// Textures that have already been uploaded to the GPU
+///
+/// Geometry that shares a texture can share a single copy of it on the GPU. This avoids uploading
+/// the same textures again each time geometry is recreated (e.g. for each frame of an animation).
+#[derive(Debug, Default)]
+pub struct TextureCache {
+    /// Each uploaded texture, keyed by the address of the texture it was created from. The
+    /// original texture is kept so that its address cannot be reused by another texture.
+    textures: HashMap<usize, (Arc<Texture>, Rc<SrgbTexture2d>)>,
+    /// Each texture uploaded without sRGB decoding (e.g. normal textures), keyed the same way
+    linear_textures: HashMap<usize, (Arc<Texture>, Rc<Texture2d>)>,
+}
+
+impl TextureCache {
+    /// Returns the uploaded copy of the given texture, uploading it if it is not in the cache
+    pub fn get_or_upload(
+        &mut self,
+        display: &Display,
+        texture: &Arc<Texture>,
+    ) -> Result<Rc<SrgbTexture2d>, texture::TextureCreationError> {
+        let key = Arc::as_ptr(texture) as usize;
+        if let Some((_, uploaded)) = self.textures.get(&key) {
+            return Ok(uploaded.clone());
+        }
+
+        let image = &texture.image;
+        // OpenGL places the first row of the data at v = 0, so uploading the rows top-to-bottom
+        // keeps (0, 0) at the top-left corner of the texture
+        let raw = RawImage2d::from_raw_rgba(image.clone().into_raw(), image.dimensions());
+        let uploaded = Rc::new(SrgbTexture2d::new(display, raw)?);
+        self.textures.insert(key, (texture.clone(), uploaded.clone()));
+
+        Ok(uploaded)
+    }
+
+    /// Returns the uploaded copy of the given texture, uploading it if it is not in the cache.
+    /// The values of the texture are sampled as is, rather than decoded from sRGB.
+    pub fn get_or_upload_linear(
+        &mut self,
+        display: &Display,
+        texture: &Arc<Texture>,
+    ) -> Result<Rc<Texture2d>, texture::TextureCreationError> {
+        let key = Arc::as_ptr(texture) as usize;
+        if let Some((_, uploaded)) = self.linear_textures.get(&key) {
+            return Ok(uploaded.clone());
+        }
+
+        let image = &texture.image;
+        let raw = RawImage2d::from_raw_rgba(image.clone().into_raw(), image.dimensions());
+        let uploaded = Rc::new(Texture2d::new(display, raw)?);
+        self.linear_textures.insert(key, (texture.clone(), uploaded.clone()));
+
+        Ok(uploaded)
+    }
 }
 
 impl ShaderGeometry {
@@ -37,7 +150,26 @@ impl ShaderGeometry {
         display: &Display,
         geo: &Geometry,
         model_transform: Mat4,
+        normals: NormalMode,
+    ) -> Result<Self, ShaderGeometryError> {
+        Self::with_texture_cache(display, geo, model_transform, normals, &mut TextureCache::default())
+    }
+
+    /// Uploads the given geometry with its normals generated in the given way, reusing any of its
+    /// textures that are already in the cache
+    pub fn with_texture_cache(
+        display: &Display,
+        geo: &Geometry,
+        model_transform: Mat4,
+        normals: NormalMode,
+        textures: &mut TextureCache,
     ) -> Result<Self, ShaderGeometryError> {
+        // Flat normals need vertices that are not shared between triangles
+        let geo = match normals {
+            NormalMode::Smooth => Cow::Borrowed(geo),
+            NormalMode::Flat => Cow::Owned(geo.flat_shaded()),
+        };
+
         const POSITION_ATTR_TYPE: AttributeType = AttributeType::F32F32F32;
         let position_bindings: VertexFormat = Cow::Borrowed(&[
             // This name must correspond to the name in our shaders
@@ -48,13 +180,57 @@ impl ShaderGeometry {
             // This name must correspond to the name in our shaders
             (Cow::Borrowed("normal"), 0, NORMAL_ATTR_TYPE, false),
         ]);
+        const TEX_COORD_ATTR_TYPE: AttributeType = AttributeType::F32F32;
+        let tex_coord_bindings: VertexFormat = Cow::Borrowed(&[
+            // This name must correspond to the name in our shaders
+            (Cow::Borrowed("tex_coord"), 0, TEX_COORD_ATTR_TYPE, false),
+        ]);
+        const TANGENT_ATTR_TYPE: AttributeType = AttributeType::F32F32F32F32;
+        let tangent_bindings: VertexFormat = Cow::Borrowed(&[
+            // This name must correspond to the name in our shaders
+            (Cow::Borrowed("tangent"), 0, TANGENT_ATTR_TYPE, false),
+        ]);
+        const COLOR_ATTR_TYPE: AttributeType = AttributeType::F32F32F32F32;
+        let color_bindings: VertexFormat = Cow::Borrowed(&[
+            // This name must correspond to the name in our shaders
+            (Cow::Borrowed("color"), 0, COLOR_ATTR_TYPE, false),
+        ]);
+
+        let Geometry {name: _, indices, positions, normals, tex_coords, tangents, colors, joints: _, weights: _, morph_targets: _, material} = &*geo;
+
+        // The shader always reads texture coordinates, even if there is no texture to sample
+        let tex_coords = if tex_coords.is_empty() {
+            Cow::Owned(vec![Vec2::zero(); positions.len()])
+        } else {
+            Cow::Borrowed(tex_coords)
+        };
+        // A zero tangent tells the shader that the normal texture cannot be applied
+        let tangents = if tangents.is_empty() {
+            Cow::Owned(vec![Vec4::zero(); positions.len()])
+        } else {
+            Cow::Borrowed(tangents)
+        };
+        // White leaves the diffuse color of the material unchanged
+        let colors = if colors.is_empty() {
+            Cow::Owned(vec![Rgba::white(); positions.len()])
+        } else {
+            Cow::Borrowed(colors)
+        };
 
-        let Geometry {name: _, indices, positions, normals, material} = geo;
+        let base_color_texture = material.base_color_texture.as_ref()
+            .map(|texture| textures.get_or_upload(display, texture))
+            .transpose()?;
+        let emissive_texture = material.emissive_texture.as_ref()
+            .map(|texture| textures.get_or_upload(display, texture))
+            .transpose()?;
+        let normal_texture = material.normal_texture.as_ref()
+            .map(|texture| textures.get_or_upload_linear(display, texture))
+            .transpose()?;
 
         // NOTE: By using `immutable`, we are guranteeing that the data in these buffers will
         //   *never* change.
         // See: https://docs.rs/glium/0.26.0-alpha3/glium/buffer/enum.BufferMode.html
-        Ok(Self {
+        let mesh = MeshBuffers {
             indices: IndexBuffer::immutable(display, PrimitiveType::TrianglesList, indices)?,
             // These calls to new_raw are safe assuming that the specified attribute types
             // correspond to the types of the items stored in the `positions` and `normals` fields
@@ -66,8 +242,107 @@ impl ShaderGeometry {
                 POSITION_ATTR_TYPE.get_size_bytes())? },
             normals: unsafe { VertexBuffer::new_raw(display, normals, normal_bindings,
                 NORMAL_ATTR_TYPE.get_size_bytes())? },
+            tex_coords: unsafe { VertexBuffer::new_raw(display, &tex_coords, tex_coord_bindings,
+                TEX_COORD_ATTR_TYPE.get_size_bytes())? },
+            tangents: unsafe { VertexBuffer::new_raw(display, &tangents, tangent_bindings,
+                TANGENT_ATTR_TYPE.get_size_bytes())? },
+            colors: unsafe { VertexBuffer::new_raw(display, &colors, color_bindings,
+                COLOR_ATTR_TYPE.get_size_bytes())? },
+        };
+
+        Ok(Self {
+            mesh: Rc::new(mesh),
             material: material.clone(),
+            base_color_texture,
+            emissive_texture,
+            normal_texture,
             model_transform,
+            model_inverse_transpose: inverse_transpose(model_transform),
+            bounds: math::bounds(positions.iter().map(|&pos| model_transform.mul_point(pos))),
         })
     }
+
+    /// Returns another instance of this geometry with the given transform applied on top of its
+    /// world transform. The instance shares the buffers and textures of this geometry, so nothing
+    /// is uploaded to the GPU.
+    ///
+    /// The bounds of the instance contain the transformed bounds of this geometry, so they may be
+    /// larger than the instance itself if the transform has a rotation.
+    pub fn instance(&self, instance_transform: Mat4) -> Self {
+        let model_transform = instance_transform * self.model_transform;
+        Self {
+            mesh: self.mesh.clone(),
+            material: self.material.clone(),
+            base_color_texture: self.base_color_texture.clone(),
+            emissive_texture: self.emissive_texture.clone(),
+            normal_texture: self.normal_texture.clone(),
+            model_transform,
+            model_inverse_transpose: inverse_transpose(model_transform),
+            bounds: self.bounds.map(|bounds| transform_bounds(bounds, instance_transform)),
+        }
+    }
+
+    /// Returns the (min, max) corners of the axis-aligned box in world space that contains all of
+    /// the given geometry, or None if there are no vertices
+    pub fn combined_bounds<'a>(geos: impl IntoIterator<Item = &'a ShaderGeometry>) -> Option<(Vec3, Vec3)> {
+        math::bounds(geos.into_iter().filter_map(|geo| geo.bounds).flat_map(|(min, max)| [min, max]))
+    }
+}
+
+/// Returns the (min, max) corners of the axis-aligned box that contains the given box after it is
+/// transformed by the given matrix
+fn transform_bounds((min, max): (Vec3, Vec3), transform: Mat4) -> (Vec3, Vec3) {
+    let corners = (0..8).map(|i| transform.mul_point(Vec3 {
+        x: if i & 1 == 0 { min.x } else { max.x },
+        y: if i & 2 == 0 { min.y } else { max.y },
+        z: if i & 4 == 0 { min.z } else { max.z },
+    }));
+    math::bounds(corners).expect("bug: a box always has corners")
+}
+
+/// Computes the matrix used to transform normals for geometry with the given world transform
+fn inverse_transpose(model_transform: Mat4) -> Mat4 {
+    model_transform.inverted().transposed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use approx::{assert_relative_eq, relative_eq};
+
+    #[test]
+    fn normals_perpendicular_under_non_uniform_scale() {
+        let model_transform = Mat4::scaling_3d(Vec3::new(4.0, 1.0, 0.5))
+            .rotated_z(0.7)
+            .translated_3d(Vec3::new(1.0, 2.0, 3.0));
+        let model_inverse_transpose = inverse_transpose(model_transform);
+
+        // A surface along the plane x + y = 0, with its normal
+        let tangent = Vec3::new(1.0, -1.0, 0.0);
+        let bitangent = Vec3::new(0.0, 0.0, 1.0);
+        let normal = Vec3::new(1.0, 1.0, 0.0).normalized();
+
+        let tangent = model_transform.mul_direction(tangent);
+        let bitangent = model_transform.mul_direction(bitangent);
+        let normal = model_inverse_transpose.mul_direction(normal).normalized();
+        assert_relative_eq!(normal.dot(tangent), 0.0, epsilon = 1e-5);
+        assert_relative_eq!(normal.dot(bitangent), 0.0, epsilon = 1e-5);
+
+        // The plain model matrix does not keep the normal perpendicular
+        let skewed = model_transform.mul_direction(Vec3::new(1.0, 1.0, 0.0)).normalized();
+        assert!(skewed.dot(tangent).abs() > 1e-2);
+    }
+
+    #[test]
+    fn instance_bounds_contain_rotated_box() {
+        let bounds = (Vec3::new(-1.0, 0.0, -1.0), Vec3::new(1.0, 2.0, 1.0));
+        let transform = Mat4::translation_3d(Vec3::new(10.0, 0.0, 0.0))
+            * Mat4::rotation_y(std::f32::consts::FRAC_PI_4);
+
+        let (min, max) = transform_bounds(bounds, transform);
+        let half_diagonal = 2.0f32.sqrt();
+        assert_relative_eq!(min, Vec3::new(10.0 - half_diagonal, 0.0, -half_diagonal), epsilon = 1e-5);
+        assert_relative_eq!(max, Vec3::new(10.0 + half_diagonal, 2.0, half_diagonal), epsilon = 1e-5);
+    }
 }
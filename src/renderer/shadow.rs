@@ -0,0 +1,140 @@
+use std::num::NonZeroU32;
+use std::sync::Arc;
+
+use glium::texture::DepthTexture2d;
+
+use crate::math::{Mat4, Vec3, FrustumPlanes, Decompose, Transforms};
+use crate::scene::LightType;
+
+use super::Light;
+
+/// Hard shadows cast by the primary directional light of a rendered image
+///
+/// Shadows require the geometry to be drawn an extra time from the point of view of the light, so
+/// they are disabled by default.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Shadows {
+    /// The width and height (in pixels) of the depth image rendered from the light. Larger values
+    /// produce sharper shadow edges.
+    pub resolution: NonZeroU32,
+    /// The distance (as a fraction of the size of the scene) that a surface must be behind the
+    /// closest surface to the light before it is in shadow. This keeps surfaces from shadowing
+    /// themselves (known as "shadow acne").
+    pub bias: f32,
+}
+
+impl Default for Shadows {
+    fn default() -> Self {
+        Self {
+            resolution: NonZeroU32::new(1024).unwrap(),
+            bias: 0.005,
+        }
+    }
+}
+
+/// The depth of the geometry as seen from a light, used to find the surfaces that the light does
+/// not reach
+#[derive(Debug)]
+pub struct ShadowMap {
+    /// The index of the light that casts the shadows
+    pub(in super) light: usize,
+    /// Transforms world coordinates into the clip space of the light
+    pub(in super) light_space: Mat4,
+    /// The depth of the closest surface to the light in each pixel
+    pub(in super) depth: DepthTexture2d,
+    pub(in super) bias: f32,
+}
+
+/// Returns the index of the light that casts shadows: the key light if it is a directional light,
+/// otherwise the first directional light. Returns None if there are no directional lights.
+pub(in super) fn shadow_light(lights: &[Arc<Light>], key_light: Option<usize>) -> Option<usize> {
+    let is_directional = |index: &usize| match *lights[*index].data {
+        LightType::Directional {..} => true,
+        LightType::Point {..} | LightType::Spot {..} => false,
+    };
+
+    key_light.filter(is_directional).or_else(|| (0..lights.len()).find(is_directional))
+}
+
+/// Returns the matrix that transforms world coordinates into the clip space of a directional light
+/// with the given world transform. The orthographic projection of the light fits around the given
+/// (min, max) bounds of the scene so that every surface in the scene can cast a shadow.
+pub(in super) fn light_space(light_transform: Mat4, (min, max): (Vec3, Vec3)) -> Mat4 {
+    let Transforms {rotation, ..} = light_transform.decompose();
+    // Lights shine down their local negative z-axis
+    let direction = (rotation * -Vec3::unit_z()).normalized();
+
+    let center = (min + max) / 2.0;
+    // A sphere around the bounds fits inside the projection no matter which way the light faces.
+    // The radius cannot be zero or the projection would be degenerate.
+    let radius = ((max - min).magnitude() / 2.0).max(1e-3);
+
+    // Any up direction works as long as it is not parallel to the direction of the light
+    let up = if direction.y.abs() > 0.99 { Vec3::unit_z() } else { Vec3::unit_y() };
+    let eye = center - direction * radius * 2.0;
+    let view = Mat4::look_at_rh(eye, center, up);
+    let projection = Mat4::orthographic_rh_no(FrustumPlanes {
+        left: -radius,
+        right: radius,
+        bottom: -radius,
+        top: radius,
+        near: radius,
+        far: radius * 3.0,
+    });
+
+    projection * view
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use approx::{assert_relative_eq, relative_eq};
+
+    use crate::math::{Rgb, Vec4};
+
+    #[test]
+    fn shadows_cast_by_directional_key_light() {
+        let point = Arc::new(Light {
+            data: Arc::new(LightType::Point {color: Rgb::white(), intensity: 1.0, range: None}),
+            world_transform: Mat4::identity(),
+        });
+        let directional = Arc::new(Light::directional(-Vec3::unit_y(), Rgb::white(), 1.0));
+        let lights = vec![point.clone(), directional.clone(), directional];
+
+        assert_eq!(shadow_light(&lights, Some(2)), Some(2));
+        // Point lights cannot cast shadows, so the first directional light is used instead
+        assert_eq!(shadow_light(&lights, Some(0)), Some(1));
+        assert_eq!(shadow_light(&lights, None), Some(1));
+        assert_eq!(shadow_light(&[point], None), None);
+    }
+
+    #[test]
+    fn light_space_contains_scene() {
+        // Shines straight down onto a 2x2x2 box
+        let light = Light::directional(-Vec3::unit_y(), Rgb::white(), 1.0);
+        let bounds = (Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0));
+        let light_space = light_space(light.world_transform, bounds);
+
+        let clip = |point: Vec3| {
+            let Vec4 {x, y, z, w} = light_space * Vec4::from_point(point);
+            Vec3::new(x, y, z) / w
+        };
+        // The top of the box is closer to the light than the bottom
+        let top = clip(Vec3::new(0.0, 1.0, 0.0));
+        let bottom = clip(Vec3::new(0.0, -1.0, 0.0));
+        assert!(top.z < bottom.z);
+        assert_relative_eq!(top.x, bottom.x, epsilon = 1e-6);
+        assert_relative_eq!(top.y, bottom.y, epsilon = 1e-6);
+
+        // Every corner of the box is inside the clip volume
+        for &x in &[-1.0, 1.0] {
+            for &y in &[-1.0, 1.0] {
+                for &z in &[-1.0, 1.0] {
+                    let corner = clip(Vec3::new(x, y, z));
+                    assert!(corner.map(|c| c.abs() <= 1.0).reduce_and(), "{:?} is outside", corner);
+                }
+            }
+        }
+    }
+}
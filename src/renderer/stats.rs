@@ -0,0 +1,27 @@
+use std::time::Instant;
+
+/// How long each stage of rendering took and how much was drawn
+///
+/// Times are in milliseconds. Rendered images are drawn one after another, so the times of every
+/// image in a job are added together.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RenderStats {
+    /// The time spent converting the job into a layout and fetching the cameras and lights of
+    /// each rendered image
+    pub parse_ms: f64,
+    /// The time spent posing the geometry of each rendered image and uploading it (along with
+    /// its textures) to the GPU
+    pub upload_ms: f64,
+    /// The time spent drawing, reading the images back from the GPU, and finishing them
+    /// (outlines, supersampling, scaling, and pixel format conversion)
+    pub draw_ms: f64,
+    /// The number of triangles drawn, including extra passes such as outlines and shadow maps
+    pub triangles: u64,
+    /// The number of draw calls made to the GPU
+    pub draw_calls: u64,
+}
+
+/// Returns the number of milliseconds since the given instant
+pub(in super) fn elapsed_ms(start: Instant) -> f64 {
+    start.elapsed().as_secs_f64() * 1000.0
+}
@@ -29,9 +29,14 @@
 // leave it that way.
 
 use std::num::NonZeroU32;
+use std::sync::Arc;
+use std::cell::Cell;
+use std::time::Instant;
+use std::collections::{HashMap, hash_map::Entry};
 
 use glium::{
     Program,
+    Surface,
     framebuffer::SimpleFrameBuffer,
     texture::{
         RawImage2d,
@@ -48,20 +53,54 @@ use glium::glutin::{
     event_loop::EventLoop,
 };
 use image::{RgbaImage, imageops::flip_vertical_in_place};
+use rayon::iter::{ParallelIterator, IntoParallelIterator};
 use thiserror::Error;
 
-use crate::query3d::{QueryBackend, QueryError};
+use crate::math::{Rgba, Vec3, Mat4};
+use crate::scene::{Geometry, Material, NormalMode};
+use crate::query3d::{QueryError, AnimationQuery, AnimationPosition};
 
 use super::{
     Renderer,
     RenderedImage,
+    Background,
+    OrbitFrames,
+    ShaderGeometry,
+    ShaderGeometryError,
+    Outline,
+    OutlineMode,
+    ShadingMode,
+    Supersample,
+    Wireframe,
+    Light,
+    Lighting,
+    GizmoShape,
+    Shadows,
+    ShadowMap,
+    GroundPlane,
+    ContactShadow,
     Size,
-    FileQuery,
-    Camera,
-    layout::LayoutNode,
-    imageops::{scale_to_fit, copy},
+    AspectMode,
+    Atlas,
+    DepthMode,
+    ScaleMode,
+    RenderStats,
+    layout::{LayoutNode, LayoutError},
+    stats::elapsed_ms,
+    edges::{NormalDepthBuffer, EdgeThresholds, detect_edges, draw_edges},
+    depth::{DepthImage, to_depth_image, downscale_depth},
+    shadow::{shadow_light, light_space},
+    camera::letterbox,
+    imageops::{scale_to_fit, scale_bilinear, resize_buffer, copy, extrude, downscale, composite_over, to_pixel},
 };
 
+/// The approximate size of the gizmos drawn for each light (in world units)
+const LIGHT_GIZMO_SIZE: f32 = 0.25;
+
+/// The width and depth of the ground plane (in world units), large enough that its edges are
+/// rarely in view
+const GROUND_PLANE_SIZE: f32 = 1000.0;
+
 #[derive(Debug, Error)]
 #[error(transparent)]
 pub enum ContextCreationError {
@@ -78,19 +117,40 @@ pub enum BeginRenderError {
 }
 
 #[derive(Debug, Error)]
-#[error(transparent)]
 pub enum DrawLayoutError {
+    #[error(transparent)]
     BeginRenderError(#[from] BeginRenderError),
+    #[error(transparent)]
     DrawError(#[from] glium::DrawError),
+    #[error(transparent)]
     ReadError(#[from] glium::ReadError),
+    #[error(transparent)]
     QueryError(#[from] QueryError),
+    #[error(transparent)]
+    ShaderGeometryError(#[from] ShaderGeometryError),
+    #[error(transparent)]
+    LayoutError(#[from] LayoutError),
+    #[error("Key light index {index} is out of range, there are only {num_lights} lights")]
+    KeyLightOutOfRange {index: usize, num_lights: usize},
+    #[error("Contact shadow radius must be greater than zero, got {radius}")]
+    InvalidContactShadowRadius {radius: f32},
 }
 
 pub(in super) struct Shaders {
     /// The cel shader used for drawing the sprites
     pub cel: Program,
+    /// The flat shader used for drawing the sprites without any cel shading
+    pub flat: Program,
+    /// The shader used for drawing only the silhouette of the sprites
+    pub mask: Program,
     /// The outline shader used for drawing an outline around the sprites
     pub outline: Program,
+    /// The shader used for drawing the normals and depths that edge detection is performed on
+    pub normals: Program,
+    /// The shader used for drawing the depth of the geometry as seen from a light
+    pub shadow: Program,
+    /// The shader used for drawing the ground plane and its contact shadow
+    pub ground: Program,
 }
 
 /// The data backing one of the Renderers
@@ -123,6 +183,12 @@ pub struct ThreadRenderContext {
     shaders: Shaders,
     /// The data backing each Renderer
     render_data: Vec<RenderData>,
+    /// The statistics of everything rendered since they were last taken
+    stats: Cell<RenderStats>,
+    /// The ground plane geometry, uploaded the first time a ground plane is drawn
+    ground_plane: Option<ShaderGeometry>,
+    /// The geometry of each light gizmo shape, uploaded the first time it is drawn
+    light_gizmos: HashMap<GizmoShape, ShaderGeometry>,
 }
 
 impl ThreadRenderContext {
@@ -151,6 +217,22 @@ impl ThreadRenderContext {
             None,
         )?;
 
+        // Shares the vertex shader of the cel shader so that geometry is placed identically
+        let flat_shader = Program::from_source(
+            &display,
+            include_str!("shader/cel.vs"),
+            include_str!("shader/flat.fs"),
+            None,
+        )?;
+
+        // Also shares the vertex shader so that the silhouette matches the shaded geometry
+        let mask_shader = Program::from_source(
+            &display,
+            include_str!("shader/cel.vs"),
+            include_str!("shader/mask.fs"),
+            None,
+        )?;
+
         let outline_shader = Program::from_source(
             &display,
             include_str!("shader/outline.vs"),
@@ -158,17 +240,59 @@ impl ThreadRenderContext {
             None,
         )?;
 
+        let normals_shader = Program::from_source(
+            &display,
+            include_str!("shader/normals.vs"),
+            include_str!("shader/normals.fs"),
+            None,
+        )?;
+
+        let shadow_shader = Program::from_source(
+            &display,
+            include_str!("shader/shadow.vs"),
+            include_str!("shader/shadow.fs"),
+            None,
+        )?;
+
+        let ground_shader = Program::from_source(
+            &display,
+            include_str!("shader/ground.vs"),
+            include_str!("shader/ground.fs"),
+            None,
+        )?;
+
         Ok(Self {
             _event_loop: event_loop,
             display,
             shaders: Shaders {
                 cel: cel_shader,
+                flat: flat_shader,
+                mask: mask_shader,
                 outline: outline_shader,
+                normals: normals_shader,
+                shadow: shadow_shader,
+                ground: ground_shader,
             },
             render_data: Vec::new(),
+            stats: Cell::default(),
+            ground_plane: None,
+            light_gizmos: HashMap::new(),
         })
     }
 
+    /// Returns the statistics of everything rendered with this context since the last time they
+    /// were taken, and resets them
+    pub fn take_stats(&mut self) -> RenderStats {
+        self.stats.take()
+    }
+
+    /// Adds to the statistics of this context
+    pub(in super) fn record_stats(&self, record: impl FnOnce(&mut RenderStats)) {
+        let mut stats = self.stats.get();
+        record(&mut stats);
+        self.stats.set(stats);
+    }
+
     /// Returns a new renderer that can be used for drawing
     pub fn begin_render(&mut self, size: Size) -> Result<(RenderId, Renderer), BeginRenderError> {
         let Size {width, height} = size;
@@ -195,6 +319,7 @@ impl ThreadRenderContext {
             display: &self.display,
             shaders: &self.shaders,
             target,
+            stats: &self.stats,
         }))
     }
 
@@ -212,62 +337,605 @@ impl ThreadRenderContext {
         Ok(image)
     }
 
-    /// Scales the given image up, with no anti-aliasing or other interpolation of any kind.
-    pub fn scale(&mut self, image: &RgbaImage, scale: NonZeroU32) -> Result<RgbaImage, DrawLayoutError> {
+    /// Returns the normals and depths that were rendered using `Renderer::render_normals`
+    pub fn finish_normal_render(&mut self, render_id: RenderId) -> NormalDepthBuffer {
+        let RenderId(id) = render_id;
+        let data = self.render_data.remove(id);
+        // This is safe because the color texture is always created with a four component float
+        // format, which every version of OpenGL that we support is able to read
+        let rows: Vec<Vec<(f32, f32, f32, f32)>> = unsafe { data.color_texture.unchecked_read() };
+
+        let height = rows.len() as u32;
+        let width = rows.first().map(|row| row.len() as u32).unwrap_or(0);
+        // OpenGL returns the bottom row first
+        let pixels = rows.into_iter().rev()
+            .flatten()
+            .map(|(x, y, z, depth)| [x, y, z, depth])
+            .collect();
+
+        NormalDepthBuffer {width, height, pixels}
+    }
+
+    /// Scales the given image up using the given scaling mode
+    pub fn scale(&mut self, image: &RgbaImage, scale: NonZeroU32, mode: ScaleMode) -> Result<RgbaImage, DrawLayoutError> {
+        let mut scaled_image = RgbaImage::new(0, 0);
+        self.scale_into(image, scale, mode, &mut scaled_image)?;
+        Ok(scaled_image)
+    }
+
+    /// Scales the given image into the given target, which is only reallocated if its size is not
+    /// already the size of the scaled image
+    pub fn scale_into(
+        &mut self,
+        image: &RgbaImage,
+        scale: NonZeroU32,
+        mode: ScaleMode,
+        target: &mut RgbaImage,
+    ) -> Result<(), DrawLayoutError> {
         //TODO: Do this scaling using the GPU. Should the error type still be DrawLayoutError?
 
         //TODO: Could optimize the case of scale == 1
         let scale = scale.get();
         let (width, height) = image.dimensions();
-        let mut scaled_image = RgbaImage::new(width * scale, height * scale);
-        scale_to_fit(&image, &mut scaled_image);
+        resize_buffer(target, (width * scale, height * scale));
+        match mode {
+            ScaleMode::Nearest => scale_to_fit(image, target),
+            ScaleMode::Bilinear => scale_bilinear(image, target),
+        }
 
-        Ok(scaled_image)
+        Ok(())
+    }
+
+    /// Renders evenly spaced frames of the given animation and packs them into an atlas image that
+    /// is at most `max_width` pixels wide. Each frame is trimmed down to its visible pixels before
+    /// it is packed.
+    ///
+    /// Every frame is rendered using the given image, with its geometry posed at the frame's
+    /// position in the animation. If `animation` is None, the default animation is used.
+    pub fn draw_animation_atlas(
+        &mut self,
+        image: RenderedImage,
+        animation: Option<String>,
+        steps: NonZeroU32,
+        max_width: NonZeroU32,
+    ) -> Result<Atlas, DrawLayoutError> {
+        let steps = steps.get();
+        let frames = (0..steps).map(|step| {
+            let mut frame = image.clone();
+            // Geometry that was already resolved cannot be posed any differently
+            for query in frame.geometry.queries_mut() {
+                query.animation = Some(AnimationQuery {
+                    name: animation.clone(),
+                    position: AnimationPosition::RelativeTime {
+                        start_time: 0.0,
+                        weight: step as f32 / steps as f32,
+                    },
+                });
+            }
+
+            self.render_gpu(frame)
+        }).collect::<Result<Vec<_>, _>>()?;
+        // Only the GPU work needs to happen on this thread
+        let frames = frames.into_par_iter()
+            .map(|frame| frame.finish().0)
+            .collect::<Vec<_>>();
+
+        Ok(Atlas::pack(&frames, max_width))
+    }
+
+    /// Returns an iterator over `steps` copies of the given image, each with the camera rotated
+    /// a further step around the world origin. The camera, lights, and geometry are fetched
+    /// once up front and shared by every frame.
+    pub fn orbit_frames(&mut self, image: RenderedImage, steps: NonZeroU32) -> Result<OrbitFrames, DrawLayoutError> {
+        let image = image.resolve(&self.display)?;
+        Ok(OrbitFrames::new(image, steps)?)
     }
 
     /// Draws the given layout, returning the image that was rendered
     pub fn draw(&mut self, layout: LayoutNode) -> Result<RgbaImage, DrawLayoutError> {
+        self.draw_with_depth(layout).map(|(image, _)| image)
+    }
+
+    /// Draws the given layout, returning the image that was rendered along with a depth image if
+    /// any of the rendered images in the layout asked for one
+    ///
+    /// The depth image is the same size as the rendered image. Areas of the layout without a depth
+    /// image of their own are left at the farthest depth.
+    pub fn draw_with_depth(&mut self, layout: LayoutNode) -> Result<(RgbaImage, Option<DepthImage>), DrawLayoutError> {
         let Size {width, height} = layout.size();
 
-        let mut final_image = RgbaImage::new(width.get(), height.get());
-        for (offset, node) in layout.iter_targets() {
+        let bleed = match &layout {
+            LayoutNode::Grid(grid) => grid.bleed,
+            LayoutNode::Packed(packed) => packed.bleed,
+            _ => 0,
+        };
+
+        // OpenGL can only be used from the thread that owns this context, so every cell is first
+        // rendered on this thread
+        let mut cells = Vec::new();
+        for (offset, cell, node) in layout.iter_cells() {
             use LayoutNode::*;
-            match node {
-                RenderedImage(image) => {
-                    let image = self.draw_render(image)?;
-                    copy(&image, &mut final_image, (offset.x, offset.y));
-                },
 
-                Grid(_) => {
-                    let image = self.draw(node)?;
-                    copy(&image, &mut final_image, (offset.x, offset.y));
-                },
+            let content = match node {
+                RenderedImage(image) => CellContent::Rendered(self.render_gpu(image)?),
+                Grid(_) | Packed(_) => CellContent::Drawn(self.draw_with_depth(node)?),
+                // Draw nothing other than the background
+                Empty {..} => CellContent::Empty,
+            };
+            cells.push((offset, cell, content));
+        }
 
-                Empty {..} => {
-                    // Draw nothing
-                },
+        // The rest of the work for each cell only uses the CPU, so the cells are finished in
+        // parallel and then copied into the final image in order
+        let start = Instant::now();
+        let cells = cells.into_par_iter().map(|(offset, cell, content)| {
+            let drawn = match content {
+                CellContent::Rendered(render) => Some(render.finish()),
+                CellContent::Drawn(drawn) => Some(drawn),
+                CellContent::Empty => None,
+            };
+            (offset, cell, drawn)
+        }).collect::<Vec<_>>();
+
+        let mut final_image = RgbaImage::new(width.get(), height.get());
+        let mut final_depth: Option<DepthImage> = None;
+        for (offset, cell, drawn) in cells {
+            cell.fill_background(&mut final_image);
+
+            if let Some((image, depth)) = drawn {
+                cell.draw_image(&image, offset, &mut final_image);
+
+                if let Some(depth) = depth {
+                    let final_depth = final_depth.get_or_insert_with(|| {
+                        DepthImage::from_pixel(width.get(), height.get(), image::Luma([1.0]))
+                    });
+                    image::imageops::replace(final_depth, &depth, offset.x, offset.y);
+                }
+            }
+
+            if bleed > 0 {
+                extrude(&mut final_image, (cell.offset.x, cell.offset.y), cell.size, bleed);
+            }
+        }
+
+        self.record_stats(|stats| stats.draw_ms += elapsed_ms(start));
+
+        Ok((final_image, final_depth))
+    }
+
+    /// Draws the depth of the given geometry as seen from the light that casts shadows, or returns
+    /// None if there is no light that can cast shadows (or no geometry to shadow)
+    fn render_shadow_map(
+        &self,
+        geos: &[Arc<ShaderGeometry>],
+        lights: &[Arc<Light>],
+        key_light: Option<usize>,
+        shadows: Shadows,
+    ) -> Result<Option<ShadowMap>, DrawLayoutError> {
+        let Shadows {resolution, bias} = shadows;
+        let light = match shadow_light(lights, key_light) {
+            Some(light) => light,
+            None => return Ok(None),
+        };
+        let bounds = match ShaderGeometry::combined_bounds(geos.iter().map(|geo| &**geo)) {
+            Some(bounds) => bounds,
+            None => return Ok(None),
+        };
+        let light_space = light_space(lights[light].world_transform, bounds);
+
+        let depth = DepthTexture2d::empty_with_format(&self.display, DepthFormat::F32,
+            MipmapsOption::NoMipmap, resolution.get(), resolution.get())
+            .map_err(BeginRenderError::from)?;
+        {
+            let target = SimpleFrameBuffer::depth_only(&self.display, &depth)
+                .map_err(BeginRenderError::from)?;
+            let mut renderer = Renderer {
+                display: &self.display,
+                shaders: &self.shaders,
+                target,
+                stats: &self.stats,
+            };
+            renderer.target.clear_depth(1.0);
+            for geo in geos {
+                renderer.render_shadow_depth(geo, light_space)?;
             }
         }
 
-        Ok(final_image)
+        Ok(Some(ShadowMap {light, light_space, depth, bias}))
     }
 
-    fn draw_render(&mut self, image: RenderedImage) -> Result<RgbaImage, DrawLayoutError> {
-        let RenderedImage {size, background, camera, lights, ambient_light, geometry, outline} = image;
-        let FileQuery {query, file} = geometry;
-        let Camera {view, projection} = *camera.fetch_camera()?;
+    /// Returns the ground plane geometry, uploading it if this is the first time it is needed
+    fn ground_plane(&mut self) -> Result<&ShaderGeometry, ShaderGeometryError> {
+        if self.ground_plane.is_none() {
+            // The material is unused because the ground shader only needs the positions
+            let plane = Geometry::plane(GROUND_PLANE_SIZE, Arc::new(Material::default()));
+            self.ground_plane = Some(ShaderGeometry::new(&self.display, &plane, Mat4::identity(),
+                NormalMode::Smooth)?);
+        }
+
+        // This unwrap() will never panic because the plane was just uploaded if it was missing
+        Ok(self.ground_plane.as_ref().unwrap())
+    }
+
+    /// Returns the geometry of the given light gizmo shape, uploading it if this is the first time
+    /// it is needed
+    fn light_gizmo(&mut self, shape: GizmoShape) -> Result<&ShaderGeometry, ShaderGeometryError> {
+        let display = &self.display;
+        match self.light_gizmos.entry(shape) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => {
+                let gizmo = ShaderGeometry::new(display, &shape.geometry(), Mat4::identity(),
+                    NormalMode::Smooth)?;
+                Ok(entry.insert(gizmo))
+            },
+        }
+    }
+
+    /// Renders the given image and reads it back from the GPU. The result still needs to be
+    /// finished before it can be used.
+    fn render_gpu(&mut self, image: RenderedImage) -> Result<RawRender, DrawLayoutError> {
+        let RenderedImage {
+            size,
+            background,
+            cell_background: _,
+            camera,
+            framing,
+            aspect,
+            lights,
+            ambient_light,
+            key_light,
+            bands,
+            shading,
+            color_space,
+            exposure,
+            gamma,
+            geometry,
+            outline,
+            rim,
+            specular,
+            shadows,
+            ground,
+            light_gizmos,
+            wireframe,
+            supersample,
+            depth,
+        } = image;
+        // A mask only contains the silhouette of the geometry, so nothing is drawn behind it
+        let (background, ground, light_gizmos) = match shading {
+            ShadingMode::Mask => (Background::Color(Rgba::zero()), None, false),
+            _ => (background, ground, light_gizmos),
+        };
+        let start = Instant::now();
+        let camera = camera.fetch_camera()?;
         let lights = lights.fetch_lights()?;
+        validate_key_light(key_light, lights.len())?;
+        validate_ground(ground.as_ref())?;
+        self.record_stats(|stats| stats.parse_ms += elapsed_ms(start));
+
+        // The geometry is fetched before rendering so that the shadow map can be drawn first
+        let start = Instant::now();
+        let geos = geometry.fetch_geometry(&self.display)?;
+        self.record_stats(|stats| stats.upload_ms += elapsed_ms(start));
+        let start = Instant::now();
+        let camera = match framing {
+            Some(framing) => {
+                let bounds = ShaderGeometry::combined_bounds(geos.iter().map(|geo| &**geo));
+                framing.apply(&camera, size, bounds)
+            },
+            None => (*camera).clone(),
+        };
+
+        // A letterboxed image is rendered at the size of the area that keeps the aspect ratio of
+        // the camera, and the bars are added around it once it is finished
+        let (letterbox, size) = match aspect {
+            AspectMode::Stretch => (None, size),
+            AspectMode::Letterbox => {
+                let (offset, area) = letterbox(size, camera.aspect_ratio());
+                (Some((offset, size)), area)
+            },
+        };
+
+        // The size that the image is actually rendered at
+        let render_size = match supersample {
+            Some(Supersample {factor: (factor_x, factor_y), ..}) => Size {
+                // Safe because multiplying two non-zero values cannot be zero
+                width: unsafe { NonZeroU32::new_unchecked(size.width.get() * factor_x.get()) },
+                height: unsafe { NonZeroU32::new_unchecked(size.height.get() * factor_y.get()) },
+            },
+            None => size,
+        };
+        let shadow_map = match shadows {
+            // Flat shading and masks do not draw shadows
+            Some(shadows) if shading == ShadingMode::Cel => {
+                self.render_shadow_map(&geos, &lights, key_light, shadows)?
+            },
+            _ => None,
+        };
 
-        let (render_id, mut renderer) = self.begin_render(size)?;
-        renderer.clear(background);
+        // The ground plane is centered beneath the geometry
+        let bounds = ShaderGeometry::combined_bounds(geos.iter().map(|geo| &**geo));
+        let ground = match (ground, bounds) {
+            (Some(ground), Some(bounds)) => {
+                Some((self.ground_plane()?.instance(ground_plane_transform(&ground, bounds)), ground))
+            },
+            _ => None,
+        };
+
+        let gizmos = if light_gizmos {
+            lights.iter().map(|light| {
+                let gizmo = self.light_gizmo(light.gizmo_shape())?
+                    .instance(light.gizmo_transform(LIGHT_GIZMO_SIZE));
+                Ok(ShaderGeometry {material: Arc::new(light.gizmo_material()), ..gizmo})
+            }).collect::<Result<Vec<_>, ShaderGeometryError>>()?
+        } else {
+            Vec::new()
+        };
+
+        let (render_id, mut renderer) = self.begin_render(render_size)?;
+        // Background images are drawn behind the geometry once it has been rendered
+        let clear_color = match &background {
+            &Background::Color(color) => color,
+            Background::Image(_) => Rgba::zero(),
+        };
+        renderer.clear(clear_color);
 
-        let mut file = file.lock().expect("bug: file lock was poisoned");
-        let geos = file.query_geometry(&query, renderer.display())?;
-        for geo in &*geos {
-            renderer.render(&*geo, &lights, ambient_light, view, projection, &outline)?;
+        // The ground is drawn first so that it is behind any transparent parts of the geometry
+        if let Some((plane, ground)) = ground {
+            renderer.render_ground(&plane, &ground, &camera)?;
+        }
+
+        let lighting = Lighting {
+            lights: &lights,
+            ambient_light,
+            key_light,
+            bands: &bands,
+            shadow: shadow_map.as_ref(),
+            color_space,
+            exposure,
+            gamma,
+        };
+        // A wireframe that is not an overlay replaces the shaded geometry entirely
+        let wireframe_only = matches!(wireframe, Some(Wireframe {overlay: false, ..}));
+        if wireframe_only {
+            // The hidden edges can only be found if the geometry is in the depth buffer
+            if let Some(Wireframe {depth_test: true, ..}) = wireframe {
+                for geo in &*geos {
+                    renderer.render_depth_only(geo, &camera)?;
+                }
+            }
+        } else {
+            for geo in &*geos {
+                match shading {
+                    ShadingMode::Cel => renderer.render(geo, lighting, &camera, &outline, rim, specular)?,
+                    ShadingMode::Flat => renderer.render_flat(geo, lighting, &camera)?,
+                    ShadingMode::Mask => renderer.render_mask(geo, &camera)?,
+                }
+            }
+        }
+
+        if let Some(Wireframe {color, thickness, overlay: _, depth_test}) = wireframe {
+            // The lines are as wide in the final image as they would be without supersampling
+            let thickness = match supersample {
+                Some(Supersample {factor: (factor_x, factor_y), ..}) => {
+                    thickness * factor_x.get().max(factor_y.get()) as f32
+                },
+                None => thickness,
+            };
+            for geo in &*geos {
+                renderer.render_wireframe(geo, &camera, color, thickness, depth_test)?;
+            }
+        }
+
+        // Gizmos are drawn in the flat color of their light with no outline
+        let no_outline = Outline {
+            mode: OutlineMode::InvertedHull {thickness: 0.0},
+            color: Rgba::zero(),
+        };
+        for gizmo in &gizmos {
+            renderer.render(gizmo, lighting.unlit(), &camera, &no_outline, None, None)?;
         }
 
         let image = self.finish_render(render_id)?;
-        Ok(image)
+
+        let edge_outline = match outline.mode {
+            // Flat shading and masks never draw an outline
+            _ if shading != ShadingMode::Cel => None,
+            OutlineMode::EdgeDetect {thickness, depth_threshold, normal_threshold} if thickness > 0 => {
+                Some((thickness, EdgeThresholds {depth: depth_threshold, normal: normal_threshold}))
+            },
+            _ => None,
+        };
+        // Both edge detection and depth images need the normal and depth of every pixel
+        let normals = if edge_outline.is_some() || depth.is_some() {
+            let (normals_id, mut renderer) = self.begin_render(render_size)?;
+            renderer.clear(Rgba::zero());
+            for geo in &*geos {
+                renderer.render_normals(geo, &camera)?;
+            }
+            Some(self.finish_normal_render(normals_id))
+        } else {
+            None
+        };
+
+        self.record_stats(|stats| stats.draw_ms += elapsed_ms(start));
+
+        Ok(RawRender {
+            size,
+            image,
+            normals,
+            edge_outline: edge_outline.map(|(thickness, thresholds)| (thickness, thresholds, outline.color)),
+            depth: depth.map(|mode| (mode, camera.projection)),
+            supersample,
+            letterbox,
+            background,
+        })
+    }
+}
+
+/// The contents of a cell of a layout, before it is finished
+enum CellContent {
+    /// An image that was rendered but has not been finished yet
+    Rendered(RawRender),
+    /// A nested layout that was already drawn, along with its depth image (if any)
+    Drawn((RgbaImage, Option<DepthImage>)),
+    Empty,
+}
+
+/// An image that was rendered and read back from the GPU, but does not have its outline, depth
+/// image, supersampling, or background applied yet
+///
+/// Finishing the image only uses the CPU, so it can be done on any thread.
+struct RawRender {
+    /// The final size of the image
+    size: Size,
+    /// The image as it was rendered, possibly at a larger size for supersampling
+    image: RgbaImage,
+    /// The normals and depths of the rendered image, if anything needs them
+    normals: Option<NormalDepthBuffer>,
+    /// The thickness, thresholds, and color of the edge detected outline, if any
+    edge_outline: Option<(u32, EdgeThresholds, Rgba)>,
+    /// The way to store the depth image and the projection of the camera, if a depth image is
+    /// needed
+    depth: Option<(DepthMode, Mat4)>,
+    supersample: Option<Supersample>,
+    /// The offset of the rendered image within the final image and the size of the final image,
+    /// if the image is letterboxed
+    letterbox: Option<((u32, u32), Size)>,
+    background: Background,
+}
+
+impl RawRender {
+    /// Applies everything that is drawn after rendering, returning the final image and its depth
+    /// image (if any)
+    fn finish(self) -> (RgbaImage, Option<DepthImage>) {
+        let Self {size, mut image, normals, edge_outline, depth, supersample, letterbox, background} = self;
+
+        if let (Some((thickness, thresholds, color)), Some(normals)) = (edge_outline, &normals) {
+            let edges = detect_edges(normals, thresholds);
+            // The outline is drawn before downscaling, so it needs to be scaled up to match
+            let (scale_x, scale_y) = supersample
+                .map(|Supersample {factor: (x, y), ..}| (x.get(), y.get()))
+                .unwrap_or((1, 1));
+            draw_edges(&mut image, &edges, (thickness * scale_x, thickness * scale_y), color);
+        }
+
+        let depth = match (depth, &normals) {
+            (Some((mode, projection)), Some(normals)) => {
+                let depth = to_depth_image(normals, projection, mode);
+                Some(match supersample {
+                    Some(Supersample {factor, ..}) => downscale_depth(&depth, factor),
+                    None => depth,
+                })
+            },
+            _ => None,
+        };
+
+        let image = match supersample {
+            Some(Supersample {factor, filter}) => downscale(&image, factor, filter),
+            None => image,
+        };
+
+        // The bars are the background color, or transparent so that the background image shows
+        // through them
+        let (size, image, depth) = match letterbox {
+            Some(((x, y), full_size)) => {
+                let bar_color = match &background {
+                    &Background::Color(color) => color,
+                    Background::Image(_) => Rgba::zero(),
+                };
+                let mut full_image = RgbaImage::from_pixel(full_size.width.get(), full_size.height.get(),
+                    to_pixel(bar_color));
+                copy(&image, &mut full_image, (x, y));
+
+                // The bars are at the farthest depth, like any other area with no geometry
+                let depth = depth.map(|depth| {
+                    let mut full_depth = DepthImage::from_pixel(full_size.width.get(),
+                        full_size.height.get(), image::Luma([1.0]));
+                    image::imageops::replace(&mut full_depth, &depth, x, y);
+                    full_depth
+                });
+
+                (full_size, full_image, depth)
+            },
+            None => (size, image, depth),
+        };
+
+        let image = match background {
+            Background::Color(_) => image,
+            Background::Image(background) => {
+                let mut background = (*background.resized(size)).clone();
+                composite_over(&image, &mut background);
+                background
+            },
+        };
+
+        (image, depth)
+    }
+}
+
+/// Returns an error if the given key light is not the index of one of the lights
+fn validate_key_light(key_light: Option<usize>, num_lights: usize) -> Result<(), DrawLayoutError> {
+    match key_light {
+        Some(index) if index >= num_lights => Err(DrawLayoutError::KeyLightOutOfRange {index, num_lights}),
+        _ => Ok(()),
+    }
+}
+
+/// Returns an error if the contact shadow of the given ground plane would make the ground shader
+/// divide by zero
+fn validate_ground(ground: Option<&GroundPlane>) -> Result<(), DrawLayoutError> {
+    match ground.and_then(|ground| ground.shadow) {
+        Some(ContactShadow {radius, ..}) if radius <= 0.0 || radius.is_nan() => {
+            Err(DrawLayoutError::InvalidContactShadowRadius {radius})
+        },
+        _ => Ok(()),
+    }
+}
+
+/// Returns the transform that places the ground plane at its height, centered beneath the given
+/// (min, max) bounds of the geometry
+fn ground_plane_transform(ground: &GroundPlane, (min, max): (Vec3, Vec3)) -> Mat4 {
+    let center = (min + max) / 2.0;
+    Mat4::translation_3d(Vec3::new(center.x, ground.height, center.z))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_light_must_be_in_range() {
+        assert!(validate_key_light(None, 0).is_ok());
+        assert!(validate_key_light(Some(1), 2).is_ok());
+        match validate_key_light(Some(2), 2) {
+            Err(DrawLayoutError::KeyLightOutOfRange {index: 2, num_lights: 2}) => {},
+            result => panic!("expected an out of range error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn contact_shadow_radius_must_be_positive() {
+        let ground = |radius| GroundPlane {
+            height: 0.0,
+            color: None,
+            shadow: Some(ContactShadow {radius, ..ContactShadow::default()}),
+        };
+
+        assert!(validate_ground(None).is_ok());
+        assert!(validate_ground(Some(&GroundPlane {shadow: None, ..ground(0.0)})).is_ok());
+        assert!(validate_ground(Some(&ground(0.5))).is_ok());
+        for &radius in &[0.0, -1.0, f32::NAN] {
+            match validate_ground(Some(&ground(radius))) {
+                Err(DrawLayoutError::InvalidContactShadowRadius {..}) => {},
+                result => panic!("expected an invalid radius error for {}, got {:?}", radius, result),
+            }
+        }
+    }
+
+    #[test]
+    fn ground_plane_centered_beneath_geometry() {
+        let ground = GroundPlane {height: -1.0, color: None, shadow: None};
+        let transform = ground_plane_transform(&ground, (Vec3::new(-1.0, 2.0, 3.0), Vec3::new(3.0, 4.0, 5.0)));
+        assert_eq!(transform.mul_point(Vec3::zero()), Vec3::new(1.0, -1.0, 4.0));
     }
 }
@@ -1,19 +1,28 @@
 mod mesh;
 mod geometry;
 mod material;
+mod texture;
 mod node;
 mod camera_type;
 mod light_type;
+mod shapes;
+mod skin;
+mod animation;
 
 pub use mesh::*;
 pub use geometry::*;
 pub use material::*;
+pub use texture::*;
 pub use node::*;
 pub use camera_type::*;
 pub use light_type::*;
+pub use skin::*;
+pub use animation::*;
 
 use std::sync::Arc;
 
+use crate::math::{self, Vec3};
+
 #[derive(Debug, Clone)]
 pub struct Scene {
     /// The name of the scene (possibly empty), or None if the 3D file this was loaded from does
@@ -27,14 +36,58 @@ impl Scene {
     pub fn from_gltf(
         scene: gltf::Scene,
         meshes: &[Arc<Mesh>],
+        skins: &[Arc<Skin>],
         cameras: &[Arc<CameraType>],
         lights: &[Arc<LightType>],
     ) -> Self {
         Self {
             name: Some(scene.name().unwrap_or("").to_string()),
             roots: scene.nodes()
-                .map(|node| Arc::new(Node::from_gltf(node, meshes, cameras, lights)))
+                .map(|node| Arc::new(Node::from_gltf(node, meshes, skins, cameras, lights)))
                 .collect(),
         }
     }
+
+    /// Returns the (min, max) corners of the axis-aligned box in world space that contains every
+    /// vertex of every mesh in this scene, or None if the scene has no vertices
+    ///
+    /// Meshes are placed using the transforms of their nodes, without any animation, skinning, or
+    /// morph targets applied.
+    pub fn bounds(&self) -> Option<(Vec3, Vec3)> {
+        let nodes = self.roots.iter().flat_map(|root| root.traverse());
+        let mesh_bounds = nodes.filter_map(|(parent_trans, node)| {
+            node.mesh()?.transformed_bounds(parent_trans * node.transform)
+        });
+        math::bounds(mesh_bounds.flat_map(|(min, max)| [min, max]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::math::Mat4;
+
+    #[test]
+    fn bounds_apply_node_transforms() {
+        let plane = Geometry::plane(2.0, Arc::new(Material::default()));
+        let mesh = Arc::new(Mesh {name: None, geometry: vec![plane], morph_weights: Vec::new()});
+        let node = |index, transform, children| Arc::new(Node {
+            index,
+            name: None,
+            data: Some(NodeData::Mesh(mesh.clone())),
+            transform,
+            decomposed: NodeTransform::default(),
+            skin: None,
+            morph_weights: None,
+            children,
+        });
+        let child = node(1, Mat4::translation_3d(Vec3::new(0.0, 0.0, 5.0)), Vec::new());
+        let root = node(0, Mat4::scaling_3d(Vec3::broadcast(2.0)), vec![child]);
+
+        let scene = Scene {name: None, roots: vec![root]};
+        // The offset of the child is scaled along with it
+        assert_eq!(scene.bounds(), Some((Vec3::new(-2.0, 0.0, -2.0), Vec3::new(2.0, 0.0, 12.0))));
+        assert_eq!(Scene {name: None, roots: Vec::new()}.bounds(), None);
+    }
 }
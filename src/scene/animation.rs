@@ -0,0 +1,290 @@
+use std::collections::HashMap;
+
+use crate::math::{Mat4, Vec3, Vec4, Quaternion};
+
+use super::Node;
+
+/// The local transform of a node, split into its translation, rotation, and scale
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NodeTransform {
+    pub translation: Vec3,
+    pub rotation: Quaternion,
+    pub scale: Vec3,
+}
+
+impl Default for NodeTransform {
+    fn default() -> Self {
+        Self {
+            translation: Vec3::zero(),
+            rotation: Quaternion::identity(),
+            scale: Vec3::one(),
+        }
+    }
+}
+
+impl NodeTransform {
+    /// Combines the parts of this transform into a single matrix
+    pub fn to_matrix(self) -> Mat4 {
+        let Self {translation, rotation, scale} = self;
+        // glTF allows us to construct a matrix by performing T * R * S
+        // See: https://github.com/KhronosGroup/glTF/tree/master/specification/2.0#transformations
+        Mat4::translation_3d(translation) * Mat4::from(rotation) * Mat4::scaling_3d(scale)
+    }
+}
+
+/// The method used to compute the value of a channel between two of its keyframes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    /// The value of the previous keyframe is used until the next keyframe is reached
+    Step,
+    /// The values are linearly interpolated (rotations are spherically interpolated)
+    Linear,
+    /// The values are interpolated with a cubic spline. Every keyframe stores an in-tangent, a
+    /// value, and an out-tangent, in that order.
+    CubicSpline,
+}
+
+impl From<gltf::animation::Interpolation> for Interpolation {
+    fn from(interpolation: gltf::animation::Interpolation) -> Self {
+        use gltf::animation::Interpolation::*;
+        match interpolation {
+            Step => Interpolation::Step,
+            Linear => Interpolation::Linear,
+            CubicSpline => Interpolation::CubicSpline,
+        }
+    }
+}
+
+/// The part of a node's transform that is animated by a channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelTarget {
+    Translation,
+    Rotation,
+    Scale,
+}
+
+/// Keyframes that animate a single part of the transform of a single node
+#[derive(Debug, Clone)]
+pub struct Channel {
+    /// The index of the node being animated
+    pub node: usize,
+    /// The part of the node's transform being animated
+    pub target: ChannelTarget,
+    pub interpolation: Interpolation,
+    /// The time of each keyframe, in seconds
+    pub times: Vec<f32>,
+    /// The value at each keyframe. Translations and scales are stored in the xyz components.
+    /// Rotations are stored as quaternions (xyzw).
+    ///
+    /// For cubic spline interpolation, there are three values (in-tangent, value, out-tangent)
+    /// for every keyframe.
+    pub values: Vec<Vec4>,
+}
+
+impl Channel {
+    /// Computes the value of this channel at the given time (in seconds). Times outside of the
+    /// range of the keyframes use the value of the first or last keyframe.
+    pub fn sample(&self, time: f32) -> Vec4 {
+        let Self {target, interpolation, times, ..} = self;
+        let value = |index: usize| self.keyframe_value(index);
+
+        // Index of the first keyframe after the given time
+        let next = times.iter().position(|&keyframe| keyframe > time).unwrap_or(times.len());
+        if next == 0 {
+            return value(0);
+        } else if next == times.len() {
+            return value(times.len() - 1);
+        }
+
+        let prev = next - 1;
+        let delta = times[next] - times[prev];
+        let factor = (time - times[prev]) / delta;
+
+        use Interpolation::*;
+        let sampled = match interpolation {
+            Step => return value(prev),
+
+            Linear => match target {
+                ChannelTarget::Rotation => Quaternion::slerp(
+                    Quaternion::from_vec4(value(prev)),
+                    Quaternion::from_vec4(value(next)),
+                    factor,
+                ).into_vec4(),
+                ChannelTarget::Translation | ChannelTarget::Scale => {
+                    Vec4::lerp(value(prev), value(next), factor)
+                },
+            },
+
+            CubicSpline => {
+                // See: https://github.com/KhronosGroup/glTF/tree/master/specification/2.0#appendix-c-spline-interpolation
+                let out_tangent = self.values[prev * 3 + 2] * delta;
+                let in_tangent = self.values[next * 3] * delta;
+                let (t, t2, t3) = (factor, factor * factor, factor * factor * factor);
+                value(prev) * (2.0*t3 - 3.0*t2 + 1.0)
+                    + out_tangent * (t3 - 2.0*t2 + t)
+                    + value(next) * (-2.0*t3 + 3.0*t2)
+                    + in_tangent * (t3 - t2)
+            },
+        };
+
+        match target {
+            // Interpolated rotations need to be normalized to remain valid
+            ChannelTarget::Rotation => sampled.normalized(),
+            ChannelTarget::Translation | ChannelTarget::Scale => sampled,
+        }
+    }
+
+    /// The value at the given keyframe, skipping any tangents
+    fn keyframe_value(&self, index: usize) -> Vec4 {
+        match self.interpolation {
+            Interpolation::CubicSpline => self.values[index * 3 + 1],
+            Interpolation::Step | Interpolation::Linear => self.values[index],
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Animation {
+    /// The name of the animation (if any)
+    pub name: Option<String>,
+    pub channels: Vec<Channel>,
+}
+
+impl Animation {
+    pub fn from_gltf(anim: gltf::Animation, buffers: &[gltf::buffer::Data]) -> Self {
+        let channels = anim.channels().filter_map(|channel| {
+            use gltf::animation::{Property, util::ReadOutputs};
+
+            let target = match channel.target().property() {
+                Property::Translation => ChannelTarget::Translation,
+                Property::Rotation => ChannelTarget::Rotation,
+                Property::Scale => ChannelTarget::Scale,
+                // Morph targets are not supported yet
+                Property::MorphTargetWeights => return None,
+            };
+
+            let reader = channel.reader(|buffer| Some(&buffers[buffer.index()]));
+            let times = reader.read_inputs()
+                .expect("Unable to read keyframe times from glTF animation")
+                .collect();
+            let values = match reader.read_outputs()
+                .expect("Unable to read keyframe values from glTF animation")
+            {
+                ReadOutputs::Translations(values) | ReadOutputs::Scales(values) => {
+                    values.map(|[x, y, z]| Vec4::new(x, y, z, 0.0)).collect()
+                },
+                ReadOutputs::Rotations(values) => values.into_f32().map(Vec4::from).collect(),
+                ReadOutputs::MorphTargetWeights(_) => unreachable!("bug: morph targets are skipped above"),
+            };
+
+            Some(Channel {
+                node: channel.target().node().index(),
+                target,
+                interpolation: channel.sampler().interpolation().into(),
+                times,
+                values,
+            })
+        }).collect();
+
+        Self {
+            name: anim.name().map(|name| name.to_string()),
+            channels,
+        }
+    }
+
+    /// The time of the last keyframe in this animation, in seconds
+    pub fn duration(&self) -> f32 {
+        self.channels.iter()
+            .filter_map(|channel| channel.times.last().copied())
+            .fold(0.0, f32::max)
+    }
+
+    /// Computes the transform of every node animated by this animation at the given time (in
+    /// seconds)
+    pub fn sample(&self, time: f32) -> Pose {
+        let mut pose = Pose::default();
+        for channel in &self.channels {
+            let value = channel.sample(time);
+            let transform = pose.transforms.entry(channel.node).or_default();
+            match channel.target {
+                ChannelTarget::Translation => transform.translation = Some(Vec3::from(value)),
+                ChannelTarget::Rotation => transform.rotation = Some(Quaternion::from_vec4(value)),
+                ChannelTarget::Scale => transform.scale = Some(Vec3::from(value)),
+            }
+        }
+        pose
+    }
+}
+
+/// The parts of a node's transform that were set by an animation
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct PartialTransform {
+    translation: Option<Vec3>,
+    rotation: Option<Quaternion>,
+    scale: Option<Vec3>,
+}
+
+/// The state of all of the nodes animated by an animation at a single point in time
+///
+/// An empty pose leaves every node at its original transform.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Pose {
+    /// The animated transform of each node, by node index
+    transforms: HashMap<usize, PartialTransform>,
+}
+
+impl Pose {
+    /// Returns the local transform of the given node in this pose
+    pub fn local_transform(&self, node: &Node) -> Mat4 {
+        match self.transforms.get(&node.index) {
+            Some(&PartialTransform {translation, rotation, scale}) => {
+                // Anything not animated keeps its value from the original transform
+                let original = node.decomposed;
+                NodeTransform {
+                    translation: translation.unwrap_or(original.translation),
+                    rotation: rotation.unwrap_or(original.rotation),
+                    scale: scale.unwrap_or(original.scale),
+                }.to_matrix()
+            },
+
+            None => node.transform,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use approx::{assert_relative_eq, relative_eq};
+
+    fn channel(target: ChannelTarget, interpolation: Interpolation, values: Vec<Vec4>) -> Channel {
+        Channel {node: 0, target, interpolation, times: vec![1.0, 2.0], values}
+    }
+
+    #[test]
+    fn sample_linear_and_step_channels() {
+        let values = vec![Vec4::new(0.0, 0.0, 0.0, 0.0), Vec4::new(2.0, 4.0, 6.0, 0.0)];
+        let linear = channel(ChannelTarget::Translation, Interpolation::Linear, values.clone());
+        let step = channel(ChannelTarget::Translation, Interpolation::Step, values);
+
+        assert_relative_eq!(linear.sample(1.5), Vec4::new(1.0, 2.0, 3.0, 0.0));
+        assert_relative_eq!(step.sample(1.5), Vec4::new(0.0, 0.0, 0.0, 0.0));
+        assert_relative_eq!(step.sample(2.0), Vec4::new(2.0, 4.0, 6.0, 0.0));
+
+        // Times outside of the keyframes are clamped to the first and last keyframes
+        assert_relative_eq!(linear.sample(0.0), Vec4::new(0.0, 0.0, 0.0, 0.0));
+        assert_relative_eq!(linear.sample(3.0), Vec4::new(2.0, 4.0, 6.0, 0.0));
+    }
+
+    #[test]
+    fn sample_rotation_with_slerp() {
+        let start = Quaternion::rotation_y(0.0);
+        let end = Quaternion::rotation_y(std::f32::consts::PI / 2.0);
+        let rotation = channel(ChannelTarget::Rotation, Interpolation::Linear,
+            vec![start.into_vec4(), end.into_vec4()]);
+
+        let expected = Quaternion::rotation_y(std::f32::consts::PI / 4.0);
+        assert_relative_eq!(Quaternion::from_vec4(rotation.sample(1.5)), expected, epsilon = 1e-6);
+    }
+}
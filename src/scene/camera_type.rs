@@ -1,4 +1,4 @@
-use crate::math::{Mat4, FrustumPlanes, Radians};
+use crate::math::{Mat4, FrustumPlanes, Radians, Handedness};
 
 #[derive(Debug, Clone)]
 pub enum CameraType {
@@ -70,33 +70,36 @@ impl CameraType {
         }
     }
 
-    /// The perspective/orthographic projection matrix of the camera.
+    /// The perspective/orthographic projection matrix of the camera, for a camera in a coordinate
+    /// system with the given handedness.
     ///
     /// Camera coordinates -> Homogenous coordinates
-    pub fn to_projection(&self) -> Mat4 {
+    pub fn to_projection(&self, handedness: Handedness) -> Mat4 {
         // OpenGL clip planes are -1 to 1, thus we use the _no method
         use CameraType::*;
         match *self {
             Perspective {name: _, aspect_ratio, field_of_view_y, near_z, far_z} => match far_z {
                 Some(far_z) => {
-                    Mat4::perspective_rh_no(field_of_view_y.get_radians(), aspect_ratio, near_z, far_z)
+                    handedness.perspective_no(field_of_view_y.get_radians(), aspect_ratio, near_z, far_z)
                 },
 
                 None => {
                     // Infinite projection matrix
                     // Source: http://www.terathon.com/gdc07_lengyel.pdf
                     let focal_length = 1.0 / (field_of_view_y.get_radians() / 2.0).tan();
+                    // Points in front of the camera have this sign in view space
+                    let forward = handedness.forward_z();
                     Mat4::new(
-                        focal_length,   0.0,                            0.0,    0.0,
-                        0.0,            focal_length / aspect_ratio,    0.0,    0.0,
-                        0.0,            0.0,                           -1.0,   -2.0*near_z,
-                        0.0,            0.0,                           -1.0,    0.0,
+                        focal_length,   0.0,                            0.0,        0.0,
+                        0.0,            focal_length / aspect_ratio,    0.0,        0.0,
+                        0.0,            0.0,                            forward,   -2.0*near_z,
+                        0.0,            0.0,                            forward,    0.0,
                     )
                 },
             },
 
             Orthographic {name: _, mag_x, mag_y, near_z, far_z} => {
-                Mat4::orthographic_rh_no(FrustumPlanes {
+                handedness.orthographic_no(FrustumPlanes {
                     left: -mag_x/2.0,
                     right: mag_x/2.0,
                     bottom: -mag_y/2.0,
@@ -1,6 +1,6 @@
 use std::sync::Arc;
 
-use crate::math::Vec3;
+use crate::math::{Vec2, Vec3, Vec4, Mat4, Rgba};
 
 use super::Material;
 
@@ -15,10 +15,48 @@ pub struct Geometry {
     pub positions: Vec<Vec3>,
     /// The normal of each vertex of the geometry
     pub normals: Vec<Vec3>,
+    /// The texture coordinate of each vertex of the geometry, or empty if the geometry has no
+    /// texture coordinates. (0, 0) is the top-left corner of the texture.
+    pub tex_coords: Vec<Vec2>,
+    /// The tangent of each vertex, used to orient the normal texture of the material, or empty if
+    /// the geometry has no tangents. The w component is 1.0 or -1.0 and gives the handedness of
+    /// the tangent space: the bitangent is `cross(normal, tangent.xyz) * w`.
+    pub tangents: Vec<Vec4>,
+    /// The color of each vertex, multiplied with the diffuse color of the material, or empty if
+    /// the geometry has no vertex colors
+    pub colors: Vec<Rgba>,
+    /// The index of up to four skin joints that influence each vertex, or empty if this geometry
+    /// is not skinned
+    pub joints: Vec<[u16; 4]>,
+    /// The weight of each joint in `joints`, or empty if this geometry is not skinned
+    pub weights: Vec<[f32; 4]>,
+    /// The morph targets (blend shapes) that can be blended into this geometry, or empty if the
+    /// geometry has none
+    pub morph_targets: Vec<MorphTarget>,
     /// The material associated with this geometry
     pub material: Arc<Material>,
 }
 
+/// The way that the normals used to shade geometry are generated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum NormalMode {
+    /// The normals of the vertices are used as is, which shades curved surfaces smoothly
+    #[default]
+    Smooth,
+    /// Every vertex of a triangle uses the normal of the triangle itself, so that each facet of
+    /// the geometry is shaded with a single flat shade
+    Flat,
+}
+
+/// The offsets that a morph target (blend shape) applies to the vertices of a geometry
+#[derive(Debug, Clone, Default)]
+pub struct MorphTarget {
+    /// The offset of the position of each vertex, or empty if the target does not move vertices
+    pub positions: Vec<Vec3>,
+    /// The offset of the normal of each vertex, or empty if the target does not change normals
+    pub normals: Vec<Vec3>,
+}
+
 impl Geometry {
     pub fn from_obj(model: tobj::Model, materials: &[Arc<Material>]) -> Self {
         let tobj::Model {name, mesh} = model;
@@ -28,7 +66,19 @@ impl Geometry {
             indices: mesh.indices,
             positions: mesh.positions.chunks(3).map(|sl| Vec3::from_slice(sl)).collect(),
             normals: mesh.normals.chunks(3).map(|sl| Vec3::from_slice(sl)).collect(),
-            material: mesh.material_id.map(|id| materials[id].clone()).unwrap_or_default(),
+            // OBJ texture coordinates have (0, 0) at the bottom-left corner of the texture
+            tex_coords: mesh.texcoords.chunks(2).map(|sl| Vec2::new(sl[0], 1.0 - sl[1])).collect(),
+            // OBJ materials never have a normal texture, so tangents are not needed
+            tangents: Vec::new(),
+            // OBJ files do not support vertex colors
+            colors: Vec::new(),
+            // OBJ files do not support skinning
+            joints: Vec::new(),
+            weights: Vec::new(),
+            // OBJ files do not support morph targets
+            morph_targets: Vec::new(),
+            material: mesh.material_id.map(|id| materials[id].clone())
+                .unwrap_or_else(|| Arc::new(Material::obj_default())),
         }
     }
 
@@ -43,7 +93,7 @@ impl Geometry {
         let name = None;
 
         let reader = prim.reader(|buffer| Some(&buffers[buffer.index()]));
-        let indices = reader.read_indices()
+        let indices: Vec<u32> = reader.read_indices()
             .expect("Unable to read index buffer from glTF geometry")
             .into_u32()
             .collect();
@@ -55,6 +105,27 @@ impl Geometry {
             .expect("Unable to read vertex normals from glTF geometry")
             .map(Vec3::from)
             .collect();
+        let tex_coords: Vec<_> = reader.read_tex_coords(0)
+            .map(|tex_coords| tex_coords.into_f32().map(Vec2::from).collect())
+            .unwrap_or_default();
+        let tangents = reader.read_tangents()
+            .map(|tangents| tangents.map(Vec4::from).collect());
+        // glTF vertex colors are already in linear space, like the other colors in the shader
+        let colors = reader.read_colors(0)
+            .map(|colors| colors.into_rgba_f32().map(Rgba::from).collect())
+            .unwrap_or_default();
+        let joints = reader.read_joints(0)
+            .map(|joints| joints.into_u16().collect())
+            .unwrap_or_default();
+        let weights = reader.read_weights(0)
+            .map(|weights| weights.into_f32().collect())
+            .unwrap_or_default();
+        let morph_targets = reader.read_morph_targets()
+            .map(|(positions, normals, _)| MorphTarget {
+                positions: positions.map(|positions| positions.map(Vec3::from).collect()).unwrap_or_default(),
+                normals: normals.map(|normals| normals.map(Vec3::from).collect()).unwrap_or_default(),
+            })
+            .collect();
 
         // index() returns None if the material is the glTF default material
         // See: https://github.com/KhronosGroup/glTF/tree/92f59a0dbefe2d54cff38dba103cd70462cc778b/specification/2.0#default-material
@@ -69,6 +140,324 @@ impl Geometry {
             "glTF geometry must have exactly as many positions as normals"
         );
 
-        Self {name, indices, positions, normals, material}
+        // Tangents are only needed to apply a normal texture, so they are only generated for
+        // geometry that has one
+        let tangents = match tangents {
+            Some(tangents) => tangents,
+            None if material.normal_texture.is_some() && !tex_coords.is_empty() => {
+                generate_tangents(&indices, &positions, &normals, &tex_coords)
+            },
+            None => Vec::new(),
+        };
+
+        Self {
+            name,
+            indices,
+            positions,
+            normals,
+            tex_coords,
+            tangents,
+            colors,
+            joints,
+            weights,
+            morph_targets,
+            material,
+        }
+    }
+
+    /// Returns true if each vertex of this geometry is influenced by skin joints
+    pub fn is_skinned(&self) -> bool {
+        !self.joints.is_empty() && !self.weights.is_empty()
+    }
+
+    /// Returns true if any of the given morph target weights would change this geometry
+    pub fn is_morphed_by(&self, weights: &[f32]) -> bool {
+        self.morph_targets.iter().zip(weights).any(|(_, &weight)| weight != 0.0)
+    }
+
+    /// Blends the morph targets of this geometry into its vertices, using the given weight for
+    /// each target. Targets without a weight are not applied.
+    ///
+    /// Each position becomes `base + Σ weight * offset`. Normals are offset the same way and then
+    /// normalized. Tangents are left as they are. Weights that are all zero leave the geometry
+    /// unchanged.
+    pub fn morphed(&self, weights: &[f32]) -> Self {
+        let mut geo = self.clone();
+        let mut normals_changed = false;
+        for (target, &weight) in self.morph_targets.iter().zip(weights) {
+            if weight == 0.0 {
+                continue;
+            }
+
+            for (pos, &offset) in geo.positions.iter_mut().zip(&target.positions) {
+                *pos += offset * weight;
+            }
+            for (norm, &offset) in geo.normals.iter_mut().zip(&target.normals) {
+                *norm += offset * weight;
+                normals_changed = true;
+            }
+        }
+
+        if normals_changed {
+            for norm in &mut geo.normals {
+                *norm = norm.normalized();
+            }
+        }
+
+        geo
+    }
+
+    /// Applies linear blend skinning to this geometry using the given matrix for each joint
+    ///
+    /// Each position, normal and tangent is transformed by the weighted sum of the matrices of
+    /// the joints that influence it.
+    pub fn skinned(&self, joint_matrices: &[Mat4]) -> Self {
+        let skin_matrices: Vec<_> = self.joints.iter().zip(&self.weights)
+            .map(|(joints, weights)| {
+                joints.iter().zip(weights).fold(Mat4::zero(), |acc, (&joint, &weight)| {
+                    acc + joint_matrices[joint as usize] * weight
+                })
+            })
+            .collect();
+
+        let (positions, normals) = self.positions.iter()
+            .zip(&self.normals)
+            .zip(&skin_matrices)
+            .map(|((&pos, &norm), skin_matrix)| {
+                (skin_matrix.mul_point(pos), skin_matrix.mul_direction(norm).normalized())
+            })
+            .unzip();
+        // The handedness of the tangent space is not changed by skinning
+        let tangents = self.tangents.iter()
+            .zip(&skin_matrices)
+            .map(|(&tangent, skin_matrix)| {
+                let direction = skin_matrix.mul_direction(Vec3::from(tangent)).normalized();
+                Vec4 {w: tangent.w, ..Vec4::from(direction)}
+            })
+            .collect();
+
+        Self {
+            name: self.name.clone(),
+            indices: self.indices.clone(),
+            positions,
+            normals,
+            tex_coords: self.tex_coords.clone(),
+            tangents,
+            colors: self.colors.clone(),
+            joints: self.joints.clone(),
+            weights: self.weights.clone(),
+            morph_targets: self.morph_targets.clone(),
+            material: self.material.clone(),
+        }
+    }
+
+    /// Returns a copy of this geometry where every triangle has its own vertices, all of which
+    /// have the normal of the triangle
+    ///
+    /// Each face normal points the same way as the original normals of its vertices, so meshes
+    /// with either winding order are shaded from the outside.
+    pub fn flat_shaded(&self) -> Self {
+        let normals = self.indices.chunks_exact(3).flat_map(|triangle| {
+            let [a, b, c] = [0, 1, 2].map(|i| triangle[i] as usize);
+            let (p0, p1, p2) = (self.positions[a], self.positions[b], self.positions[c]);
+            let vertex_normals = [a, b, c].iter()
+                .filter_map(|&i| self.normals.get(i))
+                .fold(Vec3::zero(), |sum, &norm| sum + norm);
+
+            let face_normal = (p1 - p0).cross(p2 - p0);
+            let normal = if face_normal.magnitude_squared() > 0.0 {
+                let face_normal = face_normal.normalized();
+                if face_normal.dot(vertex_normals) < 0.0 { -face_normal } else { face_normal }
+            } else {
+                // A degenerate triangle has no face normal, so it keeps the normal of its vertices
+                vertex_normals.try_normalized().unwrap_or_else(Vec3::zero)
+            };
+            [normal; 3]
+        }).collect();
+
+        let indices = &self.indices[..self.indices.len() / 3 * 3];
+        Self {
+            name: self.name.clone(),
+            indices: (0..indices.len() as u32).collect(),
+            positions: unwelded(&self.positions, indices),
+            normals,
+            tex_coords: unwelded(&self.tex_coords, indices),
+            tangents: unwelded(&self.tangents, indices),
+            colors: unwelded(&self.colors, indices),
+            joints: unwelded(&self.joints, indices),
+            weights: unwelded(&self.weights, indices),
+            morph_targets: self.morph_targets.iter().map(|target| MorphTarget {
+                positions: unwelded(&target.positions, indices),
+                normals: unwelded(&target.normals, indices),
+            }).collect(),
+            material: self.material.clone(),
+        }
+    }
+}
+
+/// Generates a tangent for each vertex from the texture coordinates of the triangles around it
+///
+/// The tangent points in the direction that the u texture coordinate increases, and the
+/// bitangent points in the direction that the v texture coordinate decreases, since normal
+/// textures point +y towards the top of the texture. Both are averaged over the triangles that
+/// share the vertex and the tangent is made perpendicular to the normal of the vertex.
+fn generate_tangents(indices: &[u32], positions: &[Vec3], normals: &[Vec3], tex_coords: &[Vec2]) -> Vec<Vec4> {
+    let mut tangents = vec![Vec3::zero(); positions.len()];
+    let mut bitangents = vec![Vec3::zero(); positions.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| triangle[i] as usize);
+        let (edge1, edge2) = (positions[b] - positions[a], positions[c] - positions[a]);
+        let (uv1, uv2) = (tex_coords[b] - tex_coords[a], tex_coords[c] - tex_coords[a]);
+        // Flipped so that the bitangent points towards the top of the texture
+        let (uv1, uv2) = (Vec2::new(uv1.x, -uv1.y), Vec2::new(uv2.x, -uv2.y));
+
+        let det = uv1.x * uv2.y - uv2.x * uv1.y;
+        // Triangles with degenerate texture coordinates do not have a tangent space
+        if det == 0.0 {
+            continue;
+        }
+        let tangent = (edge1 * uv2.y - edge2 * uv1.y) / det;
+        let bitangent = (edge2 * uv1.x - edge1 * uv2.x) / det;
+        for &vertex in &[a, b, c] {
+            tangents[vertex] += tangent;
+            bitangents[vertex] += bitangent;
+        }
+    }
+
+    tangents.into_iter().zip(bitangents).zip(normals).map(|((tangent, bitangent), &normal)| {
+        let tangent = (tangent - normal * normal.dot(tangent)).try_normalized()
+            // Any direction perpendicular to the normal will do if there is no tangent
+            .or_else(|| normal.cross(Vec3::unit_x()).try_normalized())
+            .or_else(|| normal.cross(Vec3::unit_y()).try_normalized())
+            .unwrap_or_else(Vec3::unit_x);
+        let handedness = if normal.cross(tangent).dot(bitangent) < 0.0 { -1.0 } else { 1.0 };
+        Vec4 {w: handedness, ..Vec4::from(tangent)}
+    }).collect()
+}
+
+/// Returns the value of each given vertex attribute for every index, so that no vertex is shared
+/// between triangles. Attributes that the geometry does not have stay empty.
+fn unwelded<T: Copy>(values: &[T], indices: &[u32]) -> Vec<T> {
+    if values.is_empty() {
+        return Vec::new();
+    }
+
+    indices.iter().map(|&index| values[index as usize]).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use approx::{assert_relative_eq, relative_eq};
+
+    #[test]
+    fn skinning_blends_joint_matrices() {
+        let geo = Geometry {
+            name: None,
+            indices: vec![0, 1, 2],
+            positions: vec![Vec3::zero(), Vec3::unit_x(), Vec3::unit_y()],
+            normals: vec![Vec3::unit_z(); 3],
+            tex_coords: Vec::new(),
+            tangents: Vec::new(),
+            colors: Vec::new(),
+            joints: vec![[0, 0, 0, 0], [1, 0, 0, 0], [0, 1, 0, 0]],
+            weights: vec![[1.0, 0.0, 0.0, 0.0], [1.0, 0.0, 0.0, 0.0], [0.5, 0.5, 0.0, 0.0]],
+            morph_targets: Vec::new(),
+            material: Default::default(),
+        };
+        let joint_matrices = [Mat4::identity(), Mat4::translation_3d(Vec3::new(0.0, 0.0, 2.0))];
+
+        let skinned = geo.skinned(&joint_matrices);
+        assert_relative_eq!(skinned.positions[0], Vec3::zero());
+        assert_relative_eq!(skinned.positions[1], Vec3::new(1.0, 0.0, 2.0));
+        assert_relative_eq!(skinned.positions[2], Vec3::new(0.0, 1.0, 1.0));
+        assert_relative_eq!(skinned.normals[2], Vec3::unit_z());
+    }
+
+    #[test]
+    fn flat_shading_splits_shared_vertices() {
+        // Two triangles folded along the shared edge from (0, 0, 0) to (1, 0, 0)
+        let geo = Geometry {
+            name: None,
+            indices: vec![0, 1, 2, 1, 0, 3],
+            positions: vec![Vec3::zero(), Vec3::unit_x(), Vec3::unit_y(), Vec3::unit_z() * -1.0],
+            normals: vec![Vec3::new(0.0, 1.0, 1.0).normalized(); 4],
+            tex_coords: vec![Vec2::zero(), Vec2::unit_x(), Vec2::unit_y(), Vec2::one()],
+            tangents: Vec::new(),
+            colors: Vec::new(),
+            joints: Vec::new(),
+            weights: Vec::new(),
+            morph_targets: Vec::new(),
+            material: Default::default(),
+        };
+
+        let flat = geo.flat_shaded();
+        assert_eq!(flat.indices, &[0, 1, 2, 3, 4, 5]);
+        assert_eq!(flat.positions.len(), 6);
+        assert_eq!(flat.tex_coords[3], Vec2::unit_x());
+        assert!(flat.colors.is_empty());
+        for i in 0..3 {
+            assert_relative_eq!(flat.normals[i], Vec3::unit_z());
+            assert_relative_eq!(flat.normals[i + 3], Vec3::unit_y());
+        }
+    }
+
+    #[test]
+    fn morph_targets_blend_offsets() {
+        let geo = Geometry {
+            name: None,
+            indices: vec![0, 1, 2],
+            positions: vec![Vec3::zero(), Vec3::unit_x(), Vec3::unit_y()],
+            normals: vec![Vec3::unit_z(); 3],
+            tex_coords: Vec::new(),
+            tangents: Vec::new(),
+            colors: Vec::new(),
+            joints: Vec::new(),
+            weights: Vec::new(),
+            morph_targets: vec![
+                MorphTarget {
+                    positions: vec![Vec3::zero(), Vec3::unit_x(), Vec3::zero()],
+                    normals: Vec::new(),
+                },
+                MorphTarget {
+                    positions: vec![Vec3::unit_z(); 3],
+                    normals: vec![Vec3::unit_x(), Vec3::zero(), Vec3::zero()],
+                },
+            ],
+            material: Default::default(),
+        };
+
+        assert!(!geo.is_morphed_by(&[0.0, 0.0]));
+        let unchanged = geo.morphed(&[0.0, 0.0]);
+        assert_eq!(unchanged.positions, geo.positions);
+        assert_eq!(unchanged.normals, geo.normals);
+
+        let morphed = geo.morphed(&[0.5, 1.0]);
+        assert_relative_eq!(morphed.positions[0], Vec3::unit_z());
+        assert_relative_eq!(morphed.positions[1], Vec3::new(1.5, 0.0, 1.0));
+        assert_relative_eq!(morphed.normals[0], Vec3::new(1.0, 0.0, 1.0).normalized());
+        assert_relative_eq!(morphed.normals[1], Vec3::unit_z());
+    }
+
+    #[test]
+    fn generated_tangents_follow_texture_coordinates() {
+        // A square facing +z with (0, 0) at the top-left corner of the texture
+        let positions = [Vec3::new(0.0, 1.0, 0.0), Vec3::zero(), Vec3::unit_x(), Vec3::new(1.0, 1.0, 0.0)];
+        let normals = [Vec3::unit_z(); 4];
+        let tex_coords = [Vec2::zero(), Vec2::unit_y(), Vec2::one(), Vec2::unit_x()];
+        let indices = [0, 1, 2, 0, 2, 3];
+
+        let tangents = generate_tangents(&indices, &positions, &normals, &tex_coords);
+        for &tangent in &tangents {
+            assert_relative_eq!(tangent, Vec4::new(1.0, 0.0, 0.0, 1.0));
+        }
+
+        // Mirroring the texture horizontally flips the tangent and the handedness
+        let mirrored: Vec<_> = tex_coords.iter().map(|uv| Vec2::new(1.0 - uv.x, uv.y)).collect();
+        let tangents = generate_tangents(&indices, &positions, &normals, &mirrored);
+        for &tangent in &tangents {
+            assert_relative_eq!(tangent, Vec4::new(-1.0, 0.0, 0.0, -1.0));
+        }
     }
 }
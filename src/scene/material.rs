@@ -1,8 +1,33 @@
-use crate::math::Rgba;
+use std::sync::Arc;
+use std::path::Path;
+
+use crate::math::{Rgb, Rgba};
+
+use super::Texture;
 
 #[derive(Debug)]
 pub struct Material {
     pub diffuse_color: Rgba,
+    /// A texture that is multiplied with the diffuse color, if any
+    pub base_color_texture: Option<Arc<Texture>>,
+    /// The color of the light emitted by the material. This light is not affected by the lighting
+    /// in the scene, so it is added after cel shading.
+    pub emissive_color: Rgb,
+    /// A texture that is multiplied with the emissive color, if any
+    pub emissive_texture: Option<Arc<Texture>>,
+    /// A tangent-space normal texture that perturbs the normals of the surface before it is cel
+    /// shaded, if any. The texture stores linear values, not colors.
+    pub normal_texture: Option<Arc<Texture>>,
+    /// The amount that the x and y components of the normals sampled from the normal texture are
+    /// scaled by. 1.0 uses the normals as is.
+    pub normal_scale: f32,
+    /// If not None, any part of the surface whose alpha (from the diffuse color, vertex colors and
+    /// base color texture) is less than this value is not drawn at all. This is the `MASK` alpha
+    /// mode in glTF and is used for cutout textures (e.g. foliage and hair cards).
+    pub alpha_cutoff: Option<f32>,
+    /// The index of refraction of the material, used to determine how much light is reflected at
+    /// different viewing angles
+    pub ior: f32,
 }
 
 impl Default for Material {
@@ -11,23 +36,88 @@ impl Default for Material {
         // See: https://github.com/KhronosGroup/glTF/tree/92f59a0dbefe2d54cff38dba103cd70462cc778b/specification/2.0#reference-pbrmetallicroughness
         Self {
             diffuse_color: Rgba::white(),
+            base_color_texture: None,
+            emissive_color: Rgb::black(),
+            emissive_texture: None,
+            normal_texture: None,
+            normal_scale: 1.0,
+            alpha_cutoff: None,
+            // Default value from the KHR_materials_ior extension
+            ior: 1.5,
         }
     }
 }
 
-impl From<tobj::Material> for Material {
-    fn from(mat: tobj::Material) -> Self {
+impl Material {
+    /// The material used for OBJ geometry that does not have a material (e.g. because its MTL
+    /// file is missing)
+    pub fn obj_default() -> Self {
         Self {
-            diffuse_color: Rgba::from_opaque(mat.diffuse),
+            diffuse_color: Rgba::new(0.5, 0.5, 0.5, 1.0),
+            ..Self::default()
+        }
+    }
+
+    /// Creates a material from an MTL file, with any texture file names resolved relative to the
+    /// given directory
+    pub fn from_obj(mat: tobj::Material, base_dir: &Path) -> Self {
+        let [r, g, b] = mat.diffuse;
+
+        // Textures that cannot be loaded are skipped so that the diffuse color is still used
+        let base_color_texture = Some(&mat.diffuse_texture)
+            .filter(|name| !name.is_empty())
+            .and_then(|name| image::open(base_dir.join(name)).ok())
+            .map(|image| Arc::new(Texture::new(image.to_rgba())));
+
+        // The emissive color (Ke) is a common extension to MTL files that tobj leaves unparsed
+        let emissive_color = mat.unknown_param.get("Ke")
+            .and_then(|value| {
+                let values: Vec<f32> = value.split_whitespace().map(|x| x.parse().ok()).collect::<Option<_>>()?;
+                match values[..] {
+                    [r, g, b] => Some(Rgb {r, g, b}),
+                    _ => None,
+                }
+            })
+            .unwrap_or_else(Rgb::black);
+
+        Self {
+            // The dissolve (d) value in an MTL file is the opacity of the material
+            diffuse_color: Rgba {r, g, b, a: mat.dissolve},
+            base_color_texture,
+            emissive_color,
+            emissive_texture: None,
+            // Bump maps in MTL files are often height maps rather than normal maps, so they are
+            // not used
+            normal_texture: None,
+            normal_scale: 1.0,
+            // MTL files have no alpha mode, so the dissolve value is never used as a cutout
+            alpha_cutoff: None,
+            // The optical density (Ni) in an MTL file is the index of refraction
+            ior: mat.optical_density,
         }
     }
-}
 
-impl<'a> From<gltf::Material<'a>> for Material {
-    fn from(mat: gltf::Material<'a>) -> Self {
-        let [r, g, b, a] = mat.pbr_metallic_roughness().base_color_factor();
+    /// Creates a material from glTF, with its textures taken from the given textures (referenced
+    /// by texture index)
+    pub fn from_gltf(mat: gltf::Material, textures: &[Arc<Texture>]) -> Self {
+        let pbr = mat.pbr_metallic_roughness();
+        let [r, g, b, a] = pbr.base_color_factor();
+        let texture = |info: gltf::texture::Info| textures[info.texture().index()].clone();
         Self {
             diffuse_color: Rgba {r, g, b, a},
+            base_color_texture: pbr.base_color_texture().map(texture),
+            emissive_color: Rgb::from(mat.emissive_factor()),
+            emissive_texture: mat.emissive_texture().map(texture),
+            normal_texture: mat.normal_texture().map(|info| textures[info.texture().index()].clone()),
+            normal_scale: mat.normal_texture().map(|info| info.scale()).unwrap_or(1.0),
+            // Blending is not supported, so BLEND materials are drawn as if they were opaque
+            alpha_cutoff: match mat.alpha_mode() {
+                gltf::material::AlphaMode::Mask => Some(mat.alpha_cutoff()),
+                gltf::material::AlphaMode::Opaque | gltf::material::AlphaMode::Blend => None,
+            },
+            // The version of the gltf crate we use does not expose the KHR_materials_ior
+            // extension, so the default value from that extension is always used
+            ior: Material::default().ior,
         }
     }
 }
@@ -2,6 +2,8 @@ use std::sync::Arc;
 
 use rayon::prelude::*;
 
+use crate::math::{self, Vec3, Mat4};
+
 use super::{Material, Geometry};
 
 #[derive(Debug, Clone)]
@@ -11,6 +13,9 @@ pub struct Mesh {
     pub name: Option<String>,
     /// The geometry stored in this mesh and their associated materials
     pub geometry: Vec<Geometry>,
+    /// The default weight of each morph target of the geometry in this mesh, or empty if the mesh
+    /// has no morph targets
+    pub morph_weights: Vec<f32>,
 }
 
 impl Mesh {
@@ -24,6 +29,7 @@ impl Mesh {
             geometry: models.into_par_iter()
                 .map(|model| Geometry::from_obj(model, materials))
                 .collect(),
+            morph_weights: Vec::new(),
         }
     }
 
@@ -34,9 +40,32 @@ impl Mesh {
     ) -> Self {
         Self {
             name: Some(mesh.name().unwrap_or("").to_string()),
+            morph_weights: mesh.weights().map(|weights| weights.to_vec()).unwrap_or_default(),
             geometry: mesh.primitives()
                 .map(|prim| Geometry::from_gltf(prim, materials, buffers))
                 .collect(),
         }
     }
+
+    /// Uniformly scales every vertex position of this mesh by the given factor
+    pub fn scale(&mut self, factor: f32) {
+        for geo in &mut self.geometry {
+            for pos in &mut geo.positions {
+                *pos *= factor;
+            }
+        }
+    }
+
+    /// Returns the (min, max) corners of the axis-aligned box that contains every vertex of this
+    /// mesh, or None if the mesh has no vertices
+    pub fn bounds(&self) -> Option<(Vec3, Vec3)> {
+        math::bounds(self.geometry.iter().flat_map(|geo| geo.positions.iter().copied()))
+    }
+
+    /// Returns the (min, max) corners of the axis-aligned box that contains every vertex of this
+    /// mesh after it is transformed by the given matrix, or None if the mesh has no vertices
+    pub fn transformed_bounds(&self, transform: Mat4) -> Option<(Vec3, Vec3)> {
+        math::bounds(self.geometry.iter()
+            .flat_map(|geo| geo.positions.iter().map(|&pos| transform.mul_point(pos))))
+    }
 }
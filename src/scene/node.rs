@@ -1,9 +1,9 @@
 use std::sync::Arc;
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 
 use crate::math::{Mat4, Quaternion};
 
-use super::{Mesh, CameraType, LightType};
+use super::{Mesh, Skin, CameraType, LightType, NodeTransform, Pose};
 
 #[derive(Debug, Clone)]
 pub enum NodeData {
@@ -14,6 +14,8 @@ pub enum NodeData {
 
 #[derive(Debug, Clone)]
 pub struct Node {
+    /// The index of the node in the 3D file it was loaded from
+    pub index: usize,
     /// The name of the node (possibly empty), or None if the 3D file this was loaded from does
     /// not support node names
     pub name: Option<String>,
@@ -21,6 +23,15 @@ pub struct Node {
     pub data: Option<NodeData>,
     /// The **local** transform of this node, independent of its parents
     pub transform: Mat4,
+    /// The **local** transform of this node split into its translation, rotation, and scale
+    ///
+    /// Animations replace parts of this transform, leaving the other parts unchanged.
+    pub decomposed: NodeTransform,
+    /// The skin used to deform the mesh of this node, if any
+    pub skin: Option<Arc<Skin>>,
+    /// The weight of each morph target of the mesh of this node, or None if the default weights
+    /// of the mesh should be used
+    pub morph_weights: Option<Vec<f32>>,
     /// The children of this node
     ///
     /// Each child's global transform is dependent on this node's transform
@@ -31,9 +42,11 @@ impl Node {
     pub fn from_gltf(
         node: gltf::Node,
         meshes: &[Arc<Mesh>],
+        skins: &[Arc<Skin>],
         cameras: &[Arc<CameraType>],
         lights: &[Arc<LightType>],
     ) -> Self {
+        let index = node.index();
         let name = Some(node.name().unwrap_or("").to_string());
 
         let data = match (node.mesh(), node.camera(), node.light()) {
@@ -70,6 +83,15 @@ impl Node {
                 trans_mat * rot_mat * scale_mat
             },
         };
+        let (translation, [rx, ry, rz, rw], scale) = node.transform().decomposed();
+        let decomposed = NodeTransform {
+            translation: translation.into(),
+            rotation: Quaternion::from_xyzw(rx, ry, rz, rw),
+            scale: scale.into(),
+        };
+
+        let skin = node.skin().map(|skin| skins[skin.index()].clone());
+        let morph_weights = node.weights().map(|weights| weights.to_vec());
 
         // Important property: Every unique node in the scene graph is represented by a single
         // Arc<Node>. That is, we are careful to never call from_gltf on the same node twice.
@@ -83,10 +105,10 @@ impl Node {
         // This code only works because the node hierarchy is a tree. Otherwise, it would recurse
         // forever and we'd have to rewrite it to use two passes and cycle detection.
         let children = node.children()
-            .map(|child| Arc::new(Node::from_gltf(child, meshes, cameras, lights)))
+            .map(|child| Arc::new(Node::from_gltf(child, meshes, skins, cameras, lights)))
             .collect();
 
-        Self {name, data, transform, children}
+        Self {index, name, data, transform, decomposed, skin, morph_weights, children}
     }
 
     pub fn mesh(&self) -> Option<&Arc<Mesh>> {
@@ -117,32 +139,122 @@ pub trait Traverse {
     /// Traverse a node hierarchy, treating self as a root node, yielding each node and the world
     /// transform of that node's parent. Note that since this is a world transform, it will reflect
     /// the total transformation up the entire hierarchy.
-    fn traverse(&self) -> TraverseNodes;
+    fn traverse(&self) -> TraverseNodes<'static>;
+
+    /// Traverse a node hierarchy in the same way as `traverse`, but with the local transform of
+    /// each node taken from the given pose
+    fn traverse_posed<'a>(&self, pose: &'a Pose) -> TraverseNodes<'a>;
+
+    /// Traverse a node hierarchy in the same way as `traverse`, but with the local transform of
+    /// each node taken from the given pose (if any) and multiplied by the override for the name
+    /// of the node (if any)
+    fn traverse_with<'a>(
+        &self,
+        pose: Option<&'a Pose>,
+        overrides: &'a HashMap<String, Mat4>,
+    ) -> TraverseNodes<'a>;
 }
 
 impl Traverse for Arc<Node> {
-    fn traverse(&self) -> TraverseNodes {
+    fn traverse(&self) -> TraverseNodes<'static> {
         let mut queue = VecDeque::new();
         queue.push_back((Mat4::identity(), self.clone()));
-        TraverseNodes {queue}
+        TraverseNodes {queue, pose: None, overrides: None}
+    }
+
+    fn traverse_posed<'a>(&self, pose: &'a Pose) -> TraverseNodes<'a> {
+        let mut queue = VecDeque::new();
+        queue.push_back((Mat4::identity(), self.clone()));
+        TraverseNodes {queue, pose: Some(pose), overrides: None}
+    }
+
+    fn traverse_with<'a>(
+        &self,
+        pose: Option<&'a Pose>,
+        overrides: &'a HashMap<String, Mat4>,
+    ) -> TraverseNodes<'a> {
+        let mut queue = VecDeque::new();
+        queue.push_back((Mat4::identity(), self.clone()));
+        TraverseNodes {queue, pose, overrides: Some(overrides)}
     }
 }
 
-pub struct TraverseNodes {
+pub struct TraverseNodes<'a> {
     /// A queue of each node to be traversed, and its parent transform
     queue: VecDeque<(Mat4, Arc<Node>)>,
+    /// The pose to take local transforms from, or None if the original transforms should be used
+    pose: Option<&'a Pose>,
+    /// The transforms to apply on top of the local transforms of the nodes with each name
+    overrides: Option<&'a HashMap<String, Mat4>>,
 }
 
-impl Iterator for TraverseNodes {
+impl<'a> TraverseNodes<'a> {
+    /// Returns the local transform of the given node, taken from the pose of this traversal (if
+    /// any) and multiplied by the override for the name of the node (if any)
+    pub fn local_transform(&self, node: &Node) -> Mat4 {
+        let local_transform = match self.pose {
+            Some(pose) => pose.local_transform(node),
+            None => node.transform,
+        };
+
+        let overridden = self.overrides
+            .and_then(|overrides| overrides.get(node.name.as_ref()?));
+        match overridden {
+            Some(&transform) => local_transform * transform,
+            None => local_transform,
+        }
+    }
+}
+
+impl<'a> Iterator for TraverseNodes<'a> {
     type Item = (Mat4, Arc<Node>);
 
     fn next(&mut self) -> Option<Self::Item> {
         // This code assumes that the node hierarchy is not cyclic
         let (parent_trans, node) = self.queue.pop_front()?;
 
-        let world_transform = parent_trans * node.transform;
+        let world_transform = parent_trans * self.local_transform(&node);
         self.queue.extend(node.children.iter().map(|node| (world_transform, node.clone())));
 
         Some((parent_trans, node))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::math::Vec3;
+
+    fn node(index: usize, name: &str, offset: Vec3, children: Vec<Arc<Node>>) -> Arc<Node> {
+        Arc::new(Node {
+            index,
+            name: Some(name.to_string()),
+            data: None,
+            transform: Mat4::translation_3d(offset),
+            decomposed: NodeTransform::default(),
+            skin: None,
+            morph_weights: None,
+            children,
+        })
+    }
+
+    #[test]
+    fn overrides_are_inherited_by_children() {
+        let child = node(1, "hand", Vec3::unit_x(), Vec::new());
+        let root = node(0, "arm", Vec3::unit_y(), vec![child]);
+
+        let mut overrides = HashMap::new();
+        overrides.insert("arm".to_string(), Mat4::scaling_3d(Vec3::broadcast(2.0)));
+        let mut traversal = root.traverse_with(None, &overrides);
+        let mut world_positions = Vec::new();
+        while let Some((parent_trans, node)) = traversal.next() {
+            let world_transform = parent_trans * traversal.local_transform(&node);
+            world_positions.push(world_transform.mul_point(Vec3::zero()));
+        }
+
+        // The override is applied in the local space of the arm, so the arm itself does not move
+        // but the offset of the hand from the arm is scaled
+        assert_eq!(world_positions, &[Vec3::unit_y(), Vec3::new(2.0, 1.0, 0.0)]);
+    }
+}
@@ -0,0 +1,121 @@
+//! Simple procedurally generated geometry
+
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+use crate::math::Vec3;
+
+use super::{Geometry, Material};
+
+impl Geometry {
+    /// Generates a sphere centered at the origin, divided into the given number of slices (around
+    /// the y-axis) and stacks (from pole to pole)
+    pub fn uv_sphere(radius: f32, slices: u32, stacks: u32, material: Arc<Material>) -> Self {
+        assert!(slices >= 3 && stacks >= 2, "not enough slices or stacks to form a sphere");
+
+        let mut positions = Vec::new();
+        let mut normals = Vec::new();
+        for stack in 0..=stacks {
+            let polar = stack as f32 / stacks as f32 * PI;
+            for slice in 0..=slices {
+                let azimuth = slice as f32 / slices as f32 * 2.0 * PI;
+                let normal = Vec3::new(
+                    polar.sin() * azimuth.cos(),
+                    polar.cos(),
+                    polar.sin() * azimuth.sin(),
+                );
+                positions.push(normal * radius);
+                normals.push(normal);
+            }
+        }
+
+        let mut indices = Vec::new();
+        let row = slices + 1;
+        for stack in 0..stacks {
+            for slice in 0..slices {
+                let top_left = stack * row + slice;
+                let bottom_left = top_left + row;
+                // Counter-clockwise when viewed from outside of the sphere
+                indices.extend_from_slice(&[top_left, top_left + 1, bottom_left]);
+                indices.extend_from_slice(&[top_left + 1, bottom_left + 1, bottom_left]);
+            }
+        }
+
+        Self {name: None, indices, positions, normals, tex_coords: Vec::new(), tangents: Vec::new(), colors: Vec::new(), joints: Vec::new(), weights: Vec::new(), morph_targets: Vec::new(), material}
+    }
+
+    /// Generates a cone with its tip at the origin that opens along the -z axis, ending in a flat
+    /// base at z = -height
+    pub fn cone(radius: f32, height: f32, slices: u32, material: Arc<Material>) -> Self {
+        assert!(slices >= 3, "not enough slices to form a cone");
+
+        let mut indices = Vec::new();
+        let mut positions = Vec::new();
+        let mut normals = Vec::new();
+        let mut triangle = |vertices: [Vec3; 3]| {
+            // Every triangle has its own vertices so the normals are flat
+            let [a, b, c] = vertices;
+            let normal = (b - a).cross(c - a).normalized();
+            for &pos in &[a, b, c] {
+                indices.push(positions.len() as u32);
+                positions.push(pos);
+                normals.push(normal);
+            }
+        };
+
+        let tip = Vec3::zero();
+        let base_center = Vec3::new(0.0, 0.0, -height);
+        let rim = |slice: u32| {
+            let angle = slice as f32 / slices as f32 * 2.0 * PI;
+            Vec3::new(radius * angle.cos(), radius * angle.sin(), -height)
+        };
+        for slice in 0..slices {
+            let (current, next) = (rim(slice), rim(slice + 1));
+            // Counter-clockwise when viewed from outside of the cone
+            triangle([tip, current, next]);
+            triangle([base_center, next, current]);
+        }
+
+        Self {name: None, indices, positions, normals, tex_coords: Vec::new(), tangents: Vec::new(), colors: Vec::new(), joints: Vec::new(), weights: Vec::new(), morph_targets: Vec::new(), material}
+    }
+
+    /// Generates a square in the xz-plane centered at the origin, facing up the +y axis
+    pub fn plane(size: f32, material: Arc<Material>) -> Self {
+        let half = size / 2.0;
+        let positions = vec![
+            Vec3::new(-half, 0.0, -half),
+            Vec3::new(-half, 0.0, half),
+            Vec3::new(half, 0.0, half),
+            Vec3::new(half, 0.0, -half),
+        ];
+        let normals = vec![Vec3::unit_y(); 4];
+        // Counter-clockwise when viewed from above
+        let indices = vec![0, 1, 2, 0, 2, 3];
+
+        Self {name: None, indices, positions, normals, tex_coords: Vec::new(), tangents: Vec::new(), colors: Vec::new(), joints: Vec::new(), weights: Vec::new(), morph_targets: Vec::new(), material}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plane_faces_up() {
+        let plane = Geometry::plane(4.0, Arc::new(Material::default()));
+
+        assert_eq!(plane.positions.len(), 4);
+        for pos in &plane.positions {
+            assert_eq!(pos.y, 0.0);
+            assert_eq!((pos.x.abs(), pos.z.abs()), (2.0, 2.0));
+        }
+        assert!(plane.normals.iter().all(|&normal| normal == Vec3::unit_y()));
+
+        // Each triangle is wound so that it faces the same way as its normals
+        for tri in plane.indices.chunks(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| plane.positions[tri[i] as usize]);
+            let facing = (b - a).cross(c - a).normalized();
+            assert_eq!(facing, Vec3::unit_y());
+        }
+    }
+}
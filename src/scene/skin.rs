@@ -0,0 +1,37 @@
+use std::collections::HashMap;
+
+use crate::math::Mat4;
+
+#[derive(Debug, Clone)]
+pub struct Skin {
+    /// The index of the node used as each joint of this skin
+    pub joints: Vec<usize>,
+    /// The matrix that brings each joint into the coordinate space of the skinned geometry
+    pub inverse_bind_matrices: Vec<Mat4>,
+}
+
+impl Skin {
+    pub fn from_gltf(skin: gltf::Skin, buffers: &[gltf::buffer::Data]) -> Self {
+        let joints: Vec<_> = skin.joints().map(|joint| joint.index()).collect();
+
+        let reader = skin.reader(|buffer| Some(&buffers[buffer.index()]));
+        // When the inverse bind matrices are omitted, each matrix is the identity matrix
+        // See: https://github.com/KhronosGroup/glTF/tree/master/specification/2.0#skins
+        let inverse_bind_matrices = match reader.read_inverse_bind_matrices() {
+            Some(matrices) => matrices.map(Mat4::from_col_arrays).collect(),
+            None => vec![Mat4::identity(); joints.len()],
+        };
+
+        Self {joints, inverse_bind_matrices}
+    }
+
+    /// Computes the matrix that transforms each vertex of the skinned geometry into world space
+    /// based on the given world transform of each joint (referenced by node index)
+    pub fn joint_matrices(&self, world_transforms: &HashMap<usize, Mat4>) -> Vec<Mat4> {
+        self.joints.iter().zip(&self.inverse_bind_matrices).map(|(joint, &inverse_bind)| {
+            let joint_transform = world_transforms.get(joint)
+                .expect("glTF skin joints must be part of the same scene as the skinned mesh");
+            *joint_transform * inverse_bind
+        }).collect()
+    }
+}
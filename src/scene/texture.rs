@@ -0,0 +1,144 @@
+use image::RgbaImage;
+
+/// The way that texture coordinates outside of the range 0.0 to 1.0 are mapped onto a texture
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WrapMode {
+    /// The texture is tiled, so coordinate 1.25 is the same as coordinate 0.25
+    #[default]
+    Repeat,
+    /// Coordinates are clamped to the edge of the texture, so coordinate 1.25 is the same as
+    /// coordinate 1.0
+    ClampToEdge,
+    /// The texture is tiled with every other tile mirrored, so coordinate 1.25 is the same as
+    /// coordinate 0.75
+    MirroredRepeat,
+}
+
+impl WrapMode {
+    pub fn from_gltf(mode: gltf::texture::WrappingMode) -> Self {
+        use gltf::texture::WrappingMode::*;
+        match mode {
+            Repeat => WrapMode::Repeat,
+            ClampToEdge => WrapMode::ClampToEdge,
+            MirroredRepeat => WrapMode::MirroredRepeat,
+        }
+    }
+
+    /// Returns the horizontal (s) and vertical (t) wrap modes of a glTF sampler
+    pub fn from_gltf_sampler(sampler: &gltf::texture::Sampler) -> (Self, Self) {
+        (Self::from_gltf(sampler.wrap_s()), Self::from_gltf(sampler.wrap_t()))
+    }
+}
+
+/// The way that a texture is sampled between the centers of its texels
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FilterMode {
+    /// Uses the color of the nearest texel, which keeps the texels of pixel art crisp
+    #[default]
+    Nearest,
+    /// Interpolates between the colors of the four nearest texels, which avoids blocky results
+    /// for high resolution textures
+    Bilinear,
+}
+
+impl FilterMode {
+    /// Returns the filter mode of a glTF sampler. The magnification filter is used if the
+    /// sampler has one, otherwise the minification filter is used (ignoring its mipmap mode).
+    /// Samplers without either filter use `Nearest`.
+    pub fn from_gltf_sampler(sampler: &gltf::texture::Sampler) -> Self {
+        use gltf::texture::{MagFilter, MinFilter};
+        let is_linear = match (sampler.mag_filter(), sampler.min_filter()) {
+            (Some(mag_filter), _) => mag_filter == MagFilter::Linear,
+            (None, Some(min_filter)) => matches!(min_filter,
+                MinFilter::Linear | MinFilter::LinearMipmapNearest | MinFilter::LinearMipmapLinear),
+            (None, None) => false,
+        };
+
+        if is_linear { FilterMode::Bilinear } else { FilterMode::Nearest }
+    }
+}
+
+/// An image used to color the surface of geometry
+///
+/// The first row of the image is the top of the texture, so texture coordinate (0, 0) refers to
+/// its top-left corner.
+#[derive(Debug)]
+pub struct Texture {
+    pub image: RgbaImage,
+    /// The way that texture coordinates outside of the texture are wrapped horizontally (s) and
+    /// vertically (t)
+    pub wrap: (WrapMode, WrapMode),
+    /// The way that the texture is sampled between the centers of its texels
+    pub filter: FilterMode,
+}
+
+impl Texture {
+    /// Creates a texture that repeats in both directions and uses nearest neighbor sampling
+    pub fn new(image: RgbaImage) -> Self {
+        Self {
+            image,
+            wrap: (WrapMode::default(), WrapMode::default()),
+            filter: FilterMode::default(),
+        }
+    }
+
+    /// Creates a texture from a glTF image that repeats in both directions and uses nearest
+    /// neighbor sampling. The sampler of the glTF texture that uses the image can be applied
+    /// with `WrapMode::from_gltf_sampler` and `FilterMode::from_gltf_sampler`.
+    pub fn from_gltf(data: &gltf::image::Data) -> Self {
+        use gltf::image::Format::*;
+        // The number of channels in each format
+        let channels = match data.format {
+            R8 => 1,
+            R8G8 => 2,
+            R8G8B8 | B8G8R8 => 3,
+            R8G8B8A8 | B8G8R8A8 => 4,
+        };
+        let is_bgr = matches!(data.format, B8G8R8 | B8G8R8A8);
+
+        let pixels = data.pixels.chunks(channels).flat_map(|pixel| {
+            // glTF images are PNG or JPEG files, so images with one or two channels come from
+            // grayscale images (with or without alpha)
+            let [r, g, b, a] = match channels {
+                1 => [pixel[0], pixel[0], pixel[0], 255],
+                2 => [pixel[0], pixel[0], pixel[0], pixel[1]],
+                3 => [pixel[0], pixel[1], pixel[2], 255],
+                _ => [pixel[0], pixel[1], pixel[2], pixel[3]],
+            };
+
+            if is_bgr { [b, g, r, a] } else { [r, g, b, a] }
+        }).collect();
+
+        let image = RgbaImage::from_raw(data.width, data.height, pixels)
+            .expect("bug: glTF image data did not match its size");
+
+        Self::new(image)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use gltf::image::{Data, Format};
+
+    #[test]
+    fn convert_gltf_image_formats() {
+        let bgr = Texture::from_gltf(&Data {
+            pixels: vec![10, 20, 30, 40, 50, 60],
+            format: Format::B8G8R8,
+            width: 2,
+            height: 1,
+        });
+        assert_eq!(bgr.image.get_pixel(0, 0).0, [30, 20, 10, 255]);
+        assert_eq!(bgr.image.get_pixel(1, 0).0, [60, 50, 40, 255]);
+
+        let gray_alpha = Texture::from_gltf(&Data {
+            pixels: vec![0x80, 0x40],
+            format: Format::R8G8,
+            width: 1,
+            height: 1,
+        });
+        assert_eq!(gray_alpha.image.get_pixel(0, 0).0, [0x80, 0x80, 0x80, 0x40]);
+    }
+}
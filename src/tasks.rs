@@ -1,21 +1,25 @@
 mod file_cache;
+mod job_spec;
 
 pub use file_cache::*;
+pub use job_spec::*;
 
 use std::sync::{Arc, Mutex};
 use std::path::{Path, PathBuf};
 use std::num::NonZeroU32;
+use std::collections::HashMap;
 
 use interpolation::lerp;
 use thiserror::Error;
 
 use crate::math::{Mat4, Vec3, Rgb};
 use crate::config;
-use crate::scene::{CameraType, LightType};
+use crate::scene::{CameraType, LightType, NormalMode};
 use crate::query3d::{
     File,
     FileError,
     CameraQuery,
+    CameraFilter,
     GeometryQuery,
     GeometryFilter,
     AnimationQuery,
@@ -25,17 +29,30 @@ use crate::renderer::{
     ThreadRenderContext,
     DrawLayoutError,
     RenderJob,
+    PixelFormat,
     RenderNode,
     RenderLayout,
     LayoutType,
+    FillOrder,
     RenderedImage,
+    AspectMode,
     Size,
     Outline,
+    OutlineMode,
+    EdgeThresholds,
+    CelBands,
+    ShadingMode,
+    ColorSpace,
     Light,
     RenderLights,
     Camera,
     RenderCamera,
+    RenderGeometry,
     FileQuery,
+    Supersample,
+    DownscaleFilter,
+    ScaleMode,
+    CellAlignment,
 };
 
 #[derive(Debug, Error)]
@@ -69,22 +86,37 @@ pub fn generate_pose_task(
     base_dir: &Path,
     file_cache: &mut WeakFileCache,
 ) -> Result<Task, FileError> {
-    let config::Pose {model, path, width, height, camera, scale, background, outline} = pose;
+    let config::Pose {
+        model,
+        path,
+        width,
+        height,
+        camera,
+        scale,
+        scale_mode,
+        background,
+        outline,
+        supersample,
+        supersample_filter,
+    } = pose;
 
     let (file, geometry) = match model {
         config::PoseModel::GltfFrame {gltf, animation, time} => {
             let file = file_cache.open_gltf(&gltf.resolve(base_dir))?;
 
-            let geometry = FileQuery {
+            let geometry = RenderGeometry::Query(FileQuery {
                 query: GeometryQuery {
                     models: GeometryFilter::all_in_default_scene(),
                     animation: Some(AnimationQuery {
                         name: animation,
                         position: AnimationPosition::Time(time),
                     }),
+                    morph_weights: None,
+                    transform_overrides: HashMap::new(),
+                    normals: NormalMode::Smooth,
                 },
                 file: file.clone(),
-            };
+            });
 
             (file, geometry)
         },
@@ -92,13 +124,16 @@ pub fn generate_pose_task(
         config::PoseModel::Model(path) => {
             let file = file_cache.open(&path.resolve(base_dir))?;
 
-            let geometry = FileQuery {
+            let geometry = RenderGeometry::Query(FileQuery {
                 query: GeometryQuery {
                     models: GeometryFilter::all_in_default_scene(),
                     animation: None,
+                    morph_weights: None,
+                    transform_overrides: HashMap::new(),
+                    normals: NormalMode::Smooth,
                 },
                 file: file.clone(),
-            };
+            });
 
             (file, geometry)
         },
@@ -106,10 +141,17 @@ pub fn generate_pose_task(
 
     let job = RenderJob {
         scale,
+        scale_mode: config_to_scale_mode(scale_mode),
+        flip_y: false,
+        premultiply: false,
+        format: PixelFormat::default(),
         root: RenderNode::RenderedImage(RenderedImage {
             size: Size {width, height},
-            background,
+            background: background.into(),
+            cell_background: None,
             camera: preset_to_camera(&camera, &file),
+            framing: None,
+            aspect: AspectMode::Stretch,
             //TODO: Figure out how we want to allow lights to be configured
             lights: RenderLights::Lights(Arc::new(vec![Arc::new(Light {
                 data: Arc::new(LightType::Directional {
@@ -119,8 +161,22 @@ pub fn generate_pose_task(
                 world_transform: Mat4::rotation_x((-60.0f32).to_radians()),
             })])),
             ambient_light: Rgb::white() * 0.5,
+            key_light: None,
+            bands: CelBands::default(),
+            shading: ShadingMode::Cel,
+            color_space: ColorSpace::Linear,
+            exposure: 1.0,
+            gamma: 1.0,
             geometry,
             outline: config_to_outline(outline),
+            rim: None,
+            specular: None,
+            shadows: None,
+            ground: None,
+            light_gizmos: false,
+            wireframe: None,
+            supersample: config_to_supersample(supersample, supersample_filter),
+            depth: None,
         }),
     };
 
@@ -135,7 +191,7 @@ pub fn generate_spritesheet_task(
     base_dir: &Path,
     file_cache: &mut WeakFileCache,
 ) -> Result<Task, FileError> {
-    let config::Spritesheet {path, animations, scale, background} = sheet;
+    let config::Spritesheet {path, animations, scale, scale_mode, background, bleed, spacing, padding, align} = sheet;
 
     let cols = animations.iter().map(|anim| anim.frames.len()).max()
         .expect("zero-length animations are not supported");
@@ -146,8 +202,9 @@ pub fn generate_spritesheet_task(
     for anim in animations {
         let extra = cols - anim.frames.len();
 
-        let config::Animation {frames, frame_width, frame_height, camera, outline} = anim;
+        let config::Animation {frames, frame_width, frame_height, camera, outline, supersample, supersample_filter} = anim;
         let outline = config_to_outline(outline);
+        let supersample = config_to_supersample(supersample, supersample_filter);
 
         let frame_size = Size {width: frame_width, height: frame_height};
 
@@ -163,8 +220,11 @@ pub fn generate_spritesheet_task(
 
                     nodes.push(RenderNode::RenderedImage(RenderedImage {
                         size: frame_size,
-                        background,
+                        background: background.into(),
+                        cell_background: None,
                         camera: camera.clone(),
+                        framing: None,
+                        aspect: AspectMode::Stretch,
                         //TODO: Figure out how we want to allow lights to be configured
                         lights: RenderLights::Lights(Arc::new(vec![Arc::new(Light {
                             data: Arc::new(LightType::Directional {
@@ -174,7 +234,13 @@ pub fn generate_spritesheet_task(
                             world_transform: Mat4::rotation_x((-60.0f32).to_radians()),
                         })])),
                         ambient_light: Rgb::white() * 0.5,
-                        geometry: FileQuery {
+                        key_light: None,
+                        bands: CelBands::default(),
+                        shading: ShadingMode::Cel,
+                        color_space: ColorSpace::Linear,
+                        exposure: 1.0,
+                        gamma: 1.0,
+                        geometry: RenderGeometry::Query(FileQuery {
                             query: GeometryQuery {
                                 models: GeometryFilter::all_in_default_scene(),
 
@@ -190,12 +256,23 @@ pub fn generate_spritesheet_task(
                                             weight,
                                         },
                                     }
-                                })
+                                }),
+                                morph_weights: None,
+                                transform_overrides: HashMap::new(),
+                                normals: NormalMode::Smooth,
                             },
 
                             file: file.clone(),
-                        },
+                        }),
                         outline: outline.clone(),
+                        rim: None,
+                        specular: None,
+                        shadows: None,
+                        ground: None,
+                        light_gizmos: false,
+                        wireframe: None,
+                        depth: None,
+                        supersample,
                     }));
                 }
             },
@@ -208,8 +285,11 @@ pub fn generate_spritesheet_task(
 
                     nodes.push(RenderNode::RenderedImage(RenderedImage {
                         size: frame_size,
-                        background,
+                        background: background.into(),
+                        cell_background: None,
                         camera,
+                        framing: None,
+                        aspect: AspectMode::Stretch,
                         //TODO: Figure out how we want to allow lights to be configured
                         lights: RenderLights::Lights(Arc::new(vec![Arc::new(Light {
                             data: Arc::new(LightType::Directional {
@@ -219,16 +299,33 @@ pub fn generate_spritesheet_task(
                             world_transform: Mat4::rotation_x((-60.0f32).to_radians()),
                         })])),
                         ambient_light: Rgb::white() * 0.5,
-                        geometry: FileQuery {
+                        key_light: None,
+                        bands: CelBands::default(),
+                        shading: ShadingMode::Cel,
+                        color_space: ColorSpace::Linear,
+                        exposure: 1.0,
+                        gamma: 1.0,
+                        geometry: RenderGeometry::Query(FileQuery {
                             query: GeometryQuery {
                                 models: GeometryFilter::all_in_default_scene(),
                                 // Use the default state of the scene
                                 animation: None,
+                                morph_weights: None,
+                                transform_overrides: HashMap::new(),
+                                normals: NormalMode::Smooth,
                             },
 
                             file,
-                        },
+                        }),
                         outline: outline.clone(),
+                        rim: None,
+                        specular: None,
+                        shadows: None,
+                        ground: None,
+                        light_gizmos: false,
+                        wireframe: None,
+                        depth: None,
+                        supersample,
                     }));
                 }
             },
@@ -236,17 +333,28 @@ pub fn generate_spritesheet_task(
 
         // Fill out the rest of the row with extra empty cells
         for _ in 0..extra {
-            nodes.push(RenderNode::Empty {size: frame_size});
+            nodes.push(RenderNode::Empty {size: frame_size, background: None});
         }
     }
 
     let job = RenderJob {
         scale,
+        scale_mode: config_to_scale_mode(scale_mode),
+        flip_y: false,
+        premultiply: false,
+        format: PixelFormat::default(),
         root: RenderNode::Layout(RenderLayout {
             nodes,
             layout: LayoutType::Grid {
                 cols: NonZeroU32::new(cols).expect("zero-length animations are not supported"),
+                // Each animation is a row of the spritesheet
+                fill: FillOrder::RowMajor,
+                spacing,
+                padding,
+                align: config_to_alignment(align),
             },
+            background: None,
+            bleed,
         }),
     };
 
@@ -266,7 +374,7 @@ fn preset_to_camera(cam: &config::PresetCamera, file: &Arc<Mutex<File>>) -> Rend
 }
 
 fn config_to_camera(cam: &config::Camera) -> RenderCamera {
-    let &config::Camera {eye, target, aspect_ratio, fov_y, near_z, far_z} = cam;
+    let &config::Camera {eye, target, aspect_ratio, fov_y, near_z, far_z, handedness} = cam;
     let field_of_view_y = fov_y.into();
     let cam_type = CameraType::Perspective {
         name: None,
@@ -277,8 +385,9 @@ fn config_to_camera(cam: &config::Camera) -> RenderCamera {
     };
 
     RenderCamera::Camera(Arc::new(Camera {
-        view: Mat4::look_at_rh(eye, target, Vec3::up()),
-        projection: cam_type.to_projection(),
+        view: handedness.look_at(eye, target, Vec3::up()),
+        projection: cam_type.to_projection(handedness),
+        handedness,
     }))
 }
 
@@ -286,9 +395,12 @@ fn named_to_camera(named: &config::NamedCamera, file: &Arc<Mutex<File>>) -> Rend
     let config::NamedCamera {name, scene} = named;
 
     RenderCamera::Query(FileQuery {
-        query: CameraQuery::Named {
-            name: name.clone(),
-            scene: scene.clone(),
+        query: CameraQuery {
+            camera: CameraFilter::Named {
+                name: name.clone(),
+                scene: scene.clone(),
+            },
+            animation: None,
         },
 
         file: file.clone(),
@@ -296,7 +408,48 @@ fn named_to_camera(named: &config::NamedCamera, file: &Arc<Mutex<File>>) -> Rend
 }
 
 fn config_to_outline(outline: config::Outline) -> Outline {
-    let config::Outline {thickness, color} = outline;
+    let config::Outline {thickness, units, color} = outline;
+
+    let mode = match units {
+        config::OutlineUnits::Model => OutlineMode::InvertedHull {thickness},
+        config::OutlineUnits::Pixels => {
+            let EdgeThresholds {depth, normal} = EdgeThresholds::default();
+            OutlineMode::EdgeDetect {
+                thickness: thickness.round() as u32,
+                depth_threshold: depth,
+                normal_threshold: normal,
+            }
+        },
+    };
+
+    Outline {mode, color}
+}
+
+fn config_to_supersample(factor: NonZeroU32, filter: config::DownscaleFilter) -> Option<Supersample> {
+    let filter = match filter {
+        config::DownscaleFilter::Box => DownscaleFilter::Box,
+        config::DownscaleFilter::Lanczos3 => DownscaleFilter::Lanczos3,
+        config::DownscaleFilter::Dominant => DownscaleFilter::Dominant,
+    };
 
-    Outline {thickness, color}
+    match factor.get() {
+        1 => None,
+        _ => Some(Supersample {factor: (factor, factor), filter}),
+    }
+}
+
+fn config_to_scale_mode(scale_mode: config::ScaleMode) -> ScaleMode {
+    match scale_mode {
+        config::ScaleMode::Nearest => ScaleMode::Nearest,
+        config::ScaleMode::Bilinear => ScaleMode::Bilinear,
+    }
+}
+
+fn config_to_alignment(align: config::CellAlignment) -> CellAlignment {
+    match align {
+        config::CellAlignment::TopLeft => CellAlignment::TopLeft,
+        config::CellAlignment::TopCenter => CellAlignment::TopCenter,
+        config::CellAlignment::Center => CellAlignment::Center,
+        config::CellAlignment::BottomCenter => CellAlignment::BottomCenter,
+    }
 }
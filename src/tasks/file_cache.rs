@@ -9,12 +9,28 @@ use crate::query3d::{File, FileError};
 /// If the file has not been opened or if the current reference to the file has been dropped,
 /// a new Rc<File> will be created. The purpose of using weak references explicitly is to ensure
 /// that files are dropped in a timely manner, even if this cache is accidentally left around.
-#[derive(Debug, Default)]
+#[derive(Debug)]
 pub struct WeakFileCache {
     cache: HashMap<PathBuf, Weak<Mutex<File>>>,
+    /// The size of one unit in any OBJ files opened through this cache, in meters
+    obj_unit_scale: f32,
+}
+
+impl Default for WeakFileCache {
+    fn default() -> Self {
+        Self::with_obj_unit_scale(1.0)
+    }
 }
 
 impl WeakFileCache {
+    /// Creates a cache that opens OBJ files with the given unit scale
+    pub fn with_obj_unit_scale(obj_unit_scale: f32) -> Self {
+        Self {
+            cache: HashMap::default(),
+            obj_unit_scale,
+        }
+    }
+
     /// Attempt to get a file from the cache
     ///
     /// Returns None if the file was never opened or if it has since been closed
@@ -24,7 +40,8 @@ impl WeakFileCache {
 
     /// Opens a 3D file based on its extension
     pub fn open(&mut self, path: &Path) -> Result<Arc<Mutex<File>>, FileError> {
-        self.open_with(path, File::open)
+        let obj_unit_scale = self.obj_unit_scale;
+        self.open_with(path, |path| File::open_with_obj_unit_scale(path, obj_unit_scale))
     }
 
     /// Opens a glTF file
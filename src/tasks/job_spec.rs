@@ -0,0 +1,453 @@
+use std::io;
+use std::fs;
+use std::env;
+use std::sync::{Arc, Mutex};
+use std::path::Path;
+use std::collections::HashMap;
+
+use thiserror::Error;
+
+use crate::math::{Mat4, Vec3, Quaternion, Radians};
+use crate::config::{self, JobSpec, NodeSpec, ImageSpec, AnimationSpec, CameraSpec, LightSpec};
+use crate::scene::{CameraType, LightType, NormalMode};
+use crate::query3d::{
+    File,
+    FileError,
+    CameraQuery,
+    CameraFilter,
+    LightQuery,
+    GeometryQuery,
+    GeometryFilter,
+    AnimationQuery,
+    AnimationPosition,
+};
+use crate::renderer::{
+    RenderJob,
+    RenderNode,
+    RenderLayout,
+    LayoutType,
+    RenderedImage,
+    Size,
+    CelBands,
+    Light,
+    RenderLights,
+    Camera,
+    RenderCamera,
+    RenderGeometry,
+    FileQuery,
+    PixelFormat,
+    FillOrder,
+    AspectMode,
+    ShadingMode,
+    ColorSpace,
+};
+
+use super::{WeakFileCache, config_to_outline, config_to_scale_mode, config_to_alignment};
+
+#[derive(Debug, Error)]
+pub enum JobSpecError {
+    #[error("Unable to read job specification: {0}")]
+    IoError(#[from] io::Error),
+    /// The document could not be parsed. The message includes the offending field and its line.
+    #[error("Invalid job specification: {0}")]
+    ParseError(#[from] serde_json::Error),
+    #[error("Invalid job specification: `{field}` {reason}")]
+    InvalidField {
+        /// The path to the field from the root of the document, e.g. `root.nodes[2].key_light`
+        field: String,
+        reason: String,
+    },
+    #[error("Unable to open the model for `{field}`: {error}")]
+    FileError {
+        field: String,
+        #[source]
+        error: FileError,
+    },
+}
+
+impl RenderJob {
+    /// Loads a render job from the JSON job specification at the given path. The paths of the 3D
+    /// files in the specification are relative to the directory that contains it.
+    pub fn from_spec(path: &Path) -> Result<Self, JobSpecError> {
+        let spec: JobSpec = serde_json::from_str(&fs::read_to_string(path)?)?;
+        let base_dir = env::current_dir()?.join(path);
+        let base_dir = base_dir.parent()
+            .expect("bug: an absolute path to a file should always have a parent directory");
+        let mut file_cache = WeakFileCache::with_obj_unit_scale(spec.obj_unit_scale);
+
+        spec_to_job(spec, base_dir, &mut file_cache)
+    }
+}
+
+/// Converts a parsed job specification into a render job, opening its 3D files through the given
+/// cache. The paths of the 3D files are resolved relative to `base_dir`.
+pub fn spec_to_job(
+    spec: JobSpec,
+    base_dir: &Path,
+    file_cache: &mut WeakFileCache,
+) -> Result<RenderJob, JobSpecError> {
+    let JobSpec {scale, scale_mode, flip_y, premultiply, format, obj_unit_scale: _, root} = spec;
+
+    Ok(RenderJob {
+        scale,
+        scale_mode: config_to_scale_mode(scale_mode),
+        flip_y,
+        premultiply,
+        format: spec_to_format(format),
+        root: spec_to_node(root, "root", base_dir, file_cache)?,
+    })
+}
+
+fn spec_to_node(
+    node: NodeSpec,
+    field: &str,
+    base_dir: &Path,
+    file_cache: &mut WeakFileCache,
+) -> Result<RenderNode, JobSpecError> {
+    use NodeSpec::*;
+    let (nodes, layout, background, bleed) = match node {
+        Image(image) => return spec_to_image(image, field, base_dir, file_cache),
+
+        Grid {nodes, cols, fill, spacing, padding, align, background, bleed} => {
+            let layout = LayoutType::Grid {
+                cols,
+                fill: spec_to_fill(fill),
+                spacing,
+                padding,
+                align: config_to_alignment(align),
+            };
+            (nodes, layout, background, bleed)
+        },
+
+        Packed {nodes, width, background, bleed} => {
+            (nodes, LayoutType::Packed {width}, background, bleed)
+        },
+
+        Empty {width, height, background} => {
+            return Ok(RenderNode::Empty {size: Size {width, height}, background});
+        },
+    };
+
+    if nodes.is_empty() {
+        return Err(invalid(format!("{}.nodes", field), "must contain at least one node"));
+    }
+
+    let nodes = nodes.into_iter().enumerate()
+        .map(|(i, node)| spec_to_node(node, &format!("{}.nodes[{}]", field, i), base_dir, file_cache))
+        .collect::<Result<_, _>>()?;
+
+    Ok(RenderNode::Layout(RenderLayout {nodes, layout, background, bleed}))
+}
+
+fn spec_to_image(
+    image: ImageSpec,
+    field: &str,
+    base_dir: &Path,
+    file_cache: &mut WeakFileCache,
+) -> Result<RenderNode, JobSpecError> {
+    let ImageSpec {
+        width,
+        height,
+        model,
+        scene,
+        nodes,
+        animation,
+        camera,
+        aspect,
+        lights,
+        ambient_light,
+        key_light,
+        shading,
+        color_space,
+        exposure,
+        gamma,
+        background,
+        outline,
+    } = image;
+
+    if exposure < 0.0 {
+        return Err(invalid(format!("{}.exposure", field), "must not be negative"));
+    }
+    if gamma <= 0.0 {
+        return Err(invalid(format!("{}.gamma", field), "must be greater than zero"));
+    }
+
+    let file = file_cache.open(&model.resolve(base_dir)).map_err(|error| JobSpecError::FileError {
+        field: format!("{}.model", field),
+        error,
+    })?;
+
+    let animation = animation.map(|AnimationSpec {name, time}| AnimationQuery {
+        name,
+        position: AnimationPosition::Time(time),
+    });
+
+    let size = Size {width, height};
+    let camera = spec_to_camera(camera, size, &animation, &file);
+
+    let lights = match lights {
+        Some(lights) => {
+            if let Some(key_light) = key_light {
+                if key_light >= lights.len() {
+                    return Err(invalid(format!("{}.key_light", field),
+                        format!("is {} but there are only {} lights", key_light, lights.len())));
+                }
+            }
+
+            let lights = lights.into_iter().enumerate()
+                .map(|(i, light)| spec_to_light(light, &format!("{}.lights[{}]", field, i)))
+                .collect::<Result<_, _>>()?;
+            RenderLights::Lights(Arc::new(lights))
+        },
+
+        None => RenderLights::Query(FileQuery {
+            query: LightQuery::Scene {name: scene.clone()},
+            file: file.clone(),
+        }),
+    };
+
+    let models = match nodes {
+        Some(names) => GeometryFilter::Nodes {names, scene},
+        None => GeometryFilter::Scene {name: scene},
+    };
+
+    Ok(RenderNode::RenderedImage(RenderedImage {
+        size,
+        background: background.into(),
+        cell_background: None,
+        camera,
+        framing: None,
+        aspect: spec_to_aspect(aspect),
+        lights,
+        ambient_light,
+        key_light,
+        bands: CelBands::default(),
+        shading: spec_to_shading(shading),
+        color_space: spec_to_color_space(color_space),
+        exposure,
+        gamma,
+        geometry: RenderGeometry::Query(FileQuery {
+            query: GeometryQuery {
+                models,
+                animation,
+                morph_weights: None,
+                transform_overrides: HashMap::new(),
+                normals: NormalMode::Smooth,
+            },
+
+            file,
+        }),
+        outline: config_to_outline(outline),
+        rim: None,
+        specular: None,
+        shadows: None,
+        ground: None,
+        light_gizmos: false,
+        wireframe: None,
+        supersample: None,
+        depth: None,
+    }))
+}
+
+fn spec_to_camera(
+    camera: CameraSpec,
+    size: Size,
+    animation: &Option<AnimationQuery>,
+    file: &Arc<Mutex<File>>,
+) -> RenderCamera {
+    use CameraSpec::*;
+    let (eye, target, cam_type, handedness) = match camera {
+        File {name, scene} => {
+            let camera = match name {
+                Some(name) => CameraFilter::Named {name, scene},
+                None => CameraFilter::FirstInScene {name: scene},
+            };
+
+            return RenderCamera::Query(FileQuery {
+                query: CameraQuery {camera, animation: animation.clone()},
+                file: file.clone(),
+            });
+        },
+
+        Perspective {eye, target, aspect_ratio, fov_y, near_z, far_z, handedness} => {
+            let aspect_ratio = aspect_ratio
+                .unwrap_or_else(|| size.width.get() as f32 / size.height.get() as f32);
+            let cam_type = CameraType::Perspective {
+                name: None,
+                aspect_ratio,
+                field_of_view_y: fov_y.into(),
+                near_z,
+                far_z,
+            };
+            (eye, target, cam_type, handedness)
+        },
+
+        Orthographic {eye, target, mag_x, mag_y, near_z, far_z, handedness} => {
+            let cam_type = CameraType::Orthographic {name: None, mag_x, mag_y, near_z, far_z};
+            (eye, target, cam_type, handedness)
+        },
+    };
+
+    RenderCamera::Camera(Arc::new(Camera {
+        view: handedness.look_at(eye, target, Vec3::up()),
+        projection: cam_type.to_projection(handedness),
+        handedness,
+    }))
+}
+
+fn spec_to_light(light: LightSpec, field: &str) -> Result<Arc<Light>, JobSpecError> {
+    // Lights shine down their local negative z-axis
+    let rotation = |direction: Vec3| {
+        if direction.magnitude_squared() > 0.0 {
+            Ok(Quaternion::rotation_from_to_3d(-Vec3::unit_z(), direction))
+        } else {
+            Err(invalid(format!("{}.direction", field), "must not be zero"))
+        }
+    };
+
+    use LightSpec::*;
+    let light = match light {
+        Directional {direction, color, intensity} => Light {
+            data: Arc::new(LightType::Directional {color, intensity}),
+            world_transform: Mat4::from(rotation(direction)?),
+        },
+
+        Point {position, color, intensity, range} => Light {
+            data: Arc::new(LightType::Point {color, intensity, range}),
+            world_transform: Mat4::translation_3d(position),
+        },
+
+        Spot {position, direction, color, intensity, range, inner_cone_angle, outer_cone_angle} => {
+            if inner_cone_angle >= outer_cone_angle {
+                return Err(invalid(format!("{}.inner_cone_angle", field),
+                    "must be less than outer_cone_angle"));
+            }
+
+            Light {
+                data: Arc::new(LightType::Spot {
+                    color,
+                    intensity,
+                    range,
+                    inner_cone_angle: Radians::from(inner_cone_angle),
+                    outer_cone_angle: Radians::from(outer_cone_angle),
+                }),
+                world_transform: Mat4::translation_3d(position) * Mat4::from(rotation(direction)?),
+            }
+        },
+    };
+
+    Ok(Arc::new(light))
+}
+
+fn spec_to_format(format: config::PixelFormat) -> PixelFormat {
+    match format {
+        config::PixelFormat::Rgba8 => PixelFormat::Rgba8,
+        config::PixelFormat::Rgb8 => PixelFormat::Rgb8,
+        config::PixelFormat::Gray8 => PixelFormat::Gray8,
+    }
+}
+
+fn spec_to_fill(fill: config::FillOrder) -> FillOrder {
+    match fill {
+        config::FillOrder::RowMajor => FillOrder::RowMajor,
+        config::FillOrder::ColumnMajor => FillOrder::ColumnMajor,
+    }
+}
+
+fn spec_to_aspect(aspect: config::AspectMode) -> AspectMode {
+    match aspect {
+        config::AspectMode::Stretch => AspectMode::Stretch,
+        config::AspectMode::Letterbox => AspectMode::Letterbox,
+    }
+}
+
+fn spec_to_shading(shading: config::ShadingMode) -> ShadingMode {
+    match shading {
+        config::ShadingMode::Cel => ShadingMode::Cel,
+        config::ShadingMode::Flat => ShadingMode::Flat,
+        config::ShadingMode::Mask => ShadingMode::Mask,
+    }
+}
+
+fn spec_to_color_space(color_space: config::ColorSpace) -> ColorSpace {
+    match color_space {
+        config::ColorSpace::Linear => ColorSpace::Linear,
+        config::ColorSpace::Srgb => ColorSpace::Srgb,
+    }
+}
+
+fn invalid(field: String, reason: impl Into<String>) -> JobSpecError {
+    JobSpecError::InvalidField {field, reason: reason.into()}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::renderer::CellAlignment;
+
+    #[test]
+    fn spec_with_nested_layouts_and_tagged_enums() {
+        let spec: JobSpec = serde_json::from_str(r#"{
+            "scale": 2,
+            "format": "Gray8",
+            "root": {
+                "type": "Grid",
+                "cols": 2,
+                "fill": "ColumnMajor",
+                "align": "Center",
+                "nodes": [
+                    {"type": "Empty", "width": 8, "height": 8},
+                    {
+                        "type": "Packed",
+                        "width": 16,
+                        "nodes": [{"type": "Empty", "width": 4, "height": 4}]
+                    }
+                ]
+            }
+        }"#).unwrap();
+
+        let base_dir = env::current_dir().unwrap();
+        let job = spec_to_job(spec, &base_dir, &mut WeakFileCache::default()).unwrap();
+        assert_eq!(job.scale.get(), 2);
+        assert_eq!(job.format, PixelFormat::Gray8);
+        let layout = match job.root {
+            RenderNode::Layout(layout) => layout,
+            _ => panic!("expected a layout"),
+        };
+        assert!(matches!(layout.layout, LayoutType::Grid {
+            fill: FillOrder::ColumnMajor,
+            align: CellAlignment::Center,
+            ..
+        }));
+        assert!(matches!(&layout.nodes[1], RenderNode::Layout(RenderLayout {layout: LayoutType::Packed {..}, ..})));
+
+        let light: LightSpec = serde_json::from_str(r#"{"type": "Spot", "position": {"x": 0, "y": 1, "z": 0},
+            "direction": {"x": 0, "y": -1, "z": 0}, "inner_cone_angle": 50}"#).unwrap();
+        let err = spec_to_light(light, "root.lights[0]").unwrap_err();
+        assert_eq!(err.to_string(),
+            "Invalid job specification: `root.lights[0].inner_cone_angle` must be less than outer_cone_angle");
+
+        let light: LightSpec = serde_json::from_str(r#"{"type": "Directional",
+            "direction": {"x": 0, "y": 0, "z": 0}}"#).unwrap();
+        let err = spec_to_light(light, "root.lights[1]").unwrap_err();
+        assert_eq!(err.to_string(), "Invalid job specification: `root.lights[1].direction` must not be zero");
+    }
+
+    #[test]
+    fn empty_layouts_and_unknown_fields_are_errors() {
+        let spec: JobSpec = serde_json::from_str(r#"{
+            "root": {"type": "Grid", "cols": 1, "nodes": [{"type": "Packed", "width": 4, "nodes": []}]}
+        }"#).unwrap();
+        let base_dir = env::current_dir().unwrap();
+        let err = spec_to_job(spec, &base_dir, &mut WeakFileCache::default()).unwrap_err();
+        assert_eq!(err.to_string(), "Invalid job specification: `root.nodes[0].nodes` must contain at least one node");
+
+        let err = serde_json::from_str::<JobSpec>(r#"{
+            "root": {"type": "Empty", "width": 4, "height": 4, "colour": null}
+        }"#).unwrap_err();
+        assert!(err.to_string().contains("colour"), "{}", err);
+        let err = serde_json::from_str::<JobSpec>(r#"{"root": {"type": "Circle"}}"#).unwrap_err();
+        assert!(err.to_string().contains("Circle"), "{}", err);
+    }
+}